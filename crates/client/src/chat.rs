@@ -0,0 +1,103 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use macroquad::prelude::*;
+use shared::protocol::{sanitize_chat, ChatMessage, MAX_CHAT_LEN, SERVER_CHAT_ID};
+
+use crate::themes::ThemeConfig;
+
+/// Messages shown at most.
+const CHAT_SHOWN: usize = 6;
+/// How long a message stays up, fading out over the last second.
+const CHAT_SECONDS: f32 = 10.0;
+const LINE_HEIGHT: f32 = 22.0;
+const ENTRY_WIDTH: f32 = 520.0;
+/// Bottom of the chat, just above the HUD.
+const BOTTOM_MARGIN: f32 = 130.0;
+
+/// What the game should do after the chat line handled input.
+pub enum ChatAction {
+    Send { text: String, team: bool },
+    Cancel,
+}
+
+/// The line a chat message is typed into, open while the player types.
+pub struct ChatEntry {
+    text: String,
+    team: bool,
+}
+
+impl ChatEntry {
+    pub fn new(team: bool) -> Self {
+        // Characters queue up until read, including the one that opened the chat
+        while get_char_pressed().is_some() {}
+        Self { text: String::new(), team }
+    }
+
+    /// `Enter` sends, `Escape` gives up.
+    pub fn update(&mut self) -> Option<ChatAction> {
+        // Characters come out newest first
+        let mut typed = Vec::new();
+        while let Some(c) = get_char_pressed() {
+            typed.push(c);
+        }
+        for c in typed.into_iter().rev() {
+            if !c.is_control() && self.text.chars().count() < MAX_CHAT_LEN {
+                self.text.push(c);
+            }
+        }
+        if is_key_pressed(KeyCode::Backspace) {
+            self.text.pop();
+        }
+
+        if is_key_pressed(KeyCode::Escape) {
+            return Some(ChatAction::Cancel);
+        }
+        if is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::KpEnter) {
+            let text = sanitize_chat(&self.text);
+            if text.is_empty() {
+                return Some(ChatAction::Cancel);
+            }
+            return Some(ChatAction::Send { text, team: self.team });
+        }
+        None
+    }
+
+    pub fn draw(&self, theme: &ThemeConfig) {
+        let y = screen_height() - BOTTOM_MARGIN;
+        draw_rectangle(10.0, y - LINE_HEIGHT + 4.0, ENTRY_WIDTH, LINE_HEIGHT + 4.0, Color::from_rgba(0, 0, 0, 200));
+        draw_rectangle_lines(10.0, y - LINE_HEIGHT + 4.0, ENTRY_WIDTH, LINE_HEIGHT + 4.0, 1.0, theme.hud_primary);
+        let prompt = if self.team { "TEAM:" } else { "SAY:" };
+        draw_text(prompt, 16.0, y, 18.0, theme.hud_accent);
+        let cursor = if (get_time() * 2.0) as i64 % 2 == 0 { "_" } else { "" };
+        let left = 22.0 + measure_text(prompt, None, 18, 1.0).width;
+        draw_text(&format!("{}{}", self.text, cursor), left, y, 18.0, theme.text_primary);
+    }
+}
+
+/// The last few chat messages above the HUD. They fade out after a while,
+/// but stay up while the player is typing.
+pub fn draw_log(log: &VecDeque<(ChatMessage, Instant)>, typing: bool, theme: &ThemeConfig) {
+    // One line up for the entry while it's open
+    let bottom = screen_height() - BOTTOM_MARGIN - if typing { LINE_HEIGHT + 8.0 } else { 0.0 };
+    let shown = log.iter().rev().take(CHAT_SHOWN);
+    for (line, (message, received)) in shown.enumerate() {
+        let alpha = if typing { 1.0 } else { (CHAT_SECONDS - received.elapsed().as_secs_f32()).clamp(0.0, 1.0) };
+        if alpha <= 0.0 {
+            continue;
+        }
+        let y = bottom - line as f32 * LINE_HEIGHT;
+        let fade = |color: Color| Color::new(color.r, color.g, color.b, color.a * alpha);
+
+        let (name, name_color) = match message {
+            ChatMessage { player_id: SERVER_CHAT_ID, .. } => ("SERVER:".to_string(), YELLOW),
+            ChatMessage { team: true, username, .. } => (format!("(TEAM) {}:", username), theme.hud_accent),
+            ChatMessage { username, .. } => (format!("{}:", username), theme.hud_secondary),
+        };
+        let name_width = measure_text(&name, None, 18, 1.0).width;
+        let text_width = measure_text(&message.text, None, 18, 1.0).width;
+        draw_rectangle(10.0, y - LINE_HEIGHT + 6.0, name_width + text_width + 20.0, LINE_HEIGHT, Color::new(0.0, 0.0, 0.0, 0.5 * alpha));
+        draw_text(&name, 16.0, y, 18.0, fade(name_color));
+        draw_text(&message.text, 22.0 + name_width, y, 18.0, fade(theme.text_primary));
+    }
+}
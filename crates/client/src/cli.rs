@@ -0,0 +1,184 @@
+//! Command line options. Anything given here is filled in on the main menu,
+//! or skips it entirely, so the client can be launched from scripts.
+
+use std::fmt;
+
+use shared::address::{self, AddressError};
+use shared::level::LevelSource;
+use shared::protocol::{validate_username, UsernameError};
+
+pub const USAGE: &str = "\
+Usage: client [OPTIONS]
+
+Options:
+  --server <ADDRESS>     Server to join, host, host:port (port defaults to 34254) or an alias
+  --alias <NAME=ADDRESS> Save NAME as a short name for ADDRESS, can be repeated
+  --name <NAME>          Player name
+  --offline              Skip the menu and play alone
+  --level <LEVEL>        Level to play offline: a number, 7@1234 or a level file name
+  --windowed             Start in a window
+  --fullscreen           Start fullscreen
+  --resolution <WxH>     Window size, e.g. 1280x720
+  --help                 Print this help and exit
+
+Anything not given can be filled in on the main menu.
+";
+
+/// Smallest and largest window sizes accepted.
+const MIN_RESOLUTION: (i32, i32) = (320, 240);
+const MAX_RESOLUTION: (i32, i32) = (7680, 4320);
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Options {
+    /// Checked to be a well formed address, but not looked up yet.
+    pub server: Option<String>,
+    /// Aliases to save, name and address.
+    pub aliases: Vec<(String, String)>,
+    pub name: Option<String>,
+    pub offline: bool,
+    pub level: Option<String>,
+    /// `None` leaves it to the platform default.
+    pub fullscreen: Option<bool>,
+    pub resolution: Option<(i32, i32)>,
+    pub help: bool,
+}
+
+#[derive(Debug)]
+pub enum CliError {
+    UnknownFlag(String),
+    MissingValue(&'static str),
+    BadAddress(AddressError),
+    BadAlias(String),
+    BadName(UsernameError),
+    BadLevel(String),
+    BadResolution(String),
+    /// Two flags that can't be used together.
+    Conflict(&'static str, &'static str),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::UnknownFlag(flag) => write!(f, "unknown option '{}'", flag),
+            CliError::MissingValue(flag) => write!(f, "{} needs a value", flag),
+            CliError::BadAddress(error) => write!(f, "--server: {}", error),
+            CliError::BadAlias(value) => write!(f, "--alias: '{}' is not NAME=ADDRESS", value),
+            CliError::BadName(error) => write!(f, "--name: {}", error),
+            CliError::BadLevel(level) => write!(f, "'{}' is not a level number or level file name", level),
+            CliError::BadResolution(value) => write!(
+                f,
+                "'{}' is not a resolution between {}x{} and {}x{}",
+                value, MIN_RESOLUTION.0, MIN_RESOLUTION.1, MAX_RESOLUTION.0, MAX_RESOLUTION.1
+            ),
+            CliError::Conflict(first, second) => write!(f, "{} can't be used with {}", first, second),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+/// Flags that take a value.
+const VALUE_FLAGS: [&str; 5] = ["--server", "--alias", "--name", "--level", "--resolution"];
+
+impl Options {
+    /// Reads the process arguments. Prints help or the error and exits when
+    /// there's nothing to run.
+    pub fn from_env() -> Self {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        match Self::parse(&args) {
+            Ok(options) if options.help => {
+                print!("{}", USAGE);
+                std::process::exit(0);
+            }
+            Ok(options) => options,
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                eprintln!("Run with --help to see the options");
+                std::process::exit(2);
+            }
+        }
+    }
+
+    /// Parses the command line, without the program name.
+    pub fn parse(args: &[String]) -> Result<Self, CliError> {
+        let mut options = Self::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--help" | "-h" => {
+                    options.help = true;
+                    return Ok(options);
+                }
+                "--offline" => options.offline = true,
+                "--windowed" | "--fullscreen" => {
+                    let fullscreen = arg == "--fullscreen";
+                    if options.fullscreen == Some(!fullscreen) {
+                        return Err(CliError::Conflict("--windowed", "--fullscreen"));
+                    }
+                    options.fullscreen = Some(fullscreen);
+                }
+                _ => {
+                    // Both `--name bob` and `--name=bob`
+                    let (name, inline) = match arg.split_once('=') {
+                        Some((name, value)) => (name, Some(value.to_string())),
+                        None => (arg.as_str(), None),
+                    };
+                    let flag = *VALUE_FLAGS
+                        .iter()
+                        .find(|flag| **flag == name)
+                        .ok_or_else(|| CliError::UnknownFlag(arg.clone()))?;
+                    let value = match inline {
+                        Some(value) => value,
+                        None => args.next().cloned().ok_or(CliError::MissingValue(flag))?,
+                    };
+                    options.set(flag, value)?;
+                }
+            }
+        }
+
+        if options.server.is_some() && options.offline {
+            return Err(CliError::Conflict("--server", "--offline"));
+        }
+        if options.server.is_some() && options.level.is_some() {
+            return Err(CliError::Conflict("--server", "--level"));
+        }
+        // Picking a level only makes sense alone
+        if options.level.is_some() {
+            options.offline = true;
+        }
+        Ok(options)
+    }
+
+    fn set(&mut self, flag: &'static str, value: String) -> Result<(), CliError> {
+        match flag {
+            "--server" => {
+                address::parse(&value).map_err(CliError::BadAddress)?;
+                self.server = Some(value.trim().to_string());
+            }
+            "--alias" => {
+                let (alias, address) = value.split_once('=').ok_or_else(|| CliError::BadAlias(value.clone()))?;
+                let (alias, address) = (alias.trim(), address.trim());
+                if alias.is_empty() || address.is_empty() {
+                    return Err(CliError::BadAlias(value));
+                }
+                self.aliases.push((alias.to_string(), address.to_string()));
+            }
+            "--name" => self.name = Some(validate_username(&value).map_err(CliError::BadName)?.to_string()),
+            "--level" => {
+                LevelSource::from_arg(&value, 0).ok_or_else(|| CliError::BadLevel(value.clone()))?;
+                self.level = Some(value.trim().to_string());
+            }
+            "--resolution" => self.resolution = Some(parse_resolution(&value).ok_or(CliError::BadResolution(value))?),
+            _ => unreachable!("{} is not a value flag", flag),
+        }
+        Ok(())
+    }
+}
+
+/// `1280x720` to width and height.
+fn parse_resolution(text: &str) -> Option<(i32, i32)> {
+    let (width, height) = text.trim().to_ascii_lowercase().split_once('x').map(|(w, h)| (w.parse().ok(), h.parse().ok()))?;
+    let (width, height) = (width?, height?);
+    let fits = (MIN_RESOLUTION.0..=MAX_RESOLUTION.0).contains(&width) && (MIN_RESOLUTION.1..=MAX_RESOLUTION.1).contains(&height);
+    fits.then_some((width, height))
+}
@@ -0,0 +1,46 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Directory holding the client's config files. Can be overridden with the
+/// `MULTIPLAYER_FPS_CONFIG_DIR` environment variable.
+pub fn config_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("MULTIPLAYER_FPS_CONFIG_DIR") {
+        return PathBuf::from(dir);
+    }
+    if let Some(dir) = std::env::var_os("APPDATA") {
+        return PathBuf::from(dir).join("multiplayer-fps");
+    }
+    if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME") {
+        return PathBuf::from(dir).join("multiplayer-fps");
+    }
+    if let Some(home) = std::env::var_os("HOME") {
+        return PathBuf::from(home).join(".config").join("multiplayer-fps");
+    }
+    PathBuf::from(".")
+}
+
+/// Loads `name` from the config directory, falling back to the default value
+/// when the file is missing or cannot be parsed.
+pub fn load<T: DeserializeOwned + Default>(name: &str) -> T {
+    let path = config_dir().join(name);
+    match fs::read_to_string(&path) {
+        Ok(contents) => ron::from_str(&contents).unwrap_or_else(|e| {
+            eprintln!("Warning: ignoring invalid config {}: {}", path.display(), e);
+            T::default()
+        }),
+        Err(_) => T::default(),
+    }
+}
+
+/// Writes `value` to `name` in the config directory, creating it if needed.
+pub fn save<T: Serialize>(name: &str, value: &T) -> io::Result<()> {
+    let dir = config_dir();
+    fs::create_dir_all(&dir)?;
+    let contents = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    fs::write(dir.join(name), contents)
+}
@@ -0,0 +1,503 @@
+use macroquad::prelude::*;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use shared::level::{Base, EnemyKind, EnemySpawn, Level, LevelIssue, Pickup, PickupKind, Point, Theme, FLOOR, WALL};
+use shared::team::Team;
+
+use crate::minimap::{pickup_color, MapView};
+use crate::themes::{LevelTheme, ThemeConfig};
+
+const PANEL_WIDTH: f32 = 280.0;
+const MARGIN: f32 = 20.0;
+const ROW_HEIGHT: f32 = 28.0;
+const ROW_SPACING: f32 = 2.0;
+const MAX_NAME_LEN: usize = 32;
+const MAX_DIFFICULTY: u32 = 10;
+const STATUS_SECONDS: f32 = 3.0;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Tool {
+    Wall,
+    Start,
+    Exit,
+    Spawn,
+    Enemy,
+    Patrol,
+    Pickup,
+    Base,
+}
+
+const TOOLS: [Tool; 8] = [
+    Tool::Wall,
+    Tool::Start,
+    Tool::Exit,
+    Tool::Spawn,
+    Tool::Enemy,
+    Tool::Patrol,
+    Tool::Pickup,
+    Tool::Base,
+];
+const TOOL_KEYS: [KeyCode; 8] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+];
+
+impl Tool {
+    fn label(self) -> &'static str {
+        match self {
+            Tool::Wall => "WALL",
+            Tool::Start => "START",
+            Tool::Exit => "EXIT",
+            Tool::Spawn => "PLAYER SPAWN",
+            Tool::Enemy => "ENEMY (CLICK AGAIN: TYPE)",
+            Tool::Patrol => "PATROL POINT",
+            Tool::Pickup => "PICKUP (CLICK AGAIN: TYPE)",
+            Tool::Base => "FLAG BASE (CLICK AGAIN: TEAM)",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Row {
+    Name,
+    Difficulty,
+    Theme,
+    Tool(Tool),
+    Preview,
+    Save,
+    Back,
+}
+
+const ROWS: [Row; 14] = [
+    Row::Name,
+    Row::Difficulty,
+    Row::Theme,
+    Row::Tool(Tool::Wall),
+    Row::Tool(Tool::Start),
+    Row::Tool(Tool::Exit),
+    Row::Tool(Tool::Spawn),
+    Row::Tool(Tool::Enemy),
+    Row::Tool(Tool::Patrol),
+    Row::Tool(Tool::Pickup),
+    Row::Tool(Tool::Base),
+    Row::Preview,
+    Row::Save,
+    Row::Back,
+];
+
+/// What the game should do after the editor handled input.
+pub enum EditorAction {
+    /// Play the level being edited in first person.
+    Preview,
+    Close,
+}
+
+/// Top-down level editor. Left click paints with the selected tool, right
+/// click clears a cell. Painting and placing things leave doors, push
+/// walls, traps and one-way cells as they are; only clearing removes them.
+/// Patrol points are added to the enemy placed or clicked last.
+pub struct Editor {
+    pub level: Level,
+    path: PathBuf,
+    tool: Tool,
+    // Wall value being painted while the button is held, decided by the
+    // first cell clicked so a drag either only adds or only removes
+    painting: Option<bool>,
+    selected_enemy: Option<usize>,
+    editing_name: bool,
+    issues: Vec<LevelIssue>,
+    status: Option<(String, Instant)>,
+}
+
+impl Editor {
+    pub fn new(level: Level, path: PathBuf) -> Self {
+        let issues = level.validate();
+        Self {
+            level,
+            path,
+            tool: Tool::Wall,
+            painting: None,
+            selected_enemy: None,
+            editing_name: false,
+            issues,
+            status: None,
+        }
+    }
+
+    fn row_rect(index: usize) -> Rect {
+        Rect::new(
+            screen_width() - PANEL_WIDTH - MARGIN,
+            60.0 + index as f32 * (ROW_HEIGHT + ROW_SPACING),
+            PANEL_WIDTH,
+            ROW_HEIGHT,
+        )
+    }
+
+    fn view(&self) -> MapView {
+        let area = Rect::new(
+            MARGIN,
+            MARGIN,
+            screen_width() - PANEL_WIDTH - 3.0 * MARGIN,
+            screen_height() - 2.0 * MARGIN,
+        );
+        MapView::fit(self.level.width, self.level.height, area)
+    }
+
+    /// Shows a message under the panel for a few seconds.
+    pub fn set_status(&mut self, message: impl Into<String>) {
+        self.status = Some((message.into(), Instant::now()));
+    }
+
+    pub fn update(&mut self) -> Option<EditorAction> {
+        if self.editing_name {
+            self.edit_name();
+            return None;
+        }
+
+        if is_key_pressed(KeyCode::Escape) {
+            return Some(EditorAction::Close);
+        }
+        if is_key_pressed(KeyCode::F5) {
+            return Some(EditorAction::Preview);
+        }
+        let ctrl = is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl);
+        if ctrl && is_key_pressed(KeyCode::S) {
+            self.save();
+        }
+        for (tool, key) in TOOLS.iter().zip(TOOL_KEYS) {
+            if is_key_pressed(key) {
+                self.tool = *tool;
+            }
+        }
+
+        let mouse = Vec2::from(mouse_position());
+        if is_mouse_button_pressed(MouseButton::Left) {
+            if let Some(index) = (0..ROWS.len()).find(|&index| Self::row_rect(index).contains(mouse)) {
+                return self.activate(ROWS[index], mouse.x < Self::row_rect(index).center().x);
+            }
+        }
+
+        self.paint(mouse);
+        self.issues = self.level.validate();
+        None
+    }
+
+    fn activate(&mut self, row: Row, left_half: bool) -> Option<EditorAction> {
+        let step: i32 = if left_half { -1 } else { 1 };
+        match row {
+            Row::Name => {
+                self.editing_name = true;
+                clear_input_queue();
+            }
+            Row::Difficulty => {
+                self.level.difficulty = (self.level.difficulty as i32 + step).clamp(1, MAX_DIFFICULTY as i32) as u32;
+            }
+            Row::Theme => {
+                let current = self
+                    .level
+                    .theme
+                    .and_then(|theme| Theme::ALL.iter().position(|t| *t == theme))
+                    .unwrap_or(0);
+                let next = (current as i32 + step).rem_euclid(Theme::ALL.len() as i32) as usize;
+                self.level.theme = Some(Theme::ALL[next]);
+            }
+            Row::Tool(tool) => self.tool = tool,
+            Row::Preview => return Some(EditorAction::Preview),
+            Row::Save => self.save(),
+            Row::Back => return Some(EditorAction::Close),
+        }
+        None
+    }
+
+    fn edit_name(&mut self) {
+        if is_key_pressed(KeyCode::Enter) || is_key_pressed(KeyCode::Escape) || is_mouse_button_pressed(MouseButton::Left) {
+            self.editing_name = false;
+            return;
+        }
+        if is_key_pressed(KeyCode::Backspace) {
+            self.level.name.pop();
+        }
+        // The queue hands out the newest character first
+        let mut typed = Vec::new();
+        while let Some(c) = get_char_pressed() {
+            typed.push(c);
+        }
+        for c in typed.into_iter().rev() {
+            if !c.is_control() && self.level.name.chars().count() < MAX_NAME_LEN {
+                self.level.name.push(c);
+            }
+        }
+    }
+
+    fn paint(&mut self, mouse: Vec2) {
+        let left = is_mouse_button_down(MouseButton::Left);
+        let right = is_mouse_button_down(MouseButton::Right);
+        if !left && !right {
+            self.painting = None;
+            return;
+        }
+        let Some((x, y)) = self.view().cell_at(mouse, self.level.width, self.level.height) else {
+            return;
+        };
+        let (center_x, center_y) = (x as f32 + 0.5, y as f32 + 0.5);
+
+        if right {
+            self.remove_items(x, y);
+            self.level.set_wall(x, y, false);
+            return;
+        }
+
+        match self.tool {
+            Tool::Wall => {
+                let wall = *self.painting.get_or_insert(self.level.cell(x, y) != WALL);
+                // Only plain walls and floor are painted over
+                if matches!(self.level.cell(x, y), WALL | FLOOR) {
+                    self.level.set_wall(x, y, wall);
+                }
+            }
+            Tool::Start => {
+                self.open_cell(x, y);
+                self.level.start_x = center_x;
+                self.level.start_y = center_y;
+            }
+            Tool::Exit => {
+                self.open_cell(x, y);
+                self.level.exit_x = center_x;
+                self.level.exit_y = center_y;
+            }
+            // The rest act once per click, dragging doesn't repeat them
+            _ if !is_mouse_button_pressed(MouseButton::Left) => {}
+            Tool::Spawn => {
+                let count = self.level.spawns.len();
+                self.level.spawns.retain(|spawn| !in_cell(spawn.x, spawn.y, x, y));
+                if count == self.level.spawns.len() {
+                    self.open_cell(x, y);
+                    self.level.spawns.push(Point { x: center_x, y: center_y });
+                }
+            }
+            Tool::Enemy => {
+                match self.level.enemies.iter().position(|enemy| in_cell(enemy.x, enemy.y, x, y)) {
+                    // Clicking the selected enemy again cycles its type
+                    Some(index) if self.selected_enemy == Some(index) => {
+                        let enemy = &mut self.level.enemies[index];
+                        let next = EnemyKind::ALL.iter().position(|kind| *kind == enemy.kind).unwrap_or(0) + 1;
+                        enemy.kind = EnemyKind::ALL[next % EnemyKind::ALL.len()];
+                    }
+                    Some(index) => self.selected_enemy = Some(index),
+                    None => {
+                        self.open_cell(x, y);
+                        self.level.enemies.push(EnemySpawn {
+                            kind: EnemyKind::default(),
+                            x: center_x,
+                            y: center_y,
+                            patrol: Vec::new(),
+                        });
+                        self.selected_enemy = Some(self.level.enemies.len() - 1);
+                    }
+                }
+            }
+            Tool::Patrol => {
+                let Some(enemy) = self.selected_enemy.and_then(|index| self.level.enemies.get_mut(index)) else {
+                    self.set_status("Place or click an enemy first");
+                    return;
+                };
+                let count = enemy.patrol.len();
+                enemy.patrol.retain(|point| !in_cell(point.x, point.y, x, y));
+                if count == enemy.patrol.len() {
+                    enemy.patrol.push(Point { x: center_x, y: center_y });
+                    self.open_cell(x, y);
+                }
+            }
+            Tool::Pickup => match self.level.pickups.iter_mut().find(|pickup| in_cell(pickup.x, pickup.y, x, y)) {
+                // Clicking an item again cycles its kind
+                Some(pickup) => {
+                    let next = PickupKind::ALL.iter().position(|kind| *kind == pickup.kind).unwrap_or(0) + 1;
+                    pickup.kind = PickupKind::ALL[next % PickupKind::ALL.len()];
+                }
+                None => {
+                    self.open_cell(x, y);
+                    self.level.pickups.push(Pickup { kind: PickupKind::Ammo, x: center_x, y: center_y });
+                }
+            },
+            // One base per team, so a new one goes to a team that has none
+            Tool::Base => match self.level.bases.iter_mut().find(|base| in_cell(base.x, base.y, x, y)) {
+                Some(base) => base.team = base.team.other(),
+                None => match Team::ALL.into_iter().find(|team| self.level.bases.iter().all(|base| base.team != *team)) {
+                    Some(team) => {
+                        self.open_cell(x, y);
+                        self.level.bases.push(Base { team, x: center_x, y: center_y });
+                    }
+                    None => self.set_status("Both teams have a base, right click one to move it"),
+                },
+            },
+        }
+    }
+
+    /// Makes a plain wall walkable so something can be placed on it.
+    fn open_cell(&mut self, x: usize, y: usize) {
+        if self.level.cell(x, y) == WALL {
+            self.open_cell(x, y);
+        }
+    }
+
+    /// Removes everything placed in a cell: player spawns, enemies, patrol
+    /// points, pickups and flag bases.
+    fn remove_items(&mut self, x: usize, y: usize) {
+        let enemies = self.level.enemies.len();
+        self.level.spawns.retain(|spawn| !in_cell(spawn.x, spawn.y, x, y));
+        self.level.enemies.retain(|enemy| !in_cell(enemy.x, enemy.y, x, y));
+        for enemy in &mut self.level.enemies {
+            enemy.patrol.retain(|point| !in_cell(point.x, point.y, x, y));
+        }
+        self.level.pickups.retain(|pickup| !in_cell(pickup.x, pickup.y, x, y));
+        self.level.bases.retain(|base| !in_cell(base.x, base.y, x, y));
+        if enemies != self.level.enemies.len() {
+            self.selected_enemy = None;
+        }
+    }
+
+    fn save(&mut self) {
+        let message = match self.level.save(&self.path) {
+            Ok(()) if self.issues.is_empty() => format!("Saved to {}", self.path.display()),
+            Ok(()) => format!("Saved to {} with {} problem(s)", self.path.display(), self.issues.len()),
+            Err(e) => format!("Could not save: {}", e),
+        };
+        self.set_status(message);
+    }
+
+    pub fn draw(&self, theme: &ThemeConfig) {
+        clear_background(Color::from_rgba(10, 10, 20, 255));
+
+        let view = self.view();
+        let maze = self.level.to_maze();
+        let grid = view.cell_rect(0, 0);
+        draw_rectangle(
+            grid.x,
+            grid.y,
+            self.level.width as f32 * view.cell_size,
+            self.level.height as f32 * view.cell_size,
+            Color::from_rgba(0, 0, 30, 255),
+        );
+        view.draw_walls(&maze, Color::from_rgba(100, 150, 255, 255));
+        view.draw_exit(&maze, RED);
+
+        let radius = view.cell_size * 0.3;
+        let start = view.cell_point(self.level.start_x, self.level.start_y);
+        draw_circle(start.x, start.y, radius, GREEN);
+        for spawn in &self.level.spawns {
+            let point = view.cell_point(spawn.x, spawn.y);
+            draw_circle_lines(point.x, point.y, radius, 2.0, GREEN);
+        }
+        for (index, enemy) in self.level.enemies.iter().enumerate() {
+            let point = view.cell_point(enemy.x, enemy.y);
+            let selected = self.selected_enemy == Some(index);
+
+            // Patrol route, closed back to the spawn
+            let mut route: Vec<Vec2> = vec![point];
+            route.extend(enemy.patrol.iter().map(|waypoint| view.cell_point(waypoint.x, waypoint.y)));
+            let route_color = if selected { WHITE } else { Color::from_rgba(255, 0, 255, 120) };
+            if route.len() > 1 {
+                for (from, to) in route.iter().zip(route.iter().cycle().skip(1)) {
+                    draw_line(from.x, from.y, to.x, to.y, 1.5, route_color);
+                }
+            }
+            for waypoint in &route[1..] {
+                draw_circle(waypoint.x, waypoint.y, radius * 0.4, route_color);
+            }
+
+            let color = match enemy.kind {
+                EnemyKind::Grunt => MAGENTA,
+                EnemyKind::Scout => SKYBLUE,
+                EnemyKind::Brute => ORANGE,
+            };
+            draw_circle(point.x, point.y, radius, color);
+            if selected {
+                draw_circle_lines(point.x, point.y, radius + 2.0, 2.0, WHITE);
+            }
+        }
+        for pickup in &self.level.pickups {
+            let point = view.cell_point(pickup.x, pickup.y);
+            draw_rectangle(point.x - radius, point.y - radius, radius * 2.0, radius * 2.0, pickup_color(pickup.kind));
+        }
+        // Team colours as the level's theme shows them in the game
+        let level_theme = self.level.theme.map_or(LevelTheme::CandyMaze, LevelTheme::from);
+        for base in &self.level.bases {
+            let point = view.cell_point(base.x, base.y);
+            let color = level_theme.get_team_color(base.team);
+            draw_rectangle_lines(point.x - radius, point.y - radius, radius * 2.0, radius * 2.0, 2.0, color);
+            draw_triangle(vec2(point.x, point.y - radius), vec2(point.x + radius, point.y - radius * 0.5), vec2(point.x, point.y), color);
+        }
+
+        // Outline the cell under the cursor
+        let mouse = Vec2::from(mouse_position());
+        if let Some((x, y)) = view.cell_at(mouse, self.level.width, self.level.height) {
+            let rect = view.cell_rect(x, y);
+            draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 2.0, theme.hud_accent);
+        }
+
+        self.draw_panel(theme);
+    }
+
+    fn draw_panel(&self, theme: &ThemeConfig) {
+        let left = screen_width() - PANEL_WIDTH - MARGIN;
+        draw_text("LEVEL EDITOR", left, 40.0, 28.0, theme.hud_accent);
+
+        for (index, row) in ROWS.iter().enumerate() {
+            let rect = Self::row_rect(index);
+            let selected = matches!(row, Row::Tool(tool) if *tool == self.tool) || (*row == Row::Name && self.editing_name);
+            let background = if selected { Color::from_rgba(255, 255, 255, 60) } else { Color::from_rgba(0, 0, 0, 180) };
+            draw_rectangle(rect.x, rect.y, rect.w, rect.h, background);
+            draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, theme.hud_primary);
+
+            let label = match row {
+                Row::Name if self.editing_name => format!("NAME: {}_", self.level.name),
+                Row::Name => format!("NAME: {}", self.level.name),
+                Row::Difficulty => format!("<  DIFFICULTY: {}  >", self.level.difficulty),
+                Row::Theme => format!("<  THEME: {}  >", self.level.theme.map_or("Default", Theme::label)),
+                Row::Tool(tool) => {
+                    let key = TOOLS.iter().position(|t| t == tool).unwrap_or(0) + 1;
+                    format!("[{}] {}", key, tool.label())
+                }
+                Row::Preview => "PREVIEW (F5)".to_string(),
+                Row::Save => "SAVE (CTRL+S)".to_string(),
+                Row::Back => "BACK (ESC)".to_string(),
+            };
+            draw_text(&label, rect.x + 10.0, rect.y + rect.h / 2.0 + 6.0, 18.0, theme.text_primary);
+        }
+
+        let mut y = Self::row_rect(ROWS.len()).y + 12.0;
+        if let Some(enemy) = self.selected_enemy.and_then(|index| self.level.enemies.get(index)) {
+            let text = format!("ENEMY: {}, {} PATROL POINT(S)", enemy.kind.label().to_uppercase(), enemy.patrol.len());
+            draw_text(&text, left, y, 16.0, theme.text_secondary);
+            y += 20.0;
+        }
+        if let Some((message, since)) = &self.status {
+            if since.elapsed().as_secs_f32() < STATUS_SECONDS {
+                draw_text(message, left, y, 16.0, theme.hud_accent);
+                y += 20.0;
+            }
+        }
+        if self.issues.is_empty() {
+            draw_text("No problems found", left, y, 16.0, GREEN);
+        }
+        for issue in &self.issues {
+            if y > screen_height() - 10.0 {
+                break;
+            }
+            draw_text(&issue.to_string(), left, y, 16.0, ORANGE);
+            y += 18.0;
+        }
+    }
+}
+
+/// Whether a position in cells lies in cell `(x, y)`.
+fn in_cell(px: f32, py: f32, x: usize, y: usize) -> bool {
+    px as usize == x && py as usize == y
+}
@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use shared::address;
+
+use crate::config;
+
+pub const HOSTS_FILE: &str = "hosts.ron";
+
+/// Servers kept in the history.
+const MAX_HISTORY: usize = 8;
+
+/// Longest alias name, in characters.
+pub const MAX_ALIAS_LEN: usize = 24;
+
+/// One server joined, or tried, before.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HostEntry {
+    /// As it was typed, which may be an alias.
+    pub address: String,
+    /// Name the player used there.
+    pub name: String,
+    /// Seconds since the Unix epoch of the last attempt.
+    pub last_connected: u64,
+    /// Whether the server let the player in that time.
+    pub succeeded: bool,
+}
+
+/// Servers joined before and short names for them, persisted in
+/// [`HOSTS_FILE`]. Aliases work anywhere an address does, so `office` can
+/// stand for `10.0.0.5:34254`.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hosts {
+    /// Most recent first.
+    pub history: Vec<HostEntry>,
+    pub aliases: BTreeMap<String, String>,
+    /// Name used last time, filled in for next time.
+    pub last_name: String,
+}
+
+impl Hosts {
+    pub fn load() -> Self {
+        config::load(HOSTS_FILE)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        config::save(HOSTS_FILE, self)
+    }
+
+    /// What `address` stands for if it's an alias, else `address` itself.
+    pub fn expand<'a>(&'a self, address: &'a str) -> &'a str {
+        let address = address.trim();
+        self.aliases.get(address).map_or(address, String::as_str)
+    }
+
+    /// The alias for an expanded address, if it has one.
+    pub fn alias_of(&self, address: &str) -> Option<&str> {
+        self.aliases.iter().find(|(_, target)| *target == address).map(|(alias, _)| alias.as_str())
+    }
+
+    /// Records an attempt to join `address`, moving it to the top of the
+    /// history.
+    pub fn record(&mut self, address: &str, name: &str, succeeded: bool) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+        self.history.retain(|entry| entry.address != address);
+        self.history.insert(
+            0,
+            HostEntry { address: address.to_string(), name: name.to_string(), last_connected: now, succeeded },
+        );
+        self.history.truncate(MAX_HISTORY);
+        if succeeded {
+            self.last_name = name.to_string();
+        }
+    }
+
+    pub fn forget(&mut self, index: usize) {
+        if index < self.history.len() {
+            self.history.remove(index);
+        }
+    }
+
+    /// Adds or replaces an alias for a server address.
+    pub fn set_alias(&mut self, alias: &str, target: &str) -> Result<(), String> {
+        let alias = alias.trim();
+        let valid = alias.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if alias.is_empty() || !valid || alias.chars().count() > MAX_ALIAS_LEN {
+            return Err(format!(
+                "alias '{}' must be 1-{} letters, digits, '-' or '_'",
+                alias, MAX_ALIAS_LEN
+            ));
+        }
+        address::parse(target).map_err(|error| error.to_string())?;
+        if self.aliases.contains_key(target.trim()) {
+            return Err(format!("'{}' is itself an alias", target.trim()));
+        }
+        self.aliases.insert(alias.to_string(), target.trim().to_string());
+        Ok(())
+    }
+}
+
+/// How long ago a Unix time was, roughly, for the history list.
+pub fn ago(then: u64) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+    match now.saturating_sub(then) {
+        seconds if seconds < 60 => "just now".to_string(),
+        seconds if seconds < 3600 => format!("{}m ago", seconds / 60),
+        seconds if seconds < 86400 => format!("{}h ago", seconds / 3600),
+        seconds => format!("{}d ago", seconds / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hosts_survive_a_round_trip() {
+        let mut hosts = Hosts::default();
+        hosts.record("office", "alice", true);
+        hosts.set_alias("office", "10.0.0.5:34254").unwrap();
+        let text = ron::ser::to_string_pretty(&hosts, ron::ser::PrettyConfig::default()).unwrap();
+        let loaded: Hosts = ron::from_str(&text).unwrap();
+        assert_eq!(loaded.history, hosts.history);
+        assert_eq!(loaded.aliases, hosts.aliases);
+        assert_eq!(loaded.last_name, "alice");
+    }
+
+    #[test]
+    fn aliases_expand_to_their_address() {
+        let mut hosts = Hosts::default();
+        hosts.set_alias(" office ", "10.0.0.5:34254").unwrap();
+        assert_eq!(hosts.expand("office"), "10.0.0.5:34254");
+        assert_eq!(hosts.expand(" office "), "10.0.0.5:34254");
+        assert_eq!(hosts.expand("example.com"), "example.com");
+        assert_eq!(hosts.alias_of("10.0.0.5:34254"), Some("office"));
+
+        assert!(hosts.set_alias("", "10.0.0.5").is_err());
+        assert!(hosts.set_alias("my office", "10.0.0.5").is_err());
+        assert!(hosts.set_alias(&"a".repeat(MAX_ALIAS_LEN + 1), "10.0.0.5").is_err());
+        assert!(hosts.set_alias("home", "office").is_err());
+    }
+
+    #[test]
+    fn history_keeps_the_latest_servers_once_each() {
+        let mut hosts = Hosts::default();
+        for i in 0..MAX_HISTORY + 3 {
+            hosts.record(&format!("10.0.0.{}", i), "alice", false);
+        }
+        assert_eq!(hosts.history.len(), MAX_HISTORY);
+        assert_eq!(hosts.history[0].address, format!("10.0.0.{}", MAX_HISTORY + 2));
+        assert_eq!(hosts.last_name, "");
+
+        hosts.record("10.0.0.5", "bob", true);
+        assert_eq!(hosts.history.len(), MAX_HISTORY);
+        assert_eq!(hosts.history[0].address, "10.0.0.5");
+        assert!(hosts.history[0].succeeded);
+        assert_eq!(hosts.history.iter().filter(|entry| entry.address == "10.0.0.5").count(), 1);
+        assert_eq!(hosts.last_name, "bob");
+    }
+}
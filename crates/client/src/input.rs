@@ -0,0 +1,498 @@
+use std::collections::BTreeMap;
+use std::io;
+
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+pub const CONTROLS_FILE: &str = "controls.ron";
+
+/// Stick travel ignored around the centre.
+const AXIS_DEADZONE: f32 = 0.2;
+/// Stick travel needed to capture an axis on the rebinding screen.
+const AXIS_CAPTURE_THRESHOLD: f32 = 0.7;
+
+/// Everything the player can do, independent of which key or button does it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    StrafeLeft,
+    StrafeRight,
+    TurnLeft,
+    TurnRight,
+    LookUp,
+    LookDown,
+    Fire,
+    Reload,
+    NextWeapon,
+    /// Picks the weapon at this index of `WeaponKind::ALL`, or the option
+    /// of the intermission ballot while there is one.
+    SelectWeapon(usize),
+    Use,
+    ToggleMap,
+    Scoreboard,
+    CycleTheme,
+    Chat,
+    TeamChat,
+    Pause,
+}
+
+impl Action {
+    pub const ALL: [Action; 21] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::StrafeLeft,
+        Action::StrafeRight,
+        Action::TurnLeft,
+        Action::TurnRight,
+        Action::LookUp,
+        Action::LookDown,
+        Action::Fire,
+        Action::Reload,
+        Action::NextWeapon,
+        Action::SelectWeapon(0),
+        Action::SelectWeapon(1),
+        Action::SelectWeapon(2),
+        Action::Use,
+        Action::ToggleMap,
+        Action::Scoreboard,
+        Action::CycleTheme,
+        Action::Chat,
+        Action::TeamChat,
+        Action::Pause,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::MoveForward => "Move forward",
+            Action::MoveBackward => "Move backward",
+            Action::StrafeLeft => "Strafe left",
+            Action::StrafeRight => "Strafe right",
+            Action::TurnLeft => "Turn left",
+            Action::TurnRight => "Turn right",
+            Action::LookUp => "Look up",
+            Action::LookDown => "Look down",
+            Action::Fire => "Fire",
+            Action::Reload => "Reload",
+            Action::NextWeapon => "Next weapon",
+            Action::SelectWeapon(0) => "Weapon 1",
+            Action::SelectWeapon(1) => "Weapon 2",
+            Action::SelectWeapon(2) => "Weapon 3",
+            Action::SelectWeapon(_) => "Weapon",
+            Action::Use => "Use",
+            Action::ToggleMap => "Toggle map",
+            Action::Scoreboard => "Scoreboard",
+            Action::CycleTheme => "Cycle theme",
+            Action::Chat => "Chat",
+            Action::TeamChat => "Team chat",
+            Action::Pause => "Pause",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MouseBinding {
+    Left,
+    Right,
+    Middle,
+}
+
+impl MouseBinding {
+    fn button(self) -> MouseButton {
+        match self {
+            MouseBinding::Left => MouseButton::Left,
+            MouseBinding::Right => MouseButton::Right,
+            MouseBinding::Middle => MouseButton::Middle,
+        }
+    }
+}
+
+/// Gamepad buttons, named after their position on an Xbox style layout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AxisDirection {
+    Positive,
+    Negative,
+}
+
+/// A physical input that can trigger an [`Action`].
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(#[serde(with = "key_name")] KeyCode),
+    Mouse(MouseBinding),
+    Pad(PadButton),
+    Axis(PadAxis, AxisDirection),
+}
+
+impl Binding {
+    fn is_pad(&self) -> bool {
+        matches!(self, Binding::Pad(_) | Binding::Axis(..))
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Binding::Key(key) => {
+                let name = format!("{:?}", key);
+                // Key1 reads better as plain 1
+                match name.strip_prefix("Key") {
+                    Some(digit) if digit.len() == 1 => digit.to_string(),
+                    _ => name,
+                }
+            }
+            Binding::Mouse(button) => format!("Mouse {:?}", button),
+            Binding::Pad(button) => format!("Pad {:?}", button),
+            Binding::Axis(axis, AxisDirection::Positive) => format!("Pad {:?}+", axis),
+            Binding::Axis(axis, AxisDirection::Negative) => format!("Pad {:?}-", axis),
+        }
+    }
+}
+
+/// Bindings for every action, persisted in [`CONTROLS_FILE`].
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ActionMap(BTreeMap<Action, Vec<Binding>>);
+
+impl Default for ActionMap {
+    fn default() -> Self {
+        use AxisDirection::{Negative, Positive};
+        use Binding::{Axis, Key, Mouse, Pad};
+
+        let mut map = BTreeMap::new();
+        map.insert(Action::MoveForward, vec![Key(KeyCode::W), Key(KeyCode::Up), Axis(PadAxis::LeftStickY, Positive)]);
+        map.insert(Action::MoveBackward, vec![Key(KeyCode::S), Key(KeyCode::Down), Axis(PadAxis::LeftStickY, Negative)]);
+        map.insert(Action::StrafeLeft, vec![Key(KeyCode::A), Axis(PadAxis::LeftStickX, Negative)]);
+        map.insert(Action::StrafeRight, vec![Key(KeyCode::D), Axis(PadAxis::LeftStickX, Positive)]);
+        map.insert(Action::TurnLeft, vec![Key(KeyCode::Left), Axis(PadAxis::RightStickX, Negative)]);
+        map.insert(Action::TurnRight, vec![Key(KeyCode::Right), Axis(PadAxis::RightStickX, Positive)]);
+        map.insert(Action::LookUp, vec![Axis(PadAxis::RightStickY, Positive)]);
+        map.insert(Action::LookDown, vec![Axis(PadAxis::RightStickY, Negative)]);
+        map.insert(Action::Fire, vec![Key(KeyCode::Space), Mouse(MouseBinding::Left), Pad(PadButton::RightTrigger)]);
+        map.insert(Action::Reload, vec![Key(KeyCode::R), Pad(PadButton::East)]);
+        map.insert(Action::NextWeapon, vec![Key(KeyCode::Q), Pad(PadButton::RightBumper)]);
+        map.insert(Action::SelectWeapon(0), vec![Key(KeyCode::Key1)]);
+        map.insert(Action::SelectWeapon(1), vec![Key(KeyCode::Key2)]);
+        map.insert(Action::SelectWeapon(2), vec![Key(KeyCode::Key3)]);
+        map.insert(Action::Use, vec![Key(KeyCode::E), Pad(PadButton::West)]);
+        map.insert(Action::ToggleMap, vec![Key(KeyCode::M), Pad(PadButton::Select)]);
+        map.insert(Action::Scoreboard, vec![Key(KeyCode::Tab)]);
+        map.insert(Action::CycleTheme, vec![Key(KeyCode::T), Pad(PadButton::North)]);
+        map.insert(Action::Chat, vec![Key(KeyCode::Y)]);
+        map.insert(Action::TeamChat, vec![Key(KeyCode::U)]);
+        map.insert(Action::Pause, vec![Key(KeyCode::Escape), Pad(PadButton::Start)]);
+        Self(map)
+    }
+}
+
+impl ActionMap {
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.0.get(&action).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Replaces the bindings of `action` on the same device (keyboard and
+    /// mouse, or gamepad) as `binding`, leaving the other device untouched.
+    pub fn rebind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.0.entry(action).or_default();
+        bindings.retain(|b| b.is_pad() != binding.is_pad());
+        bindings.push(binding);
+    }
+
+    pub fn clear(&mut self, action: Action) {
+        self.0.insert(action, Vec::new());
+    }
+
+    /// Label of the first keyboard or mouse binding of `action`, for the
+    /// hints on the HUD; `-` when it has none.
+    pub fn hint(&self, action: Action) -> String {
+        self.bindings(action).iter().find(|b| !b.is_pad()).map_or_else(|| "-".to_string(), Binding::label)
+    }
+}
+
+/// Action map plus the devices it reads from.
+pub struct Input {
+    pub map: ActionMap,
+    gamepad: gamepad::Gamepad,
+}
+
+impl Input {
+    /// Loads bindings from the config file. Actions missing from the file
+    /// keep their default bindings.
+    pub fn load() -> Self {
+        let mut map = ActionMap::default();
+        let loaded: ActionMap = config::load(CONTROLS_FILE);
+        map.0.extend(loaded.0);
+        Self {
+            map,
+            gamepad: gamepad::Gamepad::new(),
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        config::save(CONTROLS_FILE, &self.map)
+    }
+
+    /// Reads gamepad events, must be called once per frame.
+    pub fn poll(&mut self) {
+        self.gamepad.poll();
+    }
+
+    /// How strongly `action` is held, from 0.0 to 1.0. Keys and buttons are
+    /// either fully on or off, sticks are analog.
+    pub fn strength(&self, action: Action) -> f32 {
+        self.map
+            .bindings(action)
+            .iter()
+            .map(|binding| match binding {
+                Binding::Key(key) => is_key_down(*key) as u8 as f32,
+                Binding::Mouse(button) => is_mouse_button_down(button.button()) as u8 as f32,
+                Binding::Pad(button) => self.gamepad.is_down(*button) as u8 as f32,
+                Binding::Axis(axis, direction) => {
+                    let value = match direction {
+                        AxisDirection::Positive => self.gamepad.axis(*axis),
+                        AxisDirection::Negative => -self.gamepad.axis(*axis),
+                    };
+                    ((value - AXIS_DEADZONE) / (1.0 - AXIS_DEADZONE)).clamp(0.0, 1.0)
+                }
+            })
+            .fold(0.0, f32::max)
+    }
+
+    /// Whether `action` was triggered this frame. Stick bindings only count
+    /// as held, never as pressed.
+    pub fn is_pressed(&self, action: Action) -> bool {
+        self.map.bindings(action).iter().any(|binding| match binding {
+            Binding::Key(key) => is_key_pressed(*key),
+            Binding::Mouse(button) => is_mouse_button_pressed(button.button()),
+            Binding::Pad(button) => self.gamepad.is_pressed(*button),
+            Binding::Axis(..) => false,
+        })
+    }
+
+    /// Any key, mouse button, gamepad button or stick pushed this frame, used
+    /// to capture a new binding.
+    pub fn captured_binding(&self) -> Option<Binding> {
+        if let Some(key) = get_keys_pressed().into_iter().next() {
+            return Some(Binding::Key(key));
+        }
+        for button in [MouseBinding::Left, MouseBinding::Right, MouseBinding::Middle] {
+            if is_mouse_button_pressed(button.button()) {
+                return Some(Binding::Mouse(button));
+            }
+        }
+        if let Some(button) = self.gamepad.pressed_buttons().first() {
+            return Some(Binding::Pad(*button));
+        }
+        for axis in [PadAxis::LeftStickX, PadAxis::LeftStickY, PadAxis::RightStickX, PadAxis::RightStickY] {
+            let value = self.gamepad.axis(axis);
+            if value > AXIS_CAPTURE_THRESHOLD {
+                return Some(Binding::Axis(axis, AxisDirection::Positive));
+            }
+            if value < -AXIS_CAPTURE_THRESHOLD {
+                return Some(Binding::Axis(axis, AxisDirection::Negative));
+            }
+        }
+        None
+    }
+}
+
+/// Serializes key codes by name so the config file stays readable.
+mod key_name {
+    use macroquad::prelude::KeyCode;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    const KEYS: &[KeyCode] = &[
+        KeyCode::Space, KeyCode::Apostrophe, KeyCode::Comma, KeyCode::Minus, KeyCode::Period,
+        KeyCode::Slash, KeyCode::Key0, KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4,
+        KeyCode::Key5, KeyCode::Key6, KeyCode::Key7, KeyCode::Key8, KeyCode::Key9,
+        KeyCode::Semicolon, KeyCode::Equal, KeyCode::A, KeyCode::B, KeyCode::C, KeyCode::D,
+        KeyCode::E, KeyCode::F, KeyCode::G, KeyCode::H, KeyCode::I, KeyCode::J, KeyCode::K,
+        KeyCode::L, KeyCode::M, KeyCode::N, KeyCode::O, KeyCode::P, KeyCode::Q, KeyCode::R,
+        KeyCode::S, KeyCode::T, KeyCode::U, KeyCode::V, KeyCode::W, KeyCode::X, KeyCode::Y,
+        KeyCode::Z, KeyCode::LeftBracket, KeyCode::Backslash, KeyCode::RightBracket,
+        KeyCode::GraveAccent, KeyCode::Escape, KeyCode::Enter, KeyCode::Tab, KeyCode::Backspace,
+        KeyCode::Insert, KeyCode::Delete, KeyCode::Right, KeyCode::Left, KeyCode::Down,
+        KeyCode::Up, KeyCode::PageUp, KeyCode::PageDown, KeyCode::Home, KeyCode::End,
+        KeyCode::CapsLock, KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5,
+        KeyCode::F6, KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11,
+        KeyCode::F12, KeyCode::Kp0, KeyCode::Kp1, KeyCode::Kp2, KeyCode::Kp3, KeyCode::Kp4,
+        KeyCode::Kp5, KeyCode::Kp6, KeyCode::Kp7, KeyCode::Kp8, KeyCode::Kp9,
+        KeyCode::KpDecimal, KeyCode::KpDivide, KeyCode::KpMultiply, KeyCode::KpSubtract,
+        KeyCode::KpAdd, KeyCode::KpEnter, KeyCode::LeftShift, KeyCode::LeftControl,
+        KeyCode::LeftAlt, KeyCode::RightShift, KeyCode::RightControl, KeyCode::RightAlt,
+    ];
+
+    pub fn serialize<S: Serializer>(key: &KeyCode, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:?}", key))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<KeyCode, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        KEYS.iter()
+            .copied()
+            .find(|key| format!("{:?}", key) == name)
+            .ok_or_else(|| de::Error::custom(format!("unknown key `{}`", name)))
+    }
+}
+
+#[cfg(feature = "gamepad")]
+mod gamepad {
+    use super::{PadAxis, PadButton};
+    use gilrs::{Axis, Button, EventType, Gilrs};
+
+    pub struct Gamepad {
+        gilrs: Option<Gilrs>,
+        pressed: Vec<PadButton>,
+    }
+
+    impl Gamepad {
+        pub fn new() -> Self {
+            let gilrs = Gilrs::new()
+                .map_err(|e| eprintln!("Warning: gamepad support unavailable: {}", e))
+                .ok();
+            Self { gilrs, pressed: Vec::new() }
+        }
+
+        pub fn poll(&mut self) {
+            self.pressed.clear();
+            let Some(gilrs) = &mut self.gilrs else { return };
+            while let Some(event) = gilrs.next_event() {
+                if let EventType::ButtonPressed(button, _) = event.event {
+                    if let Some(button) = from_gilrs(button) {
+                        self.pressed.push(button);
+                    }
+                }
+            }
+        }
+
+        pub fn is_down(&self, button: PadButton) -> bool {
+            let Some(gilrs) = &self.gilrs else { return false };
+            gilrs.gamepads().any(|(_, pad)| pad.is_pressed(to_gilrs(button)))
+        }
+
+        pub fn is_pressed(&self, button: PadButton) -> bool {
+            self.pressed.contains(&button)
+        }
+
+        pub fn pressed_buttons(&self) -> &[PadButton] {
+            &self.pressed
+        }
+
+        /// Value of `axis` on whichever connected gamepad pushes it furthest.
+        pub fn axis(&self, axis: PadAxis) -> f32 {
+            let Some(gilrs) = &self.gilrs else { return 0.0 };
+            let axis = match axis {
+                PadAxis::LeftStickX => Axis::LeftStickX,
+                PadAxis::LeftStickY => Axis::LeftStickY,
+                PadAxis::RightStickX => Axis::RightStickX,
+                PadAxis::RightStickY => Axis::RightStickY,
+            };
+            gilrs
+                .gamepads()
+                .map(|(_, pad)| pad.value(axis))
+                .fold(0.0, |a: f32, b: f32| if b.abs() > a.abs() { b } else { a })
+        }
+    }
+
+    fn to_gilrs(button: PadButton) -> Button {
+        match button {
+            PadButton::South => Button::South,
+            PadButton::East => Button::East,
+            PadButton::North => Button::North,
+            PadButton::West => Button::West,
+            PadButton::LeftBumper => Button::LeftTrigger,
+            PadButton::RightBumper => Button::RightTrigger,
+            PadButton::LeftTrigger => Button::LeftTrigger2,
+            PadButton::RightTrigger => Button::RightTrigger2,
+            PadButton::Select => Button::Select,
+            PadButton::Start => Button::Start,
+            PadButton::LeftThumb => Button::LeftThumb,
+            PadButton::RightThumb => Button::RightThumb,
+            PadButton::DPadUp => Button::DPadUp,
+            PadButton::DPadDown => Button::DPadDown,
+            PadButton::DPadLeft => Button::DPadLeft,
+            PadButton::DPadRight => Button::DPadRight,
+        }
+    }
+
+    fn from_gilrs(button: Button) -> Option<PadButton> {
+        Some(match button {
+            Button::South => PadButton::South,
+            Button::East => PadButton::East,
+            Button::North => PadButton::North,
+            Button::West => PadButton::West,
+            Button::LeftTrigger => PadButton::LeftBumper,
+            Button::RightTrigger => PadButton::RightBumper,
+            Button::LeftTrigger2 => PadButton::LeftTrigger,
+            Button::RightTrigger2 => PadButton::RightTrigger,
+            Button::Select => PadButton::Select,
+            Button::Start => PadButton::Start,
+            Button::LeftThumb => PadButton::LeftThumb,
+            Button::RightThumb => PadButton::RightThumb,
+            Button::DPadUp => PadButton::DPadUp,
+            Button::DPadDown => PadButton::DPadDown,
+            Button::DPadLeft => PadButton::DPadLeft,
+            Button::DPadRight => PadButton::DPadRight,
+            _ => return None,
+        })
+    }
+}
+
+/// Without the `gamepad` feature no pad is ever connected.
+#[cfg(not(feature = "gamepad"))]
+mod gamepad {
+    use super::{PadAxis, PadButton};
+
+    pub struct Gamepad;
+
+    impl Gamepad {
+        pub fn new() -> Self {
+            Self
+        }
+
+        pub fn poll(&mut self) {}
+
+        pub fn is_down(&self, _button: PadButton) -> bool {
+            false
+        }
+
+        pub fn is_pressed(&self, _button: PadButton) -> bool {
+            false
+        }
+
+        pub fn pressed_buttons(&self) -> &[PadButton] {
+            &[]
+        }
+
+        pub fn axis(&self, _axis: PadAxis) -> f32 {
+            0.0
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use std::io;
+use std::path::PathBuf;
+
+use shared::level::Level;
+
+use crate::config;
+
+/// Where a level downloaded from a server is kept, named by its hash so the
+/// same level is only downloaded once.
+fn path(hash: u64) -> PathBuf {
+    config::config_dir().join("levels").join(format!("{:016x}.ron", hash))
+}
+
+/// A level downloaded earlier, if the cached file still has the right hash.
+pub fn load(hash: u64) -> Option<Level> {
+    Level::load(path(hash)).ok().filter(|level| level.content_hash() == hash)
+}
+
+pub fn store(level: &Level) -> io::Result<()> {
+    level.save(path(level.content_hash()))
+}
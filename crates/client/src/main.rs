@@ -1,28 +1,74 @@
 use macroquad::prelude::*;
-use std::net::UdpSocket;
-use std::time::Instant;
-use std::io::{self, Write};
+use std::collections::{HashSet, VecDeque};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use std::f32::consts::PI;
+use std::path::PathBuf;
 
+mod chat;
+mod cli;
+mod config;
+mod editor;
+mod hosts;
+mod input;
+mod level_cache;
+mod menu;
+mod minimap;
+mod net;
+mod pause;
+mod rebind;
+mod settings;
 mod themes;
-use themes::{LevelTheme, ThemeConfig};
+use chat::{ChatAction, ChatEntry};
+use cli::Options;
+use editor::{Editor, EditorAction};
+use hosts::Hosts;
+use input::{Action, Input};
+use menu::{MainMenu, MenuAction};
+use minimap::{pickup_color, MapView};
+use net::Connection;
+use pause::{PauseAction, PauseMenu};
+use settings::Settings;
+use shared::flag::FlagState;
+use shared::level::{EnemyKind, EnemySpawn, Level, LevelSource, PickupKind};
+use shared::maze::{Direction, Maze};
+use shared::mechanism::Mechanisms;
+use shared::mode::{GameMode, Life, LifeState};
+use shared::pickup::{Boosts, Pickups, AMMO_PACK, HEALTH_PACK, ROCKET_PACK};
+use shared::movement::{self, FixedTimestep, PlayerInput, PlayerState, MOVE_SPEED, STEP_SECONDS, STRAFE_SPEED};
+use shared::protocol::{ClientMessage, PlayerStatus};
+use shared::score::{ScoreEvent, Tally};
+use shared::team::Team;
+use shared::trap::{Exposure, TrapEffect, TrapKind};
+use shared::weapon::{AmmoKind, Arsenal, Projectile, WeaponKind};
+use shared::CELL_SIZE;
+use themes::LevelTheme;
 
-const MAZE_WIDTH: usize = 16;
-const MAZE_HEIGHT: usize = 16;
-const CELL_SIZE: f32 = 64.0;
 const FOV: f32 = PI / 3.0; // 60 degrees field of view
 const RENDER_DISTANCE: f32 = 1000.0;
+const MAX_HEALTH: i32 = 100;
+/// Traps closer than this show up on the minimap from then on.
+const TRAP_DISCOVERY_RANGE: f32 = CELL_SIZE * 2.0;
+/// How close a rocket has to pass an enemy or player to go off.
+const ROCKET_PROXIMITY: f32 = CELL_SIZE * 0.3;
+const EXPLOSION_SECONDS: f32 = 0.4;
+/// How long the server's message of the day stays up after joining.
+const MOTD_SECONDS: f32 = 8.0;
+/// How long a line stays in the kill feed, and how many are shown at once.
+const FEED_SECONDS: f32 = 6.0;
+const FEED_SHOWN: usize = 5;
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 struct Enemy {
+    kind: EnemyKind,
     x: f32,
     y: f32,
     angle: f32,
     health: i32,
-    patrol_target_x: f32,
-    patrol_target_y: f32,
     last_seen_player: Instant,
     state: EnemyState,
+    // Patrol route in world units, starting with the spawn point
+    route: Vec<(f32, f32)>,
+    next_waypoint: usize,
 }
 
 #[derive(Clone, Copy)]
@@ -33,38 +79,82 @@ enum EnemyState {
 }
 
 impl Enemy {
-    fn new(x: f32, y: f32, angle: f32) -> Self {
+    fn new(spawn: &EnemySpawn) -> Self {
+        let to_world = |x: f32, y: f32| (x * CELL_SIZE, y * CELL_SIZE);
+        let (x, y) = to_world(spawn.x, spawn.y);
+        let mut route = vec![(x, y)];
+        route.extend(spawn.patrol.iter().map(|point| to_world(point.x, point.y)));
         Self {
+            kind: spawn.kind,
             x,
             y,
-            angle,
-            health: 50,
-            patrol_target_x: x,
-            patrol_target_y: y,
+            angle: 0.0,
+            health: spawn.kind.health(),
             last_seen_player: Instant::now(),
             state: EnemyState::Patrolling,
+            route,
+            next_waypoint: 1,
         }
     }
 }
 
 struct GameState {
+    // Rendered position, interpolated between the last two simulation steps
     player_x: f32,
     player_y: f32,
     player_angle: f32,
-    maze: [[bool; MAZE_WIDTH]; MAZE_HEIGHT],
+    player_pitch: f32,
+    player_radius: f32,
+    sim: PlayerState,
+    prev_sim: PlayerState,
+    timestep: FixedTimestep,
+    input_seq: u32,
+    // Inputs sent to the server but not yet acknowledged, with the speed
+    // factor they were predicted with, replayed on top of every
+    // authoritative snapshot
+    pending_inputs: VecDeque<(u32, PlayerInput, f32)>,
+    maze: Maze,
+    /// The level being played, source of the maze, spawns and enemies.
+    current_level: Level,
+    /// Doors, switches and push walls. Run locally offline, mirrored from
+    /// the server online.
+    mechanisms: Mechanisms,
+    /// Offline, how the player is doing with the traps. Online the server
+    /// applies them.
+    exposure: Exposure,
+    /// Trap cells the player has come close to, the only ones on the minimap.
+    discovered_traps: HashSet<(usize, usize)>,
+    /// Items in the level. Run locally offline, mirrored from the server online.
+    pickups: Pickups,
+    boosts: Boosts,
+    /// Seed for generated levels. Picked locally offline, sent by the server online.
+    seed: u64,
     level: usize,
-    score: i32,
-    exit_x: f32,
-    exit_y: f32,
+    /// Kills, deaths and score. Kept locally offline, the server's online.
+    tally: Tally,
+    /// Recent kills and flag events as text, whether the local player was
+    /// part of it and when, newest last.
+    kill_feed: VecDeque<(String, bool, Instant)>,
     server_addr: String,
     username: String,
-    mouse_sensitivity: f32,
-    last_mouse_x: f32,
-    frame_start: Instant,
+    settings: Settings,
+    input: Input,
+    show_minimap: bool,
+    paused: bool,
+    pause_menu: PauseMenu,
+    /// Open while the player types a chat message.
+    chat_entry: Option<ChatEntry>,
+    editor: Option<Editor>,
+    // Playing the level open in the editor rather than the real one
+    previewing: bool,
+    quit_requested: bool,
     frame_times: Vec<f32>,
     health: i32,
-    ammo: i32,
-    game_won: bool,
+    life: Life,
+    arsenal: Arsenal,
+    // Rockets in flight, and explosions as position and seconds left
+    projectiles: Vec<Projectile>,
+    explosions: Vec<(f32, f32, f32)>,
     last_frame_time: Instant,
     fps_counter: f32,
     crosshair_pulse: f32,
@@ -72,56 +162,69 @@ struct GameState {
     enemies: Vec<Enemy>,
     last_enemy_attack: Instant,
     current_theme: LevelTheme,
+    connection: Option<Connection>,
 }
 
 impl GameState {
-    fn new(username: String, server_addr: String) -> Self {
-        let mut maze = [[false; MAZE_WIDTH]; MAZE_HEIGHT];
-        
-        // Create a simple maze (Level 1)
-        // Outer walls
-        for i in 0..MAZE_WIDTH {
-            maze[0][i] = true;
-            maze[MAZE_HEIGHT - 1][i] = true;
-        }
-        for i in 0..MAZE_HEIGHT {
-            maze[i][0] = true;
-            maze[i][MAZE_WIDTH - 1] = true;
-        }
-        
-        // Internal walls to create corridors
-        maze[2][2] = true; maze[2][3] = true; maze[2][4] = true;
-        maze[4][6] = true; maze[5][6] = true; maze[6][6] = true;
-        maze[8][2] = true; maze[8][3] = true; maze[8][4] = true; maze[8][5] = true;
-        maze[10][8] = true; maze[11][8] = true; maze[12][8] = true;
-        maze[6][10] = true; maze[7][10] = true; maze[8][10] = true;
-        maze[4][12] = true; maze[5][12] = true; maze[6][12] = true;
+    fn new(username: String, server_addr: String, connection: Option<Connection>) -> Self {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or_default();
+        let current_level = Level::numbered(1, seed);
+        let maze = current_level.to_maze();
+        let (spawn_x, spawn_y) = maze.start_position();
+        let spawn = PlayerState::new(spawn_x, spawn_y);
+        let enemies = current_level.enemies.iter().map(Enemy::new).collect();
+        let current_theme = current_level.theme.map_or(LevelTheme::CandyMaze, Into::into);
+        let mechanisms = Mechanisms::new(&current_level);
+        let pickups = Pickups::new(&current_level, &maze, current_level.content_hash());
         
         Self {
-            player_x: 3.5 * CELL_SIZE,
-            player_y: 3.5 * CELL_SIZE,
+            player_x: spawn.x,
+            player_y: spawn.y,
             player_angle: 0.0,
+            player_pitch: 0.0,
+            player_radius: current_level.player_radius(),
+            sim: spawn,
+            prev_sim: spawn,
+            timestep: FixedTimestep::default(),
+            input_seq: 0,
+            pending_inputs: VecDeque::new(),
             maze,
-            exit_x: 13.5 * CELL_SIZE,
-            exit_y: 13.5 * CELL_SIZE,
+            current_level,
+            mechanisms,
+            exposure: Exposure::default(),
+            discovered_traps: HashSet::new(),
+            pickups,
+            boosts: Boosts::default(),
+            seed,
             server_addr,
             username,
-            mouse_sensitivity: 0.003,
-            last_mouse_x: 0.0,
-            frame_start: Instant::now(),
+            settings: Settings::load(),
+            input: Input::load(),
+            show_minimap: true,
+            paused: false,
+            // Editing is local, the server wouldn't know about the changed walls
+            pause_menu: PauseMenu::new(connection.is_none()),
+            chat_entry: None,
+            editor: None,
+            previewing: false,
+            quit_requested: false,
             frame_times: Vec::with_capacity(60),
-            health: 100,
-            ammo: 30,
+            health: MAX_HEALTH,
+            life: Life::new(GameMode::default()),
+            arsenal: Arsenal::default(),
+            projectiles: Vec::new(),
+            explosions: Vec::new(),
             level: 1,
-            score: 0,
-            game_won: false,
+            tally: Tally::default(),
+            kill_feed: VecDeque::new(),
             last_frame_time: Instant::now(),
             fps_counter: 60.0,
             crosshair_pulse: 0.0,
             wall_hit_flash: 0.0,
-            enemies: Vec::new(),
+            enemies,
             last_enemy_attack: Instant::now(),
-            current_theme: LevelTheme::CandyMaze,
+            current_theme,
+            connection,
         }
     }
 
@@ -145,41 +248,109 @@ impl GameState {
         self.crosshair_pulse += delta * 3.0;
         self.wall_hit_flash = (self.wall_hit_flash - delta * 2.0).max(0.0);
 
-        // Mouse look (professional FPS controls)
-        let (mouse_x, _) = mouse_position();
-        if self.last_mouse_x != 0.0 {
-            let mouse_delta = mouse_x - self.last_mouse_x;
-            self.player_angle += mouse_delta * self.mouse_sensitivity;
-        }
-        self.last_mouse_x = mouse_x;
+        self.input.poll();
 
-        let move_speed = 200.0 * delta; // Increased for better feel
-        let strafe_speed = 180.0 * delta;
+        if let Some(editor) = &mut self.editor {
+            if !self.previewing {
+                match editor.update() {
+                    Some(EditorAction::Preview) => self.start_preview(),
+                    Some(EditorAction::Close) => self.close_editor(),
+                    None => {}
+                }
+                return;
+            }
+            if is_key_pressed(KeyCode::Escape) || self.input.is_pressed(Action::Pause) {
+                self.stop_preview();
+                return;
+            }
+        }
 
-        // Professional FPS movement (WASD + mouse)
-        let mut new_x = self.player_x;
-        let mut new_y = self.player_y;
+        // Escape releases the cursor into the pause menu. It always works,
+        // even if the Pause action was rebound, so players can't lock themselves in.
+        if self.paused {
+            match self.pause_menu.update(&mut self.settings, &mut self.input) {
+                Some(PauseAction::Resume) => {
+                    self.set_paused(false);
+                    if let Err(e) = self.settings.save() {
+                        eprintln!("Warning: could not save settings: {}", e);
+                    }
+                }
+                Some(PauseAction::OpenEditor) => self.open_editor(),
+                Some(PauseAction::Quit) => self.quit_requested = true,
+                None => {}
+            }
+            self.sync_with_server();
+            return;
+        }
 
-        // Handle input
-        if is_key_down(KeyCode::W) {
-            new_x += self.player_angle.cos() * move_speed;
-            new_y += self.player_angle.sin() * move_speed;
+        // Typing takes the keyboard, so the player stands still meanwhile.
+        // Escape closes the chat line rather than opening the pause menu.
+        if let Some(entry) = &mut self.chat_entry {
+            match entry.update() {
+                Some(ChatAction::Send { text, team }) => {
+                    if let Some(connection) = &mut self.connection {
+                        connection.say(&text, team);
+                    }
+                    self.chat_entry = None;
+                }
+                Some(ChatAction::Cancel) => self.chat_entry = None,
+                None => {}
+            }
+            self.sync_with_server();
+            return;
         }
-        if is_key_down(KeyCode::S) {
-            new_x -= self.player_angle.cos() * move_speed;
-            new_y -= self.player_angle.sin() * move_speed;
+        // Chat is for talking to other players, so only online
+        if let Some(connection) = &self.connection {
+            if self.input.is_pressed(Action::Chat) {
+                self.chat_entry = Some(ChatEntry::new(false));
+            } else if self.input.is_pressed(Action::TeamChat) && connection.mode.has_teams() {
+                self.chat_entry = Some(ChatEntry::new(true));
+            }
+            if self.chat_entry.is_some() {
+                self.sync_with_server();
+                return;
+            }
         }
-        if is_key_down(KeyCode::A) {
-            new_x += (self.player_angle - PI/2.0).cos() * strafe_speed;
-            new_y += (self.player_angle - PI/2.0).sin() * strafe_speed;
+
+        if is_key_pressed(KeyCode::Escape) || self.input.is_pressed(Action::Pause) {
+            self.set_paused(true);
+            self.sync_with_server();
+            return;
         }
-        if is_key_down(KeyCode::D) {
-            new_x += (self.player_angle + PI/2.0).cos() * strafe_speed;
-            new_y += (self.player_angle + PI/2.0).sin() * strafe_speed;
+
+        // The death screen counts down to the respawn. Offline the client
+        // respawns itself, online it waits for the server to say so.
+        if !self.life.is_alive() {
+            if self.life.update(delta) && self.connection.is_none() {
+                self.life.respawn();
+                self.reset_player();
+            }
+            if matches!(self.life.state(), LifeState::GameOver { .. }) && is_key_pressed(KeyCode::Enter) {
+                self.restart();
+            }
+            self.sync_with_server();
+            return;
         }
+
+        // Mouse look (professional FPS controls). The cursor is grabbed, so
+        // deltas are raw relative motion and never stop at the window edge.
+        let mouse_delta = -mouse_delta_position() * vec2(screen_width(), screen_height()) / 2.0;
+        self.player_angle += mouse_delta.x * self.settings.mouse_sensitivity;
+        // Moving the mouse up looks up
+        self.player_pitch = shared::clamp_pitch(self.player_pitch - mouse_delta.y * self.settings.mouse_sensitivity);
+
+        // Keyboard and gamepad turning (keyboard-only players, right stick)
+        let turn = self.input.strength(Action::TurnRight) - self.input.strength(Action::TurnLeft);
+        self.player_angle += turn * 2.0 * delta;
+        let look = self.input.strength(Action::LookUp) - self.input.strength(Action::LookDown);
+        self.player_pitch = shared::clamp_pitch(self.player_pitch + look * 1.5 * delta);
+
+        // Professional FPS movement (WASD + mouse)
+        let forward = self.input.strength(Action::MoveForward) - self.input.strength(Action::MoveBackward);
+        let strafe = self.input.strength(Action::StrafeRight) - self.input.strength(Action::StrafeLeft);
         
-        // Debug: Cycle through themes with T key
-        if is_key_pressed(KeyCode::T) {
+        // Debug: Cycle through themes
+        if self.input.is_pressed(Action::CycleTheme) {
             self.current_theme = match self.current_theme {
                 LevelTheme::CandyMaze => LevelTheme::Cyberpunk,
                 LevelTheme::Cyberpunk => LevelTheme::MoroccanBazaar,
@@ -187,257 +358,600 @@ impl GameState {
             };
         }
 
-        // Arrow keys for keyboard-only players
-        if is_key_down(KeyCode::Left) {
-            self.player_angle -= 2.0 * delta;
+        if self.input.is_pressed(Action::ToggleMap) {
+            self.show_minimap = !self.show_minimap;
         }
-        if is_key_down(KeyCode::Right) {
-            self.player_angle += 2.0 * delta;
+
+        match &mut self.connection {
+            Some(connection) if connection.intermission.is_some() => {
+                for option in 0..WeaponKind::ALL.len() {
+                    if self.input.is_pressed(Action::SelectWeapon(option)) {
+                        connection.vote(option);
+                    }
+                }
+            }
+            _ => {
+                for (index, weapon) in WeaponKind::ALL.into_iter().enumerate() {
+                    if self.input.is_pressed(Action::SelectWeapon(index)) {
+                        self.arsenal.select(weapon);
+                    }
+                }
+            }
         }
-        if is_key_down(KeyCode::Up) {
-            new_x += self.player_angle.cos() * move_speed;
-            new_y += self.player_angle.sin() * move_speed;
+        // Scrolling down or Next weapon goes on to the next one
+        let wheel = mouse_wheel().1;
+        if self.input.is_pressed(Action::NextWeapon) || wheel < 0.0 {
+            self.arsenal.cycle(1);
+        } else if wheel > 0.0 {
+            self.arsenal.cycle(-1);
         }
-        if is_key_down(KeyCode::Down) {
-            new_x -= self.player_angle.cos() * move_speed;
-            new_y -= self.player_angle.sin() * move_speed;
+        if self.input.is_pressed(Action::Reload) {
+            self.arsenal.reload();
         }
 
-        // Enhanced collision detection with wall hit feedback
-        if !self.is_wall(new_x, new_y) {
-            self.player_x = new_x;
-            self.player_y = new_y;
-        } else {
-            // Wall hit effect
-            self.wall_hit_flash = 0.3;
+        // Fixed-step simulation, identical to what the server runs. It waits
+        // while the next level downloads, the old walls no longer apply.
+        let downloading = self.connection.as_ref().is_some_and(|connection| connection.download_progress().is_some());
+        let steps = if downloading { 0 } else { self.timestep.advance(delta) };
+        for _ in 0..steps {
+            let input = PlayerInput { forward, strafe, angle: self.player_angle, pitch: self.player_pitch };
+            self.step_simulation(input);
         }
+        let (render_x, render_y) = self.prev_sim.lerp(&self.sim, self.timestep.alpha());
+        self.player_x = render_x;
+        self.player_y = render_y;
 
-        // Check if player reached the exit
-        let distance_to_exit = ((self.player_x - self.exit_x).powi(2) + (self.player_y - self.exit_y).powi(2)).sqrt();
-        if distance_to_exit < 40.0 {
+        // Check if player reached the exit. Online, the server decides.
+        if self.connection.is_none() && self.maze.reached_exit(self.sim.x, self.sim.y) {
+            if self.previewing {
+                self.stop_preview();
+                if let Some(editor) = &mut self.editor {
+                    editor.set_status("Preview: exit reached");
+                }
+                return;
+            }
             self.advance_level();
         }
 
-        // Shooting mechanics with enhanced feedback
-        if is_key_pressed(KeyCode::Space) && self.ammo > 0 {
-            self.shoot();
+        // Doors, switches and push walls. Online the server runs them and
+        // only the request to use one goes out.
+        let use_pressed = self.input.is_pressed(Action::Use);
+        match &self.connection {
+            Some(connection) => {
+                if use_pressed {
+                    let _ = connection.send(&ClientMessage::Use);
+                }
+            }
+            None => {
+                let players = [(self.sim.x, self.sim.y)];
+                if use_pressed {
+                    self.mechanisms.use_from(&mut self.maze, &players, self.sim.x, self.sim.y, self.player_angle);
+                }
+                self.mechanisms.update(&mut self.maze, &players, delta);
+                self.apply_traps(delta);
+                self.pickups.update(delta);
+                let (health, arsenal) = (self.health, &self.arsenal);
+                let wants = |kind| match kind {
+                    PickupKind::Health => health < MAX_HEALTH,
+                    PickupKind::Ammo => arsenal.wants_ammo(),
+                    PickupKind::Speed | PickupKind::Damage => true,
+                };
+                if let Some(kind) = self.pickups.collect(self.sim.x, self.sim.y, wants) {
+                    self.take_pickup(kind);
+                }
+            }
+        }
+        self.boosts.update(delta);
+        self.discover_traps();
+
+        // Shooting mechanics with enhanced feedback. Automatic weapons keep
+        // firing while the trigger is held.
+        self.arsenal.update(delta);
+        let held = self.input.strength(Action::Fire) > 0.5;
+        if let Some(weapon) = self.arsenal.fire(self.input.is_pressed(Action::Fire), held) {
+            self.shoot(weapon);
             self.crosshair_pulse = 0.0; // Reset crosshair animation
         }
+        self.update_projectiles(delta);
         
         // Update AI enemies
         self.update_enemies(delta);
+
+        self.sync_with_server();
+    }
+
+    /// Applies an item the player took. Online the server already added
+    /// the health, it comes with the next snapshot.
+    fn take_pickup(&mut self, kind: PickupKind) {
+        match kind {
+            PickupKind::Ammo => {
+                self.arsenal.add_ammo(AmmoKind::Bullets, AMMO_PACK);
+                self.arsenal.add_ammo(AmmoKind::Rockets, ROCKET_PACK);
+            }
+            PickupKind::Health if self.connection.is_none() => self.health = (self.health + HEALTH_PACK).min(MAX_HEALTH),
+            PickupKind::Health => {}
+            PickupKind::Speed | PickupKind::Damage => self.boosts.grant(kind),
+        }
+    }
+
+    /// Offline version of what the server does with traps each tick.
+    fn apply_traps(&mut self, delta: f32) {
+        match self.exposure.step(self.mechanisms.traps(), self.sim.x, self.sim.y, delta) {
+            Some(TrapEffect::Damage(kind, damage)) => self.hurt(damage, kind.label()),
+            Some(TrapEffect::Teleport { x, y }) => {
+                self.sim.x = x;
+                self.sim.y = y;
+                // No interpolating across the level
+                self.prev_sim = self.sim;
+            }
+            None => {}
+        }
+    }
+
+    /// Damage from an enemy. Online the server owns health, so the damage
+    /// goes there and comes back with the next snapshot.
+    fn hurt_by_enemy(&mut self, damage: i32, kind: EnemyKind) {
+        match &mut self.connection {
+            Some(connection) => connection.send_reliable(ClientMessage::Hurt { damage, by: kind }),
+            None => self.hurt(damage, kind.label()),
+        }
+    }
+
+    /// Damage offline, from enemies and traps.
+    fn hurt(&mut self, damage: i32, by: &str) {
+        self.health -= damage;
+        self.wall_hit_flash = 0.5; // Red flash when hit
+        if self.health > 0 {
+            return;
+        }
+        // A preview just starts over, it isn't a real game
+        if self.previewing {
+            self.reset_player();
+            return;
+        }
+        self.life.die(by);
+        self.tally.record(self.life.mode(), ScoreEvent::Death);
+        self.add_to_feed(by.to_string(), self.username.clone());
+    }
+
+    /// Starts over once the game is over. Offline that's the first level
+    /// again with no score, online the server brings the player back.
+    fn restart(&mut self) {
+        match &mut self.connection {
+            Some(connection) => connection.send_reliable(ClientMessage::Restart),
+            None => {
+                self.life.restart();
+                self.tally = Tally::default();
+                self.load_level(1);
+            }
+        }
+    }
+
+    fn discover_traps(&mut self) {
+        let cells: Vec<(usize, usize)> = self.mechanisms.traps().cells().map(|(cell, _)| cell).collect();
+        for (x, y) in cells {
+            let centre = ((x as f32 + 0.5) * CELL_SIZE, (y as f32 + 0.5) * CELL_SIZE);
+            if (centre.0 - self.player_x).hypot(centre.1 - self.player_y) < TRAP_DISCOVERY_RANGE {
+                self.discovered_traps.insert((x, y));
+            }
+        }
+    }
+
+    /// Runs one simulation step locally and sends the input to the server.
+    fn step_simulation(&mut self, input: PlayerInput) {
+        let speed = self.boosts.speed_factor();
+        let (next, result) = movement::step_player_at(&self.maze, &self.sim, &input, self.player_radius, speed);
+
+        // Wall hit effect, only when running into a wall rather than sliding along it
+        let wanted = input.normalized();
+        let requested = (wanted.forward * MOVE_SPEED).hypot(wanted.strafe * STRAFE_SPEED) * speed * STEP_SECONDS;
+        let moved = (next.x - self.sim.x).hypot(next.y - self.sim.y);
+        if (result.blocked_x || result.blocked_y) && moved < requested * 0.5 {
+            self.wall_hit_flash = 0.3;
+        }
+
+        self.prev_sim = self.sim;
+        self.sim = next;
+
+        if let Some(connection) = &self.connection {
+            self.input_seq += 1;
+            let _ = connection.send(&ClientMessage::Input { seq: self.input_seq, input });
+            self.pending_inputs.push_back((self.input_seq, input, speed));
+        }
+    }
+
+    fn sync_with_server(&mut self) {
+        let Some(connection) = &mut self.connection else { return };
+        connection.report_ammo(!self.arsenal.wants_ammo());
+        connection.poll();
+        let level_change = connection.take_level_change();
+        let downloaded = connection.update_download();
+        let world = connection.take_world();
+        let pickups = connection.take_pickups();
+        let collected = connection.take_collected();
+        let own_state = connection.take_own_state();
+        let death = connection.take_death();
+        let respawned = connection.take_respawn();
+        let kills = connection.take_kill_feed();
+        let flag_events = connection.take_flag_events();
+        if let Some(own) = connection.own_status() {
+            self.tally = Tally { kills: own.kills, deaths: own.deaths, score: own.score };
+        }
+        if connection.mode != self.life.mode() {
+            self.life = Life::new(connection.mode);
+        }
+
+        if let Some((source, hash)) = level_change {
+            self.change_level(&source, hash);
+        }
+        match downloaded {
+            Some(Ok((source, level))) => {
+                if let Err(e) = level_cache::store(&level) {
+                    eprintln!("Warning: could not cache level {}: {}", source.encode(), e);
+                }
+                self.start_level(&source, level);
+            }
+            Some(Err(e)) => {
+                eprintln!("Error: level download failed, starting over: {}", e);
+                self.push_feed(format!("Level download failed, retrying: {}", e), true);
+            }
+            None => {}
+        }
+
+        if let Some(world) = world {
+            self.mechanisms.apply(&mut self.maze, &world);
+        }
+        if let Some(items) = pickups {
+            self.pickups = Pickups::from_items(items);
+        }
+        for kind in collected {
+            self.take_pickup(kind);
+        }
+        for (killer, victim) in kills {
+            self.add_to_feed(killer, victim);
+        }
+        for (player, team, event) in flag_events {
+            let own = player.as_deref() == Some(self.username.as_str());
+            let text = match player {
+                Some(player) => format!("{} {} the {} flag", player, event.name(), team.label()),
+                None => format!("The {} flag {}", team.label(), event.name()),
+            };
+            self.push_feed(text, own);
+        }
+        if let Some((killer, lives)) = death {
+            self.life.died(&killer, lives);
+        }
+        if respawned {
+            if matches!(self.life.state(), LifeState::GameOver { .. }) {
+                self.life.restart();
+            } else {
+                self.life.respawn();
+            }
+            self.reset_player();
+        }
+
+        // Server reconciliation: start from the authoritative position and
+        // replay the inputs it hasn't seen yet. With deterministic movement
+        // this lands exactly on the predicted position.
+        if let Some(own) = own_state {
+            if own.health < self.health {
+                self.wall_hit_flash = 0.5;
+            }
+            self.health = own.health;
+
+            self.pending_inputs.retain(|(seq, _, _)| *seq > own.last_input);
+            let mut state = PlayerState { x: own.x, y: own.y, angle: own.angle, pitch: own.pitch };
+            for (_, input, speed) in &self.pending_inputs {
+                state = movement::step_player_at(&self.maze, &state, input, self.player_radius, *speed).0;
+            }
+            // Shift the previous step by the same correction so interpolation stays smooth
+            self.prev_sim.x += state.x - self.sim.x;
+            self.prev_sim.y += state.y - self.sim.y;
+            self.sim.x = state.x;
+            self.sim.y = state.y;
+        }
+    }
+
+    /// Grabs and hides the cursor during play and releases it while paused.
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        grab_cursor(!paused);
+    }
+
+    fn open_editor(&mut self) {
+        // Built-in levels are edited in place, anything else starts a new file.
+        // A copy on disk wins over the compiled-in one so earlier edits aren't lost.
+        let builtin_path = PathBuf::from(format!("levels/level{}.ron", self.level));
+        let builtin = Level::builtin(self.level).map(|level| Level::load(&builtin_path).unwrap_or(level));
+        let (level, path) = match builtin {
+            Some(level) => (level, builtin_path),
+            None => {
+                let name = format!("Level {}", self.level);
+                (Level::from_maze(&name, self.level as u32, &self.maze), PathBuf::from("levels/custom.ron"))
+            }
+        };
+        self.editor = Some(Editor::new(level, path));
+        self.paused = false;
+        grab_cursor(false);
+    }
+
+    fn close_editor(&mut self) {
+        self.editor = None;
+        self.previewing = false;
+        // Back to the level that was being played before
+        self.load_level(self.level);
+        self.set_paused(false);
+    }
+
+    fn start_preview(&mut self) {
+        let Some(editor) = &self.editor else { return };
+        let level = editor.level.clone();
+        self.play_level(level);
+        self.previewing = true;
+        grab_cursor(true);
+    }
+
+    fn stop_preview(&mut self) {
+        self.previewing = false;
+        grab_cursor(false);
     }
 
     fn is_wall(&self, x: f32, y: f32) -> bool {
         let grid_x = (x / CELL_SIZE) as usize;
         let grid_y = (y / CELL_SIZE) as usize;
         
-        if grid_x >= MAZE_WIDTH || grid_y >= MAZE_HEIGHT {
-            return true;
-        }
-        
-        self.maze[grid_y][grid_x]
+        self.maze.is_wall(grid_x, grid_y)
     }
 
     fn advance_level(&mut self) {
-        self.level += 1;
-        self.score += 100;
-        
-        if self.level > 3 {
-            self.game_won = true;
-            return;
+        self.tally.record(self.life.mode(), ScoreEvent::Round);
+        self.load_level(self.level + 1);
+    }
+
+    /// Switches to the level the server announced: our own copy if it has
+    /// the server's hash, else one downloaded before, else a fresh download.
+    fn change_level(&mut self, source: &LevelSource, hash: u64) {
+        let local = Level::from_source(source).ok().filter(|level| level.content_hash() == hash);
+        match local.or_else(|| level_cache::load(hash)) {
+            Some(level) => self.start_level(source, level),
+            None => {
+                println!("Downloading level {} from the server", source.encode());
+                if let Some(connection) = &mut self.connection {
+                    connection.start_download(source.clone(), hash);
+                }
+            }
         }
+    }
 
-        // Reset player position
-        self.player_x = 3.5 * CELL_SIZE;
-        self.player_y = 3.5 * CELL_SIZE;
-        self.player_angle = 0.0;
+    fn start_level(&mut self, source: &LevelSource, level: Level) {
+        if let LevelSource::Numbered { number, seed } = *source {
+            self.level = number;
+            self.seed = seed;
+        }
+        self.play_level(level);
+    }
 
-        // Update theme based on level
-        self.current_theme = LevelTheme::from_level(self.level);
+    fn load_level(&mut self, level: usize) {
+        self.level = level;
 
-        // Generate new maze based on level
-        self.generate_maze_for_level(self.level as i32);
-        
-        // Spawn enemies for this level
-        self.spawn_enemies();
+        self.play_level(Level::numbered(level, self.seed));
+    }
+
+    /// Sets up the maze, theme and enemies from a level and puts the player
+    /// on their spawn point.
+    fn play_level(&mut self, level: Level) {
+        self.maze = level.to_maze();
+        self.mechanisms = Mechanisms::new(&level);
+        self.player_radius = level.player_radius();
+        self.discovered_traps.clear();
+        self.pickups = Pickups::new(&level, &self.maze, level.content_hash());
+        self.current_theme = level.theme.map_or_else(|| LevelTheme::from_level(self.level), Into::into);
+        self.enemies = level.enemies.iter().map(Enemy::new).collect();
+        self.current_level = level;
+        self.reset_player();
+    }
+
+    /// Puts the player back on their spawn point with full health and ammo.
+    /// Online, each player gets their own spawn slot so they don't overlap,
+    /// on their team's side in the team modes.
+    fn reset_player(&mut self) {
+        let connection = self.connection.as_ref();
+        let slot = connection.and_then(|connection| connection.player_id).map_or(0, |id| id.saturating_sub(1) as usize);
+        let (spawn_x, spawn_y) = match connection.and_then(|connection| connection.team) {
+            Some(team) => self.current_level.team_spawn(team, slot),
+            None => self.current_level.player_spawn(slot),
+        };
+        let spawn = PlayerState::new(spawn_x, spawn_y);
+        self.sim = spawn;
+        self.prev_sim = spawn;
+        self.pending_inputs.clear();
+        self.player_x = spawn.x;
+        self.player_y = spawn.y;
+        self.player_angle = 0.0;
+        self.player_pitch = 0.0;
         
         // Reset health and ammo for new level
-        self.health = 100;
-        self.ammo = 30;
-    }
-
-    fn generate_maze_for_level(&mut self, level: i32) {
-        // Clear maze
-        self.maze = [[false; MAZE_WIDTH]; MAZE_HEIGHT];
-        
-        // Outer walls
-        for i in 0..MAZE_WIDTH {
-            self.maze[0][i] = true;
-            self.maze[MAZE_HEIGHT - 1][i] = true;
-        }
-        for i in 0..MAZE_HEIGHT {
-            self.maze[i][0] = true;
-            self.maze[i][MAZE_WIDTH - 1] = true;
-        }
-
-        match level {
-            1 => {
-                // Level 1: Simple maze
-                self.maze[2][2] = true; self.maze[2][3] = true; self.maze[2][4] = true;
-                self.maze[4][6] = true; self.maze[5][6] = true; self.maze[6][6] = true;
-                self.maze[8][2] = true; self.maze[8][3] = true; self.maze[8][4] = true; self.maze[8][5] = true;
-                self.maze[10][8] = true; self.maze[11][8] = true; self.maze[12][8] = true;
-                self.maze[6][10] = true; self.maze[7][10] = true; self.maze[8][10] = true;
-                self.maze[4][12] = true; self.maze[5][12] = true; self.maze[6][12] = true;
-            }
-            2 => {
-                // Level 2: More complex with dead ends
-                self.maze[2][2] = true; self.maze[2][3] = true; self.maze[2][4] = true; self.maze[2][5] = true;
-                self.maze[4][2] = true; self.maze[4][4] = true; self.maze[4][6] = true; self.maze[4][8] = true;
-                self.maze[6][2] = true; self.maze[6][3] = true; self.maze[6][5] = true; self.maze[6][7] = true; self.maze[6][9] = true;
-                self.maze[8][4] = true; self.maze[8][6] = true; self.maze[8][8] = true; self.maze[8][10] = true;
-                self.maze[10][2] = true; self.maze[10][4] = true; self.maze[10][6] = true; self.maze[10][8] = true; self.maze[10][10] = true;
-                self.maze[12][3] = true; self.maze[12][5] = true; self.maze[12][7] = true; self.maze[12][9] = true;
-            }
-            3 => {
-                // Level 3: Complex but navigable maze
-                // Create a more structured complex maze
-                self.maze[2][2] = true; self.maze[2][3] = true; self.maze[2][5] = true; self.maze[2][7] = true; self.maze[2][9] = true;
-                self.maze[3][4] = true; self.maze[3][6] = true; self.maze[3][8] = true; self.maze[3][10] = true;
-                self.maze[4][2] = true; self.maze[4][3] = true; self.maze[4][5] = true; self.maze[4][7] = true; self.maze[4][9] = true; self.maze[4][11] = true;
-                self.maze[5][4] = true; self.maze[5][6] = true; self.maze[5][8] = true; self.maze[5][10] = true; self.maze[5][12] = true;
-                self.maze[6][2] = true; self.maze[6][3] = true; self.maze[6][5] = true; self.maze[6][7] = true; self.maze[6][9] = true; self.maze[6][11] = true;
-                self.maze[7][4] = true; self.maze[7][6] = true; self.maze[7][8] = true; self.maze[7][10] = true;
-                self.maze[8][2] = true; self.maze[8][3] = true; self.maze[8][5] = true; self.maze[8][7] = true; self.maze[8][9] = true; self.maze[8][11] = true;
-                self.maze[9][4] = true; self.maze[9][6] = true; self.maze[9][8] = true; self.maze[9][10] = true; self.maze[9][12] = true;
-                self.maze[10][2] = true; self.maze[10][3] = true; self.maze[10][5] = true; self.maze[10][7] = true; self.maze[10][9] = true; self.maze[10][11] = true;
-                self.maze[11][4] = true; self.maze[11][6] = true; self.maze[11][8] = true; self.maze[11][10] = true;
-                self.maze[12][2] = true; self.maze[12][3] = true; self.maze[12][5] = true; self.maze[12][7] = true; self.maze[12][9] = true;
-                // Ensure clear path to exit
-                self.maze[13][12] = false; self.maze[12][13] = false; self.maze[13][13] = false;
-            }
-            _ => {}
-        }
-    }
-
-    fn shoot(&mut self) {
-        if self.ammo > 0 {
-            self.ammo -= 1;
-            // Add muzzle flash effect
-            self.wall_hit_flash = 0.2;
-            
-            // Check if we hit any enemies
-            let hit_enemy = self.check_enemy_hit();
-            if hit_enemy {
-                self.score += 50; // More points for hitting enemies
-            } else {
-                self.score += 10; // Base shooting points
+        self.health = MAX_HEALTH;
+        self.arsenal = Arsenal::default();
+        self.projectiles.clear();
+        self.exposure.reset();
+        self.boosts = Boosts::default();
+    }
+
+    /// Fires one shot of `weapon`, strayed by up to its spread. Hitscan
+    /// weapons hit straight away, the launcher sends a rocket on its way.
+    fn shoot(&mut self, weapon: WeaponKind) {
+        let def = weapon.def();
+        // Add muzzle flash effect
+        self.wall_hit_flash = 0.2;
+        let angle = self.player_angle + rand::gen_range(-def.spread, def.spread);
+        if def.projectile_speed > 0.0 {
+            self.projectiles.push(Projectile::new(weapon, self.player_x, self.player_y, angle));
+            return;
+        }
+
+        // Points come for kills, not shots
+        self.check_hit(angle, def.damage, def.range);
+    }
+
+    /// Hits whatever is nearest in the line of fire, an enemy or, in the
+    /// modes where players fight, another player. The server decides what
+    /// a hit on a player does.
+    fn check_hit(&mut self, angle: f32, damage: i32, range: f32) -> bool {
+        let damage = damage * self.boosts.damage_factor();
+        let nearest = |a: &(usize, f32), b: &(usize, f32)| a.1.total_cmp(&b.1);
+        let enemy = self
+            .enemies
+            .iter()
+            .enumerate()
+            .filter(|(_, enemy)| enemy.health > 0)
+            .filter_map(|(index, enemy)| Some((index, self.line_of_fire(angle, range, enemy.x, enemy.y)?)))
+            .min_by(nearest);
+        let targets = self.player_targets();
+        let player = targets
+            .iter()
+            .enumerate()
+            .filter_map(|(index, &(_, x, y))| Some((index, self.line_of_fire(angle, range, x, y)?)))
+            .min_by(nearest);
+        match (enemy, player) {
+            (Some((_, enemy_distance)), Some((index, player_distance))) if player_distance < enemy_distance => {
+                self.hit_player(targets[index].0, damage)
             }
+            (Some((index, _)), _) => self.enemies[index].health -= damage,
+            (None, Some((index, _))) => self.hit_player(targets[index].0, damage),
+            (None, None) => return false,
         }
+        true
     }
-    
-    fn check_enemy_hit(&mut self) -> bool {
-        let ray_cos = self.player_angle.cos();
-        let ray_sin = self.player_angle.sin();
-        let player_x = self.player_x;
-        let player_y = self.player_y;
-        let player_angle = self.player_angle;
-        
-        for enemy in &mut self.enemies {
-            if enemy.health <= 0 { continue; }
-            
-            // Calculate distance to enemy
-            let dx = enemy.x - player_x;
-            let dy = enemy.y - player_y;
-            let distance = (dx * dx + dy * dy).sqrt();
-            
-            if distance > 300.0 { continue; } // Max shooting range
-            
-            // Check if enemy is in line of sight
-            let angle_to_enemy = dy.atan2(dx);
-            let angle_diff = (angle_to_enemy - player_angle).abs();
-            
-            if angle_diff < 0.1 { // Small angle tolerance for hitting
-                // Check for walls between player and enemy
-                let steps = (distance / 5.0) as i32;
-                let mut blocked = false;
-                
-                for i in 1..steps {
-                    let check_x = player_x + ray_cos * (i as f32 * 5.0);
-                    let check_y = player_y + ray_sin * (i as f32 * 5.0);
-                    let grid_x = (check_x / CELL_SIZE) as usize;
-                    let grid_y = (check_y / CELL_SIZE) as usize;
-                    
-                    if grid_x >= MAZE_WIDTH || grid_y >= MAZE_HEIGHT || self.maze[grid_y][grid_x] {
-                        blocked = true;
-                        break;
-                    }
-                }
-                
-                if !blocked {
-                    enemy.health -= 25; // Damage per hit
-                    return true;
-                }
+
+    /// Distance to `(x, y)` if a shot at `angle` would hit something
+    /// there: within `range`, close to the line of fire and with no wall
+    /// in between.
+    fn line_of_fire(&self, angle: f32, range: f32, x: f32, y: f32) -> Option<f32> {
+        let dx = x - self.player_x;
+        let dy = y - self.player_y;
+        let distance = (dx * dx + dy * dy).sqrt();
+        if distance > range {
+            return None;
+        }
+
+        // Small angle tolerance for hitting
+        let angle_diff = ((dy.atan2(dx) - angle + PI).rem_euclid(2.0 * PI) - PI).abs();
+        if angle_diff >= 0.1 {
+            return None;
+        }
+
+        // Check for walls between player and target
+        let steps = (distance / 5.0) as i32;
+        for i in 1..steps {
+            let check_x = self.player_x + angle.cos() * (i as f32 * 5.0);
+            let check_y = self.player_y + angle.sin() * (i as f32 * 5.0);
+            if self.maze.is_wall((check_x / CELL_SIZE) as usize, (check_y / CELL_SIZE) as usize) {
+                return None;
             }
         }
-        false
+        Some(distance)
+    }
+
+    /// Other players that can be shot right now as id and position: the
+    /// living, online, in the modes where players fight.
+    fn player_targets(&self) -> Vec<(u32, f32, f32)> {
+        let Some(connection) = self.connection.as_ref().filter(|connection| connection.mode.players_fight()) else {
+            return Vec::new();
+        };
+        connection.remote_players.iter().filter(|player| player.health > 0).map(|player| (player.id, player.x, player.y)).collect()
+    }
+
+    /// Tells the server the player shot `player_id`. It owns everyone's
+    /// health, so the damage shows in the next snapshot.
+    fn hit_player(&self, player_id: u32, damage: i32) {
+        if let Some(connection) = &self.connection {
+            let _ = connection.send(&ClientMessage::Hit { player_id, damage });
+        }
     }
     
-    fn spawn_enemies(&mut self) {
-        self.enemies.clear();
-        let num_enemies = self.level + 1; // More enemies each level
-        
-        for i in 0..num_enemies {
-            // Find valid spawn positions (not in walls, not near player)
-            let mut attempts = 0;
-            while attempts < 50 {
-                let x = (4 + (i * 3) % 8) as f32 * CELL_SIZE + CELL_SIZE / 2.0;
-                let y = (4 + (i * 2) % 8) as f32 * CELL_SIZE + CELL_SIZE / 2.0;
-                
-                if !self.is_wall(x, y) {
-                    let distance_to_player = ((x - self.player_x).powi(2) + (y - self.player_y).powi(2)).sqrt();
-                    if distance_to_player > 200.0 { // Not too close to player
-                        self.enemies.push(Enemy::new(x, y, (i as f32 * PI / 2.0) % (2.0 * PI)));
-                        break;
-                    }
+    /// Moves rockets on. One that hits a wall, comes close to an enemy or
+    /// player or has flown its range explodes and hurts everyone nearby.
+    fn update_projectiles(&mut self, delta: f32) {
+        for explosion in &mut self.explosions {
+            explosion.2 -= delta;
+        }
+        self.explosions.retain(|explosion| explosion.2 > 0.0);
+
+        let damage_factor = self.boosts.damage_factor();
+        let targets = self.player_targets();
+        let mut projectiles = std::mem::take(&mut self.projectiles);
+        projectiles.retain_mut(|projectile| {
+            let flying = projectile.advance(&self.maze, delta);
+            let positions = self.enemies.iter().filter(|enemy| enemy.health > 0).map(|enemy| (enemy.x, enemy.y));
+            let close = positions
+                .chain(targets.iter().map(|&(_, x, y)| (x, y)))
+                .any(|(x, y)| (x - projectile.x).hypot(y - projectile.y) < ROCKET_PROXIMITY);
+            if flying && !close {
+                return true;
+            }
+            for enemy in self.enemies.iter_mut().filter(|enemy| enemy.health > 0) {
+                let damage = projectile.splash_damage((enemy.x - projectile.x).hypot(enemy.y - projectile.y));
+                enemy.health -= damage * damage_factor;
+            }
+            for &(player_id, x, y) in &targets {
+                let damage = projectile.splash_damage((x - projectile.x).hypot(y - projectile.y));
+                if damage > 0 {
+                    self.hit_player(player_id, damage * damage_factor);
                 }
-                attempts += 1;
             }
-        }
+            self.explosions.push((projectile.x, projectile.y, EXPLOSION_SECONDS));
+            false
+        });
+        self.projectiles = projectiles;
     }
-    
+
     fn update_enemies(&mut self, delta: f32) {
         let player_pos = (self.player_x, self.player_y);
-        let maze = self.maze; // Copy maze for borrowing
+        let maze = &self.maze;
         
         // Collect enemy updates to avoid borrowing issues
         let mut enemy_updates = Vec::new();
-        let mut attack_occurred = false;
+        let mut attack_damage = 0;
+        let mut attacker = None;
         
         for (i, enemy) in self.enemies.iter().enumerate() {
             if enemy.health <= 0 { continue; }
             
             let distance_to_player = ((enemy.x - player_pos.0).powi(2) + (enemy.y - player_pos.1).powi(2)).sqrt();
             
-            let mut new_enemy = *enemy;
+            let mut new_enemy = enemy.clone();
             
             // AI State Machine
             match enemy.state {
                 EnemyState::Patrolling => {
-                    // Simple patrol movement
-                    let move_speed = 50.0 * delta;
-                    let new_x = enemy.x + enemy.angle.cos() * move_speed;
-                    let new_y = enemy.y + enemy.angle.sin() * move_speed;
+                    let move_speed = enemy.kind.patrol_speed() * delta;
+                    let mut step = move_speed;
+
+                    // Walk the patrol route, or wander when there is none
+                    if enemy.route.len() > 1 {
+                        let (target_x, target_y) = enemy.route[enemy.next_waypoint];
+                        let remaining = (target_x - enemy.x).hypot(target_y - enemy.y);
+                        new_enemy.angle = (target_y - enemy.y).atan2(target_x - enemy.x);
+                        if remaining <= move_speed {
+                            new_enemy.next_waypoint = (enemy.next_waypoint + 1) % enemy.route.len();
+                            step = remaining;
+                        }
+                    }
+                    let new_x = enemy.x + new_enemy.angle.cos() * step;
+                    let new_y = enemy.y + new_enemy.angle.sin() * step;
                     
                     // Check walls manually
                     let grid_x = (new_x / CELL_SIZE) as usize;
                     let grid_y = (new_y / CELL_SIZE) as usize;
-                    let is_wall = grid_x >= MAZE_WIDTH || grid_y >= MAZE_HEIGHT || maze[grid_y][grid_x];
+                    let is_wall = maze.is_wall(grid_x, grid_y);
                     
                     if !is_wall {
                         new_enemy.x = new_x;
                         new_enemy.y = new_y;
+                    } else if enemy.route.len() > 1 {
+                        // Route goes through a wall, try the next leg
+                        new_enemy.next_waypoint = (enemy.next_waypoint + 1) % enemy.route.len();
                     } else {
                         new_enemy.angle += PI / 2.0; // Turn 90 degrees
                     }
@@ -453,14 +967,14 @@ impl GameState {
                     let angle_to_player = (player_pos.1 - enemy.y).atan2(player_pos.0 - enemy.x);
                     new_enemy.angle = angle_to_player;
                     
-                    let chase_speed = 80.0 * delta;
+                    let chase_speed = enemy.kind.chase_speed() * delta;
                     let new_x = enemy.x + new_enemy.angle.cos() * chase_speed;
                     let new_y = enemy.y + new_enemy.angle.sin() * chase_speed;
                     
                     // Check walls manually
                     let grid_x = (new_x / CELL_SIZE) as usize;
                     let grid_y = (new_y / CELL_SIZE) as usize;
-                    let is_wall = grid_x >= MAZE_WIDTH || grid_y >= MAZE_HEIGHT || maze[grid_y][grid_x];
+                    let is_wall = maze.is_wall(grid_x, grid_y);
                     
                     if !is_wall {
                         new_enemy.x = new_x;
@@ -480,7 +994,8 @@ impl GameState {
                 EnemyState::Attacking => {
                     // Attack player
                     if self.last_enemy_attack.elapsed().as_secs_f32() > 1.0 {
-                        attack_occurred = true;
+                        attack_damage += enemy.kind.damage();
+                        attacker = Some(enemy.kind);
                     }
                     
                     // Return to chasing if not close enough
@@ -501,24 +1016,61 @@ impl GameState {
         }
         
         // Handle attack
-        if attack_occurred {
-            self.health -= 10;
+        if let Some(attacker) = attacker {
+            self.hurt_by_enemy(attack_damage, attacker);
             self.last_enemy_attack = Instant::now();
-            self.wall_hit_flash = 0.5; // Red flash when hit
         }
         
         // Remove dead enemies
+        let killed: Vec<EnemyKind> = self.enemies.iter().filter(|e| e.health <= 0).map(|e| e.kind).collect();
+        for kind in killed {
+            self.enemy_killed(kind);
+        }
         self.enemies.retain(|e| e.health > 0);
     }
 
+    /// Scores a kill. Online the server does the scoring and tells everyone.
+    fn enemy_killed(&mut self, kind: EnemyKind) {
+        match &mut self.connection {
+            Some(connection) => connection.send_reliable(ClientMessage::Kill { victim: kind }),
+            // A preview isn't a real game
+            None if self.previewing => {}
+            None => {
+                self.tally.record(self.life.mode(), ScoreEvent::Kill);
+                self.add_to_feed(self.username.clone(), kind.label().to_string());
+            }
+        }
+    }
+
+    fn add_to_feed(&mut self, killer: String, victim: String) {
+        let own = killer == self.username || victim == self.username;
+        self.push_feed(format!("{} killed {}", killer, victim), own);
+    }
+
+    fn push_feed(&mut self, text: String, own: bool) {
+        self.kill_feed.push_back((text, own, Instant::now()));
+        if self.kill_feed.len() > FEED_SHOWN {
+            self.kill_feed.pop_front();
+        }
+    }
+
     fn draw(&self) {
+        if let Some(editor) = &self.editor {
+            if !self.previewing {
+                editor.draw(&self.current_theme.get_config());
+                return;
+            }
+        }
+
         clear_background(BLACK);
 
         // Draw 3D first-person view (main viewport)
         self.draw_3d_view();
         
         // Draw mini-map in top-right corner
-        self.draw_minimap();
+        if self.show_minimap {
+            self.draw_minimap();
+        }
 
         // Draw FPS counter
         draw_text(
@@ -529,6 +1081,36 @@ impl GameState {
             WHITE,
         );
         self.draw_enhanced_hud();
+
+        if self.previewing {
+            let text = "PREVIEW - ESC returns to the editor";
+            let text_width = measure_text(text, None, 22, 1.0).width;
+            draw_text(text, screen_width() / 2.0 - text_width / 2.0, 30.0, 22.0, YELLOW);
+        }
+
+        self.draw_motd();
+        self.draw_kill_feed();
+        if let Some(connection) = &self.connection {
+            let theme = self.current_theme.get_config();
+            chat::draw_log(&connection.chat, self.chat_entry.is_some(), &theme);
+            if let Some(entry) = &self.chat_entry {
+                entry.draw(&theme);
+            }
+        }
+        self.draw_intermission();
+        self.draw_death_screen();
+        if !self.paused && self.input.strength(Action::Scoreboard) > 0.0 {
+            self.draw_scoreboard();
+        }
+        if let Some((received, total)) = self.connection.as_ref().and_then(|connection| connection.download_progress()) {
+            let text = format!("DOWNLOADING LEVEL {}/{}", received, total.max(1));
+            let text_width = measure_text(&text, None, 28, 1.0).width;
+            draw_text(&text, screen_width() / 2.0 - text_width / 2.0, screen_height() / 2.0, 28.0, YELLOW);
+        }
+
+        if self.paused {
+            self.pause_menu.draw(&self.settings, &self.input, &self.current_theme.get_config());
+        }
     }
 
     fn draw_3d_view(&self) {
@@ -536,10 +1118,11 @@ impl GameState {
         let screen_height = screen_height();
         let num_rays = 320;
         let theme = self.current_theme.get_config();
+        let horizon = self.horizon();
         
-        // Draw themed floor and ceiling
-        draw_rectangle(0.0, 0.0, screen_width, screen_height / 2.0, theme.ceiling_color);
-        draw_rectangle(0.0, screen_height / 2.0, screen_width, screen_height / 2.0, theme.floor_color);
+        // Draw themed floor and ceiling, split at the (pitch sheared) horizon
+        draw_rectangle(0.0, 0.0, screen_width, horizon, theme.ceiling_color);
+        draw_rectangle(0.0, horizon, screen_width, screen_height - horizon, theme.floor_color);
         
         // Add theme-specific atmospheric effects
         self.current_theme.draw_atmospheric_effects(&theme, self.crosshair_pulse);
@@ -552,27 +1135,45 @@ impl GameState {
             let ray_cos = ray_angle.cos();
             let ray_sin = ray_angle.sin();
             
+            // A door that's partly open is seen through: remember the first
+            // one and keep going, its panel is drawn over what's behind it
+            let mut door = None;
+            let mut hit_closed_door = false;
             while distance < RENDER_DISTANCE {
                 let test_x = self.player_x + ray_cos * distance;
                 let test_y = self.player_y + ray_sin * distance;
                 
-                if self.is_wall(test_x, test_y) {
+                let cell = ((test_x / CELL_SIZE) as usize, (test_y / CELL_SIZE) as usize);
+                if let Some(open) = self.mechanisms.door_at(cell.0, cell.1).map(|door| door.open) {
+                    if open <= 0.0 {
+                        hit_closed_door = true;
+                        break;
+                    }
+                    if open < 1.0 && door.is_none() {
+                        door = Some((distance, open));
+                    }
+                } else if self.is_wall(test_x, test_y) {
                     break;
                 }
                 distance += 1.0; // Higher precision
             }
             
             // Fish-eye correction
-            distance *= (ray_angle - self.player_angle).cos();
+            let fish_eye = (ray_angle - self.player_angle).cos();
+            distance *= fish_eye;
             
             // Calculate wall height with perspective
             let wall_height = (screen_height * 0.6) / (distance / CELL_SIZE + 0.1);
-            let wall_top = (screen_height / 2.0) - wall_height / 2.0;
-            let wall_bottom = (screen_height / 2.0) + wall_height / 2.0;
+            let wall_top = horizon - wall_height / 2.0;
+            let wall_bottom = horizon + wall_height / 2.0;
             
             // Themed wall rendering with distance-based shading
             let brightness_factor = 1.0 - (distance / 500.0).min(1.0);
-            let wall_color = self.current_theme.get_wall_color(&theme, brightness_factor, i);
+            let wall_color = if hit_closed_door {
+                self.current_theme.get_door_color(&theme, brightness_factor)
+            } else {
+                self.current_theme.get_wall_color(&theme, brightness_factor, i)
+            };
             
             // Draw wall with thickness for better appearance
             let x = (i as f32 / num_rays as f32) * screen_width;
@@ -584,6 +1185,16 @@ impl GameState {
             if i % 8 == 0 || distance < 100.0 {
                 draw_line(x, wall_top, x, wall_bottom, 1.0, Color::from_rgba(255, 255, 255, 100));
             }
+
+            // The door panel slides up into the ceiling as it opens
+            if let Some((door_distance, open)) = door {
+                let door_distance = door_distance * fish_eye;
+                let door_height = (screen_height * 0.6) / (door_distance / CELL_SIZE + 0.1);
+                let door_top = horizon - door_height / 2.0;
+                let brightness = 1.0 - (door_distance / 500.0).min(1.0);
+                let color = self.current_theme.get_door_color(&theme, brightness);
+                draw_rectangle(x, door_top, line_width, door_height * (1.0 - open), color);
+            }
         }
         
         // Add screen flash effect for wall hits
@@ -592,8 +1203,14 @@ impl GameState {
             draw_rectangle(0.0, 0.0, screen_width, screen_height, Color::from_rgba(255, 100, 100, flash_alpha));
         }
         
-        // Draw enemies in 3D view
+        // Draw traps, switches, items, enemies and other players in 3D view
+        self.draw_traps_3d();
+        self.draw_switches_3d();
+        self.draw_pickups_3d();
         self.draw_enemies_3d();
+        self.draw_flags_3d();
+        self.draw_remote_players_3d();
+        self.draw_projectiles_3d();
         
         // Draw professional crosshair
         self.draw_crosshair();
@@ -601,6 +1218,12 @@ impl GameState {
         // Enhanced HUD with professional styling
         self.draw_enhanced_hud();
     }
+
+    /// Screen row of the horizon. Looking up or down shears the whole view
+    /// vertically instead of rotating the camera (y-shearing).
+    fn horizon(&self) -> f32 {
+        screen_height() / 2.0 + self.player_pitch.tan() * screen_height()
+    }
     
     fn draw_enhanced_hud(&self) {
         let screen_width = screen_width();
@@ -625,14 +1248,35 @@ impl GameState {
         };
         
         draw_text(&format!("FPS: {:.0}", self.fps_counter), 20.0, hud_y + 25.0, 20.0, fps_color);
+
+        // Weapons and the keys that pick them, the one in hand highlighted
+        let mut weapon_x = screen_width - 330.0;
+        for (index, kind) in WeaponKind::ALL.into_iter().enumerate() {
+            let text = format!("{} {}", self.input.map.hint(Action::SelectWeapon(index)), kind.def().name.to_uppercase());
+            let color = if kind == self.arsenal.current() { theme.hud_accent } else { theme.text_secondary };
+            draw_text(&text, weapon_x, hud_y + 25.0, 18.0, color);
+            weapon_x += measure_text(&text, None, 18, 1.0).width + 20.0;
+        }
         
-        // Themed player info
-        draw_text(&format!("PILOT: {}", self.username), 15.0, 55.0, 18.0, theme.text_primary);
-        draw_text(&format!("LEVEL: {} | SCORE: {}", self.level, self.score), 15.0, 75.0, 18.0, theme.text_secondary);
+        // Themed player info, and the team and how the teams stand in the team modes
+        let pilot = format!("PILOT: {}", self.username);
+        draw_text(&pilot, 15.0, 55.0, 18.0, theme.text_primary);
+        let lives = self.life.lives().map(|lives| format!(" | LIVES: {}", lives)).unwrap_or_default();
+        draw_text(&format!("{} | SCORE: {}{}", self.current_level.name.to_uppercase(), self.tally.score, lives), 15.0, 75.0, 18.0, theme.text_secondary);
+        if let Some(connection) = &self.connection {
+            if let Some(team) = connection.team {
+                let [red, blue] = connection.team_scores;
+                let text = format!("{} TEAM | RED {} - {} BLUE", team.label().to_uppercase(), red, blue);
+                let x = 35.0 + measure_text(&pilot, None, 18, 1.0).width;
+                draw_text(&text, x, 55.0, 18.0, self.current_theme.get_team_color(team));
+            }
+        }
         
-        // Health and ammo bars
+        // Health and ammo bars, the ammo bar showing what's left in the magazine
         let health_width = (self.health as f32 / 100.0) * 100.0;
-        let ammo_width = (self.ammo as f32 / 30.0) * 100.0;
+        let weapon = self.arsenal.current().def();
+        let loaded = self.arsenal.loaded(self.arsenal.current());
+        let ammo_width = (loaded as f32 / weapon.magazine as f32) * 100.0;
         
         // Themed health bar
         draw_text("HEALTH:", 15.0, 100.0, 16.0, theme.text_primary);
@@ -643,27 +1287,221 @@ impl GameState {
         // Themed ammo bar
         draw_text("AMMO:", 200.0, 100.0, 16.0, theme.text_primary);
         draw_rectangle(250.0, 88.0, 100.0, 12.0, Color::from_rgba(100, 100, 0, 200));
-        draw_rectangle(250.0, 88.0, ammo_width, 12.0, if self.ammo > 10 { theme.hud_secondary } else { Color::from_rgba(255, 0, 0, 255) });
+        draw_rectangle(250.0, 88.0, ammo_width, 12.0, if loaded > weapon.magazine / 4 { theme.hud_secondary } else { Color::from_rgba(255, 0, 0, 255) });
         draw_rectangle_lines(250.0, 88.0, 100.0, 12.0, 1.0, theme.hud_primary);
+        let ammo_text = format!("{} {}/{}", weapon.name.to_uppercase(), loaded, self.arsenal.reserve(weapon.ammo));
+        draw_text(&ammo_text, 360.0, 100.0, 16.0, theme.text_primary);
+
+        // Boosts with the seconds they have left
+        let boosts = [(PickupKind::Speed, self.boosts.speed), (PickupKind::Damage, self.boosts.damage)];
+        let mut boost_x = 375.0 + measure_text(&ammo_text, None, 16, 1.0).width;
+        for (kind, seconds) in boosts.into_iter().filter(|(_, seconds)| *seconds > 0.0) {
+            let text = format!("{} {:.0}s", kind.label().to_uppercase(), seconds.ceil());
+            draw_text(&text, boost_x, 100.0, 16.0, pickup_color(kind));
+            boost_x += measure_text(&text, None, 16, 1.0).width + 15.0;
+        }
         
         // Themed mission status
-        if self.game_won {
-            let text = "🎉 MISSION COMPLETE! ALL LEVELS CLEARED! 🎉";
-            let text_width = measure_text(text, None, 28, 1.0).width;
-            draw_rectangle(screen_width/2.0 - text_width/2.0 - 10.0, screen_height/2.0 - 20.0, text_width + 20.0, 40.0, Color::from_rgba(0, 100, 0, 200));
-            draw_text(text, screen_width/2.0 - text_width/2.0, screen_height/2.0, 28.0, theme.hud_accent);
-        } else {
-            let objective_text = self.current_theme.get_objective_text();
-            draw_text(objective_text, 15.0, screen_height - 80.0, 18.0, theme.text_secondary);
-        }
+        draw_text(&self.objective(), 15.0, screen_height - 80.0, 18.0, theme.text_secondary);
         
         // Themed controls help
         draw_rectangle(5.0, screen_height - 60.0, 450.0, 55.0, Color::from_rgba(0, 0, 0, 150));
         draw_rectangle_lines(5.0, screen_height - 60.0, 450.0, 55.0, 1.0, theme.hud_primary);
-        draw_text("CONTROLS: WASD/Mouse=Move | SPACE=Shoot | T=Theme", 15.0, screen_height - 40.0, 16.0, theme.text_primary);
-        draw_text("STATUS: Connected to Combat Network", 15.0, screen_height - 20.0, 16.0, theme.hud_accent);
+        let hint = |action| self.input.map.hint(action).to_uppercase();
+        let movement = [Action::MoveForward, Action::StrafeLeft, Action::MoveBackward, Action::StrafeRight].map(hint).join("/");
+        let controls = format!(
+            "CONTROLS: {}/Mouse=Move | {}=Shoot | {}=Reload | {}=Weapon | {}=Use | {}=Chat | {}=Scores | {}=Menu",
+            movement,
+            hint(Action::Fire),
+            hint(Action::Reload),
+            hint(Action::NextWeapon),
+            hint(Action::Use),
+            hint(Action::Chat),
+            hint(Action::Scoreboard),
+            hint(Action::Pause),
+        );
+        draw_text(&controls, 15.0, screen_height - 40.0, 16.0, theme.text_primary);
+        let status = match &self.connection {
+            Some(connection) if connection.player_id.is_some() => format!("STATUS: Connected to {}", self.server_addr),
+            Some(Connection { rejected: Some(reason), .. }) => format!("STATUS: Refused by {}: {}", self.server_addr, reason),
+            Some(_) => format!("STATUS: Connecting to {}...", self.server_addr),
+            None => "STATUS: Offline".to_string(),
+        };
+        draw_text(&status, 15.0, screen_height - 20.0, 16.0, theme.hud_accent);
     }
     
+    /// What the player is after: the exit, or in the team modes what wins
+    /// the round.
+    fn objective(&self) -> String {
+        let mode = self.connection.as_ref().map_or(GameMode::default(), |connection| connection.mode);
+        let limit = mode.team_score_limit().unwrap_or_default();
+        match (mode, self.carried_flag()) {
+            (GameMode::CaptureTheFlag, Some(flag)) => format!("OBJECTIVE: Bring the {} flag to your base!", flag.label()),
+            (GameMode::CaptureTheFlag, None) => format!("OBJECTIVE: Capture the enemy flag, first team to {} wins", limit),
+            (GameMode::TeamDeathmatch, _) => format!("OBJECTIVE: First team to {} kills wins", limit),
+            _ => self.current_theme.get_objective_text().to_string(),
+        }
+    }
+
+    /// The server's message of the day, for a few seconds after joining.
+    fn draw_motd(&self) {
+        let Some((text, received)) = self.connection.as_ref().and_then(|connection| connection.motd.as_ref()) else {
+            return;
+        };
+        let age = received.elapsed().as_secs_f32();
+        if age > MOTD_SECONDS {
+            return;
+        }
+        let theme = self.current_theme.get_config();
+        // Fades out over the last second
+        let alpha = (MOTD_SECONDS - age).min(1.0);
+        let width = measure_text(text, None, 20, 1.0).width + 30.0;
+        let left = screen_width() / 2.0 - width / 2.0;
+        draw_rectangle(left, 50.0, width, 34.0, Color::new(0.0, 0.0, 0.0, 0.7 * alpha));
+        let color = theme.text_primary;
+        draw_text(text, left + 15.0, 73.0, 20.0, Color::new(color.r, color.g, color.b, alpha));
+    }
+
+    /// Round summary and level vote, shown online between rounds.
+    fn draw_intermission(&self) {
+        let Some(intermission) = self.connection.as_ref().and_then(|connection| connection.intermission.as_ref()) else {
+            return;
+        };
+        let theme = self.current_theme.get_config();
+        let (players, own_id) = self.ranking();
+        let width = SCORE_TABLE_WIDTH + 40.0;
+        let table_top = 105.0 + intermission.options.len() as f32 * 30.0;
+        let height = table_top + score_table_height(players.len()) + 30.0;
+        let left = screen_width() / 2.0 - width / 2.0;
+        let top = screen_height() / 2.0 - height / 2.0;
+        draw_rectangle(left, top, width, height, Color::from_rgba(0, 0, 0, 200));
+        draw_rectangle_lines(left, top, width, height, 2.0, theme.hud_primary);
+
+        let teams = self.connection.as_ref().is_some_and(|connection| connection.mode.has_teams());
+        let title = if teams {
+            format!("{} WINS THE ROUND", intermission.finisher.to_uppercase())
+        } else {
+            format!("{} REACHED THE EXIT", intermission.finisher.to_uppercase())
+        };
+        draw_text(&title, left + 20.0, top + 35.0, 24.0, theme.text_primary);
+        let prompt = "VOTE FOR THE NEXT LEVEL WITH THE WEAPON KEYS";
+        draw_text(prompt, left + 20.0, top + 62.0, 18.0, theme.text_secondary);
+
+        for (index, option) in intermission.options.iter().enumerate() {
+            let y = top + 95.0 + index as f32 * 30.0;
+            let chosen = intermission.own_vote == Some(index);
+            let color = if chosen { theme.hud_accent } else { theme.text_primary };
+            let marker = if chosen { ">" } else { " " };
+            let key = self.input.map.hint(Action::SelectWeapon(index)).to_uppercase();
+            draw_text(&format!("{} {}. {}", marker, key, option.name), left + 20.0, y, 20.0, color);
+            let votes = format!("{} VOTE(S)", option.votes);
+            let votes_width = measure_text(&votes, None, 18, 1.0).width;
+            draw_text(&votes, left + width - 20.0 - votes_width, y, 18.0, theme.text_secondary);
+        }
+
+        draw_score_table(&players, own_id, |id| self.team_color_of(id), left + 20.0, top + table_top, &theme);
+
+        let countdown = format!("NEXT LEVEL IN {}s", intermission.seconds_left);
+        draw_text(&countdown, left + 20.0, top + height - 12.0, 18.0, theme.hud_accent);
+    }
+
+    /// Players from best to worst and the local player's id. Offline that's
+    /// just the local player.
+    fn ranking(&self) -> (Vec<PlayerStatus>, Option<u32>) {
+        let (mut players, own_id) = match &self.connection {
+            Some(connection) => (connection.scores.clone(), connection.player_id),
+            None => {
+                let Tally { kills, deaths, score } = self.tally;
+                let own = PlayerStatus { id: 0, username: self.username.clone(), kills, deaths, score, ping: None };
+                (vec![own], Some(0))
+            }
+        };
+        players.sort_by(|a, b| b.score.cmp(&a.score).then(b.kills.cmp(&a.kills)).then(a.username.cmp(&b.username)));
+        (players, own_id)
+    }
+
+    /// Everyone's kills, deaths, score and ping, while the scoreboard key is held.
+    fn draw_scoreboard(&self) {
+        let theme = self.current_theme.get_config();
+        let (players, own_id) = self.ranking();
+        let width = SCORE_TABLE_WIDTH + 40.0;
+        let height = 60.0 + score_table_height(players.len());
+        let left = screen_width() / 2.0 - width / 2.0;
+        let top = screen_height() / 2.0 - height / 2.0;
+        draw_rectangle(left, top, width, height, Color::from_rgba(0, 0, 0, 210));
+        draw_rectangle_lines(left, top, width, height, 2.0, theme.hud_primary);
+        let mut title = format!("SCOREBOARD - {}", self.life.mode().name().to_uppercase());
+        if let Some([red, blue]) = self.connection.as_ref().filter(|connection| connection.mode.has_teams()).map(|connection| connection.team_scores) {
+            title.push_str(&format!(" - RED {} : {} BLUE", red, blue));
+        }
+        draw_text(&title, left + 20.0, top + 35.0, 24.0, theme.text_primary);
+        draw_score_table(&players, own_id, |id| self.team_color_of(id), left + 20.0, top + 50.0, &theme);
+    }
+
+    /// A player's team colour, `None` outside the team modes.
+    fn team_color_of(&self, player_id: u32) -> Option<Color> {
+        let team = self.connection.as_ref()?.team_of(player_id)?;
+        Some(self.current_theme.get_team_color(team))
+    }
+
+    /// Recent kills in the top right corner, under the minimap when it's up.
+    fn draw_kill_feed(&self) {
+        let theme = self.current_theme.get_config();
+        let mut y = if self.show_minimap { 225.0 } else { 30.0 };
+        for (text, own, at) in &self.kill_feed {
+            let age = at.elapsed().as_secs_f32();
+            if age > FEED_SECONDS {
+                continue;
+            }
+            // Fades out over the last second
+            let alpha = (FEED_SECONDS - age).min(1.0);
+            let width = measure_text(text, None, 18, 1.0).width;
+            let x = screen_width() - width - 20.0;
+            draw_rectangle(x - 8.0, y - 18.0, width + 16.0, 25.0, Color::new(0.0, 0.0, 0.0, 0.5 * alpha));
+            let color = if *own { theme.hud_accent } else { theme.text_primary };
+            draw_text(text, x, y, 18.0, Color::new(color.r, color.g, color.b, alpha));
+            y += 30.0;
+        }
+    }
+
+    /// Who killed the player and the countdown to the respawn, or the game
+    /// over screen once the lives have run out.
+    fn draw_death_screen(&self) {
+        let (title, killer, detail) = match self.life.state() {
+            LifeState::Alive => return,
+            LifeState::Dead { killer, respawn } => {
+                let countdown = if *respawn > 0.0 {
+                    format!("RESPAWNING IN {:.0}s", respawn.ceil())
+                } else {
+                    "RESPAWNING...".to_string()
+                };
+                ("YOU DIED", killer, countdown)
+            }
+            LifeState::GameOver { killer } => ("GAME OVER", killer, format!("FINAL SCORE: {}", self.tally.score)),
+        };
+        let theme = self.current_theme.get_config();
+        draw_rectangle(0.0, 0.0, screen_width(), screen_height(), Color::from_rgba(60, 0, 0, 170));
+
+        let center_x = screen_width() / 2.0;
+        let mut y = screen_height() / 2.0 - 60.0;
+        let mut line = |text: &str, size: u16, color: Color, spacing: f32| {
+            let width = measure_text(text, None, size, 1.0).width;
+            draw_text(text, center_x - width / 2.0, y, size as f32, color);
+            y += spacing;
+        };
+        line(title, 48, RED, 45.0);
+        line(&format!("KILLED BY {}", killer.to_uppercase()), 24, theme.text_primary, 32.0);
+        line(&detail, 22, theme.hud_accent, 32.0);
+        match self.life.lives() {
+            Some(0) => line("ENTER = Restart | ESC = Menu", 18, theme.text_secondary, 0.0),
+            Some(lives) => line(&format!("LIVES LEFT: {}", lives), 18, theme.text_secondary, 0.0),
+            None => {
+                let points = ScoreEvent::Death.points(self.life.mode());
+                line(&format!("{} SCORE", points), 18, theme.text_secondary, 0.0);
+            }
+        }
+    }
+
     fn draw_crosshair(&self) {
         let center_x = screen_width() / 2.0;
         let center_y = screen_height() / 2.0;
@@ -672,8 +1510,9 @@ impl GameState {
         let theme = self.current_theme.get_config();
         
         // Themed animated crosshair with pulse effect
-        let alpha = if self.ammo > 0 { 200 } else { 100 };
-        let color = if self.ammo > 0 { 
+        let loaded = self.arsenal.loaded(self.arsenal.current()) > 0;
+        let alpha = if loaded { 200 } else { 100 };
+        let color = if loaded { 
             Color::from_rgba((theme.hud_accent.r * 255.0) as u8, (theme.hud_accent.g * 255.0) as u8, (theme.hud_accent.b * 255.0) as u8, alpha) 
         } else { 
             Color::from_rgba(255, 0, 0, alpha) 
@@ -687,32 +1526,20 @@ impl GameState {
         
         // Center dot
         draw_circle(center_x, center_y, 1.5, color);
-    }
 
-    fn cast_ray(&self, angle: f32) -> (f32, f32, f32) {
-        let mut distance = 0.0;
-        let step_size = 2.0;
-        let max_distance = CELL_SIZE * 20.0;
-        
-        while distance < max_distance {
-            let x = self.player_x + angle.cos() * distance;
-            let y = self.player_y + angle.sin() * distance;
-            
-            if self.is_wall(x, y) {
-                return (distance, x, y);
-            }
-            
-            distance += step_size;
+        if let Some(progress) = self.arsenal.reload_progress() {
+            let width = 80.0;
+            draw_text("RELOADING", center_x - 32.0, center_y + 40.0, 16.0, theme.text_primary);
+            draw_rectangle(center_x - width / 2.0, center_y + 46.0, width * progress, 5.0, theme.hud_accent);
+            draw_rectangle_lines(center_x - width / 2.0, center_y + 46.0, width, 5.0, 1.0, theme.hud_primary);
         }
-        
-        (max_distance, 0.0, 0.0)
     }
 
     fn draw_minimap(&self) {
         let map_size = 180.0;
         let map_x = screen_width() - map_size - 10.0;
         let map_y = 10.0;
-        let cell_size = map_size / MAZE_WIDTH as f32;
+        let view = MapView::fit(self.maze.width(), self.maze.height(), Rect::new(map_x, map_y, map_size, map_size));
         
         // Draw minimap background with enhanced styling
         draw_rectangle(map_x - 5.0, map_y - 25.0, map_size + 10.0, map_size + 30.0, Color::from_rgba(0, 0, 0, 180));
@@ -721,46 +1548,88 @@ impl GameState {
         draw_rectangle_lines(map_x, map_y, map_size, map_size, 2.0, BLUE);
         
         // Draw maze walls with better visibility
-        for y in 0..MAZE_HEIGHT {
-            for x in 0..MAZE_WIDTH {
-                if self.maze[y][x] {
-                    draw_rectangle(
-                        map_x + x as f32 * cell_size,
-                        map_y + y as f32 * cell_size,
-                        cell_size,
-                        cell_size,
-                        Color::from_rgba(100, 150, 255, 255),
-                    );
+        view.draw_walls(&self.maze, Color::from_rgba(100, 150, 255, 255));
+        
+        // Doors fade out as they open, switches show their state, one-way
+        // passages point the way they can be walked
+        for door in self.mechanisms.doors() {
+            let rect = view.cell_rect(door.x, door.y);
+            let alpha = (255.0 * (1.0 - door.open * 0.8)) as u8;
+            draw_rectangle(rect.x, rect.y, rect.w, rect.h, Color::from_rgba(255, 160, 40, alpha));
+        }
+        for switch in self.mechanisms.switches() {
+            let point = view.cell_point(switch.x, switch.y);
+            draw_circle(point.x, point.y, 2.5, if switch.on { GREEN } else { RED });
+        }
+        for item in self.pickups.items().iter().filter(|item| item.available()) {
+            let point = view.cell_point(item.x, item.y);
+            draw_rectangle(point.x - 2.0, point.y - 2.0, 4.0, 4.0, pickup_color(item.kind));
+        }
+
+        // Traps only once the player has come across them
+        let traps = self.mechanisms.traps();
+        for &(x, y) in &self.discovered_traps {
+            let Some(kind) = traps.at(x, y) else { continue };
+            let rect = view.cell_rect(x, y);
+            draw_rectangle(rect.x, rect.y, rect.w, rect.h, self.current_theme.get_trap_color(kind));
+            if let Some(to) = traps.destination(x, y) {
+                let (from, to) = (rect.center(), view.cell_rect(to.0, to.1).center());
+                draw_line(from.x, from.y, to.x, to.y, 1.0, self.current_theme.get_trap_color(kind));
+            }
+        }
+        for y in 0..self.maze.height() {
+            for x in 0..self.maze.width() {
+                if let Some(direction) = self.maze.one_way(x, y) {
+                    draw_one_way_arrow(view.cell_rect(x, y), direction);
                 }
             }
         }
-        
+
         // Draw exit with pulsing effect
-        let exit_map_x = map_x + (self.exit_x / CELL_SIZE) * cell_size;
-        let exit_map_y = map_y + (self.exit_y / CELL_SIZE) * cell_size;
         let pulse = (self.crosshair_pulse * 2.0).sin() * 0.3 + 0.7;
-        draw_rectangle(exit_map_x, exit_map_y, cell_size, cell_size, 
-                      Color::from_rgba((255.0 * pulse) as u8, 0, 0, 255));
+        view.draw_exit(&self.maze, Color::from_rgba((255.0 * pulse) as u8, 0, 0, 255));
         
         // Draw player position and direction
-        let player_map_x = map_x + (self.player_x / CELL_SIZE) * cell_size + cell_size / 2.0;
-        let player_map_y = map_y + (self.player_y / CELL_SIZE) * cell_size + cell_size / 2.0;
+        let Vec2 { x: player_map_x, y: player_map_y } = view.world_point(self.player_x, self.player_y);
         
-        // Player dot with glow effect
-        draw_circle(player_map_x, player_map_y, 6.0, Color::from_rgba(255, 255, 0, 100));
-        draw_circle(player_map_x, player_map_y, 4.0, YELLOW);
+        // Player dot with glow effect, in the team's colour in the team modes
+        let team = self.connection.as_ref().and_then(|connection| connection.team);
+        let marker = team.map_or(YELLOW, |team| self.current_theme.get_team_color(team));
+        draw_circle(player_map_x, player_map_y, 6.0, Color::new(marker.r, marker.g, marker.b, 0.4));
+        draw_circle(player_map_x, player_map_y, 4.0, marker);
         
         // Direction indicator
         let dir_length = 12.0;
         let end_x = player_map_x + self.player_angle.cos() * dir_length;
         let end_y = player_map_y + self.player_angle.sin() * dir_length;
-        draw_line(player_map_x, player_map_y, end_x, end_y, 3.0, Color::from_rgba(255, 255, 0, 200));
+        draw_line(player_map_x, player_map_y, end_x, end_y, 3.0, Color::new(marker.r, marker.g, marker.b, 0.8));
+
+        // Teammates, and in capture the flag the bases and flags
+        if let Some(connection) = &self.connection {
+            let teammates = connection.remote_players.iter().filter(|player| player.health > 0 && team.is_some() && player.team == team);
+            for player in teammates {
+                let point = view.world_point(player.x, player.y);
+                draw_circle(point.x, point.y, 3.0, marker);
+            }
+            if connection.mode.has_flags() {
+                for team in Team::ALL {
+                    let (x, y) = self.current_level.base(team);
+                    let point = view.world_point(x, y);
+                    draw_rectangle_lines(point.x - 4.0, point.y - 4.0, 8.0, 8.0, 1.0, self.current_theme.get_team_color(team));
+                }
+                for (team, x, y) in self.flag_positions() {
+                    let point = view.world_point(x, y);
+                    draw_line(point.x, point.y - 8.0, point.x, point.y + 1.0, 1.0, WHITE);
+                    let pennant = [vec2(point.x, point.y - 8.0), vec2(point.x + 6.0, point.y - 5.5), vec2(point.x, point.y - 3.0)];
+                    draw_triangle(pennant[0], pennant[1], pennant[2], self.current_theme.get_team_color(team));
+                }
+            }
+        }
         
         // Draw enemies on minimap
         for enemy in &self.enemies {
             if enemy.health > 0 {
-                let enemy_map_x = map_x + (enemy.x / CELL_SIZE) * cell_size + cell_size / 2.0;
-                let enemy_map_y = map_y + (enemy.y / CELL_SIZE) * cell_size + cell_size / 2.0;
+                let Vec2 { x: enemy_map_x, y: enemy_map_y } = view.world_point(enemy.x, enemy.y);
                 
                 // Enemy dot (red for hostile)
                 draw_circle(enemy_map_x, enemy_map_y, 3.0, RED);
@@ -774,141 +1643,413 @@ impl GameState {
         }
         
         // Enhanced minimap title
-        draw_text("TACTICAL MAP", map_x, map_y - 8.0, 16.0, Color::from_rgba(0, 255, 255, 255));
+        draw_text(self.current_theme.get_minimap_title(), map_x, map_y - 8.0, 16.0, Color::from_rgba(0, 255, 255, 255));
     }
     
+    /// Projects a world position onto the screen. Returns the screen column and
+    /// distance when the point is inside the field of view and not hidden
+    /// behind a wall.
+    fn project_sprite(&self, x: f32, y: f32, max_distance: f32) -> Option<(f32, f32)> {
+        let dx = x - self.player_x;
+        let dy = y - self.player_y;
+        let distance = (dx * dx + dy * dy).sqrt();
+        
+        if distance > max_distance || distance < 1.0 { return None; }
+        
+        // Check if the sprite is in field of view
+        let angle_to_sprite = dy.atan2(dx);
+        let normalized_angle = (angle_to_sprite - self.player_angle + PI).rem_euclid(2.0 * PI) - PI;
+        if normalized_angle.abs() >= FOV / 2.0 {
+            return None;
+        }
+        
+        // Check if the sprite is visible (not behind walls)
+        let steps = (distance / 5.0) as i32;
+        for i in 1..steps {
+            let check_x = self.player_x + (dx / distance) * (i as f32 * 5.0);
+            let check_y = self.player_y + (dy / distance) * (i as f32 * 5.0);
+            if self.is_wall(check_x, check_y) {
+                return None;
+            }
+        }
+        
+        let screen_x = screen_width() / 2.0 + (normalized_angle / FOV) * screen_width();
+        Some((screen_x, distance))
+    }
+
+    /// Traps as patches on the floor. Spikes stick up while raised and
+    /// teleporters glow.
+    fn draw_traps_3d(&self) {
+        let horizon = self.horizon();
+        let traps = self.mechanisms.traps();
+        for ((x, y), kind) in traps.cells() {
+            let centre = ((x as f32 + 0.5) * CELL_SIZE, (y as f32 + 0.5) * CELL_SIZE);
+            let Some((screen_x, distance)) = self.project_sprite(centre.0, centre.1, RENDER_DISTANCE) else {
+                continue;
+            };
+            let wall_height = (screen_height() * 0.6) / (distance / CELL_SIZE + 0.1);
+            let floor = horizon + wall_height / 2.0;
+            let width = wall_height * 0.45;
+            let color = self.current_theme.get_trap_color(kind);
+            draw_ellipse(screen_x, floor, width, wall_height * 0.06, 0.0, color);
+            match kind {
+                TrapKind::Spikes if traps.spikes_raised() => {
+                    let spike_height = wall_height * 0.25;
+                    for offset in [-0.6, 0.0, 0.6] {
+                        let base = screen_x + offset * width;
+                        let half = width * 0.15;
+                        draw_triangle(
+                            vec2(base - half, floor),
+                            vec2(base + half, floor),
+                            vec2(base, floor - spike_height),
+                            color,
+                        );
+                    }
+                }
+                TrapKind::Teleporter => {
+                    let pulse = (self.crosshair_pulse * 2.0).sin() * 0.25 + 0.5;
+                    let column = Color::new(color.r, color.g, color.b, pulse * 0.5);
+                    draw_rectangle(screen_x - width * 0.6, floor - wall_height, width * 1.2, wall_height, column);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Items that are there right now, floating and bobbing above the floor.
+    fn draw_pickups_3d(&self) {
+        let horizon = self.horizon();
+        let bob = (self.crosshair_pulse * 1.5).sin() * 0.05;
+        for item in self.pickups.items().iter().filter(|item| item.available()) {
+            let Some((screen_x, distance)) = self.project_sprite(item.x * CELL_SIZE, item.y * CELL_SIZE, RENDER_DISTANCE) else {
+                continue;
+            };
+            let wall_height = (screen_height() * 0.6) / (distance / CELL_SIZE + 0.1);
+            let size = wall_height * 0.18;
+            let centre_y = horizon + wall_height * (0.3 + bob);
+            let color = pickup_color(item.kind);
+            draw_circle(screen_x, centre_y, size * 0.9, Color::new(color.r, color.g, color.b, 0.25));
+            match item.kind {
+                PickupKind::Health => {
+                    draw_rectangle(screen_x - size / 2.0, centre_y - size / 2.0, size, size, WHITE);
+                    draw_rectangle(screen_x - size * 0.1, centre_y - size * 0.35, size * 0.2, size * 0.7, color);
+                    draw_rectangle(screen_x - size * 0.35, centre_y - size * 0.1, size * 0.7, size * 0.2, color);
+                }
+                PickupKind::Ammo => {
+                    draw_rectangle(screen_x - size / 2.0, centre_y - size * 0.3, size, size * 0.6, color);
+                    draw_rectangle_lines(screen_x - size / 2.0, centre_y - size * 0.3, size, size * 0.6, 1.0, DARKGRAY);
+                }
+                PickupKind::Speed | PickupKind::Damage => {
+                    draw_poly(screen_x, centre_y, 4, size * 0.5, 45.0, color);
+                }
+            }
+        }
+    }
+
+    /// Rockets in flight at eye height, and the fireballs where they went off.
+    fn draw_projectiles_3d(&self) {
+        let horizon = self.horizon();
+        for projectile in &self.projectiles {
+            let Some((screen_x, distance)) = self.project_sprite(projectile.x, projectile.y, RENDER_DISTANCE) else {
+                continue;
+            };
+            let wall_height = (screen_height() * 0.6) / (distance / CELL_SIZE + 0.1);
+            draw_circle(screen_x, horizon, wall_height * 0.05, ORANGE);
+            draw_circle(screen_x, horizon, wall_height * 0.025, YELLOW);
+        }
+        for &(x, y, seconds) in &self.explosions {
+            let Some((screen_x, distance)) = self.project_sprite(x, y, RENDER_DISTANCE) else {
+                continue;
+            };
+            let wall_height = (screen_height() * 0.6) / (distance / CELL_SIZE + 0.1);
+            // Grows while it fades
+            let left = seconds / EXPLOSION_SECONDS;
+            draw_circle(screen_x, horizon, wall_height * (0.5 - 0.25 * left), Color::new(1.0, 0.5, 0.1, left * 0.8));
+            draw_circle(screen_x, horizon, wall_height * 0.15 * left, Color::new(1.0, 0.9, 0.4, left));
+        }
+    }
+
+    /// Switches as posts standing on the floor, green when on.
+    fn draw_switches_3d(&self) {
+        let horizon = self.horizon();
+        for switch in self.mechanisms.switches() {
+            let Some((screen_x, distance)) = self.project_sprite(switch.x * CELL_SIZE, switch.y * CELL_SIZE, RENDER_DISTANCE) else {
+                continue;
+            };
+            // Same scale as the walls, so the post stands on the floor
+            let wall_height = (screen_height() * 0.6) / (distance / CELL_SIZE + 0.1);
+            let width = wall_height * 0.12;
+            let height = wall_height * 0.35;
+            let bottom = horizon + wall_height / 2.0;
+            let color = if switch.on { GREEN } else { RED };
+            draw_rectangle(screen_x - width / 2.0, bottom - height, width, height, DARKGRAY);
+            draw_circle(screen_x, bottom - height, width * 0.8, color);
+        }
+    }
+
     fn draw_enemies_3d(&self) {
-        let screen_width = screen_width();
-        let screen_height = screen_height();
+        let horizon = self.horizon();
         
         for enemy in &self.enemies {
             if enemy.health <= 0 { continue; }
             
-            // Calculate distance and angle to enemy
-            let dx = enemy.x - self.player_x;
-            let dy = enemy.y - self.player_y;
-            let distance = (dx * dx + dy * dy).sqrt();
+            // Don't render distant enemies
+            let Some((screen_x, distance)) = self.project_sprite(enemy.x, enemy.y, 500.0) else { continue };
             
-            if distance > 500.0 { continue; } // Don't render distant enemies
+            // Enemy size based on distance (closer = bigger)
+            let enemy_size = (30.0 / (distance / 100.0)).clamp(5.0, 50.0);
+            let enemy_y = horizon;
             
-            // Check if enemy is in field of view
-            let angle_to_enemy = dy.atan2(dx);
-            let angle_diff = angle_to_enemy - self.player_angle;
-            let normalized_angle = ((angle_diff + PI) % (2.0 * PI)) - PI;
+            // Draw enemy as an "eye" (classic Maze Wars style)
+            let eye_color = self.current_theme.get_enemy_color(&enemy.state);
             
-            if normalized_angle.abs() < FOV / 2.0 {
-                // Check if enemy is visible (not behind walls)
-                let steps = (distance / 5.0) as i32;
-                let mut visible = true;
-                
-                for i in 1..steps {
-                    let check_x = self.player_x + (dx / distance) * (i as f32 * 5.0);
-                    let check_y = self.player_y + (dy / distance) * (i as f32 * 5.0);
-                    if self.is_wall(check_x, check_y) {
-                        visible = false;
-                        break;
+            // Draw enemy eye
+            draw_circle(screen_x, enemy_y, enemy_size / 2.0, eye_color);
+            draw_circle(screen_x, enemy_y, enemy_size / 4.0, BLACK); // Pupil
+            
+            // Health bar above enemy
+            let health_ratio = enemy.health as f32 / enemy.kind.health() as f32;
+            let bar_width = enemy_size;
+            let bar_height = 4.0;
+            let bar_y = enemy_y - enemy_size / 2.0 - 10.0;
+            
+            draw_rectangle(screen_x - bar_width / 2.0, bar_y, bar_width, bar_height, Color::from_rgba(100, 0, 0, 200));
+            draw_rectangle(screen_x - bar_width / 2.0, bar_y, bar_width * health_ratio, bar_height, 
+                         if health_ratio > 0.5 { GREEN } else { RED });
+        }
+    }
+
+    /// Where each flag is in capture the flag, in world units: at its base,
+    /// where it was dropped or with whoever carries it.
+    fn flag_positions(&self) -> Vec<(Team, f32, f32)> {
+        let Some(connection) = &self.connection else { return Vec::new() };
+        connection
+            .flags
+            .iter()
+            .filter_map(|&(team, state)| {
+                let (x, y) = match state {
+                    FlagState::AtBase => self.current_level.base(team),
+                    FlagState::Dropped { x, y, .. } => (x, y),
+                    FlagState::Carried { player_id } if Some(player_id) == connection.player_id => (self.player_x, self.player_y),
+                    FlagState::Carried { player_id } => {
+                        let carrier = connection.remote_players.iter().find(|player| player.id == player_id)?;
+                        (carrier.x, carrier.y)
                     }
-                }
-                
-                if visible {
-                    // Calculate screen position
-                    let screen_x = screen_width / 2.0 + (normalized_angle / FOV) * screen_width;
-                    
-                    // Enemy size based on distance (closer = bigger)
-                    let enemy_size = (30.0 / (distance / 100.0)).min(50.0).max(5.0);
-                    let enemy_y = screen_height / 2.0;
-                    
-                    // Draw enemy as an "eye" (classic Maze Wars style)
-                    let eye_color = match enemy.state {
-                        EnemyState::Patrolling => Color::from_rgba(255, 255, 0, 200), // Yellow
-                        EnemyState::Chasing => Color::from_rgba(255, 100, 0, 255),    // Orange
-                        EnemyState::Attacking => Color::from_rgba(255, 0, 0, 255),   // Red
-                    };
-                    
-                    // Draw enemy eye
-                    draw_circle(screen_x, enemy_y, enemy_size / 2.0, eye_color);
-                    draw_circle(screen_x, enemy_y, enemy_size / 4.0, BLACK); // Pupil
-                    
-                    // Health bar above enemy
-                    let health_ratio = enemy.health as f32 / 50.0;
-                    let bar_width = enemy_size;
-                    let bar_height = 4.0;
-                    let bar_y = enemy_y - enemy_size / 2.0 - 10.0;
-                    
-                    draw_rectangle(screen_x - bar_width / 2.0, bar_y, bar_width, bar_height, Color::from_rgba(100, 0, 0, 200));
-                    draw_rectangle(screen_x - bar_width / 2.0, bar_y, bar_width * health_ratio, bar_height, 
-                                 if health_ratio > 0.5 { GREEN } else { RED });
-                }
-            }
+                };
+                Some((team, x, y))
+            })
+            .collect()
+    }
+
+    /// The team that has the flag the local player carries, if they do.
+    fn carried_flag(&self) -> Option<Team> {
+        let connection = self.connection.as_ref()?;
+        let player_id = connection.player_id?;
+        let carried = FlagState::Carried { player_id };
+        connection.flags.iter().find(|(_, state)| *state == carried).map(|&(team, _)| team)
+    }
+
+    /// Flag bases as rings on the floor and the flags as pennants on a pole,
+    /// in their team's colour.
+    fn draw_flags_3d(&self) {
+        if !self.connection.as_ref().is_some_and(|connection| connection.mode.has_flags()) {
+            return;
+        }
+        let horizon = self.horizon();
+        let wall_height = |distance: f32| (screen_height() * 0.6) / (distance / CELL_SIZE + 0.1);
+        for team in Team::ALL {
+            let (x, y) = self.current_level.base(team);
+            let Some((screen_x, distance)) = self.project_sprite(x, y, RENDER_DISTANCE) else { continue };
+            let wall_height = wall_height(distance);
+            let color = self.current_theme.get_team_color(team);
+            draw_ellipse_lines(screen_x, horizon + wall_height / 2.0, wall_height * 0.4, wall_height * 0.06, 0.0, 2.0, color);
+        }
+        let wave = (self.crosshair_pulse * 3.0).sin() * 0.05;
+        for (team, x, y) in self.flag_positions() {
+            let Some((screen_x, distance)) = self.project_sprite(x, y, RENDER_DISTANCE) else { continue };
+            let wall_height = wall_height(distance);
+            let floor = horizon + wall_height / 2.0;
+            let top = floor - wall_height * 0.6;
+            let pole = (wall_height * 0.02).max(1.0);
+            draw_rectangle(screen_x - pole / 2.0, top, pole, floor - top, LIGHTGRAY);
+            let tip = vec2(screen_x + wall_height * (0.3 + wave), top + wall_height * 0.08);
+            draw_triangle(vec2(screen_x, top), tip, vec2(screen_x, top + wall_height * 0.16), self.current_theme.get_team_color(team));
+        }
+    }
+
+    fn draw_remote_players_3d(&self) {
+        let Some(connection) = &self.connection else { return };
+        let horizon = self.horizon();
+        let theme = self.current_theme.get_config();
+        
+        // The dead aren't drawn until they respawn
+        for player in connection.remote_players.iter().filter(|player| player.health > 0) {
+            let Some((screen_x, distance)) = self.project_sprite(player.x, player.y, RENDER_DISTANCE) else { continue };
+            // In the team modes the glow and iris show the player's team
+            let team_color = player.team.map(|team| self.current_theme.get_team_color(team));
+            let glow = team_color.map_or(theme.glow_color, |color| Color::new(color.r, color.g, color.b, 0.5));
+            let iris = team_color.unwrap_or(theme.hud_primary);
+            
+            let eye_size = (40.0 / (distance / 100.0)).clamp(6.0, 80.0);
+            let radius = eye_size / 2.0;
+            
+            // Pupil follows where the other player is looking: sideways relative
+            // to our view and up/down with their pitch.
+            let right = self.player_angle + PI / 2.0;
+            let look_x = (player.angle - right).cos();
+            let look_y = -(player.pitch / shared::MAX_PITCH);
+            let pupil_x = screen_x + look_x * radius * 0.5;
+            let pupil_y = horizon + look_y * radius * 0.5;
+            
+            // Eyeball with a themed glow, iris and pupil
+            draw_circle(screen_x, horizon, radius * 1.2, glow);
+            draw_circle(screen_x, horizon, radius, WHITE);
+            draw_circle(pupil_x, pupil_y, radius * 0.45, iris);
+            draw_circle(pupil_x, pupil_y, radius * 0.2, BLACK);
+            
+            // Name tag
+            let name_width = measure_text(&player.username, None, 16, 1.0).width;
+            draw_text(&player.username, screen_x - name_width / 2.0, horizon - radius * 1.2 - 6.0, 16.0, theme.text_primary);
         }
     }
 }
 
-fn get_user_input() -> Result<(String, String), Box<dyn std::error::Error>> {
-    println!("=== Multiplayer FPS Client ===");
-    
-    // Prompt for server IP
-    print!("Enter IP Address: ");
-    io::stdout().flush().unwrap();
-    let mut ip_input = String::new();
-    io::stdin().read_line(&mut ip_input).unwrap();
-    let server_ip = ip_input.trim();
-    
-    // Default to localhost if empty
-    let server_addr = if server_ip.is_empty() {
-        "127.0.0.1:34254".to_string()
-    } else {
-        server_ip.to_string()
+const SCORE_TABLE_WIDTH: f32 = 460.0;
+const SCORE_ROW_HEIGHT: f32 = 24.0;
+
+fn score_table_height(rows: usize) -> f32 {
+    (rows + 1) as f32 * SCORE_ROW_HEIGHT + 10.0
+}
+
+/// Header and one row per player, the local player highlighted. In the
+/// team modes a bar in front of each row shows the player's team.
+fn draw_score_table(
+    players: &[PlayerStatus],
+    own_id: Option<u32>,
+    team_color: impl Fn(u32) -> Option<Color>,
+    left: f32,
+    top: f32,
+    theme: &themes::ThemeConfig,
+) {
+    let columns = [0.0, 230.0, 300.0, 380.0, SCORE_TABLE_WIDTH - 50.0];
+    let row = |y: f32, cells: [String; 5], color: Color| {
+        for (cell, x) in cells.iter().zip(columns) {
+            draw_text(cell, left + x, y, 18.0, color);
+        }
     };
-    
-    // Prompt for username
-    print!("Enter Name: ");
-    io::stdout().flush().unwrap();
-    let mut name_input = String::new();
-    io::stdin().read_line(&mut name_input).unwrap();
-    let username = name_input.trim().to_string();
-    
-    println!("Starting...");
-    println!("Connecting to server: {}", server_addr);
-    println!("Username: {}", username);
-    
-    Ok((username, server_addr))
+    let header = ["NAME", "KILLS", "DEATHS", "SCORE", "PING"].map(String::from);
+    row(top + SCORE_ROW_HEIGHT, header, theme.text_secondary);
+    for (index, player) in players.iter().enumerate() {
+        let y = top + (index + 2) as f32 * SCORE_ROW_HEIGHT;
+        let own = Some(player.id) == own_id;
+        if own {
+            draw_rectangle(left - 6.0, y - 17.0, SCORE_TABLE_WIDTH + 12.0, SCORE_ROW_HEIGHT - 2.0, Color::from_rgba(255, 255, 255, 40));
+        }
+        if let Some(color) = team_color(player.id) {
+            draw_rectangle(left - 12.0, y - 17.0, 4.0, SCORE_ROW_HEIGHT - 2.0, color);
+        }
+        let ping = player.ping.map_or("-".to_string(), |ping| ping.to_string());
+        let cells = [
+            format!("{}. {}", index + 1, player.username),
+            player.kills.to_string(),
+            player.deaths.to_string(),
+            player.score.to_string(),
+            ping,
+        ];
+        row(y, cells, if own { theme.hud_accent } else { theme.text_primary });
+    }
+}
+
+/// Small arrow across a minimap cell pointing the way a one-way cell can be walked.
+fn draw_one_way_arrow(rect: Rect, direction: Direction) {
+    let (dx, dy) = direction.offset();
+    let along = vec2(dx as f32, dy as f32) * rect.w * 0.35;
+    let across = vec2(-along.y, along.x) * 0.6;
+    let centre = rect.center();
+    let color = Color::from_rgba(120, 255, 120, 220);
+    draw_line(centre.x - along.x, centre.y - along.y, centre.x + along.x, centre.y + along.y, 1.0, color);
+    let tip = centre + along;
+    let back = centre + along * 0.2;
+    draw_line(tip.x, tip.y, back.x + across.x, back.y + across.y, 1.0, color);
+    draw_line(tip.x, tip.y, back.x - across.x, back.y - across.y, 1.0, color);
+}
+
+/// Captures the mouse for looking around, or releases it for menus.
+fn grab_cursor(grab: bool) {
+    set_cursor_grab(grab);
+    show_mouse(!grab);
 }
 
 fn window_conf() -> Conf {
+    let options = Options::from_env();
+    let (window_width, window_height) = options.resolution.unwrap_or((800, 600));
     Conf {
         window_title: "Multiplayer FPS".to_owned(),
-        window_width: 800,
-        window_height: 600,
+        window_width,
+        window_height,
+        fullscreen: options.fullscreen.unwrap_or(false),
         ..Default::default()
     }
 }
 
 #[macroquad::main(window_conf)]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let (username, server_addr) = get_user_input()?;
-    
-    // Try to connect to server
-    match UdpSocket::bind("0.0.0.0:0") {
-        Ok(socket) => {
-            if let Ok(_) = socket.connect(&server_addr) {
-                let connect_msg = format!("CONNECT:{}", username);
-                let _ = socket.send(connect_msg.as_bytes());
-                println!("Connected to server!");
-            } else {
-                println!("Warning: Could not connect to server, running in offline mode");
+    let options = Options::from_env();
+    if !options.aliases.is_empty() {
+        let mut hosts = Hosts::load();
+        for (alias, address) in &options.aliases {
+            if let Err(error) = hosts.set_alias(alias, address) {
+                eprintln!("Error: --alias: {}", error);
+                std::process::exit(2);
             }
+            println!("Saved {} as {}", address, alias);
         }
-        Err(_) => {
-            println!("Warning: Could not create socket, running in offline mode");
+        hosts.save()?;
+    }
+
+    // Straight into the game offline, otherwise through the main menu,
+    // which does the connecting
+    let (username, server_addr, connection) = if options.offline {
+        let name = options.name.clone().or_else(|| Some(Hosts::load().last_name).filter(|name| !name.is_empty()));
+        (name.unwrap_or_else(|| "Player".to_string()), String::new(), None)
+    } else {
+        let mut menu = MainMenu::new(&options);
+        loop {
+            match menu.update() {
+                Some(MenuAction::Join { username, address, connection }) => break (username, address, Some(*connection)),
+                Some(MenuAction::Offline { username }) => break (username, String::new(), None),
+                Some(MenuAction::Quit) => return Ok(()),
+                None => {}
+            }
+            menu.draw();
+            next_frame().await;
         }
+    };
+    match &connection {
+        Some(_) => println!("Joined {} as {}", server_addr, username),
+        None => println!("Playing offline as {}", username),
     }
 
-    let mut game_state = GameState::new(username, server_addr);
+    let mut game_state = GameState::new(username, server_addr, connection);
+    if let Some(level) = &options.level {
+        let loaded = LevelSource::from_arg(level, game_state.seed)
+            .ok_or_else(|| format!("'{}' is not a level", level))
+            .and_then(|source| Level::from_source(&source).map(|level| (source, level)).map_err(|error| error.to_string()));
+        match loaded {
+            Ok((source, level)) => game_state.start_level(&source, level),
+            Err(error) => {
+                eprintln!("Error: can't load level {}: {}", level, error);
+                std::process::exit(2);
+            }
+        }
+    }
+    game_state.set_paused(false);
 
-    loop {
+    while !game_state.quit_requested {
         game_state.update();
         game_state.draw();
         next_frame().await;
     }
-}
\ No newline at end of file
+
+    Ok(())
+}
@@ -0,0 +1,576 @@
+use std::net::SocketAddr;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use macroquad::prelude::*;
+use shared::address::{self, AddressError};
+use shared::discovery::Discovery;
+use shared::protocol::{validate_username, ClientMessage, MAX_USERNAME_LEN};
+use shared::DEFAULT_PORT;
+
+use crate::cli::Options;
+use crate::hosts::{self, Hosts};
+use crate::input::Input;
+use crate::net::Connection;
+use crate::rebind::RebindMenu;
+use crate::settings::Settings;
+use crate::themes::{LevelTheme, ThemeConfig};
+
+const ROW_WIDTH: f32 = 480.0;
+const ROW_HEIGHT: f32 = 30.0;
+const ROW_SPACING: f32 = 6.0;
+
+/// Servers found on the network listed under the connect button.
+const LAN_SHOWN: usize = 3;
+/// Recent servers listed after those.
+const RECENT_SHOWN: usize = 3;
+const MAX_ADDRESS_LEN: usize = 64;
+
+/// How often to ask again while a server hasn't answered.
+const CONNECT_RETRY: Duration = Duration::from_millis(500);
+/// How long to wait for a server before giving up on it.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Name used offline when none is given.
+const OFFLINE_NAME: &str = "Player";
+
+#[derive(Clone, Copy, PartialEq)]
+enum MenuItem {
+    Address,
+    Name,
+    Connect,
+    /// Index into the servers found on the network.
+    Lan(usize),
+    /// Index into the server history.
+    Recent(usize),
+    Offline,
+    Settings,
+    Quit,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SettingsItem {
+    Sensitivity,
+    Controls,
+    Back,
+}
+
+const SETTINGS_ITEMS: [SettingsItem; 3] = [SettingsItem::Sensitivity, SettingsItem::Controls, SettingsItem::Back];
+
+enum Screen {
+    Main,
+    Settings { selected: usize },
+    Controls(RebindMenu),
+}
+
+/// What the game should do once the player leaves the main menu.
+pub enum MenuAction {
+    /// A server accepted the player.
+    Join { username: String, address: String, connection: Box<Connection> },
+    Offline { username: String },
+    Quit,
+}
+
+/// A server asked to let us in that hasn't answered yet.
+struct Pending {
+    address: String,
+    username: String,
+    /// Where the address leads, looked up on its own thread since that can
+    /// take a while.
+    lookup: Receiver<Result<SocketAddr, AddressError>>,
+    /// `None` until the lookup is done.
+    connection: Option<Connection>,
+    started: Instant,
+    last_sent: Instant,
+}
+
+/// First screen of the client: where to connect and under what name, or
+/// practice offline. Connecting happens here so a refusal or a server that
+/// isn't there can be shown and corrected without restarting.
+pub struct MainMenu {
+    screen: Screen,
+    selected: usize,
+    address: String,
+    name: String,
+    hosts: Hosts,
+    /// Looks for servers on the network while the menu is open.
+    discovery: Option<Discovery>,
+    settings: Settings,
+    input: Input,
+    pending: Option<Pending>,
+    /// Why the last attempt failed, shown until the next one.
+    error: Option<String>,
+    /// Something that worked, like saving an alias.
+    notice: Option<String>,
+}
+
+impl MainMenu {
+    /// Fills in what the command line gave, and connects straight away when
+    /// it gave both a server and a name.
+    pub fn new(options: &Options) -> Self {
+        let hosts = Hosts::load();
+        let mut menu = Self {
+            screen: Screen::Main,
+            selected: 0,
+            address: options.server.clone().unwrap_or_default(),
+            name: options.name.clone().unwrap_or_else(|| hosts.last_name.clone()),
+            hosts,
+            discovery: Discovery::lan()
+                .map_err(|e| eprintln!("Warning: can't search the network for servers: {}", e))
+                .ok(),
+            settings: Settings::load(),
+            input: Input::load(),
+            pending: None,
+            error: None,
+            notice: None,
+        };
+        let item = match (options.server.is_some(), menu.name.is_empty()) {
+            (_, true) => MenuItem::Name,
+            (false, false) => MenuItem::Address,
+            (true, false) => MenuItem::Connect,
+        };
+        menu.selected = menu.items().iter().position(|&i| i == item).unwrap_or(0);
+        if options.server.is_some() && options.name.is_some() {
+            menu.connect();
+        }
+        menu
+    }
+
+    fn items(&self) -> Vec<MenuItem> {
+        let mut items = vec![MenuItem::Address, MenuItem::Name, MenuItem::Connect];
+        let lan = self.discovery.as_ref().map_or(0, |discovery| discovery.servers().len());
+        items.extend((0..lan.min(LAN_SHOWN)).map(MenuItem::Lan));
+        items.extend((0..self.hosts.history.len().min(RECENT_SHOWN)).map(MenuItem::Recent));
+        items.extend([MenuItem::Offline, MenuItem::Settings, MenuItem::Quit]);
+        items
+    }
+
+    fn item_rect(index: usize, count: usize) -> Rect {
+        let total_height = count as f32 * (ROW_HEIGHT + ROW_SPACING);
+        let top = (screen_height() / 2.0 - total_height / 2.0).max(90.0);
+        Rect::new(
+            screen_width() / 2.0 - ROW_WIDTH / 2.0,
+            top + index as f32 * (ROW_HEIGHT + ROW_SPACING),
+            ROW_WIDTH,
+            ROW_HEIGHT,
+        )
+    }
+
+    /// Moves the selection with the arrow keys and Tab, and the mouse.
+    /// Returns whether the selected row was clicked.
+    fn navigate(selected: &mut usize, count: usize) -> bool {
+        let back = is_key_pressed(KeyCode::Up)
+            || (is_key_pressed(KeyCode::Tab) && (is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift)));
+        if back {
+            *selected = (*selected + count - 1) % count;
+        } else if is_key_pressed(KeyCode::Down) || is_key_pressed(KeyCode::Tab) {
+            *selected = (*selected + 1) % count;
+        }
+
+        // Mouse hover selects, clicking activates
+        let mouse = Vec2::from(mouse_position());
+        let mut clicked = false;
+        for index in 0..count {
+            if Self::item_rect(index, count).contains(mouse) {
+                *selected = index;
+                clicked = is_mouse_button_pressed(MouseButton::Left);
+            }
+        }
+        clicked
+    }
+
+    pub fn update(&mut self) -> Option<MenuAction> {
+        self.input.poll();
+        // Characters queue up until read, and come out newest first
+        let mut typed = Vec::new();
+        while let Some(c) = get_char_pressed() {
+            typed.push(c);
+        }
+        typed.reverse();
+
+        if let Screen::Controls(rebind) = &mut self.screen {
+            if rebind.update(&mut self.input) {
+                if let Err(e) = self.input.save() {
+                    eprintln!("Warning: could not save controls: {}", e);
+                }
+                self.screen = Screen::Settings { selected: 1 };
+            }
+            return None;
+        }
+        if let Screen::Settings { selected } = self.screen {
+            self.update_settings(selected);
+            return None;
+        }
+        if let Some(discovery) = &mut self.discovery {
+            discovery.update();
+        }
+        if self.pending.is_some() {
+            return self.update_pending();
+        }
+        self.update_main(&typed)
+    }
+
+    fn update_main(&mut self, typed: &[char]) -> Option<MenuAction> {
+        let items = self.items();
+        self.selected = self.selected.min(items.len() - 1);
+        let clicked = Self::navigate(&mut self.selected, items.len());
+        let item = items[self.selected];
+
+        // Typing goes to the selected field
+        let field = match item {
+            MenuItem::Address => Some((&mut self.address, MAX_ADDRESS_LEN)),
+            MenuItem::Name => Some((&mut self.name, MAX_USERNAME_LEN)),
+            _ => None,
+        };
+        if let Some((text, max_len)) = field {
+            for &c in typed {
+                if !c.is_control() && text.chars().count() < max_len {
+                    text.push(c);
+                }
+            }
+            if is_key_pressed(KeyCode::Backspace) {
+                text.pop();
+            }
+        }
+
+        // Delete forgets a server from the history
+        if let MenuItem::Recent(index) = item {
+            if is_key_pressed(KeyCode::Delete) {
+                self.hosts.forget(index);
+                self.save_hosts();
+                return None;
+            }
+        }
+
+        // Clicking a field only puts the cursor in it
+        let is_field = matches!(item, MenuItem::Address | MenuItem::Name);
+        if !(is_key_pressed(KeyCode::Enter) || (clicked && !is_field)) {
+            return None;
+        }
+        match item {
+            // `office=10.0.0.5` in the address field saves an alias
+            MenuItem::Address if self.address.contains('=') => self.save_alias(),
+            MenuItem::Address | MenuItem::Name | MenuItem::Connect => self.connect(),
+            MenuItem::Lan(index) => {
+                if let Some(server) = self.discovery.as_ref().and_then(|discovery| discovery.servers().get(index)) {
+                    self.address = server.address.to_string();
+                    self.connect();
+                }
+            }
+            MenuItem::Recent(index) => {
+                let entry = &self.hosts.history[index];
+                self.address = entry.address.clone();
+                if !entry.name.is_empty() {
+                    self.name = entry.name.clone();
+                }
+                self.connect();
+            }
+            MenuItem::Offline => {
+                let username = if self.name.trim().is_empty() {
+                    OFFLINE_NAME.to_string()
+                } else {
+                    match validate_username(&self.name) {
+                        Ok(name) => name.to_string(),
+                        Err(error) => {
+                            self.error = Some(capitalize(&error.to_string()));
+                            return None;
+                        }
+                    }
+                };
+                return Some(MenuAction::Offline { username });
+            }
+            MenuItem::Settings => self.screen = Screen::Settings { selected: 0 },
+            MenuItem::Quit => return Some(MenuAction::Quit),
+        }
+        None
+    }
+
+    fn update_settings(&mut self, mut selected: usize) {
+        let clicked = Self::navigate(&mut selected, SETTINGS_ITEMS.len());
+        self.screen = Screen::Settings { selected };
+        let item = SETTINGS_ITEMS[selected];
+
+        if item == SettingsItem::Sensitivity {
+            if is_key_pressed(KeyCode::Left) {
+                self.settings.adjust_sensitivity(-1);
+            }
+            if is_key_pressed(KeyCode::Right) {
+                self.settings.adjust_sensitivity(1);
+            }
+            if clicked {
+                // Left half lowers, right half raises
+                let rect = Self::item_rect(selected, SETTINGS_ITEMS.len());
+                let steps = if mouse_position().0 < rect.x + rect.w / 2.0 { -1 } else { 1 };
+                self.settings.adjust_sensitivity(steps);
+            }
+        }
+
+        let leave = is_key_pressed(KeyCode::Escape)
+            || ((clicked || is_key_pressed(KeyCode::Enter)) && item == SettingsItem::Back);
+        if leave {
+            if let Err(e) = self.settings.save() {
+                eprintln!("Warning: could not save settings: {}", e);
+            }
+            self.screen = Screen::Main;
+        } else if (clicked || is_key_pressed(KeyCode::Enter)) && item == SettingsItem::Controls {
+            self.screen = Screen::Controls(RebindMenu::new());
+        }
+    }
+
+    fn save_hosts(&self) {
+        if let Err(e) = self.hosts.save() {
+            eprintln!("Warning: could not save server history: {}", e);
+        }
+    }
+
+    fn save_alias(&mut self) {
+        let (alias, target) = self.address.split_once('=').unwrap_or_default();
+        let (alias, target) = (alias.trim().to_string(), target.trim().to_string());
+        match self.hosts.set_alias(&alias, &target) {
+            Ok(()) => {
+                self.save_hosts();
+                self.error = None;
+                self.notice = Some(format!("Saved {} as {}", target, alias));
+                self.address = alias;
+            }
+            Err(error) => self.error = Some(capitalize(&error)),
+        }
+    }
+
+    /// Records a failed attempt and says why it failed.
+    fn fail(&mut self, address: &str, username: &str, error: String) {
+        self.hosts.record(address, username, false);
+        self.save_hosts();
+        self.error = Some(error);
+    }
+
+    /// Checks the fields and asks the server to let us in.
+    fn connect(&mut self) {
+        self.error = None;
+        self.notice = None;
+        let username = match validate_username(&self.name) {
+            Ok(name) => name.to_string(),
+            Err(error) => {
+                self.error = Some(capitalize(&error.to_string()));
+                return;
+            }
+        };
+        let address = match self.address.trim() {
+            "" => "127.0.0.1".to_string(),
+            typed => typed.to_string(),
+        };
+        let (sender, lookup) = mpsc::channel();
+        let target = self.hosts.expand(&address).to_string();
+        // Nobody listens any more if the player gave up waiting
+        thread::spawn(move || sender.send(address::resolve(&target)));
+        let now = Instant::now();
+        self.pending = Some(Pending { address, username, lookup, connection: None, started: now, last_sent: now });
+    }
+
+    /// Connects once the address is looked up.
+    fn update_lookup(&mut self) {
+        let Some(pending) = self.pending.as_mut() else { return };
+        let error = match pending.lookup.try_recv() {
+            Ok(Ok(resolved)) => match Connection::connect(resolved, &pending.username) {
+                Ok(connection) => {
+                    pending.connection = Some(connection);
+                    // The server gets its full time to answer
+                    pending.started = Instant::now();
+                    pending.last_sent = pending.started;
+                    return;
+                }
+                Err(error) => format!("Can't reach {}: {}", pending.address, error),
+            },
+            Ok(Err(error)) => capitalize(&error.to_string()),
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => format!("Can't look up {}", pending.address),
+        };
+        if let Some(pending) = self.pending.take() {
+            self.fail(&pending.address, &pending.username, error);
+        }
+    }
+
+    /// Waits for the server to answer, asking again now and then since the
+    /// request may have been lost.
+    fn update_pending(&mut self) -> Option<MenuAction> {
+        if is_key_pressed(KeyCode::Escape) {
+            self.pending = None;
+            return None;
+        }
+        let pending = self.pending.as_mut()?;
+        let Some(connection) = pending.connection.as_mut() else {
+            self.update_lookup();
+            return None;
+        };
+        connection.poll();
+        if let Some(reason) = connection.rejected.take() {
+            let pending = self.pending.take()?;
+            let error = format!("{} refused to let you in: {}", pending.address, reason);
+            self.fail(&pending.address, &pending.username, error);
+        } else if connection.player_id.is_some() {
+            let pending = self.pending.take()?;
+            self.hosts.record(&pending.address, &pending.username, true);
+            self.save_hosts();
+            return Some(MenuAction::Join {
+                username: pending.username,
+                address: pending.address,
+                connection: Box::new(pending.connection?),
+            });
+        } else if pending.started.elapsed() >= CONNECT_TIMEOUT {
+            let pending = self.pending.take()?;
+            let error = format!("No answer from {}. Is the server running?", pending.address);
+            self.fail(&pending.address, &pending.username, error);
+        } else if pending.last_sent.elapsed() >= CONNECT_RETRY {
+            pending.last_sent = Instant::now();
+            let _ = connection.send(&ClientMessage::Connect { username: pending.username.clone() });
+        }
+        None
+    }
+
+    pub fn draw(&self) {
+        let theme = LevelTheme::CandyMaze.get_config();
+        clear_background(Color::from_rgba(12, 10, 24, 255));
+
+        match &self.screen {
+            Screen::Main => self.draw_main(&theme),
+            Screen::Settings { selected } => self.draw_settings(*selected, &theme),
+            Screen::Controls(rebind) => rebind.draw(&self.input, &theme),
+        }
+    }
+
+    fn draw_row(rect: Rect, selected: bool, theme: &ThemeConfig) {
+        let background = if selected { Color::from_rgba(255, 255, 255, 60) } else { Color::from_rgba(0, 0, 0, 180) };
+        draw_rectangle(rect.x, rect.y, rect.w, rect.h, background);
+        draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 2.0, theme.hud_primary);
+    }
+
+    fn draw_centered(text: &str, y: f32, size: u16, color: Color) {
+        let width = measure_text(text, None, size, 1.0).width;
+        draw_text(text, screen_width() / 2.0 - width / 2.0, y, size as f32, color);
+    }
+
+    fn draw_main(&self, theme: &ThemeConfig) {
+        let items = self.items();
+        let count = items.len();
+        Self::draw_centered("MULTIPLAYER FPS", Self::item_rect(0, count).y - 30.0, 40, theme.hud_accent);
+
+        for (index, item) in items.iter().enumerate() {
+            let rect = Self::item_rect(index, count);
+            let selected = index == self.selected;
+            Self::draw_row(rect, selected, theme);
+            let text_y = rect.y + rect.h / 2.0 + 7.0;
+
+            let (label, value, placeholder) = match item {
+                MenuItem::Address => ("SERVER", &self.address, format!("127.0.0.1:{}", DEFAULT_PORT)),
+                MenuItem::Name => ("NAME", &self.name, format!("up to {} characters", MAX_USERNAME_LEN)),
+                MenuItem::Lan(index) => {
+                    self.draw_lan_server(*index, rect, theme);
+                    continue;
+                }
+                MenuItem::Recent(index) => {
+                    self.draw_history_entry(*index, rect, theme);
+                    continue;
+                }
+                _ => {
+                    let label = match item {
+                        MenuItem::Connect => "CONNECT".to_string(),
+                        MenuItem::Offline => "PRACTICE OFFLINE".to_string(),
+                        MenuItem::Settings => "SETTINGS".to_string(),
+                        MenuItem::Quit => "QUIT".to_string(),
+                        MenuItem::Address | MenuItem::Name | MenuItem::Lan(_) | MenuItem::Recent(_) => unreachable!(),
+                    };
+                    let label_width = measure_text(&label, None, 22, 1.0).width;
+                    draw_text(&label, rect.x + rect.w / 2.0 - label_width / 2.0, text_y, 22.0, theme.text_primary);
+                    continue;
+                }
+            };
+            draw_text(label, rect.x + 12.0, text_y, 20.0, theme.text_secondary);
+            let value_x = rect.x + 90.0;
+            if value.is_empty() && !selected {
+                draw_text(&placeholder, value_x, text_y, 20.0, Color::from_rgba(255, 255, 255, 90));
+            } else {
+                draw_text(value, value_x, text_y, 20.0, theme.text_primary);
+            }
+            // Blinking cursor in the field being typed into
+            if selected && self.pending.is_none() && (get_time() * 2.0) as i64 % 2 == 0 {
+                let cursor_x = value_x + measure_text(value, None, 20, 1.0).width + 2.0;
+                draw_line(cursor_x, rect.y + 8.0, cursor_x, rect.y + rect.h - 8.0, 2.0, theme.text_primary);
+            }
+        }
+
+        let status_y = Self::item_rect(count - 1, count).y + ROW_HEIGHT + 30.0;
+        if let Some(pending) = &self.pending {
+            let dots = ".".repeat(1 + (pending.started.elapsed().as_millis() / 400 % 3) as usize);
+            let stage = if pending.connection.is_some() { "Connecting to" } else { "Looking up" };
+            let text = format!("{} {}{}  (ESC to cancel)", stage, pending.address, dots);
+            Self::draw_centered(&text, status_y, 20, theme.text_primary);
+        } else if let Some(error) = &self.error {
+            Self::draw_centered(error, status_y, 20, Color::from_rgba(255, 90, 90, 255));
+        } else if let Some(notice) = &self.notice {
+            Self::draw_centered(notice, status_y, 20, theme.text_primary);
+        } else {
+            let hint = "TAB/Arrows=Move | ENTER=Select | DEL=Forget server | alias=address saves an alias";
+            Self::draw_centered(hint, status_y, 16, theme.text_secondary);
+        }
+    }
+
+    /// A server found on the network: its name, what it's playing, how full
+    /// it is and the ping.
+    fn draw_lan_server(&self, index: usize, rect: Rect, theme: &ThemeConfig) {
+        let Some(server) = self.discovery.as_ref().and_then(|discovery| discovery.servers().get(index)) else { return };
+        let text_y = rect.y + rect.h / 2.0 + 6.0;
+        draw_text("LAN", rect.x + 10.0, text_y, 16.0, theme.hud_accent);
+        let label = format!("{} - {} ({})", server.info.name, server.info.level, server.info.mode.name());
+        draw_text(&label, rect.x + 46.0, text_y, 18.0, theme.text_primary);
+
+        let details = format!("{}/{}  {} ms", server.info.players, server.info.max_players, server.ping.as_millis());
+        let details_width = measure_text(&details, None, 16, 1.0).width;
+        draw_text(&details, rect.x + rect.w - details_width - 12.0, text_y, 16.0, theme.text_secondary);
+    }
+
+    /// A server from the history: where, as whom, when and whether it
+    /// worked.
+    fn draw_history_entry(&self, index: usize, rect: Rect, theme: &ThemeConfig) {
+        let entry = &self.hosts.history[index];
+        let text_y = rect.y + rect.h / 2.0 + 6.0;
+        let expanded = self.hosts.expand(&entry.address);
+        let label = match self.hosts.alias_of(expanded) {
+            Some(alias) => format!("{} ({})", alias, expanded),
+            None => entry.address.clone(),
+        };
+        let status = if entry.succeeded { Color::from_rgba(90, 220, 90, 255) } else { Color::from_rgba(255, 90, 90, 255) };
+        draw_circle(rect.x + 16.0, rect.y + rect.h / 2.0, 5.0, status);
+        draw_text(&label, rect.x + 30.0, text_y, 18.0, theme.text_primary);
+
+        let details = format!("{}, {}", entry.name, hosts::ago(entry.last_connected));
+        let details_width = measure_text(&details, None, 16, 1.0).width;
+        draw_text(&details, rect.x + rect.w - details_width - 12.0, text_y, 16.0, theme.text_secondary);
+    }
+
+    fn draw_settings(&self, selected: usize, theme: &ThemeConfig) {
+        let count = SETTINGS_ITEMS.len();
+        Self::draw_centered("SETTINGS", Self::item_rect(0, count).y - 30.0, 40, theme.hud_accent);
+        for (index, item) in SETTINGS_ITEMS.iter().enumerate() {
+            let rect = Self::item_rect(index, count);
+            Self::draw_row(rect, index == selected, theme);
+            let label = match item {
+                SettingsItem::Sensitivity => format!("<  MOUSE SENSITIVITY: {}  >", self.settings.sensitivity_label()),
+                SettingsItem::Controls => "CONTROLS".to_string(),
+                SettingsItem::Back => "BACK".to_string(),
+            };
+            let label_width = measure_text(&label, None, 22, 1.0).width;
+            draw_text(&label, rect.x + rect.w / 2.0 - label_width / 2.0, rect.y + rect.h / 2.0 + 7.0, 22.0, theme.text_primary);
+        }
+    }
+}
+
+/// Error messages start lower case to read well after a prefix; on their
+/// own they get a capital.
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
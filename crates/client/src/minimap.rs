@@ -0,0 +1,79 @@
+use macroquad::prelude::*;
+
+use shared::level::PickupKind;
+use shared::maze::Maze;
+use shared::CELL_SIZE;
+
+/// Colour an item is drawn in, on maps and in first person.
+pub fn pickup_color(kind: PickupKind) -> Color {
+    match kind {
+        PickupKind::Ammo => YELLOW,
+        PickupKind::Health => LIME,
+        PickupKind::Speed => SKYBLUE,
+        PickupKind::Damage => ORANGE,
+    }
+}
+
+/// Top-down drawing of a maze scaled into a screen rectangle. Used by the
+/// in-game minimap and the level editor.
+pub struct MapView {
+    origin: Vec2,
+    pub cell_size: f32,
+}
+
+impl MapView {
+    /// Largest view of a `width` x `height` grid that fits in `area`, centred.
+    pub fn fit(width: usize, height: usize, area: Rect) -> Self {
+        let cell_size = (area.w / width.max(1) as f32).min(area.h / height.max(1) as f32);
+        let size = vec2(width as f32, height as f32) * cell_size;
+        Self {
+            origin: vec2(area.x + (area.w - size.x) / 2.0, area.y + (area.h - size.y) / 2.0),
+            cell_size,
+        }
+    }
+
+    pub fn cell_rect(&self, x: usize, y: usize) -> Rect {
+        Rect::new(
+            self.origin.x + x as f32 * self.cell_size,
+            self.origin.y + y as f32 * self.cell_size,
+            self.cell_size,
+            self.cell_size,
+        )
+    }
+
+    /// Screen position of a point given in cells (`3.5` is a cell centre).
+    pub fn cell_point(&self, x: f32, y: f32) -> Vec2 {
+        self.origin + vec2(x, y) * self.cell_size
+    }
+
+    /// Screen position of a point in world units.
+    pub fn world_point(&self, x: f32, y: f32) -> Vec2 {
+        self.cell_point(x / CELL_SIZE, y / CELL_SIZE)
+    }
+
+    /// The cell under a screen position, if it's inside a `width` x `height` grid.
+    pub fn cell_at(&self, point: Vec2, width: usize, height: usize) -> Option<(usize, usize)> {
+        let cell = (point - self.origin) / self.cell_size;
+        if cell.x < 0.0 || cell.y < 0.0 {
+            return None;
+        }
+        let (x, y) = (cell.x as usize, cell.y as usize);
+        (x < width && y < height).then_some((x, y))
+    }
+
+    pub fn draw_walls(&self, maze: &Maze, color: Color) {
+        for y in 0..maze.height() {
+            for x in 0..maze.width() {
+                if maze.is_wall(x, y) {
+                    let rect = self.cell_rect(x, y);
+                    draw_rectangle(rect.x, rect.y, rect.w, rect.h, color);
+                }
+            }
+        }
+    }
+
+    pub fn draw_exit(&self, maze: &Maze, color: Color) {
+        let rect = self.cell_rect(maze.exit.0, maze.exit.1);
+        draw_rectangle(rect.x, rect.y, rect.w, rect.h, color);
+    }
+}
@@ -0,0 +1,390 @@
+use std::collections::VecDeque;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use shared::flag::{FlagEvent, FlagState};
+use shared::level::{Level, LevelSource, PickupKind};
+use shared::mechanism::MechanismState;
+use shared::mode::GameMode;
+use shared::pickup::PickupState;
+use shared::protocol::{ChatMessage, ClientMessage, PlayerSnapshot, PlayerStatus, ServerMessage, VoteOption, MAX_DATAGRAM};
+use shared::reliable::{Inbox, Outbox};
+use shared::team::Team;
+use shared::transfer::{Assembler, TransferError};
+
+/// How long to wait for requested chunks before asking again.
+const FETCH_RETRY: Duration = Duration::from_millis(500);
+
+/// Chat messages kept, newest last.
+const CHAT_HISTORY: usize = 50;
+
+/// Between rounds, while players vote for the next level.
+pub struct Intermission {
+    pub seconds_left: u32,
+    pub finisher: String,
+    pub options: Vec<VoteOption>,
+    /// Option this client voted for.
+    pub own_vote: Option<usize>,
+}
+
+/// A level being downloaded from the server.
+struct Download {
+    source: LevelSource,
+    assembler: Assembler,
+    last_request: Option<Instant>,
+}
+
+/// Non-blocking UDP connection to the game server.
+pub struct Connection {
+    socket: UdpSocket,
+    pub player_id: Option<u32>,
+    pub mode: GameMode,
+    /// Why the server turned us away, if it did.
+    pub rejected: Option<String>,
+    /// Message of the day and when it arrived.
+    pub motd: Option<(String, Instant)>,
+    pub remote_players: Vec<PlayerSnapshot>,
+    pub intermission: Option<Intermission>,
+    /// Everyone's kills, deaths, score and ping, as the server last sent them.
+    pub scores: Vec<PlayerStatus>,
+    /// The local player's team, `None` in modes without teams.
+    pub team: Option<Team>,
+    /// Kills or captures per team this round, by [`Team::index`].
+    pub team_scores: [u32; 2],
+    /// Where the flags are in capture the flag, as the server last sent them.
+    pub flags: Vec<(Team, FlagState)>,
+    /// Chat messages received and when, oldest first.
+    pub chat: VecDeque<(ChatMessage, Instant)>,
+    // Messages that have to reach the server, and those taken from it
+    outbox: Outbox<ClientMessage>,
+    inbox: Inbox,
+    own_state: Option<PlayerSnapshot>,
+    level_change: Option<(LevelSource, u64)>,
+    world: Option<MechanismState>,
+    pickups: Option<Vec<PickupState>>,
+    // Items the local player took since the last call
+    collected: Vec<PickupKind>,
+    // The local player's death since the last call: killer and lives left
+    death: Option<(String, Option<u32>)>,
+    // Kills since the last call, killer and victim
+    kill_feed: Vec<(String, String)>,
+    // Flag events since the last call, by whom if anyone
+    flag_events: Vec<(Option<String>, Team, FlagEvent)>,
+    respawned: bool,
+    // What the server was last told about the local player's ammo
+    ammo_full: bool,
+    download: Option<Download>,
+}
+
+impl Connection {
+    pub fn connect(server_addr: SocketAddr, username: &str) -> io::Result<Self> {
+        let local = if server_addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(server_addr)?;
+        socket.set_nonblocking(true)?;
+
+        let connection = Self {
+            socket,
+            player_id: None,
+            mode: GameMode::default(),
+            rejected: None,
+            motd: None,
+            remote_players: Vec::new(),
+            intermission: None,
+            scores: Vec::new(),
+            team: None,
+            team_scores: [0; 2],
+            flags: Vec::new(),
+            chat: VecDeque::new(),
+            outbox: Outbox::default(),
+            inbox: Inbox::default(),
+            own_state: None,
+            level_change: None,
+            world: None,
+            pickups: None,
+            collected: Vec::new(),
+            death: None,
+            kill_feed: Vec::new(),
+            flag_events: Vec::new(),
+            respawned: false,
+            ammo_full: false,
+            download: None,
+        };
+        connection.send(&ClientMessage::Connect {
+            username: username.to_string(),
+        })?;
+        Ok(connection)
+    }
+
+    pub fn send(&self, msg: &ClientMessage) -> io::Result<()> {
+        self.socket.send(msg.encode().as_bytes()).map(|_| ())
+    }
+
+    /// Queues a message that has to arrive. It goes out with the next
+    /// [`poll`](Self::poll) and again until the server acknowledges it.
+    pub fn send_reliable(&mut self, msg: ClientMessage) {
+        self.outbox.push(msg);
+    }
+
+    /// Drains every pending datagram and applies it to the local view of the server.
+    pub fn poll(&mut self) {
+        let mut buf = [0; MAX_DATAGRAM];
+        // WouldBlock means the queue is empty; anything else (e.g. the server
+        // refusing the port) is retried on the next poll.
+        while let Ok(size) = self.socket.recv(&mut buf) {
+            let msg = String::from_utf8_lossy(&buf[..size]);
+            match ServerMessage::decode(&msg) {
+                Some(ServerMessage::Reliable { seq, message }) => {
+                    if self.inbox.accept(seq) {
+                        self.apply(*message);
+                    }
+                    let _ = self.send(&ClientMessage::Ack { seq: self.inbox.received() });
+                }
+                Some(message) => self.apply(message),
+                None => {}
+            }
+        }
+
+        for (seq, message) in self.outbox.due(Instant::now()) {
+            let _ = self.send(&ClientMessage::Reliable { seq, message: Box::new(message) });
+        }
+    }
+
+    /// Applies one message from the server to the local view of it.
+    fn apply(&mut self, message: ServerMessage) {
+        match message {
+            ServerMessage::Accept { player_id, mode } => {
+                self.player_id = Some(player_id);
+                self.mode = mode;
+            }
+            ServerMessage::Reject { reason } => {
+                self.rejected = Some(reason);
+            }
+            ServerMessage::Motd(text) => {
+                self.motd = Some((text, Instant::now()));
+            }
+            ServerMessage::Snapshot { players } => {
+                let (own, remote): (Vec<_>, Vec<_>) = players
+                    .into_iter()
+                    .partition(|p| Some(p.id) == self.player_id);
+                self.remote_players = remote;
+                if let Some(own) = own.into_iter().next() {
+                    self.team = own.team;
+                    self.own_state = Some(own);
+                }
+            }
+            ServerMessage::World(state) => {
+                self.world = Some(state);
+            }
+            ServerMessage::Level { source, hash } => {
+                self.level_change = Some((source, hash));
+                self.world = None;
+                self.pickups = None;
+                self.flags.clear();
+                self.team_scores = [0; 2];
+                self.intermission = None;
+                self.download = None;
+                // Our position in snapshots before the change is from the old level
+                self.own_state = None;
+            }
+            ServerMessage::Intermission { seconds_left, finisher, options } => {
+                let own_vote = self.intermission.as_ref().and_then(|intermission| intermission.own_vote);
+                self.intermission = Some(Intermission { seconds_left, finisher, options, own_vote });
+            }
+            ServerMessage::Pickups(items) => {
+                self.pickups = Some(items);
+            }
+            ServerMessage::Collected { player_id, kind } if Some(player_id) == self.player_id => {
+                self.collected.push(kind);
+            }
+            ServerMessage::Died { player_id, killer, lives } => {
+                self.kill_feed.push((killer.clone(), self.name_of(player_id)));
+                if Some(player_id) == self.player_id {
+                    self.death = Some((killer, lives));
+                    self.respawned = false;
+                }
+            }
+            ServerMessage::Kill { player_id, victim } => {
+                self.kill_feed.push((self.name_of(player_id), victim));
+            }
+            ServerMessage::Scores(players) => {
+                self.scores = players;
+            }
+            ServerMessage::TeamScores(scores) => {
+                self.team_scores = scores;
+            }
+            ServerMessage::Flags(flags) => {
+                self.flags = flags;
+            }
+            ServerMessage::Flag { player_id, team, event } => {
+                let player = player_id.map(|id| self.name_of(id));
+                self.flag_events.push((player, team, event));
+            }
+            ServerMessage::Respawn { player_id } if Some(player_id) == self.player_id => {
+                self.respawned = true;
+            }
+            ServerMessage::Ping { token } => {
+                let _ = self.send(&ClientMessage::Pong { token });
+            }
+            ServerMessage::Chat(message) => {
+                self.chat.push_back((message, Instant::now()));
+                if self.chat.len() > CHAT_HISTORY {
+                    self.chat.pop_front();
+                }
+            }
+            ServerMessage::Ack { seq } => {
+                self.outbox.ack(seq);
+            }
+            ServerMessage::Chunk { hash, index, count, data } => {
+                if let Some(download) = &mut self.download {
+                    download.assembler.insert(hash, index, count, data);
+                }
+            }
+            // Other players' pickups and respawns, and nothing is wrapped twice
+            ServerMessage::Collected { .. }
+            | ServerMessage::Respawn { .. }
+            | ServerMessage::Reliable { .. }
+            | ServerMessage::Info { .. }
+            | ServerMessage::Status { .. } => {}
+        }
+    }
+}
+
+impl Connection {
+    /// Latest authoritative state of the local player, if a new one arrived.
+    pub fn take_own_state(&mut self) -> Option<PlayerSnapshot> {
+        self.own_state.take()
+    }
+
+    /// Latest door, switch and push wall state, if a new one arrived.
+    pub fn take_world(&mut self) -> Option<MechanismState> {
+        self.world.take()
+    }
+
+    /// Latest state of the level's items, if a new one arrived.
+    pub fn take_pickups(&mut self) -> Option<Vec<PickupState>> {
+        self.pickups.take()
+    }
+
+    /// Items the server gave the local player since the last call.
+    pub fn take_collected(&mut self) -> Vec<PickupKind> {
+        std::mem::take(&mut self.collected)
+    }
+
+    /// Who killed the local player and the lives they have left, if they
+    /// died since the last call.
+    pub fn take_death(&mut self) -> Option<(String, Option<u32>)> {
+        self.death.take()
+    }
+
+    /// Who killed whom since the last call, for the kill feed.
+    pub fn take_kill_feed(&mut self) -> Vec<(String, String)> {
+        std::mem::take(&mut self.kill_feed)
+    }
+
+    /// What happened to the flags since the last call and who did it, for
+    /// the kill feed.
+    pub fn take_flag_events(&mut self) -> Vec<(Option<String>, Team, FlagEvent)> {
+        std::mem::take(&mut self.flag_events)
+    }
+
+    /// The team a player is on, as the latest snapshot has it.
+    pub fn team_of(&self, player_id: u32) -> Option<Team> {
+        if Some(player_id) == self.player_id {
+            return self.team;
+        }
+        self.remote_players.iter().find(|p| p.id == player_id).and_then(|p| p.team)
+    }
+
+    /// The local player's line on the scoreboard.
+    pub fn own_status(&self) -> Option<&PlayerStatus> {
+        self.scores.iter().find(|p| Some(p.id) == self.player_id)
+    }
+
+    /// A player's name, from the scoreboard or the snapshots.
+    fn name_of(&self, player_id: u32) -> String {
+        let scored = self.scores.iter().find(|p| p.id == player_id).map(|p| &p.username);
+        let seen = || self.remote_players.iter().find(|p| p.id == player_id).map(|p| &p.username);
+        scored.or_else(seen).cloned().unwrap_or_else(|| format!("Player {}", player_id))
+    }
+
+    /// Whether the server brought the local player back since the last call.
+    pub fn take_respawn(&mut self) -> bool {
+        std::mem::take(&mut self.respawned)
+    }
+
+    /// Level the server switched to since the last call, with its content hash.
+    pub fn take_level_change(&mut self) -> Option<(LevelSource, u64)> {
+        self.level_change.take()
+    }
+
+    /// Starts downloading the level the server announced, for when there is
+    /// no local copy with the right hash.
+    pub fn start_download(&mut self, source: LevelSource, hash: u64) {
+        self.download = Some(Download { source, assembler: Assembler::new(hash), last_request: None });
+    }
+
+    /// (received, total) chunks of the level being downloaded.
+    pub fn download_progress(&self) -> Option<(usize, usize)> {
+        self.download.as_ref().map(|download| download.assembler.progress())
+    }
+
+    /// Requests missing chunks, asking again for ones that got lost, and
+    /// returns the level once it's complete and verified. A level that
+    /// doesn't check out is fetched again from scratch, the error is
+    /// returned to show.
+    pub fn update_download(&mut self) -> Option<Result<(LevelSource, Level), TransferError>> {
+        let download = self.download.as_mut()?;
+        match download.assembler.finish() {
+            Some(Ok(level)) => {
+                let download = self.download.take()?;
+                return Some(Ok((download.source, level)));
+            }
+            Some(Err(error)) => {
+                download.assembler = Assembler::new(download.assembler.hash());
+                download.last_request = None;
+                return Some(Err(error));
+            }
+            None => {}
+        }
+        if download.last_request.is_none_or(|at| at.elapsed() >= FETCH_RETRY) {
+            download.last_request = Some(Instant::now());
+            let hash = download.assembler.hash();
+            for chunk in download.assembler.missing() {
+                let _ = self.socket.send(ClientMessage::Fetch { hash, chunk }.encode().as_bytes());
+            }
+        }
+        None
+    }
+
+    /// Sends a chat message, to the player's team only if `team`. It shows
+    /// up in [`chat`](Self::chat) once the server hands it out.
+    pub fn say(&mut self, text: &str, team: bool) {
+        self.send_reliable(ClientMessage::Say { team, text: text.to_string() });
+    }
+
+    /// Tells the server whether the local player can carry more ammo, if
+    /// that changed. Ammo packs are left alone while it's full.
+    pub fn report_ammo(&mut self, full: bool) {
+        if full != self.ammo_full {
+            self.ammo_full = full;
+            self.send_reliable(ClientMessage::AmmoFull(full));
+        }
+    }
+
+    /// Votes for an intermission option. Does nothing outside the intermission.
+    pub fn vote(&mut self, option: usize) {
+        let Some(intermission) = &mut self.intermission else { return };
+        if option < intermission.options.len() {
+            intermission.own_vote = Some(option);
+            self.send_reliable(ClientMessage::Vote { option });
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let _ = self.send(&ClientMessage::Disconnect);
+    }
+}
@@ -0,0 +1,145 @@
+use macroquad::prelude::*;
+
+use crate::input::{Action, Input};
+use crate::rebind::RebindMenu;
+use crate::settings::Settings;
+use crate::themes::ThemeConfig;
+
+const ITEM_WIDTH: f32 = 320.0;
+const ITEM_HEIGHT: f32 = 44.0;
+const ITEM_SPACING: f32 = 12.0;
+
+#[derive(Clone, Copy, PartialEq)]
+enum PauseItem {
+    Resume,
+    Sensitivity,
+    Controls,
+    Editor,
+    Quit,
+}
+
+const ITEMS: [PauseItem; 5] = [PauseItem::Resume, PauseItem::Sensitivity, PauseItem::Controls, PauseItem::Editor, PauseItem::Quit];
+
+/// What the game should do after the pause menu handled input.
+pub enum PauseAction {
+    Resume,
+    OpenEditor,
+    Quit,
+}
+
+/// Menu shown while the cursor is released with Escape.
+pub struct PauseMenu {
+    selected: usize,
+    rebind: Option<RebindMenu>,
+    editor_available: bool,
+}
+
+impl PauseMenu {
+    pub fn new(editor_available: bool) -> Self {
+        Self { selected: 0, rebind: None, editor_available }
+    }
+
+    fn item_rect(index: usize) -> Rect {
+        let total_height = ITEMS.len() as f32 * (ITEM_HEIGHT + ITEM_SPACING);
+        let top = screen_height() / 2.0 - total_height / 2.0;
+        Rect::new(
+            screen_width() / 2.0 - ITEM_WIDTH / 2.0,
+            top + index as f32 * (ITEM_HEIGHT + ITEM_SPACING),
+            ITEM_WIDTH,
+            ITEM_HEIGHT,
+        )
+    }
+
+    pub fn update(&mut self, settings: &mut Settings, input: &mut Input) -> Option<PauseAction> {
+        if let Some(rebind) = &mut self.rebind {
+            if rebind.update(input) {
+                self.rebind = None;
+                if let Err(e) = input.save() {
+                    eprintln!("Warning: could not save controls: {}", e);
+                }
+            }
+            return None;
+        }
+
+        if is_key_pressed(KeyCode::Escape) || input.is_pressed(Action::Pause) {
+            return Some(PauseAction::Resume);
+        }
+        if is_key_pressed(KeyCode::Up) {
+            self.selected = (self.selected + ITEMS.len() - 1) % ITEMS.len();
+        }
+        if is_key_pressed(KeyCode::Down) {
+            self.selected = (self.selected + 1) % ITEMS.len();
+        }
+
+        // Mouse hover selects, clicking activates
+        let mouse = Vec2::from(mouse_position());
+        let mut clicked = false;
+        for index in 0..ITEMS.len() {
+            if Self::item_rect(index).contains(mouse) {
+                self.selected = index;
+                clicked = is_mouse_button_pressed(MouseButton::Left);
+            }
+        }
+
+        let item = ITEMS[self.selected];
+        if item == PauseItem::Sensitivity {
+            if is_key_pressed(KeyCode::Left) {
+                settings.adjust_sensitivity(-1);
+            }
+            if is_key_pressed(KeyCode::Right) {
+                settings.adjust_sensitivity(1);
+            }
+            if clicked {
+                // Left half lowers, right half raises
+                let rect = Self::item_rect(self.selected);
+                let steps = if mouse.x < rect.x + rect.w / 2.0 { -1 } else { 1 };
+                settings.adjust_sensitivity(steps);
+            }
+        }
+
+        if clicked || is_key_pressed(KeyCode::Enter) {
+            match item {
+                PauseItem::Resume => return Some(PauseAction::Resume),
+                PauseItem::Controls => self.rebind = Some(RebindMenu::new()),
+                PauseItem::Editor if self.editor_available => return Some(PauseAction::OpenEditor),
+                PauseItem::Editor => {}
+                PauseItem::Quit => return Some(PauseAction::Quit),
+                PauseItem::Sensitivity => {}
+            }
+        }
+        None
+    }
+
+    pub fn draw(&self, settings: &Settings, input: &Input, theme: &ThemeConfig) {
+        if let Some(rebind) = &self.rebind {
+            rebind.draw(input, theme);
+            return;
+        }
+
+        draw_rectangle(0.0, 0.0, screen_width(), screen_height(), Color::from_rgba(0, 0, 0, 160));
+
+        let title = "PAUSED";
+        let title_width = measure_text(title, None, 40, 1.0).width;
+        let title_y = Self::item_rect(0).y - 30.0;
+        draw_text(title, screen_width() / 2.0 - title_width / 2.0, title_y, 40.0, theme.hud_accent);
+
+        for (index, item) in ITEMS.iter().enumerate() {
+            let rect = Self::item_rect(index);
+            let selected = index == self.selected;
+            let background = if selected { Color::from_rgba(255, 255, 255, 60) } else { Color::from_rgba(0, 0, 0, 180) };
+            draw_rectangle(rect.x, rect.y, rect.w, rect.h, background);
+            draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 2.0, theme.hud_primary);
+
+            let label = match item {
+                PauseItem::Resume => "RESUME".to_string(),
+                PauseItem::Sensitivity => format!("<  MOUSE SENSITIVITY: {}  >", settings.sensitivity_label()),
+                PauseItem::Controls => "CONTROLS".to_string(),
+                PauseItem::Editor if self.editor_available => "LEVEL EDITOR".to_string(),
+                PauseItem::Editor => "LEVEL EDITOR (OFFLINE ONLY)".to_string(),
+                PauseItem::Quit => "QUIT".to_string(),
+            };
+            let label_width = measure_text(&label, None, 22, 1.0).width;
+            draw_text(&label, rect.x + rect.w / 2.0 - label_width / 2.0, rect.y + rect.h / 2.0 + 7.0, 22.0, theme.text_primary);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use macroquad::prelude::*;
+
+use crate::input::{Action, ActionMap, Input};
+use crate::themes::ThemeConfig;
+
+const ROW_WIDTH: f32 = 520.0;
+const ROW_HEIGHT: f32 = 22.0;
+const ROW_SPACING: f32 = 3.0;
+
+/// Rows after the action list.
+const RESET_ROW: usize = Action::ALL.len();
+const BACK_ROW: usize = Action::ALL.len() + 1;
+const ROW_COUNT: usize = Action::ALL.len() + 2;
+
+/// Screen listing every action with its bindings. Selecting an action waits
+/// for the next key, mouse button or gamepad input and binds it.
+pub struct RebindMenu {
+    selected: usize,
+    waiting: bool,
+}
+
+impl RebindMenu {
+    pub fn new() -> Self {
+        Self { selected: 0, waiting: false }
+    }
+
+    fn row_rect(index: usize) -> Rect {
+        let total_height = ROW_COUNT as f32 * (ROW_HEIGHT + ROW_SPACING);
+        let top = (screen_height() / 2.0 - total_height / 2.0).max(50.0);
+        Rect::new(
+            screen_width() / 2.0 - ROW_WIDTH / 2.0,
+            top + index as f32 * (ROW_HEIGHT + ROW_SPACING),
+            ROW_WIDTH,
+            ROW_HEIGHT,
+        )
+    }
+
+    /// Handles input for this frame. Returns true once the player leaves the
+    /// screen, at which point the bindings should be saved.
+    pub fn update(&mut self, input: &mut Input) -> bool {
+        if self.waiting {
+            if is_key_pressed(KeyCode::Escape) {
+                self.waiting = false;
+            } else if let Some(binding) = input.captured_binding() {
+                input.map.rebind(Action::ALL[self.selected], binding);
+                self.waiting = false;
+            }
+            return false;
+        }
+
+        if is_key_pressed(KeyCode::Escape) {
+            return true;
+        }
+        if is_key_pressed(KeyCode::Up) {
+            self.selected = (self.selected + ROW_COUNT - 1) % ROW_COUNT;
+        }
+        if is_key_pressed(KeyCode::Down) {
+            self.selected = (self.selected + 1) % ROW_COUNT;
+        }
+
+        let mouse = Vec2::from(mouse_position());
+        let mut clicked = false;
+        for index in 0..ROW_COUNT {
+            if Self::row_rect(index).contains(mouse) {
+                self.selected = index;
+                clicked = is_mouse_button_pressed(MouseButton::Left);
+            }
+        }
+
+        if self.selected < Action::ALL.len()
+            && (is_key_pressed(KeyCode::Backspace) || is_key_pressed(KeyCode::Delete))
+        {
+            input.map.clear(Action::ALL[self.selected]);
+        }
+
+        if clicked || is_key_pressed(KeyCode::Enter) {
+            match self.selected {
+                RESET_ROW => input.map = ActionMap::default(),
+                BACK_ROW => return true,
+                _ => self.waiting = true,
+            }
+        }
+        false
+    }
+
+    pub fn draw(&self, input: &Input, theme: &ThemeConfig) {
+        draw_rectangle(0.0, 0.0, screen_width(), screen_height(), Color::from_rgba(0, 0, 0, 200));
+
+        let title = "CONTROLS";
+        let title_width = measure_text(title, None, 32, 1.0).width;
+        draw_text(title, screen_width() / 2.0 - title_width / 2.0, Self::row_rect(0).y - 14.0, 32.0, theme.hud_accent);
+
+        for index in 0..ROW_COUNT {
+            let rect = Self::row_rect(index);
+            let selected = index == self.selected;
+            let background = if selected { Color::from_rgba(255, 255, 255, 60) } else { Color::from_rgba(0, 0, 0, 180) };
+            draw_rectangle(rect.x, rect.y, rect.w, rect.h, background);
+            draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, theme.hud_primary);
+
+            let text_y = rect.y + rect.h / 2.0 + 6.0;
+            match index {
+                RESET_ROW => {
+                    draw_text("RESET DEFAULTS", rect.x + 10.0, text_y, 18.0, theme.text_primary);
+                }
+                BACK_ROW => {
+                    draw_text("BACK", rect.x + 10.0, text_y, 18.0, theme.text_primary);
+                }
+                _ => {
+                    let action = Action::ALL[index];
+                    draw_text(action.label(), rect.x + 10.0, text_y, 18.0, theme.text_primary);
+
+                    let bindings = if selected && self.waiting {
+                        "Press a key or button... (ESC to cancel)".to_string()
+                    } else {
+                        let labels: Vec<String> = input.map.bindings(action).iter().map(|b| b.label()).collect();
+                        if labels.is_empty() { "-".to_string() } else { labels.join(", ") }
+                    };
+                    draw_text(&bindings, rect.x + 170.0, text_y, 16.0, theme.text_secondary);
+                }
+            }
+        }
+
+        let hint = "ENTER/Click=Rebind | BACKSPACE=Clear | ESC=Back";
+        let hint_width = measure_text(hint, None, 16, 1.0).width;
+        let hint_y = Self::row_rect(ROW_COUNT - 1).y + ROW_HEIGHT + 24.0;
+        draw_text(hint, screen_width() / 2.0 - hint_width / 2.0, hint_y, 16.0, theme.text_secondary);
+    }
+}
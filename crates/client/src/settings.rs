@@ -0,0 +1,57 @@
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+pub const SETTINGS_FILE: &str = "settings.ron";
+
+/// Mouse sensitivity in radians of rotation per pixel of mouse movement.
+pub const DEFAULT_MOUSE_SENSITIVITY: f32 = 0.003;
+const MIN_MOUSE_SENSITIVITY: f32 = 0.0005;
+const MAX_MOUSE_SENSITIVITY: f32 = 0.02;
+const MOUSE_SENSITIVITY_STEP: f32 = 0.0005;
+
+/// Player adjustable options, persisted in [`SETTINGS_FILE`].
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub mouse_sensitivity: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            mouse_sensitivity: DEFAULT_MOUSE_SENSITIVITY,
+        }
+    }
+}
+
+impl Settings {
+    /// Loads the settings, bringing a hand-edited sensitivity back into
+    /// the range the menus allow.
+    pub fn load() -> Self {
+        let mut settings: Self = config::load(SETTINGS_FILE);
+        settings.mouse_sensitivity = if settings.mouse_sensitivity.is_finite() {
+            settings.mouse_sensitivity.clamp(MIN_MOUSE_SENSITIVITY, MAX_MOUSE_SENSITIVITY)
+        } else {
+            DEFAULT_MOUSE_SENSITIVITY
+        };
+        settings
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        config::save(SETTINGS_FILE, self)
+    }
+
+    /// Raises or lowers the mouse sensitivity by `steps` increments.
+    pub fn adjust_sensitivity(&mut self, steps: i32) {
+        self.mouse_sensitivity = (self.mouse_sensitivity + steps as f32 * MOUSE_SENSITIVITY_STEP)
+            .clamp(MIN_MOUSE_SENSITIVITY, MAX_MOUSE_SENSITIVITY);
+    }
+
+    /// Sensitivity as shown in menus, where the default reads as `3.0`.
+    pub fn sensitivity_label(&self) -> String {
+        format!("{:.1}", self.mouse_sensitivity * 1000.0)
+    }
+}
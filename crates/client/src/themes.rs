@@ -1,4 +1,7 @@
 use macroquad::prelude::*;
+use shared::level::Theme;
+use shared::team::Team;
+use shared::trap::TrapKind;
 
 #[derive(Clone, Copy)]
 pub enum LevelTheme {
@@ -31,13 +34,24 @@ pub struct ThemeConfig {
     pub particle_color: Color,
 }
 
+impl From<Theme> for LevelTheme {
+    fn from(theme: Theme) -> Self {
+        match theme {
+            Theme::CandyMaze => LevelTheme::CandyMaze,
+            Theme::Cyberpunk => LevelTheme::Cyberpunk,
+            Theme::MoroccanBazaar => LevelTheme::MoroccanBazaar,
+        }
+    }
+}
+
 impl LevelTheme {
     pub fn from_level(level: usize) -> Self {
         match level {
             1 => LevelTheme::CandyMaze,
             2 => LevelTheme::Cyberpunk,
             3 => LevelTheme::MoroccanBazaar,
-            _ => LevelTheme::CandyMaze,
+            // Generated levels cycle through the themes
+            _ => Self::from_level(level.saturating_sub(1) % 3 + 1),
         }
     }
 
@@ -105,6 +119,19 @@ impl LevelTheme {
         }
     }
 
+    /// Team colours in the theme's palette: strawberry and blueberry,
+    /// neon red and cyan, or terracotta and lapis.
+    pub fn get_team_color(&self, team: Team) -> Color {
+        match (self, team) {
+            (LevelTheme::CandyMaze, Team::Red) => Color::from_rgba(220, 20, 60, 255),
+            (LevelTheme::CandyMaze, Team::Blue) => Color::from_rgba(65, 105, 225, 255),
+            (LevelTheme::Cyberpunk, Team::Red) => Color::from_rgba(255, 40, 80, 255),
+            (LevelTheme::Cyberpunk, Team::Blue) => Color::from_rgba(0, 200, 255, 255),
+            (LevelTheme::MoroccanBazaar, Team::Red) => Color::from_rgba(204, 78, 52, 255),
+            (LevelTheme::MoroccanBazaar, Team::Blue) => Color::from_rgba(38, 97, 156, 255),
+        }
+    }
+
     pub fn get_minimap_title(&self) -> &'static str {
         match self {
             LevelTheme::CandyMaze => "CANDY MAP",
@@ -121,6 +148,29 @@ impl LevelTheme {
         }
     }
 
+    /// Traps look like part of the theme's world: caramel, live wiring or
+    /// hot coals underfoot, rock candy, chrome or iron spikes, and a sugar
+    /// swirl, data stream or brass portal for teleporters.
+    pub fn get_trap_color(&self, kind: TrapKind) -> Color {
+        match (self, kind) {
+            (LevelTheme::CandyMaze, TrapKind::Hazard) => Color::from_rgba(210, 105, 30, 220),
+            (LevelTheme::Cyberpunk, TrapKind::Hazard) => Color::from_rgba(0, 255, 128, 200),
+            (LevelTheme::MoroccanBazaar, TrapKind::Hazard) => Color::from_rgba(255, 69, 0, 220),
+            (LevelTheme::CandyMaze, TrapKind::Spikes) => Color::from_rgba(255, 250, 250, 230),
+            (LevelTheme::Cyberpunk, TrapKind::Spikes) => Color::from_rgba(192, 192, 210, 230),
+            (LevelTheme::MoroccanBazaar, TrapKind::Spikes) => Color::from_rgba(70, 60, 55, 230),
+            (LevelTheme::CandyMaze, TrapKind::Teleporter) => Color::from_rgba(186, 85, 211, 230),
+            (LevelTheme::Cyberpunk, TrapKind::Teleporter) => Color::from_rgba(0, 191, 255, 230),
+            (LevelTheme::MoroccanBazaar, TrapKind::Teleporter) => Color::from_rgba(255, 215, 0, 230),
+        }
+    }
+
+    /// Door panels stand out from the walls around them in the theme's accent colour.
+    pub fn get_door_color(&self, config: &ThemeConfig, brightness: f32) -> Color {
+        let base = config.wall_accent;
+        Color::new(base.r * brightness, base.g * brightness, base.b * brightness, 1.0)
+    }
+
     pub fn get_wall_color(&self, config: &ThemeConfig, brightness: f32, ray_index: usize) -> Color {
         match self {
             LevelTheme::CandyMaze => {
@@ -139,7 +189,7 @@ impl LevelTheme {
             },
             LevelTheme::Cyberpunk => {
                 // Neon-lit walls with occasional accent lines
-                let base_color = if ray_index % 32 == 0 {
+                let base_color = if ray_index.is_multiple_of(32) {
                     config.wall_accent // Neon accent lines
                 } else {
                     config.wall_primary
@@ -221,7 +271,7 @@ impl LevelTheme {
                 
                 // Digital rain
                 for i in 0..15 {
-                    let x = (i as f32 * screen_width / 15.0);
+                    let x = i as f32 * screen_width / 15.0;
                     let y = (crosshair_pulse * 100.0 + i as f32 * 50.0) % (screen_height + 100.0);
                     draw_rectangle(x, y, 2.0, 20.0, Color::from_rgba(0, 255, 0, 100));
                 }
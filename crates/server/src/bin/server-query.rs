@@ -0,0 +1,188 @@
+//! Asks a running server what it's doing, without joining it.
+//!
+//! Usage: `cargo run -p server --bin server-query -- [--json] [--timeout SECONDS] <ADDRESS>`
+//!
+//! Prints the server's name, version, level, mode, uptime and players with
+//! their kills, deaths, scores and pings, as text or as JSON for monitoring scripts.
+//! Exits with 0 when the server answered, 1 when it didn't and 2 when the
+//! arguments are wrong or the address can't be found.
+
+use std::net::{SocketAddr, UdpSocket};
+use std::process::ExitCode;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use shared::address;
+use shared::protocol::{ClientMessage, ServerMessage, ServerStatus, MAX_DATAGRAM};
+
+const USAGE: &str = "Usage: server-query [--json] [--timeout SECONDS] <ADDRESS>";
+
+/// How often to ask again while there's no answer.
+const RETRY: Duration = Duration::from_millis(500);
+
+fn main() -> ExitCode {
+    let mut json = false;
+    let mut timeout = Duration::from_secs(2);
+    let mut target = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--timeout" => match args.next().and_then(|value| value.parse::<f32>().ok()).filter(|&s| s > 0.0) {
+                Some(seconds) => timeout = Duration::from_secs_f32(seconds),
+                None => return usage("--timeout needs a number of seconds"),
+            },
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ if arg.starts_with("--") => return usage(&format!("unknown option '{}'", arg)),
+            _ if target.is_none() => target = Some(arg),
+            _ => return usage("only one address can be queried at a time"),
+        }
+    }
+    let Some(target) = target else { return usage("no server address given") };
+    let server = match address::resolve(&target) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    match query(server, timeout) {
+        Ok(Some((status, ping))) => {
+            if json {
+                println!("{}", to_json(&status, ping));
+            } else {
+                print_status(&status, ping);
+            }
+            ExitCode::SUCCESS
+        }
+        Ok(None) => {
+            eprintln!("No answer from {} within {:.1}s", target, timeout.as_secs_f32());
+            ExitCode::from(1)
+        }
+        Err(e) => {
+            eprintln!("Error: can't query {}: {}", target, e);
+            ExitCode::from(1)
+        }
+    }
+}
+
+fn usage(error: &str) -> ExitCode {
+    eprintln!("Error: {}", error);
+    eprintln!("{}", USAGE);
+    ExitCode::from(2)
+}
+
+/// Sends `QUERY` until the server answers or `timeout` runs out. Returns
+/// the status and how long the answer took. Every `QUERY` carries a new
+/// token, so answers meant for someone else are ignored and a late answer
+/// still gets the ping of the `QUERY` it answers.
+fn query(server: SocketAddr, timeout: Duration) -> std::io::Result<Option<(ServerStatus, Duration)>> {
+    let local = if server.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(server)?;
+    socket.set_read_timeout(Some(Duration::from_millis(50)))?;
+
+    let started = Instant::now();
+    let mut token = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_micros() as u64).unwrap_or_default();
+    let mut sent: Vec<(u64, Instant)> = Vec::new();
+    let mut buf = vec![0; MAX_DATAGRAM];
+    while started.elapsed() < timeout {
+        if sent.last().is_none_or(|(_, at)| at.elapsed() >= RETRY) {
+            token = token.wrapping_add(1);
+            sent.push((token, Instant::now()));
+            socket.send(ClientMessage::Query { token }.encode().as_bytes())?;
+        }
+        // Nothing yet, or the port refused it; either way, try again
+        let Ok(size) = socket.recv(&mut buf) else { continue };
+        let Some(ServerMessage::Status { token, status }) = ServerMessage::decode(&String::from_utf8_lossy(&buf[..size]))
+        else {
+            continue;
+        };
+        if let Some((_, at)) = sent.iter().find(|(sent_token, _)| *sent_token == token) {
+            return Ok(Some((status, at.elapsed())));
+        }
+    }
+    Ok(None)
+}
+
+fn print_status(status: &ServerStatus, ping: Duration) {
+    let info = &status.info;
+    println!("{} (version {}), up {}", info.name, status.version, format_uptime(status.uptime));
+    println!(
+        "Level: {}, mode: {}, players: {}/{}, ping: {} ms",
+        info.level,
+        info.mode.name(),
+        info.players,
+        info.max_players,
+        ping.as_millis()
+    );
+    if status.players.is_empty() {
+        return;
+    }
+    println!();
+    println!("{:>4}  {:<16}  {:>5}  {:>6}  {:>6}  {:>6}", "ID", "NAME", "KILLS", "DEATHS", "SCORE", "PING");
+    for p in &status.players {
+        let ping = p.ping.map_or("-".to_string(), |ping| format!("{} ms", ping));
+        println!("{:>4}  {:<16}  {:>5}  {:>6}  {:>6}  {:>6}", p.id, p.username, p.kills, p.deaths, p.score, ping);
+    }
+}
+
+/// `1d 2h 03m 04s`, leaving out leading zero units.
+fn format_uptime(seconds: u64) -> String {
+    let (days, hours, minutes, seconds) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60, seconds % 60);
+    match (days, hours, minutes) {
+        (0, 0, 0) => format!("{}s", seconds),
+        (0, 0, _) => format!("{}m {:02}s", minutes, seconds),
+        (0, _, _) => format!("{}h {:02}m {:02}s", hours, minutes, seconds),
+        _ => format!("{}d {}h {:02}m {:02}s", days, hours, minutes, seconds),
+    }
+}
+
+fn to_json(status: &ServerStatus, ping: Duration) -> String {
+    let info = &status.info;
+    let players: Vec<String> = status
+        .players
+        .iter()
+        .map(|p| {
+            let ping = p.ping.map_or("null".to_string(), |ping| ping.to_string());
+            format!(
+                r#"{{"id":{},"name":{},"kills":{},"deaths":{},"score":{},"ping_ms":{}}}"#,
+                p.id,
+                json_string(&p.username),
+                p.kills,
+                p.deaths,
+                p.score,
+                ping
+            )
+        })
+        .collect();
+    format!(
+        r#"{{"name":{},"version":{},"uptime_seconds":{},"level":{},"mode":"{}","players":{},"max_players":{},"ping_ms":{},"player_list":[{}]}}"#,
+        json_string(&info.name),
+        json_string(&status.version),
+        status.uptime,
+        json_string(&info.level),
+        info.mode.name(),
+        info.players,
+        info.max_players,
+        ping.as_millis(),
+        players.join(",")
+    )
+}
+
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
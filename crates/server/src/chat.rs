@@ -0,0 +1,19 @@
+//! Keeping chat from flooding everyone's screen.
+
+use std::time::{Duration, Instant};
+
+use crate::limit::RateLimit;
+
+/// Messages a player can send in a row.
+pub const BURST: f32 = 4.0;
+
+/// After the burst, one more message is allowed this often.
+pub const REFILL: Duration = Duration::from_secs(2);
+
+/// What a player sending too fast is told.
+pub const TOO_FAST: &str = "You're sending messages too fast, wait a moment";
+
+/// A new player's chat allowance.
+pub fn rate_limit(now: Instant) -> RateLimit {
+    RateLimit::new(now, BURST, REFILL)
+}
@@ -0,0 +1,334 @@
+//! Server settings: defaults, overridden by a RON config file, overridden
+//! by command line flags.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use shared::mode::GameMode;
+use shared::protocol::MAX_PLAYERS;
+use shared::team::FriendlyFire;
+use shared::DEFAULT_PORT;
+
+use crate::rotation::INTERMISSION;
+
+/// Read when no `--config` is given, if it exists.
+pub const DEFAULT_CONFIG_FILE: &str = "server.ron";
+
+/// Longest server name, in characters.
+pub const MAX_NAME_LEN: usize = 32;
+
+/// Longest message of the day, in characters.
+pub const MAX_MOTD_LEN: usize = 200;
+
+pub const USAGE: &str = "\
+Usage: server [OPTIONS]
+
+Options:
+  --config <FILE>          Read settings from a RON file (default: server.ron if it exists)
+  --name <NAME>            Name shown to players looking for a game on the network
+  --bind <ADDRESS>         Address to listen on, 0.0.0.0 for every interface
+  --port <PORT>            UDP port to listen on, 0 for any free one
+  --max-players <N>        Players allowed at once
+  --tick-rate <HZ>         Snapshots sent per second
+  --mode <MODE>            survival, deathmatch, team-deathmatch or ctf
+  --friendly-fire <RULE>   What shooting a teammate does: off, on or reflect
+  --rotation <LEVELS>      Comma separated levels to cycle through, e.g. 1,2,arena,7@1234
+  --timeout <SECONDS>      Drop players silent for this long
+  --intermission <SECONDS> Time to vote for the next level between rounds
+  --motd <TEXT>            Message shown to players when they join
+  --print-default-config   Print the default config file and exit
+  --help                   Print this help and exit
+
+Flags override the config file.
+";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Shown in the client's list of servers on the network.
+    pub name: String,
+    pub bind: IpAddr,
+    pub port: u16,
+    pub max_players: usize,
+    pub tick_rate: u32,
+    pub mode: GameMode,
+    /// What shooting a teammate does in the team modes.
+    pub friendly_fire: FriendlyFire,
+    /// Levels to cycle through, see [`Rotation::parse`](crate::rotation::Rotation::parse).
+    /// Empty plays the numbered levels forever.
+    pub rotation: Vec<String>,
+    /// Seconds without a message before a player is dropped.
+    pub client_timeout: u64,
+    /// Seconds to vote for the next level.
+    pub intermission: u64,
+    /// Shown to players when they join, nothing if empty.
+    pub motd: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            name: "Multiplayer FPS server".to_string(),
+            bind: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: DEFAULT_PORT,
+            max_players: 8,
+            tick_rate: 60,
+            // Respawning for a score penalty suits a shared server better
+            // than running out of lives
+            mode: GameMode::Deathmatch,
+            friendly_fire: FriendlyFire::Off,
+            rotation: Vec::new(),
+            client_timeout: 10,
+            intermission: INTERMISSION.as_secs(),
+            motd: String::new(),
+        }
+    }
+}
+
+/// What the command line asks for.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Run(Config),
+    PrintDefaultConfig,
+    Help,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read { path: PathBuf, error: io::Error },
+    Parse { path: PathBuf, error: ron::error::SpannedError },
+    UnknownFlag(String),
+    MissingValue(&'static str),
+    BadValue { flag: &'static str, value: String },
+    /// A setting out of its allowed range.
+    Invalid { setting: &'static str, reason: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Read { path, error } => write!(f, "can't read {}: {}", path.display(), error),
+            ConfigError::Parse { path, error } => write!(f, "{} is not a valid config: {}", path.display(), error),
+            ConfigError::UnknownFlag(flag) => write!(f, "unknown option '{}'", flag),
+            ConfigError::MissingValue(flag) => write!(f, "{} needs a value", flag),
+            ConfigError::BadValue { flag, value } => write!(f, "'{}' is not a valid value for {}", value, flag),
+            ConfigError::Invalid { setting, reason } => write!(f, "{} {}", setting, reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Flags that take a value.
+const VALUE_FLAGS: [&str; 12] = [
+    "--config",
+    "--name",
+    "--bind",
+    "--port",
+    "--max-players",
+    "--tick-rate",
+    "--mode",
+    "--friendly-fire",
+    "--rotation",
+    "--timeout",
+    "--intermission",
+    "--motd",
+];
+
+impl Config {
+    /// Parses the command line, without the program name. Settings come
+    /// from the config file first, then from the flags.
+    pub fn from_args(args: &[String]) -> Result<Command, ConfigError> {
+        let default_file = Path::new(DEFAULT_CONFIG_FILE);
+        Self::parse_args(args, default_file.exists().then_some(default_file))
+    }
+
+    /// [`from_args`](Self::from_args) with the file to read when there's
+    /// no `--config`, if any.
+    fn parse_args(args: &[String], default_file: Option<&Path>) -> Result<Command, ConfigError> {
+        let mut flags: Vec<(&'static str, String)> = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--help" | "-h" => return Ok(Command::Help),
+                "--print-default-config" => return Ok(Command::PrintDefaultConfig),
+                _ => {}
+            }
+            // Both `--port 1234` and `--port=1234`
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+            let flag = *VALUE_FLAGS
+                .iter()
+                .find(|flag| **flag == name)
+                .ok_or_else(|| ConfigError::UnknownFlag(arg.clone()))?;
+            let value = match inline {
+                Some(value) => value,
+                None => args.next().cloned().ok_or(ConfigError::MissingValue(flag))?,
+            };
+            flags.push((flag, value));
+        }
+
+        let mut config = match flags.iter().rev().find(|(flag, _)| *flag == "--config") {
+            Some((_, path)) => Self::load(Path::new(path))?,
+            None => match default_file {
+                Some(path) => Self::load(path)?,
+                None => Self::default(),
+            },
+        };
+        for (flag, value) in flags {
+            config.set(flag, value)?;
+        }
+        config.validate()?;
+        Ok(Command::Run(config))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|error| ConfigError::Read { path: path.to_path_buf(), error })?;
+        ron::from_str(&text).map_err(|error| ConfigError::Parse { path: path.to_path_buf(), error })
+    }
+
+    fn set(&mut self, flag: &'static str, value: String) -> Result<(), ConfigError> {
+        fn parse<T: std::str::FromStr>(flag: &'static str, value: &str) -> Result<T, ConfigError> {
+            value.trim().parse().map_err(|_| ConfigError::BadValue { flag, value: value.to_string() })
+        }
+        match flag {
+            "--name" => self.name = value.trim().to_string(),
+            "--bind" => self.bind = parse(flag, &value)?,
+            "--port" => self.port = parse(flag, &value)?,
+            "--max-players" => self.max_players = parse(flag, &value)?,
+            "--tick-rate" => self.tick_rate = parse(flag, &value)?,
+            "--mode" => {
+                self.mode = GameMode::parse(value.trim()).ok_or(ConfigError::BadValue { flag, value: value.clone() })?
+            }
+            "--friendly-fire" => {
+                self.friendly_fire =
+                    FriendlyFire::parse(value.trim()).ok_or(ConfigError::BadValue { flag, value: value.clone() })?
+            }
+            "--rotation" => self.rotation = value.split(',').map(|entry| entry.trim().to_string()).collect(),
+            "--timeout" => self.client_timeout = parse(flag, &value)?,
+            "--intermission" => self.intermission = parse(flag, &value)?,
+            "--motd" => self.motd = value,
+            _ => {} // --config, already read
+        }
+        Ok(())
+    }
+
+    /// Checks every setting is in its allowed range.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |setting, reason: &str| Err(ConfigError::Invalid { setting, reason: reason.to_string() });
+        if self.name.is_empty() || self.name.chars().count() > MAX_NAME_LEN {
+            return invalid("name", &format!("must be 1 to {} characters", MAX_NAME_LEN));
+        }
+        if self.name.chars().any(char::is_control) {
+            return invalid("name", "can't contain line breaks or control characters");
+        }
+        if !(1..=MAX_PLAYERS).contains(&self.max_players) {
+            return invalid("max_players", &format!("must be between 1 and {}", MAX_PLAYERS));
+        }
+        if !(10..=240).contains(&self.tick_rate) {
+            return invalid("tick_rate", "must be between 10 and 240");
+        }
+        if !(1..=3600).contains(&self.client_timeout) {
+            return invalid("client_timeout", "must be between 1 and 3600 seconds");
+        }
+        if !(1..=300).contains(&self.intermission) {
+            return invalid("intermission", "must be between 1 and 300 seconds");
+        }
+        if self.rotation.iter().any(String::is_empty) {
+            return invalid("rotation", "has an empty entry");
+        }
+        if self.motd.chars().count() > MAX_MOTD_LEN {
+            return invalid("motd", &format!("is longer than {} characters", MAX_MOTD_LEN));
+        }
+        if self.motd.chars().any(char::is_control) {
+            return invalid("motd", "can't contain line breaks or control characters");
+        }
+        Ok(())
+    }
+
+    /// The config file form, with a comment saying what it is.
+    pub fn to_ron(&self) -> String {
+        let pretty = ron::ser::PrettyConfig::new().indentor("    ".to_string());
+        let body = ron::ser::to_string_pretty(self, pretty).unwrap_or_default();
+        format!("// Multiplayer FPS server config, see `server --help`\n{}\n", body)
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        SocketAddr::new(self.bind, self.port)
+    }
+
+    pub fn tick(&self) -> Duration {
+        Duration::from_secs_f32(1.0 / self.tick_rate as f32)
+    }
+
+    pub fn client_timeout(&self) -> Duration {
+        Duration::from_secs(self.client_timeout)
+    }
+
+    pub fn intermission(&self) -> Duration {
+        Duration::from_secs(self.intermission)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    /// Parses `line` as if there were no config file next to the server.
+    fn parse(line: &str) -> Result<Command, ConfigError> {
+        Config::parse_args(&args(line), None)
+    }
+
+    fn run(line: &str) -> Result<Config, ConfigError> {
+        match parse(line)? {
+            Command::Run(config) => Ok(config),
+            command => panic!("expected a config, got {:?}", command),
+        }
+    }
+
+    #[test]
+    fn flags_override_the_defaults() {
+        let config = run("--port 4000 --bind=127.0.0.1 --mode ctf --rotation 1,arena --max-players 2 --name Office --friendly-fire=reflect").unwrap();
+        assert_eq!(config.name, "Office");
+        assert_eq!(config.address(), "127.0.0.1:4000".parse().unwrap());
+        assert_eq!((config.mode, config.friendly_fire), (GameMode::CaptureTheFlag, FriendlyFire::Reflect));
+        assert_eq!(config.rotation, vec!["1", "arena"]);
+        assert_eq!(config.max_players, 2);
+        assert_eq!(config.tick_rate, Config::default().tick_rate);
+        assert_eq!(parse("--port 1 --help").unwrap(), Command::Help);
+    }
+
+    #[test]
+    fn bad_values_are_reported() {
+        assert!(matches!(run("--port 70000"), Err(ConfigError::BadValue { flag: "--port", .. })));
+        assert!(matches!(run("--mode tag"), Err(ConfigError::BadValue { flag: "--mode", .. })));
+        assert!(matches!(run("--friendly-fire half"), Err(ConfigError::BadValue { flag: "--friendly-fire", .. })));
+        assert!(matches!(run("--port"), Err(ConfigError::MissingValue("--port"))));
+        assert!(matches!(run("--colour red"), Err(ConfigError::UnknownFlag(_))));
+        assert!(matches!(run("--tick-rate 5"), Err(ConfigError::Invalid { setting: "tick_rate", .. })));
+        assert!(matches!(run("--max-players 0"), Err(ConfigError::Invalid { setting: "max_players", .. })));
+        assert!(matches!(run("--max-players 65"), Err(ConfigError::Invalid { setting: "max_players", .. })));
+        assert!(matches!(run("--rotation 1,,2"), Err(ConfigError::Invalid { setting: "rotation", .. })));
+    }
+
+    #[test]
+    fn default_config_file_reads_back() {
+        let text = Config::default().to_ron();
+        assert_eq!(ron::from_str::<Config>(&text).unwrap(), Config::default());
+        // Missing settings keep their defaults
+        let partial: Config = ron::from_str("(port: 5000, mode: Survival)").unwrap();
+        assert_eq!((partial.port, partial.max_players), (5000, Config::default().max_players));
+        assert!(ron::from_str::<Config>("(prot: 5000)").is_err());
+    }
+}
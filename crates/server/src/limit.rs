@@ -0,0 +1,50 @@
+//! Keeping players from doing things faster than they're allowed to.
+
+use std::time::{Duration, Instant};
+
+/// How many times something may be done, refilling over time: a burst in a
+/// row, then once per refill.
+pub struct RateLimit {
+    burst: f32,
+    refill: Duration,
+    allowance: f32,
+    last: Instant,
+}
+
+impl RateLimit {
+    pub fn new(now: Instant, burst: f32, refill: Duration) -> Self {
+        Self { burst, refill, allowance: burst, last: now }
+    }
+
+    /// Whether it may be done at `now`. Counts it if so.
+    pub fn allow(&mut self, now: Instant) -> bool {
+        let earned = now.saturating_duration_since(self.last).as_secs_f32() / self.refill.as_secs_f32();
+        self.allowance = (self.allowance + earned).min(self.burst);
+        self.last = now;
+        if self.allowance < 1.0 {
+            return false;
+        }
+        self.allowance -= 1.0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_burst_is_allowed_then_one_per_refill() {
+        let (start, refill) = (Instant::now(), Duration::from_secs(2));
+        let mut limit = RateLimit::new(start, 4.0, refill);
+        assert!((0..4).all(|_| limit.allow(start)));
+        assert!(!limit.allow(start));
+        assert!(!limit.allow(start + refill / 2));
+        assert!(limit.allow(start + refill));
+        assert!(!limit.allow(start + refill));
+        // A long quiet spell doesn't save up more than a burst
+        let later = start + refill * 100;
+        assert!((0..4).all(|_| limit.allow(later)));
+        assert!(!limit.allow(later));
+    }
+}
@@ -1,31 +1,893 @@
-use std::net::UdpSocket;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use shared::flag::{FlagEvent, Flags};
+use shared::level::{Level, LevelSource, PickupKind};
+use shared::maze::Maze;
+use shared::mechanism::Mechanisms;
+use shared::mode::{GameMode, Life};
+use shared::movement::{self, PlayerState};
+use shared::pickup::{Boosts, Pickups, DAMAGE_BOOST, HEALTH_PACK};
+use shared::protocol::{
+    sanitize_chat, sanitize_username, ChatMessage, ClientMessage, PlayerSnapshot, PlayerStatus, ServerInfo, ServerMessage,
+    ServerStatus, UsernameError, MAX_DATAGRAM, SERVER_CHAT_ID,
+};
+use shared::reliable::{Inbox, Outbox};
+use shared::score::{ScoreEvent, Tally};
+use shared::team::{overfull_team, smaller_team, FriendlyFire, Team};
+use shared::transfer;
+use shared::trap::{Exposure, TrapEffect};
+use shared::weapon::WeaponKind;
+use shared::CELL_SIZE;
+
+use config::{Command, Config, USAGE};
+use limit::RateLimit;
+use rotation::{Rotation, Vote};
+
+mod chat;
+mod config;
+mod limit;
+mod rotation;
+
+const MAX_HEALTH: i32 = 100;
+
+/// How often players are pinged and sent the scoreboard.
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Movement steps a player may get ahead of real time, a quarter of a
+/// second's worth like a client catching up after a hitch. Inputs beyond
+/// that are dropped, so sending them faster doesn't move anyone faster.
+const INPUT_BURST: f32 = movement::STEP_RATE as f32 / 4.0;
+
+/// Hits a player may land at once, like a rocket catching a few players.
+/// After that one per shot of the fastest firing weapon.
+const HIT_BURST: f32 = 4.0;
+
+struct Player {
+    id: u32,
+    username: String,
+    state: PlayerState,
+    last_input: u32,
+    /// One step per [`movement::STEP_SECONDS`].
+    step_budget: RateLimit,
+    /// Hits on other players, see [`HIT_BURST`].
+    hit_budget: RateLimit,
+    last_seen: Instant,
+    health: i32,
+    exposure: Exposure,
+    boosts: Boosts,
+    life: Life,
+    /// `None` in modes without teams.
+    team: Option<Team>,
+    tally: Tally,
+    /// Whether the client says it can't carry more ammo.
+    ammo_full: bool,
+    /// Enemies killed on this level. Each client has its own, so nobody can
+    /// kill more than the level has.
+    level_kills: usize,
+    /// Last measured round trip.
+    ping: Option<Duration>,
+    /// Reliable messages taken from this player.
+    inbox: Inbox,
+    /// Messages that have to reach this player, such as chat and deaths.
+    outbox: Outbox<ServerMessage>,
+    chat_limit: RateLimit,
+}
+
+impl Player {
+    /// Back to a spawn point with full health.
+    fn respawn(&mut self, state: PlayerState) {
+        self.state = state;
+        self.health = MAX_HEALTH;
+        self.exposure.reset();
+        self.boosts = Boosts::default();
+    }
+
+    /// Takes damage unless already dead. Returns the message announcing the
+    /// death if this killed them.
+    fn hurt(&mut self, damage: i32, by: &str) -> Option<ServerMessage> {
+        if !self.life.is_alive() {
+            return None;
+        }
+        self.health = (self.health - damage).max(0);
+        if self.health > 0 {
+            return None;
+        }
+        println!("{} was killed by {}", self.username, by);
+        self.life.die(by);
+        self.tally.record(self.life.mode(), ScoreEvent::Death);
+        Some(ServerMessage::Died { player_id: self.id, killer: by.to_string(), lives: self.life.lives() })
+    }
+
+    /// Turns what the player typed into a chat message for the others.
+    /// `None` when there's nothing to say or they're saying it too often,
+    /// in which case they're told so.
+    fn say(&mut self, text: &str, team: bool) -> Option<ChatMessage> {
+        let text = sanitize_chat(text);
+        if text.is_empty() {
+            return None;
+        }
+        if !self.chat_limit.allow(Instant::now()) {
+            self.outbox.push(server_notice(chat::TOO_FAST));
+            return None;
+        }
+        println!("{}{}: {}", if team { "(team) " } else { "" }, self.username, text);
+        Some(ChatMessage { player_id: self.id, username: self.username.clone(), team, text })
+    }
+}
+
+struct Game {
+    mode: GameMode,
+    friendly_fire: FriendlyFire,
+    /// How long the vote between rounds lasts.
+    intermission: Duration,
+    rotation: Rotation,
+    /// Where the current level came from, sent to clients so they load the same one.
+    source: LevelSource,
+    layout: Level,
+    /// The level in RON form, handed out in chunks to clients that don't have it.
+    file: String,
+    hash: u64,
+    maze: Maze,
+    /// Doors, switches and push walls, written into `maze` as they move.
+    mechanisms: Mechanisms,
+    pickups: Pickups,
+    /// Only played with in capture the flag.
+    flags: Flags,
+    /// Kills or captures this round, by [`Team::index`].
+    team_scores: [u32; 2],
+    /// Open between rounds while players pick the next level.
+    vote: Option<Vote>,
+}
+
+impl Game {
+    fn new(config: &Config, rotation: Rotation) -> Self {
+        let (source, layout) = rotation.current();
+        let mut game = Self {
+            mode: config.mode,
+            friendly_fire: config.friendly_fire,
+            intermission: config.intermission(),
+            rotation,
+            source,
+            file: layout.to_ron().unwrap_or_default(),
+            hash: layout.content_hash(),
+            maze: layout.to_maze(),
+            mechanisms: Mechanisms::new(&layout),
+            pickups: Pickups::default(),
+            flags: Flags::new(&layout),
+            team_scores: [0; 2],
+            layout,
+            vote: None,
+        };
+        game.pickups = game.level_pickups();
+        game
+    }
+
+    /// Items for the current level. Levels without any get them scattered
+    /// from the level hash, the same spots a client would pick offline.
+    fn level_pickups(&self) -> Pickups {
+        Pickups::new(&self.layout, &self.maze, self.hash)
+    }
+
+    fn set_level(&mut self, source: LevelSource, layout: Level) {
+        self.file = layout.to_ron().unwrap_or_default();
+        self.hash = layout.content_hash();
+        self.maze = layout.to_maze();
+        self.mechanisms = Mechanisms::new(&layout);
+        self.flags = Flags::new(&layout);
+        self.source = source;
+        self.layout = layout;
+        self.pickups = self.level_pickups();
+    }
+
+    /// Where player `id` starts, spread over the level's spawn points so
+    /// players don't all appear on top of each other. Teams start on their
+    /// own side.
+    fn spawn_state(&self, id: u32, team: Option<Team>) -> PlayerState {
+        let slot = id.saturating_sub(1) as usize;
+        let (x, y) = match team {
+            Some(team) => self.layout.team_spawn(team, slot),
+            None => self.layout.player_spawn(slot),
+        };
+        PlayerState::new(x, y)
+    }
+
+    /// What anyone looking for a game is told.
+    fn info(&self, config: &Config, players: usize) -> ServerInfo {
+        ServerInfo {
+            name: config.name.clone(),
+            level: self.layout.name.clone(),
+            mode: self.mode,
+            players,
+            max_players: config.max_players,
+        }
+    }
+
+    fn status(&self, config: &Config, players: &HashMap<SocketAddr, Player>, uptime: Duration) -> ServerStatus {
+        ServerStatus {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            uptime: uptime.as_secs(),
+            info: self.info(config, players.len()),
+            players: scoreboard(players),
+        }
+    }
+
+    fn level_message(&self) -> ServerMessage {
+        ServerMessage::Level { source: self.source.clone(), hash: self.hash }
+    }
+
+    /// Ends the round, scores it and opens the vote for the next level.
+    /// `finisher` is who ended it: a player at the exit or the winning team.
+    fn start_intermission(&mut self, finisher: &str, players: &mut HashMap<SocketAddr, Player>) {
+        for player in players.values_mut() {
+            player.tally.record(self.mode, ScoreEvent::Round);
+        }
+        self.vote = Some(Vote::new(&self.rotation, finisher, self.intermission));
+    }
+
+    /// The team that has reached the mode's kill or capture limit, if one has.
+    fn winning_team(&self) -> Option<Team> {
+        let limit = self.mode.team_score_limit()?;
+        Team::ALL.into_iter().find(|team| self.team_scores[team.index()] >= limit)
+    }
+
+    /// Drops the flag `player` carries where they are, e.g. because they died.
+    fn drop_flag(&mut self, player: &Player) -> Option<ServerMessage> {
+        let team = self.flags.drop(player.id, player.state.x, player.state.y)?;
+        println!("{} dropped the {} flag", player.username, team.label());
+        Some(ServerMessage::Flag { player_id: Some(player.id), team, event: FlagEvent::Dropped })
+    }
+
+    /// `from` shot player `target_id`. The client aims, so the server
+    /// checks what it can: the shooter has to be alive, the target within
+    /// reach of the longest range weapon with nothing but open cells in
+    /// between, and hits can't come faster than the fastest weapon fires.
+    /// Damage is capped at the hardest hitting weapon with a damage boost.
+    /// Rocket splash doesn't reach round corners. Teammates get what the
+    /// friendly fire setting says. Returns the deaths and dropped flags it
+    /// caused.
+    fn hit(&mut self, from: SocketAddr, target_id: u32, damage: i32, players: &mut HashMap<SocketAddr, Player>) -> Vec<ServerMessage> {
+        let mut events = Vec::new();
+        // Players keep moving during the intermission, but the round is already over
+        if !self.mode.players_fight() || self.vote.is_some() {
+            return events;
+        }
+        let Some(target_at) = players.values().find(|player| player.id == target_id).map(|player| (player.state.x, player.state.y))
+        else {
+            return events;
+        };
+        let Some(shooter) = players.get_mut(&from).filter(|player| player.life.is_alive() && player.id != target_id) else {
+            return events;
+        };
+        // The shooter aimed at where the last snapshot had the target, a
+        // cell of leeway covers the moving since
+        let reach = WeaponKind::ALL
+            .into_iter()
+            .map(|kind| kind.def().range + kind.def().splash_radius)
+            .fold(0.0, f32::max)
+            + CELL_SIZE;
+        let shooter_at = (shooter.state.x, shooter.state.y);
+        let in_reach = (target_at.0 - shooter_at.0).hypot(target_at.1 - shooter_at.1) <= reach;
+        if !in_reach || !self.maze.clear_line(shooter_at, target_at) || !shooter.hit_budget.allow(Instant::now()) {
+            return events;
+        }
+        let (shooter_name, shooter_team) = (shooter.username.clone(), shooter.team);
+        let Some(target) = players.values_mut().find(|player| player.id == target_id) else { return events };
+
+        let max = WeaponKind::ALL.into_iter().map(|kind| kind.def().damage).max().unwrap_or(0) * DAMAGE_BOOST;
+        let damage = damage.clamp(0, max);
+        let teammates = shooter_team.is_some() && shooter_team == target.team;
+        let (to_target, to_shooter) = if teammates { self.friendly_fire.split(damage) } else { (damage, 0) };
+        if let Some(death) = target.hurt(to_target, &shooter_name) {
+            events.push(death);
+            events.extend(self.drop_flag(target));
+            // Taking out a teammate earns nothing
+            if let Some(shooter) = players.get_mut(&from).filter(|_| !teammates) {
+                shooter.tally.record(self.mode, ScoreEvent::Kill);
+                if let (Some(team), GameMode::TeamDeathmatch) = (shooter.team, self.mode) {
+                    self.team_scores[team.index()] += 1;
+                }
+            }
+        }
+        if let Some(shooter) = players.get_mut(&from) {
+            if let Some(death) = shooter.hurt(to_shooter, "Friendly fire") {
+                events.push(death);
+                events.extend(self.drop_flag(shooter));
+            }
+        }
+        events
+    }
+
+    /// Runs the timers of dropped flags and lets players take, return and
+    /// capture flags they touch. Returns what happened for the kill feed.
+    fn update_flags(&mut self, players: &mut HashMap<SocketAddr, Player>, seconds: f32) -> Vec<ServerMessage> {
+        if !self.mode.has_flags() || self.vote.is_some() {
+            return Vec::new();
+        }
+        let mut events = Vec::new();
+        for team in self.flags.update(seconds) {
+            println!("The {} flag went back to its base", team.label());
+            events.push(ServerMessage::Flag { player_id: None, team, event: FlagEvent::Returned });
+        }
+        for player in players.values_mut().filter(|player| player.life.is_alive()) {
+            let Some(team) = player.team else { continue };
+            let Some((flag, event)) = self.flags.touch(player.id, team, player.state.x, player.state.y) else { continue };
+            match event {
+                FlagEvent::Captured => {
+                    player.tally.record(self.mode, ScoreEvent::Capture);
+                    self.team_scores[team.index()] += 1;
+                }
+                FlagEvent::Returned => player.tally.record(self.mode, ScoreEvent::Return),
+                FlagEvent::Taken | FlagEvent::Dropped => {}
+            }
+            println!("{} {} the {} flag", player.username, event.name(), flag.label());
+            events.push(ServerMessage::Flag { player_id: Some(player.id), team: flag, event });
+        }
+        events
+    }
+
+    /// Moves players from a team two or more bigger than the other until
+    /// they're even, latest to join first, leaving flag carriers alone. The
+    /// living start over on their new side. Returns their respawns.
+    fn balance_teams(&self, players: &mut HashMap<SocketAddr, Player>) -> Vec<ServerMessage> {
+        let mut respawns = Vec::new();
+        loop {
+            let count = |team| players.values().filter(|player| player.team == Some(team)).count();
+            let Some(from) = overfull_team(count(Team::Red), count(Team::Blue)) else { break };
+            let Some(player) = players
+                .values_mut()
+                .filter(|player| player.team == Some(from) && self.flags.carried_by(player.id).is_none())
+                .max_by_key(|player| player.id)
+            else {
+                break;
+            };
+            let team = from.other();
+            player.team = Some(team);
+            println!("{} moved to the {} team to even the teams", player.username, team.label());
+            player.outbox.push(server_notice(&format!("You were moved to the {} team to even the teams", team.label())));
+            if player.life.is_alive() {
+                player.respawn(self.spawn_state(player.id, player.team));
+                respawns.push(ServerMessage::Respawn { player_id: player.id });
+            }
+        }
+        respawns
+    }
+
+    /// Hurts players standing on hazards or raised spikes and moves those
+    /// who stepped onto a teleporter. Returns the deaths and dropped flags
+    /// it caused.
+    fn apply_traps(&mut self, players: &mut HashMap<SocketAddr, Player>, seconds: f32) -> Vec<ServerMessage> {
+        let mut deaths = Vec::new();
+        for player in players.values_mut().filter(|player| player.life.is_alive()) {
+            let (x, y) = (player.state.x, player.state.y);
+            match player.exposure.step(self.mechanisms.traps(), x, y, seconds) {
+                Some(TrapEffect::Damage(kind, damage)) => {
+                    if let Some(death) = player.hurt(damage, kind.label()) {
+                        deaths.push(death);
+                        deaths.extend(self.drop_flag(player));
+                    }
+                }
+                Some(TrapEffect::Teleport { x, y }) => {
+                    player.state.x = x;
+                    player.state.y = y;
+                }
+                None => {}
+            }
+        }
+        deaths
+    }
+
+    /// Runs the respawn countdowns and brings back players whose time is
+    /// up. Returns their ids.
+    fn respawn_players(&self, players: &mut HashMap<SocketAddr, Player>, seconds: f32) -> Vec<u32> {
+        let mut respawned = Vec::new();
+        for player in players.values_mut() {
+            if player.life.update(seconds) {
+                player.life.respawn();
+                player.respawn(self.spawn_state(player.id, player.team));
+                respawned.push(player.id);
+            }
+        }
+        respawned
+    }
+
+    /// Runs respawn and boost timers, gives items to players touching them
+    /// and tells each what they took.
+    fn hand_out_pickups(&mut self, players: &mut HashMap<SocketAddr, Player>, seconds: f32) {
+        self.pickups.update(seconds);
+        for player in players.values_mut().filter(|player| player.life.is_alive()) {
+            player.boosts.update(seconds);
+            let (hurt, ammo_full) = (player.health < MAX_HEALTH, player.ammo_full);
+            let wants = |kind| match kind {
+                PickupKind::Health => hurt,
+                PickupKind::Ammo => !ammo_full,
+                PickupKind::Speed | PickupKind::Damage => true,
+            };
+            let Some(kind) = self.pickups.collect(player.state.x, player.state.y, wants) else { continue };
+            match kind {
+                PickupKind::Health => player.health = (player.health + HEALTH_PACK).min(MAX_HEALTH),
+                // Ammo is counted by the client
+                PickupKind::Ammo => {}
+                PickupKind::Speed | PickupKind::Damage => player.boosts.grant(kind),
+            }
+            player.outbox.push(ServerMessage::Collected { player_id: player.id, kind });
+        }
+    }
+
+    /// Closes the vote, loads the winning level and moves everyone to their spawns.
+    fn finish_intermission(&mut self, players: &mut HashMap<SocketAddr, Player>) {
+        let Some(vote) = self.vote.take() else { return };
+        self.rotation.advance_to(vote.winner());
+        let (source, layout) = self.rotation.current();
+        self.set_level(source, layout);
+        self.team_scores = [0; 2];
+        for player in players.values_mut() {
+            player.level_kills = 0;
+        }
+        // The dead come back on the new level when their countdown is up
+        for player in players.values_mut().filter(|player| player.life.is_alive()) {
+            player.respawn(self.spawn_state(player.id, player.team));
+        }
+        println!("Next level: {} ({})", self.layout.name, self.source.encode());
+    }
+}
+
+/// Seconds between shots of the fastest firing weapon.
+fn fastest_fire_interval() -> Duration {
+    let seconds = WeaponKind::ALL.into_iter().map(|kind| kind.def().fire_interval).fold(f32::MAX, f32::min);
+    Duration::from_secs_f32(seconds)
+}
+
+fn send(socket: &UdpSocket, msg: &ServerMessage, addr: SocketAddr) {
+    if let Err(e) = socket.send_to(msg.encode().as_bytes(), addr) {
+        eprintln!("Error sending to {}: {}", addr, e);
+    }
+}
+
+fn broadcast(socket: &UdpSocket, msg: &ServerMessage, players: &HashMap<SocketAddr, Player>) {
+    let encoded = msg.encode();
+    for addr in players.keys() {
+        if let Err(e) = socket.send_to(encoded.as_bytes(), addr) {
+            eprintln!("Error sending to {}: {}", addr, e);
+        }
+    }
+}
+
+/// Queues a message that has to arrive for every player. It goes out with
+/// the next tick and again until acknowledged.
+fn broadcast_reliable(msg: &ServerMessage, players: &mut HashMap<SocketAddr, Player>) {
+    for player in players.values_mut() {
+        player.outbox.push(msg.clone());
+    }
+}
+
+/// Takes reliable message `seq` from the player at `addr` if it's the next
+/// one, and acknowledges it either way. Nothing is taken from strangers.
+fn take_reliable(socket: &UdpSocket, players: &mut HashMap<SocketAddr, Player>, addr: SocketAddr, seq: u32) -> bool {
+    let Some(player) = players.get_mut(&addr) else { return false };
+    player.last_seen = Instant::now();
+    let next = player.inbox.accept(seq);
+    send(socket, &ServerMessage::Ack { seq: player.inbox.received() }, addr);
+    next
+}
+
+/// Every player's tally and ping, by id.
+fn scoreboard(players: &HashMap<SocketAddr, Player>) -> Vec<PlayerStatus> {
+    let mut list: Vec<PlayerStatus> = players
+        .values()
+        .map(|p| PlayerStatus {
+            id: p.id,
+            username: p.username.clone(),
+            kills: p.tally.kills,
+            deaths: p.tally.deaths,
+            score: p.tally.score,
+            ping: p.ping.map(|ping| ping.as_millis() as u32),
+        })
+        .collect();
+    list.sort_by_key(|p| p.id);
+    list
+}
+
+/// Queues a chat message for everyone it's meant for, sender included:
+/// the sender's team for team chat, everyone otherwise.
+fn deliver_chat(message: ChatMessage, players: &mut HashMap<SocketAddr, Player>) {
+    let sender = players.values().find(|player| player.id == message.player_id);
+    let team = sender.and_then(|sender| sender.team).filter(|_| message.team);
+    for player in players.values_mut().filter(|player| team.is_none() || player.team == team) {
+        player.outbox.push(ServerMessage::Chat(message.clone()));
+    }
+}
+
+/// A chat message from the server itself.
+fn server_notice(text: &str) -> ServerMessage {
+    ServerMessage::Chat(ChatMessage { player_id: SERVER_CHAT_ID, username: "Server".to_string(), team: false, text: text.to_string() })
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config = match Config::from_args(&args) {
+        Ok(Command::Run(config)) => config,
+        Ok(Command::PrintDefaultConfig) => {
+            print!("{}", Config::default().to_ron());
+            return Ok(());
+        }
+        Ok(Command::Help) => {
+            print!("{}", USAGE);
+            return Ok(());
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprintln!("Run with --help to see the options");
+            std::process::exit(2);
+        }
+    };
+
     println!("=== Multiplayer FPS Server ===");
-    
-    let socket = UdpSocket::bind("127.0.0.1:34254")
-        .or_else(|_| UdpSocket::bind("127.0.0.1:34255"))
-        .or_else(|_| UdpSocket::bind("127.0.0.1:34256"))?;
-    
+
+    let socket = UdpSocket::bind(config.address()).map_err(|e| format!("can't listen on {}: {}", config.address(), e))?;
+
     let addr = socket.local_addr()?;
-    println!("Server listening on {}", addr);
+    println!("{} listening on {}", config.name, addr);
     println!("Waiting for clients to connect...");
-    
-    let mut buf = [0; 1024];
-    
+
+    let tick = config.tick();
+    socket.set_read_timeout(Some(tick))?;
+
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or_default();
+    println!("Level seed: {}", seed);
+    let rotation = if config.rotation.is_empty() {
+        Rotation::endless(seed)
+    } else {
+        Rotation::parse(&config.rotation.join(","), seed).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(2);
+        })
+    };
+    let mut game = Game::new(&config, rotation);
+    println!("Mode: {}, up to {} players", game.mode.name(), config.max_players);
+    if game.mode.has_teams() {
+        println!("Friendly fire: {}", config.friendly_fire.name());
+    }
+    println!("First level: {} ({})", game.layout.name, game.source.encode());
+    let mut players: HashMap<SocketAddr, Player> = HashMap::new();
+    let mut next_player_id = 1;
+    let started = Instant::now();
+    let mut last_tick = Instant::now();
+    let mut last_ping = Instant::now();
+    // Mechanisms as last sent, to send them again only when they change
+    let mut last_world = None;
+    let mut buf = [0; MAX_DATAGRAM];
+
     loop {
         match socket.recv_from(&mut buf) {
             Ok((size, src)) => {
                 let msg = String::from_utf8_lossy(&buf[..size]);
-                println!("Received from {}: {}", src, msg);
-                
-                // Echo back for now
-                let response = format!("Server received: {}", msg);
-                socket.send_to(response.as_bytes(), src)?;
+                let decoded = ClientMessage::decode(&msg);
+                if decoded.is_none() {
+                    eprintln!("Ignoring malformed message from {}: {}", src, msg);
+                }
+                let message = match decoded {
+                    // Handled like any other the first time, repeats are only acknowledged
+                    Some(ClientMessage::Reliable { seq, message }) => take_reliable(&socket, &mut players, src, seq).then_some(*message),
+                    message => message,
+                };
+                match message {
+                    Some(ClientMessage::Connect { username })
+                        if !players.contains_key(&src) && players.len() >= config.max_players =>
+                    {
+                        println!("Turned away {} from {}: server full", sanitize_username(&username), src);
+                        send(&socket, &ServerMessage::Reject { reason: "server is full".to_string() }, src);
+                    }
+                    Some(ClientMessage::Connect { username }) if sanitize_username(&username).is_empty() => {
+                        send(&socket, &ServerMessage::Reject { reason: UsernameError::Empty.to_string() }, src);
+                    }
+                    Some(ClientMessage::Connect { username }) => {
+                        let count = |team| players.values().filter(|player| player.team == Some(team)).count();
+                        let team = game.mode.has_teams().then(|| smaller_team(count(Team::Red), count(Team::Blue)));
+                        let player = players.entry(src).or_insert_with(|| {
+                            let username = sanitize_username(&username);
+                            let id = next_player_id;
+                            next_player_id += 1;
+                            match team {
+                                Some(team) => println!("Player {} ({}) connected from {}, {} team", id, username, src, team.label()),
+                                None => println!("Player {} ({}) connected from {}", id, username, src),
+                            }
+                            // Without it the player is stuck on whatever maze they had
+                            let mut outbox = Outbox::default();
+                            outbox.push(game.level_message());
+                            Player {
+                                id,
+                                username,
+                                state: game.spawn_state(id, team),
+                                last_input: 0,
+                                step_budget: RateLimit::new(
+                                    Instant::now(),
+                                    INPUT_BURST,
+                                    Duration::from_secs_f32(movement::STEP_SECONDS),
+                                ),
+                                hit_budget: RateLimit::new(Instant::now(), HIT_BURST, fastest_fire_interval()),
+                                last_seen: Instant::now(),
+                                health: MAX_HEALTH,
+                                exposure: Exposure::default(),
+                                boosts: Boosts::default(),
+                                life: Life::new(game.mode),
+                                team,
+                                tally: Tally::default(),
+                                ammo_full: false,
+                                level_kills: 0,
+                                ping: None,
+                                inbox: Inbox::default(),
+                                outbox,
+                                chat_limit: chat::rate_limit(Instant::now()),
+                            }
+                        });
+                        send(&socket, &ServerMessage::Accept { player_id: player.id, mode: game.mode }, src);
+                        if !config.motd.is_empty() {
+                            send(&socket, &ServerMessage::Motd(config.motd.clone()), src);
+                        }
+                        if let Some(vote) = &game.vote {
+                            send(&socket, &vote.message(), src);
+                        }
+                    }
+                    Some(ClientMessage::Input { seq, input }) => {
+                        let mut finisher = None;
+                        if let Some(player) = players.get_mut(&src) {
+                            player.last_seen = Instant::now();
+                            // Inputs arriving late or twice have already been applied,
+                            // the dead stay where they fell and nobody moves faster
+                            // than the steps they've had time for
+                            if seq > player.last_input && player.life.is_alive() && player.step_budget.allow(Instant::now()) {
+                                player.last_input = seq;
+                                let speed = player.boosts.speed_factor();
+                                let (state, _) = movement::step_player_at(&game.maze, &player.state, &input, game.layout.player_radius(), speed);
+                                player.state = state;
+                                // Team rounds are won by kills or captures, not the exit
+                                if game.maze.reached_exit(state.x, state.y) && !game.mode.has_teams() {
+                                    finisher = Some(player.username.clone());
+                                }
+                            }
+                        }
+                        // Players keep moving during the intermission, but the round is already over
+                        if let (Some(finisher), None) = (finisher, &game.vote) {
+                            println!("{} reached the exit, voting for the next level", finisher);
+                            game.start_intermission(&finisher, &mut players);
+                            // The round summary ranks players by these
+                            broadcast(&socket, &ServerMessage::Scores(scoreboard(&players)), &players);
+                        }
+                    }
+                    Some(ClientMessage::Use) => {
+                        if let Some(player) = players.get(&src) {
+                            let positions: Vec<(f32, f32)> = players.values().map(|p| (p.state.x, p.state.y)).collect();
+                            let state = player.state;
+                            game.mechanisms.use_from(&mut game.maze, &positions, state.x, state.y, state.angle);
+                        }
+                    }
+                    Some(ClientMessage::Vote { option }) => {
+                        if let (Some(player), Some(vote)) = (players.get(&src), &mut game.vote) {
+                            if vote.cast(player.id, option) {
+                                broadcast(&socket, &vote.message(), &players);
+                            }
+                        }
+                    }
+                    Some(ClientMessage::Hurt { damage, by }) => {
+                        // Enemies run on the client, so this can't be checked, only bounded
+                        if let Some(player) = players.get_mut(&src) {
+                            if let Some(death) = player.hurt(damage.clamp(0, MAX_HEALTH), by.label()) {
+                                let dropped = game.drop_flag(player);
+                                broadcast_reliable(&death, &mut players);
+                                if let Some(dropped) = dropped {
+                                    broadcast_reliable(&dropped, &mut players);
+                                }
+                            }
+                        }
+                    }
+                    Some(ClientMessage::Kill { victim }) => {
+                        // Like damage from enemies this can't be checked, only bounded:
+                        // the living can kill what the level has, no more
+                        let enemies = game.layout.enemies.len();
+                        let on_level = game.layout.enemies.iter().any(|enemy| enemy.kind == victim);
+                        let killer = players
+                            .get_mut(&src)
+                            .filter(|player| on_level && player.life.is_alive() && player.level_kills < enemies);
+                        if let Some(player) = killer {
+                            println!("{} killed a {}", player.username, victim.label());
+                            player.level_kills += 1;
+                            player.tally.record(game.mode, ScoreEvent::Kill);
+                            let kill = ServerMessage::Kill { player_id: player.id, victim: victim.label().to_string() };
+                            broadcast_reliable(&kill, &mut players);
+                        }
+                    }
+                    Some(ClientMessage::Hit { player_id, damage }) => {
+                        for event in game.hit(src, player_id, damage, &mut players) {
+                            broadcast_reliable(&event, &mut players);
+                        }
+                    }
+                    Some(ClientMessage::AmmoFull(full)) => {
+                        if let Some(player) = players.get_mut(&src) {
+                            player.ammo_full = full;
+                        }
+                    }
+                    Some(ClientMessage::Restart) => {
+                        if let Some(player) = players.get_mut(&src).filter(|player| player.life.lives() == Some(0)) {
+                            println!("{} starts over", player.username);
+                            player.life.restart();
+                            player.tally = Tally::default();
+                            player.respawn(game.spawn_state(player.id, player.team));
+                            let respawn = ServerMessage::Respawn { player_id: player.id };
+                            broadcast_reliable(&respawn, &mut players);
+                        }
+                    }
+                    Some(ClientMessage::Fetch { hash, chunk }) => {
+                        // Only players get level data, and only for the level being
+                        // played, so the server can't be used to send big replies to strangers
+                        let data = transfer::chunk(&game.file, chunk).filter(|_| hash == game.hash);
+                        if let (true, Some(data)) = (players.contains_key(&src), data) {
+                            let count = transfer::chunk_count(game.file.len());
+                            let reply = ServerMessage::Chunk { hash, index: chunk, count, data: data.to_vec() };
+                            send(&socket, &reply, src);
+                        }
+                    }
+                    Some(ClientMessage::Disconnect) => {
+                        if let Some(player) = players.remove(&src) {
+                            println!("Player {} ({}) disconnected", player.id, player.username);
+                            if let Some(vote) = &mut game.vote {
+                                vote.forget(player.id);
+                            }
+                            if let Some(dropped) = game.drop_flag(&player) {
+                                broadcast_reliable(&dropped, &mut players);
+                            }
+                            for respawn in game.balance_teams(&mut players) {
+                                broadcast_reliable(&respawn, &mut players);
+                            }
+                        }
+                    }
+                    Some(ClientMessage::Pong { token }) => {
+                        // The token is when the ping went out, in milliseconds since start
+                        let now = started.elapsed().as_millis() as u64;
+                        if let Some(player) = players.get_mut(&src).filter(|_| token <= now) {
+                            player.last_seen = Instant::now();
+                            player.ping = Some(Duration::from_millis(now - token));
+                        }
+                    }
+                    Some(ClientMessage::Say { team, text }) => {
+                        // Team chat is everyone's chat in modes without teams
+                        let team = team && game.mode.has_teams();
+                        if let Some(message) = players.get_mut(&src).and_then(|player| player.say(&text, team)) {
+                            deliver_chat(message, &mut players);
+                        }
+                    }
+                    Some(ClientMessage::Ack { seq }) => {
+                        if let Some(player) = players.get_mut(&src) {
+                            player.outbox.ack(seq);
+                        }
+                    }
+                    // Anyone may ask these, players or not
+                    Some(ClientMessage::Discover { token }) => {
+                        let info = game.info(&config, players.len());
+                        send(&socket, &ServerMessage::Info { token, info }, src);
+                    }
+                    Some(ClientMessage::Query { token }) => {
+                        let status = game.status(&config, &players, started.elapsed());
+                        send(&socket, &ServerMessage::Status { token, status }, src);
+                    }
+                    // Repeats, and reliable messages are never wrapped twice
+                    Some(ClientMessage::Reliable { .. }) | None => {}
+                }
             }
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
             Err(e) => {
                 eprintln!("Error receiving data: {}", e);
             }
         }
+
+        if last_tick.elapsed() >= tick {
+            last_tick = Instant::now();
+
+            let mut dropped = Vec::new();
+            let now = Instant::now();
+            players.retain(|_, p| {
+                let alive = p.last_seen.elapsed() < config.client_timeout();
+                // Still sending, but no longer taking what it's sent
+                let stalled = p.outbox.stalled(now, config.client_timeout());
+                if !alive {
+                    println!("Player {} ({}) timed out", p.id, p.username);
+                } else if stalled {
+                    println!("Player {} ({}) stopped acknowledging messages", p.id, p.username);
+                }
+                if !alive || stalled {
+                    if let Some(vote) = &mut game.vote {
+                        vote.forget(p.id);
+                    }
+                    dropped.extend(game.drop_flag(p));
+                }
+                alive && !stalled
+            });
+            if !dropped.is_empty() {
+                dropped.extend(game.balance_teams(&mut players));
+            }
+            for event in dropped {
+                broadcast_reliable(&event, &mut players);
+            }
+
+            if let Some(vote) = &mut game.vote {
+                if vote.is_over() {
+                    game.finish_intermission(&mut players);
+                    broadcast_reliable(&game.level_message(), &mut players);
+                } else if vote.countdown_changed() {
+                    broadcast(&socket, &vote.message(), &players);
+                }
+            }
+
+            let positions: Vec<(f32, f32)> = players.values().map(|p| (p.state.x, p.state.y)).collect();
+            game.mechanisms.update(&mut game.maze, &positions, tick.as_secs_f32());
+            for death in game.apply_traps(&mut players, tick.as_secs_f32()) {
+                broadcast_reliable(&death, &mut players);
+            }
+            for player_id in game.respawn_players(&mut players, tick.as_secs_f32()) {
+                broadcast_reliable(&ServerMessage::Respawn { player_id }, &mut players);
+            }
+            game.hand_out_pickups(&mut players, tick.as_secs_f32());
+            for event in game.update_flags(&mut players, tick.as_secs_f32()) {
+                broadcast_reliable(&event, &mut players);
+            }
+            if let (Some(team), None) = (game.winning_team(), &game.vote) {
+                println!("The {} team wins the round, voting for the next level", team.label());
+                game.start_intermission(&format!("{} team", team.label()), &mut players);
+                broadcast(&socket, &ServerMessage::Scores(scoreboard(&players)), &players);
+                broadcast(&socket, &ServerMessage::TeamScores(game.team_scores), &players);
+            }
+
+            if last_ping.elapsed() >= PING_INTERVAL {
+                last_ping = Instant::now();
+                let token = started.elapsed().as_millis() as u64;
+                broadcast(&socket, &ServerMessage::Ping { token }, &players);
+                // Now and then regardless, for players who just joined or
+                // missed the last change
+                last_world = None;
+                broadcast(&socket, &ServerMessage::Scores(scoreboard(&players)), &players);
+                if game.mode.has_teams() {
+                    broadcast(&socket, &ServerMessage::TeamScores(game.team_scores), &players);
+                }
+            }
+
+            let now = Instant::now();
+            for (addr, player) in players.iter_mut() {
+                for (seq, message) in player.outbox.due(now) {
+                    send(&socket, &ServerMessage::Reliable { seq, message: Box::new(message) }, *addr);
+                }
+            }
+
+            let snapshot = ServerMessage::Snapshot {
+                players: players
+                    .values()
+                    .map(|p| PlayerSnapshot {
+                        id: p.id,
+                        username: p.username.clone(),
+                        x: p.state.x,
+                        y: p.state.y,
+                        angle: p.state.angle,
+                        pitch: p.state.pitch,
+                        last_input: p.last_input,
+                        health: p.health,
+                        team: p.team,
+                    })
+                    .collect(),
+            };
+            broadcast(&socket, &snapshot, &players);
+            let world = game.mechanisms.state();
+            if !game.mechanisms.is_empty() && last_world.as_ref() != Some(&world) {
+                broadcast(&socket, &ServerMessage::World(world.clone()), &players);
+                last_world = Some(world);
+            }
+            if !game.pickups.is_empty() {
+                broadcast(&socket, &ServerMessage::Pickups(game.pickups.items().to_vec()), &players);
+            }
+            if game.mode.has_flags() {
+                let flags = game.flags.flags().iter().map(|flag| (flag.team, flag.state)).collect();
+                broadcast(&socket, &ServerMessage::Flags(flags), &players);
+            }
+        }
     }
-}
\ No newline at end of file
+}
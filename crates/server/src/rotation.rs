@@ -0,0 +1,239 @@
+//! Which levels the server plays, and the vote for the next one between
+//! rounds.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use shared::level::{Level, LevelIssue, LevelSource, LoadError};
+use shared::protocol::{ServerMessage, VoteOption};
+
+/// How long players get to vote after a round, unless configured otherwise.
+pub const INTERMISSION: Duration = Duration::from_secs(15);
+
+/// How many levels are on the ballot.
+pub const VOTE_OPTIONS: usize = 3;
+
+/// The levels a server cycles through. Without a list it plays the numbered
+/// levels one after another forever, like single player.
+pub struct Rotation {
+    // Levels from the list, loaded up front so a broken file is reported
+    // when the server starts rather than in the middle of a match
+    levels: Vec<(LevelSource, Level)>,
+    seed: u64,
+    /// Index into `levels`, or the level number when there is no list.
+    current: usize,
+}
+
+#[derive(Debug)]
+pub enum RotationError {
+    Empty,
+    BadEntry(String),
+    Load { entry: String, error: LoadError },
+    Invalid { entry: String, issue: LevelIssue },
+}
+
+impl fmt::Display for RotationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RotationError::Empty => write!(f, "the level rotation is empty"),
+            RotationError::BadEntry(entry) => write!(f, "'{}' is not a level number, number@seed or level name", entry),
+            RotationError::Load { entry, error } => write!(f, "can't load level '{}': {}", entry, error),
+            RotationError::Invalid { entry, issue } => write!(f, "level '{}' is broken: {}", entry, issue),
+        }
+    }
+}
+
+impl std::error::Error for RotationError {}
+
+impl Rotation {
+    /// Numbered levels from 1 on, generated from `seed` past the hand-made ones.
+    pub fn endless(seed: u64) -> Self {
+        Self { levels: Vec::new(), seed, current: 1 }
+    }
+
+    /// Parses a comma separated list of levels. A number is that level with
+    /// the server's `seed`, `number@seed` picks the seed and anything else
+    /// is the name of a file in `levels/`.
+    pub fn parse(spec: &str, seed: u64) -> Result<Self, RotationError> {
+        let mut levels = Vec::new();
+        for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let source = LevelSource::from_arg(entry, seed).ok_or_else(|| RotationError::BadEntry(entry.to_string()))?;
+            let level = Level::from_source(&source).map_err(|error| RotationError::Load {
+                entry: entry.to_string(),
+                error,
+            })?;
+            if let Some(issue) = level.validate().into_iter().next() {
+                return Err(RotationError::Invalid { entry: entry.to_string(), issue });
+            }
+            levels.push((source, level));
+        }
+        if levels.is_empty() {
+            return Err(RotationError::Empty);
+        }
+        Ok(Self { levels, seed, current: 0 })
+    }
+
+    /// The level being played.
+    pub fn current(&self) -> (LevelSource, Level) {
+        self.at(self.current)
+    }
+
+    fn at(&self, position: usize) -> (LevelSource, Level) {
+        match self.levels.get(position) {
+            Some(entry) => entry.clone(),
+            None => (
+                LevelSource::Numbered { number: position, seed: self.seed },
+                Level::numbered(position, self.seed),
+            ),
+        }
+    }
+
+    /// Positions of the next few levels, the one that would come next
+    /// without a vote first. A short list offers fewer.
+    pub fn upcoming(&self, count: usize) -> Vec<usize> {
+        if self.levels.is_empty() {
+            return (1..=count).map(|step| self.current + step).collect();
+        }
+        (1..=count.min(self.levels.len()))
+            .map(|step| (self.current + step) % self.levels.len())
+            .collect()
+    }
+
+    pub fn advance_to(&mut self, position: usize) {
+        self.current = position;
+    }
+}
+
+/// Ballot for the next level, open during the intermission.
+pub struct Vote {
+    /// Player who finished the round.
+    finisher: String,
+    /// Rotation position and name of each option.
+    options: Vec<(usize, String)>,
+    // Player id to the option they picked
+    ballots: HashMap<u32, usize>,
+    ends: Instant,
+    // Countdown value last sent to clients
+    announced: Option<u32>,
+}
+
+impl Vote {
+    pub fn new(rotation: &Rotation, finisher: &str, length: Duration) -> Self {
+        let options = rotation
+            .upcoming(VOTE_OPTIONS)
+            .into_iter()
+            .map(|position| (position, rotation.at(position).1.name))
+            .collect();
+        Self {
+            finisher: finisher.to_string(),
+            options,
+            ballots: HashMap::new(),
+            ends: Instant::now() + length,
+            announced: None,
+        }
+    }
+
+    /// Records a player's vote, replacing an earlier one. Returns false for
+    /// an option that isn't on the ballot.
+    pub fn cast(&mut self, player: u32, option: usize) -> bool {
+        if option >= self.options.len() {
+            return false;
+        }
+        self.ballots.insert(player, option);
+        true
+    }
+
+    /// Drops the vote of a player who left.
+    pub fn forget(&mut self, player: u32) {
+        self.ballots.remove(&player);
+    }
+
+    fn tally(&self) -> Vec<u32> {
+        let mut votes = vec![0; self.options.len()];
+        for &option in self.ballots.values() {
+            votes[option] += 1;
+        }
+        votes
+    }
+
+    /// Rotation position of the option with the most votes. Ties, including
+    /// nobody voting, go to the earlier option.
+    pub fn winner(&self) -> usize {
+        let votes = self.tally();
+        let best = (0..votes.len()).rev().max_by_key(|&option| votes[option]).unwrap_or(0);
+        self.options.get(best).map_or(0, |(position, _)| *position)
+    }
+
+    pub fn seconds_left(&self) -> u32 {
+        self.ends.saturating_duration_since(Instant::now()).as_secs_f32().ceil() as u32
+    }
+
+    pub fn is_over(&self) -> bool {
+        Instant::now() >= self.ends
+    }
+
+    /// Whether the countdown moved on since this was last called, so clients
+    /// get one update per second rather than one per tick.
+    pub fn countdown_changed(&mut self) -> bool {
+        let seconds = self.seconds_left();
+        let changed = self.announced != Some(seconds);
+        self.announced = Some(seconds);
+        changed
+    }
+
+    pub fn message(&self) -> ServerMessage {
+        ServerMessage::Intermission {
+            seconds_left: self.seconds_left(),
+            finisher: self.finisher.clone(),
+            options: self
+                .options
+                .iter()
+                .zip(self.tally())
+                .map(|((_, name), votes)| VoteOption { name: name.clone(), votes })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_numbers_and_seeds() {
+        let rotation = Rotation::parse("2, 5@77", 9).unwrap();
+        assert_eq!(rotation.current().0, LevelSource::Numbered { number: 2, seed: 9 });
+        assert_eq!(rotation.at(1).0, LevelSource::Numbered { number: 5, seed: 77 });
+        assert!(matches!(Rotation::parse("1,../x", 9), Err(RotationError::BadEntry(_))));
+        assert!(matches!(Rotation::parse(" , ", 9), Err(RotationError::Empty)));
+    }
+
+    #[test]
+    fn upcoming_levels_wrap_around_the_list() {
+        let mut rotation = Rotation::parse("1,2,3,4", 0).unwrap();
+        rotation.advance_to(2);
+        assert_eq!(rotation.upcoming(VOTE_OPTIONS), vec![3, 0, 1]);
+        assert_eq!(Rotation::parse("1,2", 0).unwrap().upcoming(VOTE_OPTIONS), vec![1, 0]);
+        assert_eq!(Rotation::endless(0).upcoming(VOTE_OPTIONS), vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn most_votes_win_and_ties_go_to_the_next_level() {
+        let rotation = Rotation::endless(0);
+        let mut vote = Vote::new(&rotation, "alice", INTERMISSION);
+        assert_eq!(vote.winner(), 2);
+
+        assert!(vote.cast(1, 2));
+        assert!(vote.cast(2, 1));
+        assert!(!vote.cast(3, VOTE_OPTIONS));
+        assert_eq!(vote.winner(), 3);
+
+        // Changing a vote replaces it
+        assert!(vote.cast(2, 2));
+        assert_eq!(vote.winner(), 4);
+        vote.forget(1);
+        vote.forget(2);
+        assert_eq!(vote.winner(), 2);
+    }
+}
@@ -0,0 +1,39 @@
+//! Two players chatting through a real server.
+
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use shared::protocol::SERVER_CHAT_ID;
+
+use common::{free_ports, start_server, Player};
+
+#[test]
+fn chat_reaches_everyone_once_and_floods_are_stopped() {
+    let port = free_ports(1)[0];
+    let _server = start_server(port, &["--mode", "deathmatch"]);
+    let mut alice = Player::join(port, "alice");
+    let mut bob = Player::join(port, "bob");
+
+    alice.say(1, false, "hello, bob: ready?");
+    // Sent again as if the acknowledgement got lost, it still arrives once
+    alice.say(1, false, "hello, bob: ready?");
+    bob.wait_for_chat(1);
+    let hello = &bob.chat[0];
+    assert_eq!((hello.username.as_str(), hello.text.as_str(), hello.team), ("alice", "hello, bob: ready?", false));
+
+    // Three more fill the burst, the fifth in a row is refused
+    for seq in 2..=5 {
+        alice.say(seq, false, &format!("spam {}", seq));
+    }
+    alice.wait_for_chat(5);
+    let texts: Vec<&str> = alice.chat.iter().map(|message| message.text.as_str()).collect();
+    assert_eq!(texts[..4], ["hello, bob: ready?", "spam 2", "spam 3", "spam 4"]);
+    assert_eq!(alice.chat[4].player_id, SERVER_CHAT_ID);
+    // The refusal only goes to alice
+    bob.wait_for_chat(4);
+    thread::sleep(Duration::from_millis(300));
+    bob.wait_for_chat(4);
+    assert!(bob.chat.iter().all(|message| message.player_id != SERVER_CHAT_ID), "bob got {:?}", bob.chat);
+}
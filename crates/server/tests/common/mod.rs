@@ -0,0 +1,134 @@
+//! Runs the real server binary for tests, and clients to talk to it.
+
+// Each test binary only uses some of this
+#![allow(dead_code)]
+
+use std::net::{Ipv4Addr, UdpSocket};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use shared::protocol::{ChatMessage, ClientMessage, PlayerSnapshot, ServerMessage};
+use shared::reliable::Inbox;
+
+/// Stops the server when the test ends, passed or not.
+pub struct Server(Child);
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Starts a server on loopback with the given extra flags.
+pub fn start_server(port: u16, args: &[&str]) -> Server {
+    let child = Command::new(env!("CARGO_BIN_EXE_server"))
+        .args(["--bind", "127.0.0.1", "--port", &port.to_string()])
+        .args(args)
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("server should start");
+    Server(child)
+}
+
+/// `count` different free ports. The sockets are all held at once so the
+/// ports differ.
+pub fn free_ports(count: usize) -> Vec<u16> {
+    let sockets: Vec<UdpSocket> = (0..count).map(|_| UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap()).collect();
+    sockets.iter().map(|socket| socket.local_addr().unwrap().port()).collect()
+}
+
+/// A client that joins and keeps track of its chat.
+pub struct Player {
+    socket: UdpSocket,
+    pub id: u32,
+    inbox: Inbox,
+    pub chat: Vec<ChatMessage>,
+    /// Highest `Say` the server acknowledged.
+    acked: u32,
+}
+
+impl Player {
+    pub fn join(port: u16, name: &str) -> Self {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        socket.connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+        socket.set_nonblocking(true).unwrap();
+        let mut player = Self { socket, id: 0, inbox: Inbox::default(), chat: Vec::new(), acked: 0 };
+        let connect = ClientMessage::Connect { username: name.to_string() };
+        let mut id = None;
+        let accepted = player.until(
+            |_, msg| match msg {
+                ServerMessage::Accept { player_id, .. } => id.replace(*player_id).is_none(),
+                _ => false,
+            },
+            &connect,
+        );
+        assert!(accepted, "{} wasn't let in", name);
+        player.id = id.unwrap();
+        player
+    }
+
+    pub fn send(&self, msg: &ClientMessage) {
+        self.socket.send(msg.encode().as_bytes()).unwrap();
+    }
+
+    /// Sends `msg` now and then and reads everything that comes back until
+    /// `done` says so. Reliable messages are taken and acknowledged on the
+    /// way, `done` sees what they carry. False if it takes too long.
+    pub fn until(&mut self, mut done: impl FnMut(&Self, &ServerMessage) -> bool, msg: &ClientMessage) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(15);
+        let mut buf = vec![0; 65536];
+        while Instant::now() < deadline {
+            self.send(msg);
+            thread::sleep(Duration::from_millis(20));
+            while let Ok(size) = self.socket.recv(&mut buf) {
+                let Some(mut reply) = ServerMessage::decode(&String::from_utf8_lossy(&buf[..size])) else { continue };
+                if let ServerMessage::Reliable { seq, message } = reply {
+                    let next = self.inbox.accept(seq);
+                    self.send(&ClientMessage::Ack { seq: self.inbox.received() });
+                    if !next {
+                        continue;
+                    }
+                    reply = *message;
+                }
+                match &reply {
+                    ServerMessage::Chat(message) => self.chat.push(message.clone()),
+                    ServerMessage::Ack { seq } => self.acked = self.acked.max(*seq),
+                    _ => {}
+                }
+                if done(self, &reply) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    pub fn say(&mut self, seq: u32, team: bool, text: &str) {
+        let say = ClientMessage::Reliable { seq, message: Box::new(ClientMessage::Say { team, text: text.to_string() }) };
+        assert!(self.until(|player, _| player.acked >= seq, &say), "message {} wasn't acknowledged", seq);
+    }
+
+    /// Waits for `count` chat messages in all.
+    pub fn wait_for_chat(&mut self, count: usize) {
+        // Pongs keep the connection alive while waiting
+        let pong = ClientMessage::Pong { token: 0 };
+        let arrived = self.until(|player, _| player.chat.len() >= count, &pong);
+        assert!(arrived, "got {:?}", self.chat);
+    }
+
+    /// The first snapshot `accept` is happy with.
+    pub fn snapshot(&mut self, accept: impl Fn(&[PlayerSnapshot]) -> bool) -> Vec<PlayerSnapshot> {
+        let mut found = None;
+        let pong = ClientMessage::Pong { token: 0 };
+        self.until(
+            |_, msg| match msg {
+                ServerMessage::Snapshot { players } if accept(players) => found.replace(players.clone()).is_none(),
+                _ => false,
+            },
+            &pong,
+        );
+        found.expect("no snapshot like that arrived")
+    }
+}
@@ -0,0 +1,40 @@
+//! Starts two real servers on loopback and finds both of them.
+
+mod common;
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use shared::discovery::Discovery;
+use shared::mode::GameMode;
+
+use common::{free_ports, start_server};
+
+#[test]
+fn finds_two_servers_on_different_ports() {
+    let ports = free_ports(2);
+    let _servers = [
+        start_server(ports[0], &["--name", "Alpha", "--mode", "survival", "--max-players", "4"]),
+        start_server(ports[1], &["--name", "Bravo", "--mode", "deathmatch", "--max-players", "4"]),
+    ];
+
+    let targets = ports.iter().map(|&port| SocketAddr::from((Ipv4Addr::LOCALHOST, port))).collect();
+    let mut discovery = Discovery::new(targets).unwrap();
+    let deadline = Instant::now() + Duration::from_secs(15);
+    while discovery.servers().len() < 2 && Instant::now() < deadline {
+        discovery.update();
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    let found = discovery.servers();
+    assert_eq!(found.len(), 2, "found {:?}", found);
+    assert_eq!((found[0].info.name.as_str(), found[0].address.port()), ("Alpha", ports[0]));
+    assert_eq!((found[1].info.name.as_str(), found[1].address.port()), ("Bravo", ports[1]));
+    assert_eq!((found[0].info.mode, found[1].info.mode), (GameMode::Survival, GameMode::Deathmatch));
+    for server in found {
+        assert_eq!((server.info.players, server.info.max_players), (0, 4));
+        assert!(!server.info.level.is_empty());
+        assert!(server.ping < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,55 @@
+//! Asks a real server for its status while a player is connected.
+
+mod common;
+
+use std::net::{Ipv4Addr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use shared::protocol::{ClientMessage, ServerMessage, ServerStatus};
+
+use common::{free_ports, start_server};
+
+fn send(socket: &UdpSocket, msg: &ClientMessage) {
+    socket.send(msg.encode().as_bytes()).unwrap();
+}
+
+#[test]
+fn status_lists_players_with_their_ping() {
+    let port = free_ports(1)[0];
+    let _server = start_server(port, &["--name", "Status test", "--mode", "survival"]);
+
+    let player = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    player.connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+    player.set_nonblocking(true).unwrap();
+    let monitor = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    monitor.connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+    monitor.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+
+    // Keep connecting and answering pings until a query shows the ping
+    let deadline = Instant::now() + Duration::from_secs(15);
+    let mut status: Option<ServerStatus> = None;
+    let mut buf = vec![0; 65536];
+    while Instant::now() < deadline && !status.as_ref().is_some_and(|s| s.players.iter().any(|p| p.ping.is_some())) {
+        send(&player, &ClientMessage::Connect { username: "alice".to_string() });
+        while let Ok(size) = player.recv(&mut buf) {
+            if let Some(ServerMessage::Ping { token }) = ServerMessage::decode(&String::from_utf8_lossy(&buf[..size])) {
+                send(&player, &ClientMessage::Pong { token });
+            }
+        }
+        send(&monitor, &ClientMessage::Query { token: 9 });
+        if let Ok(size) = monitor.recv(&mut buf) {
+            let answer = ServerMessage::decode(&String::from_utf8_lossy(&buf[..size]));
+            if let Some(ServerMessage::Status { token: 9, status: answer }) = answer {
+                status = Some(answer);
+            }
+        }
+    }
+
+    let status = status.expect("the server should answer queries");
+    assert_eq!(status.info.name, "Status test");
+    assert_eq!(status.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!((status.info.players, status.players.len()), (1, 1));
+    let alice = &status.players[0];
+    assert_eq!((alice.username.as_str(), alice.score), ("alice", 0));
+    assert!(alice.ping.is_some_and(|ping| ping < 1000), "{:?}", alice);
+}
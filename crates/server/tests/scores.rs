@@ -0,0 +1,79 @@
+//! A player's kills scored by a real server.
+
+mod common;
+
+use std::net::{Ipv4Addr, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use shared::level::Level;
+use shared::protocol::{ClientMessage, PlayerStatus, ServerMessage};
+use shared::reliable::{Inbox, Outbox};
+use shared::score::KILL_POINTS;
+
+use common::{free_ports, start_server};
+
+#[test]
+fn kills_are_scored_but_no_more_than_the_level_has_enemies() {
+    let port = free_ports(1)[0];
+    let _server = start_server(port, &["--mode", "survival", "--rotation", "1"]);
+    let level = Level::numbered(1, 0);
+    let enemies = level.enemies.len();
+    assert!(enemies > 0);
+    let victim = level.enemies[0].kind;
+
+    let player = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    player.connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+    player.set_nonblocking(true).unwrap();
+    let send = |msg: &ClientMessage| player.send(msg.encode().as_bytes()).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(15);
+    let mut buf = vec![0; 65536];
+    let (mut inbox, mut outbox) = (Inbox::default(), Outbox::default());
+    let mut joined = false;
+    let mut kills_sent = 0;
+    let mut all_sent: Option<Instant> = None;
+    let mut feed = 0;
+    let mut scores: Option<PlayerStatus> = None;
+    while Instant::now() < deadline && scores.is_none() {
+        if !joined {
+            send(&ClientMessage::Connect { username: "alice".to_string() });
+        } else if kills_sent < enemies + 2 {
+            // More kills than there are enemies, the extra ones don't count
+            outbox.push(ClientMessage::Kill { victim });
+            kills_sent += 1;
+        } else if all_sent.is_none() && outbox.is_empty() {
+            all_sent = Some(Instant::now());
+        }
+        for (seq, message) in outbox.due(Instant::now()) {
+            send(&ClientMessage::Reliable { seq, message: Box::new(message) });
+        }
+        thread::sleep(Duration::from_millis(20));
+        while let Ok(size) = player.recv(&mut buf) {
+            let mut reply = ServerMessage::decode(&String::from_utf8_lossy(&buf[..size]));
+            if let Some(ServerMessage::Reliable { seq, message }) = reply {
+                let next = inbox.accept(seq);
+                send(&ClientMessage::Ack { seq: inbox.received() });
+                reply = next.then_some(*message);
+            }
+            match reply {
+                Some(ServerMessage::Accept { .. }) => joined = true,
+                Some(ServerMessage::Ack { seq }) => outbox.ack(seq),
+                Some(ServerMessage::Kill { victim: name, .. }) => {
+                    assert_eq!(name, victim.label());
+                    feed += 1;
+                }
+                // Only a scoreboard from after the last kill was handled
+                Some(ServerMessage::Scores(players)) if all_sent.is_some_and(|at| at.elapsed() > Duration::from_millis(200)) => {
+                    scores = players.into_iter().find(|p| p.username == "alice");
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let own = scores.expect("the server should send scores");
+    assert_eq!((own.kills as usize, own.deaths), (enemies, 0));
+    assert_eq!(own.score, enemies as i32 * KILL_POINTS);
+    assert_eq!(feed, enemies);
+}
@@ -0,0 +1,45 @@
+//! Three players in a team deathmatch on a real server.
+
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use shared::protocol::{ClientMessage, PlayerSnapshot};
+use shared::team::Team;
+
+use common::{free_ports, start_server, Player};
+
+fn find(players: &[PlayerSnapshot], id: u32) -> &PlayerSnapshot {
+    players.iter().find(|player| player.id == id).unwrap()
+}
+
+#[test]
+fn teams_are_even_talk_among_themselves_and_only_hurt_the_other_team() {
+    let port = free_ports(1)[0];
+    let _server = start_server(port, &["--mode", "team-deathmatch"]);
+    let mut alice = Player::join(port, "alice");
+    let mut bob = Player::join(port, "bob");
+    let mut carol = Player::join(port, "carol");
+
+    let players = alice.snapshot(|players| players.len() == 3);
+    let teams: Vec<_> = [alice.id, bob.id, carol.id].iter().map(|&id| find(&players, id).team).collect();
+    assert_eq!(teams, [Some(Team::Red), Some(Team::Blue), Some(Team::Red)]);
+
+    // Team chat only reaches carol, chat to everyone reaches bob too
+    alice.say(1, true, "I'll take the left");
+    alice.say(2, false, "good luck");
+    carol.wait_for_chat(2);
+    assert_eq!((carol.chat[0].text.as_str(), carol.chat[0].team), ("I'll take the left", true));
+    bob.wait_for_chat(1);
+    thread::sleep(Duration::from_millis(300));
+    bob.wait_for_chat(1);
+    let texts: Vec<&str> = bob.chat.iter().map(|message| message.text.as_str()).collect();
+    assert_eq!(texts, ["good luck"]);
+
+    // Friendly fire is off by default, the hit on carol does nothing
+    alice.send(&ClientMessage::Hit { player_id: carol.id, damage: 30 });
+    alice.send(&ClientMessage::Hit { player_id: bob.id, damage: 30 });
+    let players = alice.snapshot(|players| players.iter().any(|player| player.id == bob.id && player.health < 100));
+    assert_eq!((find(&players, bob.id).health, find(&players, carol.id).health), (70, 100));
+}
@@ -0,0 +1,110 @@
+//! Server addresses as players type them: `host`, `host:port`, an IPv4 or
+//! IPv6 address, with the port defaulting to [`DEFAULT_PORT`].
+
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+
+use crate::DEFAULT_PORT;
+
+#[derive(Debug)]
+pub enum AddressError {
+    Empty,
+    BadHost(String),
+    BadPort(String),
+    /// The host name didn't resolve to any address.
+    Unresolved { host: String, error: io::Error },
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AddressError::Empty => write!(f, "no server address given"),
+            AddressError::BadHost(host) => write!(f, "'{}' is not a host name or IP address", host),
+            AddressError::BadPort(port) => write!(f, "'{}' is not a port number", port),
+            AddressError::Unresolved { host, error } => write!(f, "can't find server '{}': {}", host, error),
+        }
+    }
+}
+
+impl std::error::Error for AddressError {}
+
+/// Splits an address into host and port, without looking the host up.
+/// IPv6 addresses with a port go in brackets, `[::1]:34254`.
+pub fn parse(input: &str) -> Result<(String, u16), AddressError> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(AddressError::Empty);
+    }
+    let (host, port) = if let Some(rest) = input.strip_prefix('[') {
+        let (host, after) = rest.split_once(']').ok_or_else(|| AddressError::BadHost(input.to_string()))?;
+        match after {
+            "" => (host, None),
+            _ => (host, Some(after.strip_prefix(':').ok_or_else(|| AddressError::BadPort(after.to_string()))?)),
+        }
+    } else if input.matches(':').count() > 1 {
+        // A bare IPv6 address
+        (input, None)
+    } else {
+        match input.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (input, None),
+        }
+    };
+    let valid = !host.is_empty() && host.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':'));
+    if !valid {
+        return Err(AddressError::BadHost(host.to_string()));
+    }
+    let port = match port {
+        Some(port) => port.parse().ok().filter(|&port| port != 0).ok_or_else(|| AddressError::BadPort(port.to_string()))?,
+        None => DEFAULT_PORT,
+    };
+    Ok((host.to_string(), port))
+}
+
+/// Parses an address and looks the host up, preferring IPv4.
+pub fn resolve(input: &str) -> Result<SocketAddr, AddressError> {
+    let (host, port) = parse(input)?;
+    let unresolved = |error| AddressError::Unresolved { host: host.clone(), error };
+    let addresses: Vec<SocketAddr> = (host.as_str(), port).to_socket_addrs().map_err(unresolved)?.collect();
+    addresses
+        .iter()
+        .find(|address| address.is_ipv4())
+        .or(addresses.first())
+        .copied()
+        .ok_or_else(|| unresolved(io::Error::new(io::ErrorKind::NotFound, "no addresses")))
+}
+
+/// `host:port` the way it would be typed, brackets around IPv6 hosts.
+pub fn display(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn port_defaults_and_hosts_can_be_names() {
+        assert_eq!(parse("192.168.1.5").unwrap(), ("192.168.1.5".to_string(), DEFAULT_PORT));
+        assert_eq!(parse(" lan-box.local:4000 ").unwrap(), ("lan-box.local".to_string(), 4000));
+        assert_eq!(parse("::1").unwrap(), ("::1".to_string(), DEFAULT_PORT));
+        assert_eq!(parse("[::1]:4000").unwrap(), ("::1".to_string(), 4000));
+        assert_eq!(display("::1", 4000), "[::1]:4000");
+        assert_eq!(resolve("127.0.0.1").unwrap(), SocketAddr::from(([127, 0, 0, 1], DEFAULT_PORT)));
+    }
+
+    #[test]
+    fn bad_addresses_say_what_is_wrong() {
+        assert!(matches!(parse("  "), Err(AddressError::Empty)));
+        assert!(matches!(parse("host:http"), Err(AddressError::BadPort(_))));
+        assert!(matches!(parse("host:0"), Err(AddressError::BadPort(_))));
+        assert!(matches!(parse("host:99999"), Err(AddressError::BadPort(_))));
+        assert!(matches!(parse("my server"), Err(AddressError::BadHost(_))));
+        assert!(matches!(parse("[::1"), Err(AddressError::BadHost(_))));
+    }
+}
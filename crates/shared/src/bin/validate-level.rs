@@ -0,0 +1,48 @@
+//! Checks level files for mistakes before they ship.
+//!
+//! Usage: `cargo run -p shared --bin validate-level -- levels/*.ron`
+//!
+//! Exits with 0 when every level is fine, 1 when any level has problems and
+//! 2 when a file can't be read or parsed.
+
+use std::process::ExitCode;
+
+use shared::level::Level;
+
+fn main() -> ExitCode {
+    let paths: Vec<String> = std::env::args().skip(1).collect();
+    if paths.is_empty() {
+        eprintln!("Usage: validate-level <level.ron>...");
+        return ExitCode::from(2);
+    }
+
+    let mut invalid = false;
+    let mut unreadable = false;
+    for path in &paths {
+        let level = match Level::load(path) {
+            Ok(level) => level,
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                unreadable = true;
+                continue;
+            }
+        };
+        let issues = level.validate();
+        if issues.is_empty() {
+            println!("{}: ok", path);
+        } else {
+            invalid = true;
+            for issue in issues {
+                println!("{}: {}", path, issue);
+            }
+        }
+    }
+
+    if unreadable {
+        ExitCode::from(2)
+    } else if invalid {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
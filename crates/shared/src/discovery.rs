@@ -0,0 +1,117 @@
+//! Finding servers on the local network.
+//!
+//! A client broadcasts `DISCOVER` to the [`DISCOVERY_PORTS`], and sends it
+//! to the same ports on loopback so servers on this machine show up too.
+//! Every server listening there answers with `INFO`, and the time the
+//! answer took is its ping. Nothing here needs a connection.
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::protocol::{ClientMessage, ServerInfo, ServerMessage};
+use crate::DEFAULT_PORT;
+
+/// Ports searched: the default one and the next few, for several servers
+/// on one machine.
+pub const DISCOVERY_PORTS: RangeInclusive<u16> = DEFAULT_PORT..=DEFAULT_PORT + 9;
+
+/// How often to ask again, which finds servers started since and keeps
+/// the pings current.
+pub const REFRESH: Duration = Duration::from_secs(2);
+
+/// Servers that haven't answered for this long are dropped from the list.
+const EXPIRY: Duration = Duration::from_secs(5);
+
+/// A server that answered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoundServer {
+    pub address: SocketAddr,
+    pub info: ServerInfo,
+    pub ping: Duration,
+    last_seen: Instant,
+}
+
+/// Broadcast and loopback addresses for every discovery port.
+pub fn lan_targets() -> Vec<SocketAddr> {
+    DISCOVERY_PORTS
+        .flat_map(|port| [SocketAddr::from((Ipv4Addr::BROADCAST, port)), SocketAddr::from((Ipv4Addr::LOCALHOST, port))])
+        .collect()
+}
+
+/// Keeps a list of the servers answering at some addresses.
+pub struct Discovery {
+    socket: UdpSocket,
+    targets: Vec<SocketAddr>,
+    // Token of the last round of queries and when it went out
+    token: u64,
+    sent: Option<Instant>,
+    servers: Vec<FoundServer>,
+}
+
+impl Discovery {
+    /// Searches the local network, see [`lan_targets`].
+    pub fn lan() -> io::Result<Self> {
+        Self::new(lan_targets())
+    }
+
+    /// Searches only `targets`.
+    pub fn new(targets: Vec<SocketAddr>) -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_broadcast(true)?;
+        socket.set_nonblocking(true)?;
+        // Start somewhere different each run so late answers to an earlier
+        // run's queries aren't taken for this one's
+        let token = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_micros() as u64).unwrap_or_default();
+        Ok(Self { socket, targets, token, sent: None, servers: Vec::new() })
+    }
+
+    /// Asks again when it's time and reads the answers. Call every frame.
+    pub fn update(&mut self) {
+        if self.sent.is_none_or(|sent| sent.elapsed() >= REFRESH) {
+            self.token = self.token.wrapping_add(1);
+            self.sent = Some(Instant::now());
+            let query = ClientMessage::Discover { token: self.token }.encode();
+            for target in &self.targets {
+                // A network without broadcast shouldn't stop the rest
+                let _ = self.socket.send_to(query.as_bytes(), target);
+            }
+        }
+
+        let mut buf = [0; 1024];
+        while let Ok((size, address)) = self.socket.recv_from(&mut buf) {
+            let msg = String::from_utf8_lossy(&buf[..size]);
+            let Some(ServerMessage::Info { token, info }) = ServerMessage::decode(&msg) else { continue };
+            let Some(sent) = self.sent.filter(|_| token == self.token) else { continue };
+            self.found(FoundServer { address, info, ping: sent.elapsed(), last_seen: Instant::now() });
+        }
+        self.servers.retain(|server| server.last_seen.elapsed() < EXPIRY);
+    }
+
+    fn found(&mut self, server: FoundServer) {
+        // A server on this machine answers both the broadcast and loopback;
+        // list it once, under the address others can reach it at
+        let same = |other: &FoundServer| {
+            other.address == server.address
+                || (other.address.port() == server.address.port()
+                    && other.info.name == server.info.name
+                    && (other.address.ip().is_loopback() || server.address.ip().is_loopback()))
+        };
+        match self.servers.iter_mut().find(|other| same(other)) {
+            Some(other) if server.address.ip().is_loopback() && !other.address.ip().is_loopback() => {
+                other.last_seen = server.last_seen;
+            }
+            Some(other) => *other = server,
+            None => {
+                self.servers.push(server);
+                self.servers.sort_by(|a, b| a.info.name.cmp(&b.info.name).then(a.address.cmp(&b.address)));
+            }
+        }
+    }
+
+    /// Servers that answered recently, by name.
+    pub fn servers(&self) -> &[FoundServer] {
+        &self.servers
+    }
+}
@@ -0,0 +1,200 @@
+//! Capture the flag: each team's flag, who has it and what touching it does.
+//!
+//! Touching the other team's flag picks it up, at its base or wherever it
+//! was dropped. A carrier who dies or leaves drops it on the spot. Touching
+//! your own team's dropped flag sends it home, and so does leaving it lying
+//! for [`RETURN_SECONDS`]. Carrying the other flag to your base while your
+//! own flag is there captures it, which sends it home too.
+//!
+//! The server runs this and sends the flags around with every snapshot.
+
+use crate::level::Level;
+use crate::team::Team;
+use crate::CELL_SIZE;
+
+/// How close a player has to come to a flag or base to touch it, in world units.
+pub const FLAG_RANGE: f32 = CELL_SIZE * 0.5;
+
+/// Seconds a dropped flag lies around before it goes home by itself.
+pub const RETURN_SECONDS: f32 = 20.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlagState {
+    AtBase,
+    Carried { player_id: u32 },
+    /// Lying where its carrier fell, in world units, until it's picked up
+    /// or goes home in `return_in` seconds.
+    Dropped { x: f32, y: f32, return_in: f32 },
+}
+
+/// Something that happened to a flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlagEvent {
+    Taken,
+    Dropped,
+    Returned,
+    Captured,
+}
+
+impl FlagEvent {
+    pub const ALL: [FlagEvent; 4] = [FlagEvent::Taken, FlagEvent::Dropped, FlagEvent::Returned, FlagEvent::Captured];
+
+    /// Name on the wire, also how it reads in the kill feed.
+    pub fn name(self) -> &'static str {
+        match self {
+            FlagEvent::Taken => "took",
+            FlagEvent::Dropped => "dropped",
+            FlagEvent::Returned => "returned",
+            FlagEvent::Captured => "captured",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|event| event.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Flag {
+    pub team: Team,
+    /// Where it's kept, in world units.
+    pub base: (f32, f32),
+    pub state: FlagState,
+}
+
+impl Flag {
+    /// Where the flag lies, `None` while someone carries it.
+    pub fn position(&self) -> Option<(f32, f32)> {
+        match self.state {
+            FlagState::AtBase => Some(self.base),
+            FlagState::Dropped { x, y, .. } => Some((x, y)),
+            FlagState::Carried { .. } => None,
+        }
+    }
+}
+
+/// Both teams' flags.
+#[derive(Debug, Clone, Default)]
+pub struct Flags {
+    flags: Vec<Flag>,
+}
+
+impl Flags {
+    /// Every team's flag at its base, see [`Level::base`].
+    pub fn new(level: &Level) -> Self {
+        let flags = Team::ALL.map(|team| Flag { team, base: level.base(team), state: FlagState::AtBase });
+        Self { flags: flags.to_vec() }
+    }
+
+    pub fn flags(&self) -> &[Flag] {
+        &self.flags
+    }
+
+    /// The flag `player_id` carries, if any.
+    pub fn carried_by(&self, player_id: u32) -> Option<Team> {
+        self.flags.iter().find(|flag| flag.state == FlagState::Carried { player_id }).map(|flag| flag.team)
+    }
+
+    /// A player of `team` standing at `(x, y)`. Returns what happened to
+    /// which flag, if anything.
+    pub fn touch(&mut self, player_id: u32, team: Team, x: f32, y: f32) -> Option<(Team, FlagEvent)> {
+        let near = |(to_x, to_y): (f32, f32)| (to_x - x).hypot(to_y - y) < FLAG_RANGE;
+        let own_home = self.flags.iter().any(|flag| flag.team == team && flag.state == FlagState::AtBase && near(flag.base));
+        for flag in &mut self.flags {
+            let event = match flag.state {
+                FlagState::Carried { player_id: carrier } if carrier == player_id && own_home => FlagEvent::Captured,
+                FlagState::Dropped { x, y, .. } if flag.team == team && near((x, y)) => FlagEvent::Returned,
+                _ if flag.team != team && flag.position().is_some_and(near) => FlagEvent::Taken,
+                _ => continue,
+            };
+            flag.state = match event {
+                FlagEvent::Taken => FlagState::Carried { player_id },
+                _ => FlagState::AtBase,
+            };
+            return Some((flag.team, event));
+        }
+        None
+    }
+
+    /// Drops whatever `player_id` carries at `(x, y)`, because they died or
+    /// left. Returns the flag's team.
+    pub fn drop(&mut self, player_id: u32, x: f32, y: f32) -> Option<Team> {
+        let flag = self.flags.iter_mut().find(|flag| flag.state == FlagState::Carried { player_id })?;
+        flag.state = FlagState::Dropped { x, y, return_in: RETURN_SECONDS };
+        Some(flag.team)
+    }
+
+    /// Runs the timers of dropped flags. Returns the teams whose flag went
+    /// home because of it.
+    pub fn update(&mut self, seconds: f32) -> Vec<Team> {
+        let mut returned = Vec::new();
+        for flag in &mut self.flags {
+            if let FlagState::Dropped { return_in, .. } = &mut flag.state {
+                *return_in -= seconds;
+                if *return_in <= 0.0 {
+                    flag.state = FlagState::AtBase;
+                    returned.push(flag.team);
+                }
+            }
+        }
+        returned
+    }
+
+    /// Sets a flag to what the server says it is.
+    pub fn apply(&mut self, team: Team, state: FlagState) {
+        if let Some(flag) = self.flags.iter_mut().find(|flag| flag.team == team) {
+            flag.state = state;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flags() -> Flags {
+        let mut level = Level::from_maze("Test", 1, &crate::maze::Maze::solid(9, 3));
+        (level.start_x, level.start_y) = (1.5, 1.5);
+        (level.exit_x, level.exit_y) = (7.5, 1.5);
+        Flags::new(&level)
+    }
+
+    fn cell(x: f32) -> f32 {
+        x * CELL_SIZE
+    }
+
+    #[test]
+    fn a_flag_is_captured_only_while_the_own_flag_is_home() {
+        let mut flags = flags();
+        let (red_base, blue_base) = (flags.flags()[0].base, flags.flags()[1].base);
+        // Red takes blue's flag, blue takes red's
+        assert_eq!(flags.touch(1, Team::Red, blue_base.0, blue_base.1), Some((Team::Blue, FlagEvent::Taken)));
+        assert_eq!(flags.touch(2, Team::Blue, red_base.0, red_base.1), Some((Team::Red, FlagEvent::Taken)));
+        assert_eq!(flags.carried_by(1), Some(Team::Blue));
+        // No capture while red's flag is away
+        assert_eq!(flags.touch(1, Team::Red, red_base.0, red_base.1), None);
+
+        // Blue's carrier falls, red walks over their own flag and sends it home
+        assert_eq!(flags.drop(2, cell(4.5), cell(1.5)), Some(Team::Red));
+        assert_eq!(flags.touch(3, Team::Red, cell(4.5), cell(1.5)), Some((Team::Red, FlagEvent::Returned)));
+        assert_eq!(flags.touch(1, Team::Red, red_base.0, red_base.1), Some((Team::Blue, FlagEvent::Captured)));
+        assert!(flags.flags().iter().all(|flag| flag.state == FlagState::AtBase));
+        assert_eq!(flags.carried_by(1), None);
+    }
+
+    #[test]
+    fn dropped_flags_can_be_picked_up_or_go_home_by_themselves() {
+        let mut flags = flags();
+        let blue_base = flags.flags()[1].base;
+        flags.touch(1, Team::Red, blue_base.0, blue_base.1);
+        assert_eq!(flags.drop(1, cell(4.5), cell(1.5)), Some(Team::Blue));
+        assert_eq!(flags.drop(1, cell(4.5), cell(1.5)), None);
+        assert_eq!(flags.touch(4, Team::Red, cell(4.5), cell(1.5)), Some((Team::Blue, FlagEvent::Taken)));
+        flags.drop(4, cell(5.5), cell(1.5));
+
+        assert_eq!(flags.update(RETURN_SECONDS - 1.0), vec![]);
+        assert_eq!(flags.update(1.0), vec![Team::Blue]);
+        assert_eq!(flags.flags()[1].position(), Some(blue_base));
+        assert_eq!(FlagEvent::parse(FlagEvent::Captured.name()), Some(FlagEvent::Captured));
+    }
+}
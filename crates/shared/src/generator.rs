@@ -0,0 +1,439 @@
+//! Seeded procedural maze generation.
+//!
+//! Mazes are carved on a lattice: cells with two odd coordinates are floor
+//! and the cell between two neighbouring floor cells is either a wall or a
+//! passage. The outer border is never carved. After the base layout, a share
+//! of the dead ends is opened up ("braiding") and a few extra walls are
+//! knocked out to add loops; both shrink as difficulty goes up.
+//!
+//! The exit is put on the floor cell farthest from the start, which also
+//! guarantees that it can be reached.
+
+use crate::level::{EnemyKind, EnemySpawn, Level, Point, Theme};
+use crate::maze::Maze;
+use crate::rng::{self, Rng};
+
+/// Largest maze the generator will build, in floor cells per side.
+pub const MAX_CELLS: usize = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// Long winding corridors with few branches.
+    RecursiveBacktracker,
+    /// Many short branches and dead ends.
+    Prim,
+    /// Open rooms joined by straight corridors.
+    Rooms,
+}
+
+impl Algorithm {
+    pub const ALL: [Algorithm; 3] = [Algorithm::RecursiveBacktracker, Algorithm::Prim, Algorithm::Rooms];
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratorParams {
+    pub algorithm: Algorithm,
+    /// Floor cells across. The wall grid is `2 * cells_wide + 1` wide.
+    pub cells_wide: usize,
+    /// Floor cells down. The wall grid is `2 * cells_high + 1` high.
+    pub cells_high: usize,
+    /// Fraction of dead ends opened up into loops, 0.0 to 1.0.
+    pub braid: f32,
+    /// Chance of knocking out each remaining wall between two floor cells.
+    pub loops: f32,
+    /// Rooms to place with [`Algorithm::Rooms`].
+    pub rooms: usize,
+}
+
+impl GeneratorParams {
+    /// Parameters for `difficulty`, starting at 1. Harder mazes are bigger,
+    /// keep more of their dead ends and have fewer shortcuts.
+    pub fn for_difficulty(difficulty: u32, algorithm: Algorithm) -> Self {
+        let difficulty = difficulty.max(1);
+        let cells = (5 + difficulty as usize).min(MAX_CELLS);
+        Self {
+            algorithm,
+            cells_wide: cells,
+            cells_high: cells,
+            braid: (0.9 - 0.12 * difficulty as f32).max(0.0),
+            loops: (0.15 - 0.02 * difficulty as f32).max(0.0),
+            rooms: (2 + difficulty as usize / 2).min(8),
+        }
+    }
+}
+
+/// Most enemies a generated level gets.
+const MAX_ENEMIES: usize = 12;
+
+/// Extra player spawns in a generated level.
+const EXTRA_SPAWNS: usize = 3;
+
+/// Builds level `number` from the session seed: maze, theme, player spawns
+/// and enemies with patrol routes.
+pub fn generate_level(number: usize, seed: u64) -> Level {
+    let level_seed = rng::derive_seed(seed, number as u64);
+    let algorithm = Algorithm::ALL[(level_seed % Algorithm::ALL.len() as u64) as usize];
+    let difficulty = number as u32;
+    let maze = generate(level_seed, &GeneratorParams::for_difficulty(difficulty, algorithm));
+
+    let mut level = Level::from_maze(&format!("Level {} - Generated", number), difficulty, &maze);
+    level.theme = Some(Theme::ALL[number.saturating_sub(1) % Theme::ALL.len()]);
+    populate(&mut level, &maze, &mut Rng::new(rng::derive_seed(level_seed, 1)), number);
+    level
+}
+
+/// Adds player spawns close to the start and `number + 1` enemies away from
+/// it, each patrolling a straight stretch of corridor.
+fn populate(level: &mut Level, maze: &Maze, rng: &mut Rng, number: usize) {
+    let distances = maze.distances_from(maze.start);
+    let floor: Vec<((usize, usize), usize)> = distances
+        .iter()
+        .enumerate()
+        .filter_map(|(index, distance)| Some(((index % maze.width(), index / maze.width()), (*distance)?)))
+        .filter(|((x, y), _)| is_lattice(*x, *y))
+        .collect();
+    let center = |(x, y): (usize, usize)| Point { x: x as f32 + 0.5, y: y as f32 + 0.5 };
+
+    let mut near: Vec<_> = floor.iter().filter(|(_, distance)| (2..=6).contains(distance)).map(|(cell, _)| *cell).collect();
+    rng.shuffle(&mut near);
+    level.spawns = near.into_iter().take(EXTRA_SPAWNS).map(center).collect();
+
+    let mut far: Vec<_> = floor
+        .iter()
+        .filter(|(cell, distance)| *distance >= 5 && *cell != maze.exit)
+        .map(|(cell, _)| *cell)
+        .collect();
+    rng.shuffle(&mut far);
+    for cell in far.into_iter().take((number + 1).min(MAX_ENEMIES)) {
+        let kind = match rng.below(10) {
+            0..=1 if number >= 5 => EnemyKind::Brute,
+            0..=4 => EnemyKind::Scout,
+            _ => EnemyKind::Grunt,
+        };
+        let Point { x, y } = center(cell);
+        let end = corridor_end(maze, cell, rng);
+        let patrol = if end == cell { Vec::new() } else { vec![center(end)] };
+        level.enemies.push(EnemySpawn { kind, x, y, patrol });
+    }
+}
+
+/// Walks from `cell` in a random open direction until a wall, at most six
+/// cells, so a patrol between the two ends always has line of sight.
+fn corridor_end(maze: &Maze, cell: (usize, usize), rng: &mut Rng) -> (usize, usize) {
+    let open: Vec<(isize, isize)> = [(1, 0), (-1, 0), (0, 1), (0, -1)]
+        .into_iter()
+        .filter(|(dx, dy)| !maze.is_wall(cell.0.wrapping_add_signed(*dx), cell.1.wrapping_add_signed(*dy)))
+        .collect();
+    if open.is_empty() {
+        return cell;
+    }
+    let (dx, dy) = open[rng.below(open.len())];
+    let mut end = cell;
+    for _ in 0..6 {
+        let next = (end.0.wrapping_add_signed(dx), end.1.wrapping_add_signed(dy));
+        if maze.is_wall(next.0, next.1) {
+            break;
+        }
+        end = next;
+    }
+    end
+}
+
+/// Generates a maze. The same seed and parameters always give the same maze.
+pub fn generate(seed: u64, params: &GeneratorParams) -> Maze {
+    let mut rng = Rng::new(seed);
+    let cells_wide = params.cells_wide.clamp(2, MAX_CELLS);
+    let cells_high = params.cells_high.clamp(2, MAX_CELLS);
+    let mut maze = Maze::solid(2 * cells_wide + 1, 2 * cells_high + 1);
+
+    let start = match params.algorithm {
+        Algorithm::RecursiveBacktracker => carve_backtracker(&mut maze, &mut rng),
+        Algorithm::Prim => carve_prim(&mut maze, &mut rng),
+        Algorithm::Rooms => carve_rooms(&mut maze, &mut rng, params.rooms),
+    };
+    braid(&mut maze, &mut rng, params.braid);
+    add_loops(&mut maze, &mut rng, params.loops);
+
+    maze.start = start;
+    maze.exit = farthest_cell(&maze, start);
+    maze
+}
+
+fn is_lattice(x: usize, y: usize) -> bool {
+    x % 2 == 1 && y % 2 == 1
+}
+
+/// Floor cells two steps away in each direction that are inside the border.
+fn lattice_neighbours(maze: &Maze, x: usize, y: usize) -> Vec<(usize, usize)> {
+    let mut neighbours = Vec::with_capacity(4);
+    if x > 1 {
+        neighbours.push((x - 2, y));
+    }
+    if y > 1 {
+        neighbours.push((x, y - 2));
+    }
+    if x + 2 < maze.width() - 1 {
+        neighbours.push((x + 2, y));
+    }
+    if y + 2 < maze.height() - 1 {
+        neighbours.push((x, y + 2));
+    }
+    neighbours
+}
+
+/// Opens `to` and the wall between it and `from`.
+fn carve_between(maze: &mut Maze, from: (usize, usize), to: (usize, usize)) {
+    maze.set_wall((from.0 + to.0) / 2, (from.1 + to.1) / 2, false);
+    maze.set_wall(to.0, to.1, false);
+}
+
+fn open_sides(maze: &Maze, x: usize, y: usize) -> usize {
+    [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+        .into_iter()
+        .filter(|&(nx, ny)| !maze.is_wall(nx, ny))
+        .count()
+}
+
+fn carve_backtracker(maze: &mut Maze, rng: &mut Rng) -> (usize, usize) {
+    let start = (1, 1);
+    maze.set_wall(start.0, start.1, false);
+    let mut stack = vec![start];
+    while let Some(&(x, y)) = stack.last() {
+        let unvisited: Vec<_> = lattice_neighbours(maze, x, y)
+            .into_iter()
+            .filter(|&(nx, ny)| maze.is_wall(nx, ny))
+            .collect();
+        if unvisited.is_empty() {
+            stack.pop();
+            continue;
+        }
+        let next = unvisited[rng.below(unvisited.len())];
+        carve_between(maze, (x, y), next);
+        stack.push(next);
+    }
+    start
+}
+
+fn carve_prim(maze: &mut Maze, rng: &mut Rng) -> (usize, usize) {
+    let start = (1, 1);
+    maze.set_wall(start.0, start.1, false);
+    let mut frontier = lattice_neighbours(maze, start.0, start.1);
+    while !frontier.is_empty() {
+        let (x, y) = frontier.swap_remove(rng.below(frontier.len()));
+        // The same cell can be queued from several sides
+        if !maze.is_wall(x, y) {
+            continue;
+        }
+        let (carved, uncarved): (Vec<_>, Vec<_>) = lattice_neighbours(maze, x, y)
+            .into_iter()
+            .partition(|&(nx, ny)| !maze.is_wall(nx, ny));
+        let from = carved[rng.below(carved.len())];
+        carve_between(maze, from, (x, y));
+        frontier.extend(uncarved);
+    }
+    start
+}
+
+/// Lattice-aligned rectangle, in grid cells, inclusive.
+#[derive(Debug, Clone, Copy)]
+struct Room {
+    left: usize,
+    top: usize,
+    right: usize,
+    bottom: usize,
+}
+
+impl Room {
+    fn overlaps(&self, other: &Room) -> bool {
+        // Keep at least one wall between rooms
+        self.left <= other.right + 2
+            && other.left <= self.right + 2
+            && self.top <= other.bottom + 2
+            && other.top <= self.bottom + 2
+    }
+
+    fn random_cell(&self, rng: &mut Rng) -> (usize, usize) {
+        let x = self.left + 2 * rng.below((self.right - self.left) / 2 + 1);
+        let y = self.top + 2 * rng.below((self.bottom - self.top) / 2 + 1);
+        (x, y)
+    }
+}
+
+fn carve_rooms(maze: &mut Maze, rng: &mut Rng, count: usize) -> (usize, usize) {
+    let cells_wide = maze.width() / 2;
+    let cells_high = maze.height() / 2;
+    let mut rooms: Vec<Room> = Vec::new();
+
+    for _ in 0..count.max(1) * 10 {
+        if rooms.len() >= count.max(1) {
+            break;
+        }
+        let room_wide = rng.range(2, 4).min(cells_wide);
+        let room_high = rng.range(2, 4).min(cells_high);
+        let column = rng.below(cells_wide - room_wide + 1);
+        let row = rng.below(cells_high - room_high + 1);
+        let room = Room {
+            left: 2 * column + 1,
+            top: 2 * row + 1,
+            right: 2 * (column + room_wide) - 1,
+            bottom: 2 * (row + room_high) - 1,
+        };
+        if rooms.iter().all(|other| !room.overlaps(other)) {
+            rooms.push(room);
+        }
+    }
+
+    // Too cramped for rooms, fall back to a plain maze
+    if rooms.len() < 2 {
+        return carve_backtracker(maze, rng);
+    }
+
+    for room in &rooms {
+        for y in room.top..=room.bottom {
+            for x in room.left..=room.right {
+                maze.set_wall(x, y, false);
+            }
+        }
+    }
+
+    // Chain the rooms with L-shaped corridors along lattice lines, which
+    // connects all of them
+    for pair in rooms.windows(2) {
+        let (from_x, from_y) = pair[0].random_cell(rng);
+        let (to_x, to_y) = pair[1].random_cell(rng);
+        let (corner_x, corner_y) = if rng.chance(0.5) { (to_x, from_y) } else { (from_x, to_y) };
+        carve_line(maze, (from_x, from_y), (corner_x, corner_y));
+        carve_line(maze, (corner_x, corner_y), (to_x, to_y));
+    }
+
+    rooms[0].random_cell(rng)
+}
+
+/// Opens a straight horizontal or vertical run of cells.
+fn carve_line(maze: &mut Maze, from: (usize, usize), to: (usize, usize)) {
+    for y in from.1.min(to.1)..=from.1.max(to.1) {
+        for x in from.0.min(to.0)..=from.0.max(to.0) {
+            maze.set_wall(x, y, false);
+        }
+    }
+}
+
+/// Opens a wall at a random share of the dead ends, joining them to a
+/// neighbouring corridor.
+fn braid(maze: &mut Maze, rng: &mut Rng, share: f32) {
+    if share <= 0.0 {
+        return;
+    }
+    for y in (1..maze.height() - 1).step_by(2) {
+        for x in (1..maze.width() - 1).step_by(2) {
+            if maze.is_wall(x, y) || open_sides(maze, x, y) != 1 || !rng.chance(share) {
+                continue;
+            }
+            let closed: Vec<_> = lattice_neighbours(maze, x, y)
+                .into_iter()
+                .filter(|&(nx, ny)| !maze.is_wall(nx, ny) && maze.is_wall((x + nx) / 2, (y + ny) / 2))
+                .collect();
+            if !closed.is_empty() {
+                let target = closed[rng.below(closed.len())];
+                carve_between(maze, (x, y), target);
+            }
+        }
+    }
+}
+
+/// Knocks out walls between two floor cells with probability `chance`.
+fn add_loops(maze: &mut Maze, rng: &mut Rng, chance: f32) {
+    if chance <= 0.0 {
+        return;
+    }
+    for y in 1..maze.height() - 1 {
+        for x in 1..maze.width() - 1 {
+            // Walls between floor cells have exactly one even coordinate
+            if !maze.is_wall(x, y) || is_lattice(x, y) || (x % 2 == 0 && y % 2 == 0) {
+                continue;
+            }
+            let (a, b) = if x % 2 == 0 { ((x - 1, y), (x + 1, y)) } else { ((x, y - 1), (x, y + 1)) };
+            if !maze.is_wall(a.0, a.1) && !maze.is_wall(b.0, b.1) && rng.chance(chance) {
+                maze.set_wall(x, y, false);
+            }
+        }
+    }
+}
+
+fn farthest_cell(maze: &Maze, start: (usize, usize)) -> (usize, usize) {
+    let distances = maze.distances_from(start);
+    let mut best = (start, 0);
+    for (index, distance) in distances.iter().enumerate() {
+        let cell = (index % maze.width(), index / maze.width());
+        if let Some(distance) = *distance {
+            if distance > best.1 && is_lattice(cell.0, cell.1) {
+                best = (cell, distance);
+            }
+        }
+    }
+    best.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dead_ends(maze: &Maze) -> usize {
+        (1..maze.height() - 1)
+            .flat_map(|y| (1..maze.width() - 1).map(move |x| (x, y)))
+            .filter(|&(x, y)| !maze.is_wall(x, y) && open_sides(maze, x, y) == 1)
+            .count()
+    }
+
+    #[test]
+    fn every_algorithm_is_solvable_and_enclosed() {
+        for algorithm in Algorithm::ALL {
+            for difficulty in 1..=12 {
+                for seed in 0..10 {
+                    let maze = generate(seed, &GeneratorParams::for_difficulty(difficulty, algorithm));
+                    assert!(maze.has_path(), "{algorithm:?} difficulty {difficulty} seed {seed}");
+                    assert_ne!(maze.start, maze.exit);
+                    for x in 0..maze.width() {
+                        assert!(maze.is_wall(x, 0) && maze.is_wall(x, maze.height() - 1));
+                    }
+                    for y in 0..maze.height() {
+                        assert!(maze.is_wall(0, y) && maze.is_wall(maze.width() - 1, y));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn generated_levels_are_valid() {
+        for number in 4..=20 {
+            for seed in 0..5 {
+                let level = generate_level(number, seed);
+                assert_eq!(level.validate(), vec![], "level {number} seed {seed}");
+                assert_eq!(level.enemies.len(), (number + 1).min(MAX_ENEMIES));
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_same_maze() {
+        for algorithm in Algorithm::ALL {
+            let params = GeneratorParams::for_difficulty(5, algorithm);
+            assert_eq!(generate(99, &params), generate(99, &params));
+        }
+    }
+
+    #[test]
+    fn harder_mazes_are_bigger_with_more_dead_ends() {
+        let easy = GeneratorParams::for_difficulty(1, Algorithm::RecursiveBacktracker);
+        let hard = GeneratorParams::for_difficulty(8, Algorithm::RecursiveBacktracker);
+        assert!(hard.cells_wide > easy.cells_wide);
+
+        // Compare dead ends per floor cell so size doesn't skew it
+        let density = |params: &GeneratorParams| {
+            let total: usize = (0..20).map(|seed| dead_ends(&generate(seed, params))).sum();
+            total as f32 / (params.cells_wide * params.cells_high) as f32
+        };
+        assert!(density(&hard) > density(&easy));
+    }
+}
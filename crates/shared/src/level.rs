@@ -0,0 +1,895 @@
+//! Level files (`levels/*.ron`) and the checks run on them.
+//!
+//! The grid is one string per row, `#` for a wall and `.` for floor. Doors
+//! (`D` opens when used, `d` when someone comes close), secret push walls
+//! (`P`) and one-way passages (`^`, `>`, `v`, `<`, walkable in the direction
+//! they point) are cells too, and so are the traps: hazard floors (`~`)
+//! and spikes that come up on a timer (`!`).
+//! Positions (start, exit, spawns, patrol routes, pickups, switches, flag
+//! bases) are in cells, so `3.5` is the middle of the fourth cell. Switch
+//! targets and teleporters name whole cells as `(column, row)`.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::generator;
+use crate::maze::{Direction, Maze};
+use crate::movement::DEFAULT_PLAYER_RADIUS;
+use crate::team::Team;
+use crate::CELL_SIZE;
+
+pub const WALL: char = '#';
+pub const FLOOR: char = '.';
+pub const DOOR: char = 'D';
+pub const AUTO_DOOR: char = 'd';
+pub const PUSH_WALL: char = 'P';
+pub const HAZARD_FLOOR: char = '~';
+pub const SPIKES: char = '!';
+pub const ONE_WAY: [(char, Direction); 4] = [
+    ('^', Direction::North),
+    ('>', Direction::East),
+    ('v', Direction::South),
+    ('<', Direction::West),
+];
+
+/// Players must fit through a corridor one cell wide.
+pub const MAX_PLAYER_RADIUS: f32 = CELL_SIZE / 2.0;
+
+/// Directory level files are looked up in, relative to the working directory.
+pub const LEVELS_DIR: &str = "levels";
+
+/// The hand-made levels, compiled in so the game doesn't depend on the
+/// working directory.
+const BUILTIN_LEVELS: [&str; 3] = [
+    include_str!("../../../levels/level1.ron"),
+    include_str!("../../../levels/level2.ron"),
+    include_str!("../../../levels/level3.ron"),
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Level {
+    pub name: String,
+    pub difficulty: u32,
+    pub description: String,
+    pub width: usize,
+    pub height: usize,
+    pub start_x: f32,
+    pub start_y: f32,
+    pub exit_x: f32,
+    pub exit_y: f32,
+    /// Look of the level. Without one the client picks by level number.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme: Option<Theme>,
+    pub grid: Vec<String>,
+    /// Extra player spawn points besides the start, used in multiplayer so
+    /// players don't all appear on top of each other.
+    #[serde(default)]
+    pub spawns: Vec<Point>,
+    #[serde(default)]
+    pub enemies: Vec<EnemySpawn>,
+    #[serde(default)]
+    pub pickups: Vec<Pickup>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub switches: Vec<Switch>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub teleporters: Vec<Teleporter>,
+    /// Where each team keeps its flag in capture the flag, at most one per
+    /// team. See [`Level::base`] for levels without them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bases: Vec<Base>,
+    /// Radius of the players' collision circle in world units, see
+    /// [`Level::player_radius`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub player_radius: Option<f32>,
+}
+
+/// A position in cells.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum EnemyKind {
+    #[default]
+    Grunt,
+    /// Fast and fragile.
+    Scout,
+    /// Slow, tough and hits hard.
+    Brute,
+}
+
+impl EnemyKind {
+    pub const ALL: [EnemyKind; 3] = [EnemyKind::Grunt, EnemyKind::Scout, EnemyKind::Brute];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            EnemyKind::Grunt => "Grunt",
+            EnemyKind::Scout => "Scout",
+            EnemyKind::Brute => "Brute",
+        }
+    }
+
+    pub fn health(self) -> i32 {
+        match self {
+            EnemyKind::Grunt => 50,
+            EnemyKind::Scout => 30,
+            EnemyKind::Brute => 120,
+        }
+    }
+
+    /// Walking speed on patrol, world units per second.
+    pub fn patrol_speed(self) -> f32 {
+        match self {
+            EnemyKind::Grunt => 50.0,
+            EnemyKind::Scout => 80.0,
+            EnemyKind::Brute => 35.0,
+        }
+    }
+
+    /// Speed when chasing a player, world units per second.
+    pub fn chase_speed(self) -> f32 {
+        match self {
+            EnemyKind::Grunt => 80.0,
+            EnemyKind::Scout => 130.0,
+            EnemyKind::Brute => 60.0,
+        }
+    }
+
+    /// Damage per attack.
+    pub fn damage(self) -> i32 {
+        match self {
+            EnemyKind::Grunt => 10,
+            EnemyKind::Scout => 5,
+            EnemyKind::Brute => 20,
+        }
+    }
+}
+
+/// Where an enemy starts and the route it walks: through each patrol point
+/// in order, then back to the spawn, over and over. Without a route it
+/// wanders.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnemySpawn {
+    #[serde(default)]
+    pub kind: EnemyKind,
+    pub x: f32,
+    pub y: f32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patrol: Vec<Point>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Theme {
+    CandyMaze,
+    Cyberpunk,
+    MoroccanBazaar,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 3] = [Theme::CandyMaze, Theme::Cyberpunk, Theme::MoroccanBazaar];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Theme::CandyMaze => "Candy Maze",
+            Theme::Cyberpunk => "Cyberpunk",
+            Theme::MoroccanBazaar => "Moroccan Bazaar",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PickupKind {
+    Ammo,
+    Health,
+    /// Faster movement for a while.
+    Speed,
+    /// Double damage for a while.
+    Damage,
+}
+
+impl PickupKind {
+    pub const ALL: [PickupKind; 4] = [PickupKind::Ammo, PickupKind::Health, PickupKind::Speed, PickupKind::Damage];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PickupKind::Ammo => "Ammo",
+            PickupKind::Health => "Health",
+            PickupKind::Speed => "Speed",
+            PickupKind::Damage => "Damage",
+        }
+    }
+
+    /// Seconds before a taken item of this kind comes back.
+    pub fn respawn_seconds(self) -> f32 {
+        match self {
+            PickupKind::Ammo => 15.0,
+            PickupKind::Health => 20.0,
+            PickupKind::Speed | PickupKind::Damage => 30.0,
+        }
+    }
+}
+
+/// An item lying in the level, position in cells.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Pickup {
+    pub kind: PickupKind,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Where a level comes from. Server rotations list these and the server
+/// names the level being played with one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LevelSource {
+    /// Level `number` as in single player: hand-made for the first few,
+    /// generated from `seed` after that.
+    Numbered { number: usize, seed: u64 },
+    /// A level file in [`LEVELS_DIR`], by name without the extension.
+    File(String),
+}
+
+impl LevelSource {
+    /// `7@1234` for level 7 with seed 1234, the file name otherwise.
+    pub fn encode(&self) -> String {
+        match self {
+            LevelSource::Numbered { number, seed } => format!("{}@{}", number, seed),
+            LevelSource::File(name) => name.clone(),
+        }
+    }
+
+    /// Parses [`encode`](Self::encode) output. File names are limited to
+    /// letters, digits, `-` and `_` so a name can't point outside the levels
+    /// directory.
+    pub fn decode(text: &str) -> Option<Self> {
+        let text = text.trim();
+        if let Some((number, seed)) = text.split_once('@') {
+            return Some(LevelSource::Numbered {
+                number: number.parse().ok().filter(|&number| number > 0)?,
+                seed: seed.parse().ok()?,
+            });
+        }
+        let valid = !text.is_empty() && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        valid.then(|| LevelSource::File(text.to_string()))
+    }
+
+    /// A level as a person names it: a bare number plays that generated
+    /// level with `seed`, anything else is read like [`decode`](Self::decode),
+    /// with or without `.ron` on the end.
+    pub fn from_arg(text: &str, seed: u64) -> Option<Self> {
+        let text = text.trim();
+        match text.parse() {
+            Ok(number) if number > 0 => Some(LevelSource::Numbered { number, seed }),
+            _ => Self::decode(text.trim_end_matches(".ron")),
+        }
+    }
+
+    /// Path of the level file, for file sources.
+    pub fn path(&self) -> Option<PathBuf> {
+        match self {
+            LevelSource::Numbered { .. } => None,
+            LevelSource::File(name) => Some(Path::new(LEVELS_DIR).join(format!("{}.ron", name))),
+        }
+    }
+}
+
+/// A switch standing on the floor. While it's on, the doors among its
+/// targets stay open and the push walls are gone.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Switch {
+    pub x: f32,
+    pub y: f32,
+    /// Door and push wall cells as `(column, row)`, where they start.
+    pub targets: Vec<(usize, usize)>,
+}
+
+/// A team's flag base.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Base {
+    pub team: Team,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Stepping onto `from` moves a player to `to`. For a two-way link add a
+/// second teleporter going back.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Teleporter {
+    pub from: (usize, usize),
+    pub to: (usize, usize),
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "{}", e),
+            LoadError::Parse(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// Something wrong with a level file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LevelIssue {
+    /// The grid has a different number of rows than `height`.
+    RowCount { expected: usize, found: usize },
+    /// A row is a different length than `width`.
+    RowLength { row: usize, expected: usize, found: usize },
+    UnknownCell { x: usize, y: usize, symbol: char },
+    MissingOuterWall { x: usize, y: usize },
+    StartOutside,
+    StartInWall,
+    ExitOutside,
+    ExitInWall,
+    ExitUnreachable,
+    SpawnOutside { index: usize },
+    SpawnInWall { index: usize },
+    EnemyOutside { index: usize },
+    EnemyInWall { index: usize },
+    PatrolOutside { enemy: usize, point: usize },
+    PatrolInWall { enemy: usize, point: usize },
+    PickupOutside { index: usize },
+    PickupInWall { index: usize },
+    SwitchOutside { index: usize },
+    SwitchInWall { index: usize },
+    /// A switch target is off the grid or on the outer wall.
+    SwitchTargetOutside { switch: usize, target: usize },
+    /// A switch target is neither a door nor a push wall.
+    SwitchTargetNotMechanism { switch: usize, target: usize },
+    /// One end of a teleporter is off the grid.
+    TeleporterOutside { index: usize },
+    /// One end of a teleporter is in a wall.
+    TeleporterInWall { index: usize },
+    BaseOutside { index: usize },
+    BaseInWall { index: usize },
+    /// A team has more than one base.
+    DuplicateBase { team: Team },
+    /// The player radius isn't positive or wouldn't fit through a corridor.
+    PlayerRadius,
+}
+
+impl fmt::Display for LevelIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelIssue::RowCount { expected, found } => {
+                write!(f, "grid has {} rows but height is {}", found, expected)
+            }
+            LevelIssue::RowLength { row, expected, found } => {
+                write!(f, "row {} is {} cells wide but width is {}", row, found, expected)
+            }
+            LevelIssue::UnknownCell { x, y, symbol } => {
+                write!(f, "unknown cell {:?} at ({}, {})", symbol, x, y)
+            }
+            LevelIssue::MissingOuterWall { x, y } => write!(f, "outer wall missing at ({}, {})", x, y),
+            LevelIssue::StartOutside => write!(f, "start is outside the grid"),
+            LevelIssue::StartInWall => write!(f, "start is inside a wall"),
+            LevelIssue::ExitOutside => write!(f, "exit is outside the grid"),
+            LevelIssue::ExitInWall => write!(f, "exit is inside a wall"),
+            LevelIssue::ExitUnreachable => write!(f, "exit can't be reached from the start"),
+            LevelIssue::SpawnOutside { index } => write!(f, "player spawn {} is outside the grid", index),
+            LevelIssue::SpawnInWall { index } => write!(f, "player spawn {} is inside a wall", index),
+            LevelIssue::PatrolOutside { enemy, point } => {
+                write!(f, "patrol point {} of enemy {} is outside the grid", point, enemy)
+            }
+            LevelIssue::PatrolInWall { enemy, point } => {
+                write!(f, "patrol point {} of enemy {} is inside a wall", point, enemy)
+            }
+            LevelIssue::EnemyOutside { index } => write!(f, "enemy {} spawns outside the grid", index),
+            LevelIssue::EnemyInWall { index } => write!(f, "enemy {} spawns inside a wall", index),
+            LevelIssue::PickupOutside { index } => write!(f, "pickup {} is outside the grid", index),
+            LevelIssue::PickupInWall { index } => write!(f, "pickup {} is inside a wall", index),
+            LevelIssue::SwitchOutside { index } => write!(f, "switch {} is outside the grid", index),
+            LevelIssue::SwitchInWall { index } => write!(f, "switch {} is inside a wall", index),
+            LevelIssue::SwitchTargetOutside { switch, target } => {
+                write!(f, "target {} of switch {} is outside the grid or on the outer wall", target, switch)
+            }
+            LevelIssue::SwitchTargetNotMechanism { switch, target } => {
+                write!(f, "target {} of switch {} is not a door or push wall", target, switch)
+            }
+            LevelIssue::TeleporterOutside { index } => write!(f, "teleporter {} leads outside the grid", index),
+            LevelIssue::TeleporterInWall { index } => write!(f, "teleporter {} starts or ends inside a wall", index),
+            LevelIssue::BaseOutside { index } => write!(f, "flag base {} is outside the grid", index),
+            LevelIssue::BaseInWall { index } => write!(f, "flag base {} is inside a wall", index),
+            LevelIssue::DuplicateBase { team } => write!(f, "the {} team has more than one flag base", team.label()),
+            LevelIssue::PlayerRadius => {
+                write!(f, "player radius must be more than 0 and less than {}", MAX_PLAYER_RADIUS)
+            }
+        }
+    }
+}
+
+impl Level {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        let text = std::fs::read_to_string(path).map_err(LoadError::Io)?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, LoadError> {
+        ron::from_str(text).map_err(LoadError::Parse)
+    }
+
+    /// The hand-made level `number`, starting at 1.
+    pub fn builtin(number: usize) -> Option<Self> {
+        let text = BUILTIN_LEVELS.get(number.checked_sub(1)?)?;
+        Some(Self::parse(text).expect("built-in level files are valid"))
+    }
+
+    /// The level played as number `number`: hand-made for the first few,
+    /// generated from `seed` after that.
+    pub fn numbered(number: usize, seed: u64) -> Self {
+        Self::builtin(number).unwrap_or_else(|| generator::generate_level(number, seed))
+    }
+
+    /// Builds or loads the level a source names.
+    pub fn from_source(source: &LevelSource) -> Result<Self, LoadError> {
+        match source {
+            LevelSource::Numbered { number, seed } => Ok(Self::numbered(*number, *seed)),
+            LevelSource::File(_) => Self::load(source.path().unwrap_or_default()),
+        }
+    }
+
+    /// 64-bit FNV-1a hash of the level's RON form. Client and server compare
+    /// it to make sure they're playing the exact same level.
+    pub fn content_hash(&self) -> u64 {
+        // Serialising plain data can't fail
+        let text = self.to_ron().unwrap_or_default();
+        text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3))
+    }
+
+    /// Where the player with spawn slot `index` appears, in world units.
+    /// Slot 0 is the start, further slots go through `spawns` and wrap.
+    pub fn player_spawn(&self, index: usize) -> (f32, f32) {
+        let count = self.spawns.len() + 1;
+        let point = match index % count {
+            0 => Point { x: self.start_x, y: self.start_y },
+            slot => self.spawns[slot - 1],
+        };
+        (point.x * CELL_SIZE, point.y * CELL_SIZE)
+    }
+
+    /// Where `team` keeps its flag, in world units. Levels without a base
+    /// for the team use the start for red and the exit for blue, so every
+    /// level can be played as capture the flag.
+    pub fn base(&self, team: Team) -> (f32, f32) {
+        let point = match self.bases.iter().find(|base| base.team == team) {
+            Some(base) => Point { x: base.x, y: base.y },
+            None if team == Team::Red => Point { x: self.start_x, y: self.start_y },
+            None => Point { x: self.exit_x, y: self.exit_y },
+        };
+        (point.x * CELL_SIZE, point.y * CELL_SIZE)
+    }
+
+    /// Like [`player_spawn`](Self::player_spawn) for a player on `team`,
+    /// going only through the spawn points closer to the team's base than
+    /// to the other one. The start and spawns all count.
+    pub fn team_spawn(&self, team: Team, index: usize) -> (f32, f32) {
+        let (own, other) = (self.base(team), self.base(team.other()));
+        let distance = |(x, y): (f32, f32), (to_x, to_y): (f32, f32)| (x - to_x).hypot(y - to_y);
+        let mut points: Vec<(f32, f32)> = (0..=self.spawns.len()).map(|slot| self.player_spawn(slot)).collect();
+        points.sort_by(|a, b| distance(*a, own).total_cmp(&distance(*b, own)));
+        // The nearest one is always on the team's side, even if it isn't near
+        let near = points.iter().skip(1).take_while(|&&point| distance(point, own) <= distance(point, other)).count() + 1;
+        points[index % near]
+    }
+
+    /// Wraps a maze, e.g. a generated one, so it can be edited and saved.
+    pub fn from_maze(name: &str, difficulty: u32, maze: &Maze) -> Self {
+        let grid = (0..maze.height())
+            .map(|y| (0..maze.width()).map(|x| if maze.is_wall(x, y) { WALL } else { FLOOR }).collect())
+            .collect();
+        Self {
+            name: name.to_string(),
+            difficulty,
+            description: String::new(),
+            width: maze.width(),
+            height: maze.height(),
+            start_x: maze.start.0 as f32 + 0.5,
+            start_y: maze.start.1 as f32 + 0.5,
+            exit_x: maze.exit.0 as f32 + 0.5,
+            exit_y: maze.exit.1 as f32 + 0.5,
+            theme: None,
+            grid,
+            spawns: Vec::new(),
+            enemies: Vec::new(),
+            pickups: Vec::new(),
+            switches: Vec::new(),
+            teleporters: Vec::new(),
+            bases: Vec::new(),
+            player_radius: None,
+        }
+    }
+
+    /// How big players are on this level, [`DEFAULT_PLAYER_RADIUS`] unless
+    /// the file says otherwise. Client and server both move players with it,
+    /// and it's part of the level hash so they can't disagree.
+    pub fn player_radius(&self) -> f32 {
+        self.player_radius.unwrap_or(DEFAULT_PLAYER_RADIUS)
+    }
+
+    /// The level in the same pretty RON layout as the files in `levels/`.
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        let config = ron::ser::PrettyConfig::new().indentor("    ".to_string()).depth_limit(2);
+        ron::ser::to_string_pretty(self, config)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let text = self.to_ron().map_err(std::io::Error::other)?;
+        if let Some(parent) = path.as_ref().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, text + "\n")
+    }
+
+    /// The symbol at `(x, y)`, a wall off the grid.
+    pub fn cell(&self, x: usize, y: usize) -> char {
+        self.grid.get(y).and_then(|row| row.chars().nth(x)).unwrap_or(WALL)
+    }
+
+    /// Whether the cell at `(x, y)` starts out blocking, which doors and push
+    /// walls do. Anything off the grid is a wall.
+    pub fn is_wall(&self, x: usize, y: usize) -> bool {
+        matches!(self.cell(x, y), WALL | DOOR | AUTO_DOOR | PUSH_WALL)
+    }
+
+    /// Sets one grid cell, ignoring cells off the grid.
+    pub fn set_wall(&mut self, x: usize, y: usize, wall: bool) {
+        if let Some(row) = self.grid.get_mut(y) {
+            if x < row.chars().count() {
+                *row = row
+                    .chars()
+                    .enumerate()
+                    .map(|(i, symbol)| if i == x { if wall { WALL } else { FLOOR } } else { symbol })
+                    .collect();
+            }
+        }
+    }
+
+    /// Builds the playable maze. Cells missing from a short row are walls.
+    pub fn to_maze(&self) -> Maze {
+        let mut maze = Maze::solid(self.width, self.height);
+        for (y, row) in self.grid.iter().enumerate() {
+            for (x, symbol) in row.chars().enumerate() {
+                maze.set_wall(x, y, self.is_wall(x, y));
+                maze.set_one_way(x, y, one_way_direction(symbol));
+            }
+        }
+        maze.start = to_cell(self.start_x, self.start_y);
+        maze.exit = to_cell(self.exit_x, self.exit_y);
+        maze
+    }
+
+    /// Checks the level for mistakes. An empty list means it's playable.
+    pub fn validate(&self) -> Vec<LevelIssue> {
+        let mut issues = Vec::new();
+
+        if self.grid.len() != self.height {
+            issues.push(LevelIssue::RowCount { expected: self.height, found: self.grid.len() });
+        }
+        for (row, cells) in self.grid.iter().enumerate() {
+            let found = cells.chars().count();
+            if found != self.width {
+                issues.push(LevelIssue::RowLength { row, expected: self.width, found });
+            }
+        }
+        // Nothing below makes sense on a grid of the wrong shape
+        if !issues.is_empty() {
+            return issues;
+        }
+
+        for (y, row) in self.grid.iter().enumerate() {
+            for (x, symbol) in row.chars().enumerate() {
+                let known = matches!(symbol, WALL | FLOOR | DOOR | AUTO_DOOR | PUSH_WALL | HAZARD_FLOOR | SPIKES)
+                    || one_way_direction(symbol).is_some();
+                if !known {
+                    issues.push(LevelIssue::UnknownCell { x, y, symbol });
+                }
+                let border = x == 0 || y == 0 || x == self.width - 1 || y == self.height - 1;
+                if border && symbol != WALL {
+                    issues.push(LevelIssue::MissingOuterWall { x, y });
+                }
+            }
+        }
+
+        let maze = self.to_maze();
+        let start_ok = self.check_point(&maze, self.start_x, self.start_y, LevelIssue::StartOutside, LevelIssue::StartInWall, &mut issues);
+        let exit_ok = self.check_point(&maze, self.exit_x, self.exit_y, LevelIssue::ExitOutside, LevelIssue::ExitInWall, &mut issues);
+        if start_ok && exit_ok && !self.opened_maze().has_path() {
+            issues.push(LevelIssue::ExitUnreachable);
+        }
+
+        for (index, spawn) in self.spawns.iter().enumerate() {
+            self.check_point(&maze, spawn.x, spawn.y, LevelIssue::SpawnOutside { index }, LevelIssue::SpawnInWall { index }, &mut issues);
+        }
+        for (index, enemy) in self.enemies.iter().enumerate() {
+            self.check_point(&maze, enemy.x, enemy.y, LevelIssue::EnemyOutside { index }, LevelIssue::EnemyInWall { index }, &mut issues);
+            for (point, waypoint) in enemy.patrol.iter().enumerate() {
+                self.check_point(
+                    &maze,
+                    waypoint.x,
+                    waypoint.y,
+                    LevelIssue::PatrolOutside { enemy: index, point },
+                    LevelIssue::PatrolInWall { enemy: index, point },
+                    &mut issues,
+                );
+            }
+        }
+        for (index, pickup) in self.pickups.iter().enumerate() {
+            self.check_point(&maze, pickup.x, pickup.y, LevelIssue::PickupOutside { index }, LevelIssue::PickupInWall { index }, &mut issues);
+        }
+        for (index, switch) in self.switches.iter().enumerate() {
+            self.check_point(&maze, switch.x, switch.y, LevelIssue::SwitchOutside { index }, LevelIssue::SwitchInWall { index }, &mut issues);
+            for (target, &(x, y)) in switch.targets.iter().enumerate() {
+                if x == 0 || y == 0 || x >= self.width - 1 || y >= self.height - 1 {
+                    issues.push(LevelIssue::SwitchTargetOutside { switch: index, target });
+                } else if !matches!(self.cell(x, y), DOOR | AUTO_DOOR | PUSH_WALL) {
+                    issues.push(LevelIssue::SwitchTargetNotMechanism { switch: index, target });
+                }
+            }
+        }
+        for (index, teleporter) in self.teleporters.iter().enumerate() {
+            let ends = [teleporter.from, teleporter.to];
+            if ends.iter().any(|&(x, y)| x >= self.width || y >= self.height) {
+                issues.push(LevelIssue::TeleporterOutside { index });
+            } else if ends.iter().any(|&(x, y)| maze.is_wall(x, y)) {
+                issues.push(LevelIssue::TeleporterInWall { index });
+            }
+        }
+        for (index, base) in self.bases.iter().enumerate() {
+            self.check_point(&maze, base.x, base.y, LevelIssue::BaseOutside { index }, LevelIssue::BaseInWall { index }, &mut issues);
+        }
+        for team in Team::ALL {
+            if self.bases.iter().filter(|base| base.team == team).count() > 1 {
+                issues.push(LevelIssue::DuplicateBase { team });
+            }
+        }
+        if !(self.player_radius() > 0.0 && self.player_radius() < MAX_PLAYER_RADIUS) {
+            issues.push(LevelIssue::PlayerRadius);
+        }
+
+        issues
+    }
+
+    /// The maze with every door and push wall open. The exit
+    /// only has to be reachable in this one, working out which switches to
+    /// flip is the player's job.
+    fn opened_maze(&self) -> Maze {
+        let mut maze = self.to_maze();
+        for (y, row) in self.grid.iter().enumerate() {
+            for (x, symbol) in row.chars().enumerate() {
+                if matches!(symbol, DOOR | AUTO_DOOR | PUSH_WALL) {
+                    maze.set_wall(x, y, false);
+                }
+            }
+        }
+        maze
+    }
+
+    /// Reports `outside` or `in_wall` for a position, returns whether it's fine.
+    fn check_point(&self, maze: &Maze, x: f32, y: f32, outside: LevelIssue, in_wall: LevelIssue, issues: &mut Vec<LevelIssue>) -> bool {
+        if !(x >= 0.0 && y >= 0.0 && x < self.width as f32 && y < self.height as f32) {
+            issues.push(outside);
+            return false;
+        }
+        let (cell_x, cell_y) = to_cell(x, y);
+        if maze.is_wall(cell_x, cell_y) {
+            issues.push(in_wall);
+            return false;
+        }
+        true
+    }
+}
+
+/// The direction a one-way cell symbol points, `None` for other symbols.
+pub fn one_way_direction(symbol: char) -> Option<Direction> {
+    ONE_WAY.iter().find(|(one_way, _)| *one_way == symbol).map(|&(_, direction)| direction)
+}
+
+fn to_cell(x: f32, y: f32) -> (usize, usize) {
+    (x.max(0.0) as usize, y.max(0.0) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(grid: &[&str]) -> Level {
+        Level {
+            name: "Test".to_string(),
+            difficulty: 1,
+            description: String::new(),
+            width: 5,
+            height: 5,
+            start_x: 1.5,
+            start_y: 1.5,
+            exit_x: 3.5,
+            exit_y: 3.5,
+            theme: None,
+            grid: grid.iter().map(|row| row.to_string()).collect(),
+            spawns: Vec::new(),
+            enemies: Vec::new(),
+            pickups: Vec::new(),
+            switches: Vec::new(),
+            teleporters: Vec::new(),
+            bases: Vec::new(),
+            player_radius: None,
+        }
+    }
+
+    #[test]
+    fn builtin_levels_are_valid() {
+        for number in 1..=BUILTIN_LEVELS.len() {
+            assert_eq!(Level::builtin(number).unwrap().validate(), vec![], "level {number}");
+        }
+    }
+
+    #[test]
+    fn saved_levels_load_back_unchanged() {
+        let mut level = Level::builtin(2).unwrap();
+        level.theme = Some(Theme::Cyberpunk);
+        level.set_wall(3, 3, true);
+        level.enemies.push(EnemySpawn {
+            kind: EnemyKind::Scout,
+            x: 5.5,
+            y: 5.5,
+            patrol: vec![Point { x: 5.5, y: 9.5 }],
+        });
+        level.pickups.push(Pickup { kind: PickupKind::Health, x: 7.5, y: 1.5 });
+        assert_eq!(Level::parse(&level.to_ron().unwrap()).unwrap(), level);
+        assert!(level.is_wall(3, 3));
+    }
+
+    #[test]
+    fn reports_shape_mismatches() {
+        let issues = level(&["#####", "#...#", "#..#", "#####"]).validate();
+        assert_eq!(
+            issues,
+            vec![
+                LevelIssue::RowCount { expected: 5, found: 4 },
+                LevelIssue::RowLength { row: 2, expected: 5, found: 4 },
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_unreachable_exit_and_open_border() {
+        let issues = level(&["#####", "#.#..", "###.#", "#...#", "#####"]).validate();
+        assert_eq!(
+            issues,
+            vec![LevelIssue::MissingOuterWall { x: 4, y: 1 }, LevelIssue::ExitUnreachable]
+        );
+    }
+
+    #[test]
+    fn reports_spawns_in_walls() {
+        let mut level = level(&["#####", "#...#", "#.#.#", "#...#", "#####"]);
+        level.start_x = 2.5;
+        level.start_y = 2.5;
+        level.exit_x = 7.0;
+        level.spawns = vec![Point { x: 3.5, y: 1.5 }, Point { x: 2.5, y: 2.5 }];
+        let enemy = |x, y, patrol| EnemySpawn { kind: EnemyKind::Grunt, x, y, patrol };
+        level.enemies = vec![
+            enemy(1.5, 3.5, vec![Point { x: 3.5, y: 3.5 }, Point { x: 9.0, y: 1.5 }]),
+            enemy(0.5, 0.5, Vec::new()),
+        ];
+        assert_eq!(
+            level.validate(),
+            vec![
+                LevelIssue::StartInWall,
+                LevelIssue::ExitOutside,
+                LevelIssue::SpawnInWall { index: 1 },
+                LevelIssue::PatrolOutside { enemy: 0, point: 1 },
+                LevelIssue::EnemyInWall { index: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn player_spawns_cycle_through_start_and_spawns() {
+        let mut level = level(&["#####", "#...#", "#...#", "#...#", "#####"]);
+        level.spawns = vec![Point { x: 2.5, y: 1.5 }];
+        assert_eq!(level.player_spawn(0), (1.5 * CELL_SIZE, 1.5 * CELL_SIZE));
+        assert_eq!(level.player_spawn(1), (2.5 * CELL_SIZE, 1.5 * CELL_SIZE));
+        assert_eq!(level.player_spawn(2), level.player_spawn(0));
+    }
+
+    #[test]
+    fn level_sources_round_trip() {
+        for source in [LevelSource::Numbered { number: 7, seed: 1234 }, LevelSource::File("arena_2".to_string())] {
+            assert_eq!(LevelSource::decode(&source.encode()), Some(source));
+        }
+        assert_eq!(LevelSource::decode("../secret"), None);
+        assert_eq!(LevelSource::decode("0@5"), None);
+        assert_eq!(LevelSource::decode(""), None);
+    }
+
+    #[test]
+    fn content_hash_follows_the_level() {
+        let original = level(&["#####", "#...#", "#...#", "#...#", "#####"]);
+        let reloaded = Level::parse(&original.to_ron().unwrap()).unwrap();
+        assert_eq!(original.content_hash(), reloaded.content_hash());
+
+        let mut changed = original.clone();
+        changed.set_wall(2, 2, true);
+        assert_ne!(original.content_hash(), changed.content_hash());
+    }
+
+    #[test]
+    fn doors_and_switches_count_as_openable() {
+        let mut level = level(&["#####", "#.#.#", "#D#.#", "#.#.#", "#####"]);
+        level.exit_x = 1.5;
+        assert_eq!(level.validate(), vec![]);
+        assert!(level.to_maze().is_wall(1, 2));
+
+        level.set_wall(1, 2, true);
+        assert_eq!(level.validate(), vec![LevelIssue::ExitUnreachable]);
+        level.grid[2] = "#P#.#".to_string();
+        assert_eq!(level.validate(), vec![]);
+        level.switches.push(Switch { x: 3.5, y: 1.5, targets: vec![(1, 2), (4, 2), (3, 2)] });
+        assert_eq!(
+            level.validate(),
+            vec![
+                LevelIssue::SwitchTargetOutside { switch: 0, target: 1 },
+                LevelIssue::SwitchTargetNotMechanism { switch: 0, target: 2 }
+            ]
+        );
+    }
+
+    #[test]
+    fn traps_are_floor_and_teleporters_need_floor_at_both_ends() {
+        let mut level = level(&["#####", "#.~.#", "#!#.#", "#...#", "#####"]);
+        assert_eq!(level.validate(), vec![]);
+        assert!(!level.to_maze().is_wall(2, 1));
+
+        level.teleporters = vec![
+            Teleporter { from: (1, 1), to: (3, 3) },
+            Teleporter { from: (1, 1), to: (2, 2) },
+            Teleporter { from: (9, 1), to: (3, 3) },
+        ];
+        assert_eq!(
+            level.validate(),
+            vec![LevelIssue::TeleporterInWall { index: 1 }, LevelIssue::TeleporterOutside { index: 2 }]
+        );
+    }
+
+    #[test]
+    fn bases_fall_back_to_start_and_exit_and_teams_spawn_near_theirs() {
+        let mut level = level(&["#####", "#...#", "#...#", "#...#", "#####"]);
+        level.spawns = vec![Point { x: 3.5, y: 3.5 }, Point { x: 1.5, y: 2.5 }, Point { x: 3.5, y: 2.5 }];
+        let world = |x: f32, y: f32| (x * CELL_SIZE, y * CELL_SIZE);
+        assert_eq!((level.base(Team::Red), level.base(Team::Blue)), (world(1.5, 1.5), world(3.5, 3.5)));
+        let red: Vec<_> = (0..3).map(|index| level.team_spawn(Team::Red, index)).collect();
+        assert_eq!(red, [world(1.5, 1.5), world(1.5, 2.5), world(1.5, 1.5)]);
+        assert_eq!(level.team_spawn(Team::Blue, 1), world(3.5, 2.5));
+
+        level.bases = vec![Base { team: Team::Blue, x: 1.5, y: 3.5 }, Base { team: Team::Blue, x: 0.5, y: 3.5 }];
+        assert_eq!(level.base(Team::Blue), world(1.5, 3.5));
+        assert_eq!(
+            level.validate(),
+            vec![LevelIssue::BaseInWall { index: 1 }, LevelIssue::DuplicateBase { team: Team::Blue }]
+        );
+    }
+
+    #[test]
+    fn player_radius_defaults_and_must_fit_a_corridor() {
+        let mut level = level(&["#####", "#...#", "#...#", "#...#", "#####"]);
+        assert_eq!(level.player_radius(), DEFAULT_PLAYER_RADIUS);
+        level.player_radius = Some(10.0);
+        let reloaded = Level::parse(&level.to_ron().unwrap()).unwrap();
+        assert_eq!((reloaded.player_radius(), reloaded.validate()), (10.0, vec![]));
+        for radius in [0.0, MAX_PLAYER_RADIUS, f32::NAN] {
+            level.player_radius = Some(radius);
+            assert_eq!(level.validate(), vec![LevelIssue::PlayerRadius]);
+        }
+    }
+}
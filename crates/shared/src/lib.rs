@@ -1 +1,37 @@
-// Shared library crate for protocol/types (placeholder)
\ No newline at end of file
+//! Shared protocol, types and constants used by both the client and the server.
+
+pub mod address;
+pub mod discovery;
+pub mod flag;
+pub mod generator;
+pub mod level;
+pub mod maze;
+pub mod mechanism;
+pub mod mode;
+pub mod movement;
+pub mod pickup;
+pub mod protocol;
+pub mod reliable;
+pub mod rng;
+pub mod score;
+pub mod team;
+pub mod transfer;
+pub mod trap;
+pub mod weapon;
+
+/// Size of one maze cell in world units.
+pub const CELL_SIZE: f32 = 64.0;
+
+/// Default UDP port the server listens on.
+pub const DEFAULT_PORT: u16 = 34254;
+
+/// Maximum look up/down angle in radians, in either direction.
+pub const MAX_PITCH: f32 = 0.4;
+
+/// Clamps a pitch angle to the range players are allowed to look in.
+pub fn clamp_pitch(pitch: f32) -> f32 {
+    if pitch.is_nan() {
+        return 0.0;
+    }
+    pitch.clamp(-MAX_PITCH, MAX_PITCH)
+}
@@ -0,0 +1,282 @@
+//! Maze layouts shared by the client and the server so both simulate
+//! movement against the same walls.
+//!
+//! Levels 1 to [`LEVEL_COUNT`] are hand-made. Every level after that is
+//! generated from the session seed, see [`crate::generator`].
+
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::level::Level;
+use crate::movement::Grid;
+use crate::CELL_SIZE;
+
+/// Size of the hand-made mazes.
+pub const MAZE_WIDTH: usize = 16;
+pub const MAZE_HEIGHT: usize = 16;
+
+/// Number of hand-made levels.
+pub const LEVEL_COUNT: usize = 3;
+
+/// How close a player has to get to the exit to finish the level.
+pub const EXIT_RADIUS: f32 = 40.0;
+
+/// A compass direction on the grid. North is up, towards row 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Direction {
+    /// Column and row offset of one step this way.
+    pub fn offset(self) -> (i32, i32) {
+        match self {
+            Direction::North => (0, -1),
+            Direction::East => (1, 0),
+            Direction::South => (0, 1),
+            Direction::West => (-1, 0),
+        }
+    }
+
+    /// Whether a move by `(dx, dy)` goes against this direction.
+    pub fn opposes(self, dx: f32, dy: f32) -> bool {
+        let (ox, oy) = self.offset();
+        dx * (ox as f32) + dy * (oy as f32) < 0.0
+    }
+}
+
+/// A rectangular wall grid with a start and an exit cell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Maze {
+    width: usize,
+    height: usize,
+    walls: Vec<bool>,
+    // Floor cells that can only be walked through one way
+    one_way: BTreeMap<(usize, usize), Direction>,
+    /// Spawn cell as `(column, row)`.
+    pub start: (usize, usize),
+    /// Exit cell as `(column, row)`.
+    pub exit: (usize, usize),
+}
+
+impl Maze {
+    /// An empty room of the given size enclosed by walls.
+    pub fn new(width: usize, height: usize) -> Self {
+        let mut maze = Self {
+            width,
+            height,
+            walls: vec![false; width * height],
+            one_way: BTreeMap::new(),
+            start: (1, 1),
+            exit: (width.saturating_sub(2), height.saturating_sub(2)),
+        };
+        for x in 0..width {
+            maze.set_wall(x, 0, true);
+            maze.set_wall(x, height - 1, true);
+        }
+        for y in 0..height {
+            maze.set_wall(0, y, true);
+            maze.set_wall(width - 1, y, true);
+        }
+        maze
+    }
+
+    /// A grid that is wall everywhere, for generators to carve into.
+    pub fn solid(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            walls: vec![true; width * height],
+            one_way: BTreeMap::new(),
+            start: (1, 1),
+            exit: (1, 1),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Whether the cell at `(x, y)` is a wall. Anything outside the grid is.
+    pub fn is_wall(&self, x: usize, y: usize) -> bool {
+        x >= self.width || y >= self.height || self.walls[y * self.width + x]
+    }
+
+    /// Sets a cell. Cells outside the grid are ignored.
+    pub fn set_wall(&mut self, x: usize, y: usize, wall: bool) {
+        if x < self.width && y < self.height {
+            self.walls[y * self.width + x] = wall;
+        }
+    }
+
+    /// The direction a one-way cell lets players through in, if it is one.
+    pub fn one_way(&self, x: usize, y: usize) -> Option<Direction> {
+        self.one_way.get(&(x, y)).copied()
+    }
+
+    /// Makes a cell one-way, or a normal cell again with `None`.
+    pub fn set_one_way(&mut self, x: usize, y: usize, direction: Option<Direction>) {
+        match direction {
+            Some(direction) => self.one_way.insert((x, y), direction),
+            None => self.one_way.remove(&(x, y)),
+        };
+    }
+
+    /// Centre of the start cell in world units.
+    pub fn start_position(&self) -> (f32, f32) {
+        cell_center(self.start)
+    }
+
+    /// Centre of the exit cell in world units.
+    pub fn exit_position(&self) -> (f32, f32) {
+        cell_center(self.exit)
+    }
+
+    /// Whether a player standing at `(x, y)` has reached the exit.
+    pub fn reached_exit(&self, x: f32, y: f32) -> bool {
+        let (exit_x, exit_y) = self.exit_position();
+        (x - exit_x).hypot(y - exit_y) < EXIT_RADIUS
+    }
+
+    /// Whether a straight line between two points in world units misses
+    /// every wall, checked every eighth of a cell along the way.
+    pub fn clear_line(&self, from: (f32, f32), to: (f32, f32)) -> bool {
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let steps = (dx.hypot(dy) / (CELL_SIZE / 8.0)).ceil() as usize;
+        (0..=steps).all(|i| {
+            let t = if steps == 0 { 0.0 } else { i as f32 / steps as f32 };
+            let (x, y) = (from.0 + dx * t, from.1 + dy * t);
+            !self.is_wall((x / CELL_SIZE) as usize, (y / CELL_SIZE) as usize)
+        })
+    }
+
+    /// Walking distance in cells from `from` to every cell, `None` where a
+    /// cell can't be reached. Indexed as `y * width + x`.
+    pub fn distances_from(&self, from: (usize, usize)) -> Vec<Option<usize>> {
+        let mut distances = vec![None; self.width * self.height];
+        if self.is_wall(from.0, from.1) {
+            return distances;
+        }
+        distances[from.1 * self.width + from.0] = Some(0);
+        let mut queue = VecDeque::from([from]);
+        while let Some((x, y)) = queue.pop_front() {
+            let distance = distances[y * self.width + x].unwrap_or_default();
+            for (nx, ny) in self.open_neighbours(x, y) {
+                let slot = &mut distances[ny * self.width + nx];
+                if slot.is_none() {
+                    *slot = Some(distance + 1);
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+        distances
+    }
+
+    /// Whether the exit can be walked to from the start.
+    pub fn has_path(&self) -> bool {
+        self.distances_from(self.start)[self.exit.1 * self.width + self.exit.0].is_some()
+    }
+
+    /// Cells a step away that can be walked into, respecting one-way cells
+    /// on both ends of the step.
+    fn open_neighbours(&self, x: usize, y: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        [(1, 0), (-1, 0), (0, 1), (0, -1)]
+            .into_iter()
+            .filter_map(move |(dx, dy)| {
+                let nx = x.checked_add_signed(dx)?;
+                let ny = y.checked_add_signed(dy)?;
+                let against = |cell| self.one_way.get(&cell).is_some_and(|d: &Direction| d.opposes(dx as f32, dy as f32));
+                (!self.is_wall(nx, ny) && !against((x, y)) && !against((nx, ny))).then_some((nx, ny))
+            })
+    }
+}
+
+impl Grid for Maze {
+    fn is_solid(&self, cell_x: i32, cell_y: i32) -> bool {
+        cell_x < 0 || cell_y < 0 || self.is_wall(cell_x as usize, cell_y as usize)
+    }
+
+    fn blocks(&self, cell_x: i32, cell_y: i32, dx: f32, dy: f32) -> bool {
+        self.is_solid(cell_x, cell_y)
+            || self.one_way(cell_x as usize, cell_y as usize).is_some_and(|direction| direction.opposes(dx, dy))
+    }
+}
+
+fn cell_center((x, y): (usize, usize)) -> (f32, f32) {
+    ((x as f32 + 0.5) * CELL_SIZE, (y as f32 + 0.5) * CELL_SIZE)
+}
+
+/// The maze for `level`: hand-made for the first [`LEVEL_COUNT`] levels and
+/// generated from `seed` after that, so everyone sharing the seed gets the
+/// same endless sequence of levels.
+pub fn level_maze(level: usize, seed: u64) -> Maze {
+    Level::numbered(level, seed).to_maze()
+}
+
+/// Builds the hand-made maze for `level` from its file in `levels/`.
+/// Levels past [`LEVEL_COUNT`] are an empty box.
+pub fn builtin_maze(level: usize) -> Maze {
+    match Level::builtin(level) {
+        Some(level) => level.to_maze(),
+        None => Maze::new(MAZE_WIDTH, MAZE_HEIGHT),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movement::{self, DEFAULT_PLAYER_RADIUS};
+
+    #[test]
+    fn builtin_levels_are_solvable() {
+        for level in 1..=LEVEL_COUNT {
+            assert!(builtin_maze(level).has_path(), "level {level}");
+        }
+    }
+
+    #[test]
+    fn walls_block_lines_between_points() {
+        let mut maze = Maze::new(5, 5);
+        let (west, east) = ((1.5 * CELL_SIZE, 2.5 * CELL_SIZE), (3.5 * CELL_SIZE, 2.5 * CELL_SIZE));
+        assert!(maze.clear_line(west, east));
+        assert!(maze.clear_line(west, west));
+        maze.set_wall(2, 2, true);
+        assert!(!maze.clear_line(west, east));
+        assert!(!maze.clear_line(east, west));
+        // Around it is still clear
+        assert!(maze.clear_line((1.5 * CELL_SIZE, 1.5 * CELL_SIZE), (3.5 * CELL_SIZE, 1.5 * CELL_SIZE)));
+    }
+
+    #[test]
+    fn one_way_cells_only_let_players_through_one_way() {
+        let mut maze = Maze::new(5, 3);
+        maze.set_one_way(2, 1, Some(Direction::East));
+        let (west, east) = (1.5 * CELL_SIZE, 3.5 * CELL_SIZE);
+        let y = 1.5 * CELL_SIZE;
+
+        let forward = movement::slide_move(&maze, west, y, 2.0 * CELL_SIZE, 0.0, DEFAULT_PLAYER_RADIUS);
+        assert_eq!(forward.x, east);
+        let back = movement::slide_move(&maze, east, y, -2.0 * CELL_SIZE, 0.0, DEFAULT_PLAYER_RADIUS);
+        assert!(back.blocked_x);
+        assert!(back.x - DEFAULT_PLAYER_RADIUS >= 3.0 * CELL_SIZE - 1.0);
+
+        maze.start = (1, 1);
+        maze.exit = (3, 1);
+        assert!(maze.has_path());
+        (maze.start, maze.exit) = (maze.exit, maze.start);
+        assert!(!maze.has_path());
+    }
+
+    #[test]
+    fn generated_levels_are_reproducible() {
+        assert_eq!(level_maze(7, 42), level_maze(7, 42));
+        assert_ne!(level_maze(7, 42), level_maze(7, 43));
+        assert_ne!(level_maze(7, 42), level_maze(8, 42));
+    }
+}
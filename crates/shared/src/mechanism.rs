@@ -0,0 +1,404 @@
+//! Doors, switches, push walls and spike timers: the parts of a level that
+//! change while it's played.
+//!
+//! The server runs them and sends their state to clients when it changes,
+//! and once a second for players who missed it; offline the client runs
+//! them itself. Changes are written
+//! straight into the [`Maze`], so movement and collision need nothing
+//! special. One-way passages never change and live in the maze itself.
+
+use crate::level::{Level, AUTO_DOOR, DOOR, PUSH_WALL};
+use crate::maze::Maze;
+use crate::trap::Traps;
+use crate::CELL_SIZE;
+
+/// How far away a player can use a door, push wall or switch.
+pub const USE_RANGE: f32 = CELL_SIZE * 1.2;
+
+/// How close a player has to come for an automatic door to open.
+pub const AUTO_DOOR_RANGE: f32 = CELL_SIZE * 1.5;
+
+/// Fraction of a door that opens or closes per second.
+const DOOR_SPEED: f32 = 1.5;
+
+/// How long a door stays fully open before it starts closing.
+const DOOR_OPEN_SECONDS: f32 = 3.0;
+
+/// Step used when looking along a player's view for something to use.
+const USE_STEP: f32 = 4.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Door {
+    pub x: usize,
+    pub y: usize,
+    /// Opens when someone comes close instead of when used.
+    pub automatic: bool,
+    /// 0 when closed, 1 when fully open. Only a fully open door can be
+    /// walked through.
+    pub open: f32,
+    opening: bool,
+    // Seconds left before a fully open door closes again
+    hold: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwitchState {
+    /// Position in cells, like in the level file.
+    pub x: f32,
+    pub y: f32,
+    pub on: bool,
+    targets: Vec<Target>,
+}
+
+/// What a switch works, found from its target cells when the level starts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Target {
+    /// Held open while the switch is on.
+    Door(usize),
+    /// Gone while the switch is on, wherever it has been pushed to.
+    PushWall(usize),
+}
+
+/// Everything about the mechanisms that changes, as sent to clients.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MechanismState {
+    /// How far each door is open, in percent.
+    pub doors: Vec<u8>,
+    pub switches: Vec<bool>,
+    /// Cell each push wall is in now.
+    pub push_walls: Vec<(usize, usize)>,
+    pub spikes_raised: bool,
+}
+
+/// The moving parts of one level.
+#[derive(Debug, Clone, Default)]
+pub struct Mechanisms {
+    doors: Vec<Door>,
+    switches: Vec<SwitchState>,
+    push_walls: Vec<(usize, usize)>,
+    traps: Traps,
+    /// The level's player radius, for telling whether someone is in the way.
+    player_radius: f32,
+}
+
+impl Mechanisms {
+    pub fn new(level: &Level) -> Self {
+        let mut mechanisms = Self { player_radius: level.player_radius(), ..Self::default() };
+        for (y, row) in level.grid.iter().enumerate() {
+            for (x, symbol) in row.chars().enumerate() {
+                match symbol {
+                    DOOR | AUTO_DOOR => mechanisms.doors.push(Door {
+                        x,
+                        y,
+                        automatic: symbol == AUTO_DOOR,
+                        open: 0.0,
+                        opening: false,
+                        hold: 0.0,
+                    }),
+                    PUSH_WALL => mechanisms.push_walls.push((x, y)),
+                    _ => {}
+                }
+            }
+        }
+        // Targets that are neither were reported by validation and do nothing
+        let target = |&(x, y): &(usize, usize)| {
+            let door = mechanisms.doors.iter().position(|door| (door.x, door.y) == (x, y)).map(Target::Door);
+            door.or_else(|| mechanisms.push_walls.iter().position(|&wall| wall == (x, y)).map(Target::PushWall))
+        };
+        let switches = level
+            .switches
+            .iter()
+            .map(|switch| SwitchState { x: switch.x, y: switch.y, on: false, targets: switch.targets.iter().filter_map(target).collect() })
+            .collect();
+        mechanisms.switches = switches;
+        mechanisms.traps = Traps::new(level);
+        mechanisms
+    }
+
+    /// Whether the level has nothing that moves, so there's nothing to sync.
+    pub fn is_empty(&self) -> bool {
+        self.doors.is_empty() && self.switches.is_empty() && self.push_walls.is_empty() && !self.traps.has_spikes()
+    }
+
+    pub fn doors(&self) -> &[Door] {
+        &self.doors
+    }
+
+    pub fn door_at(&self, x: usize, y: usize) -> Option<&Door> {
+        self.doors.iter().find(|door| door.x == x && door.y == y)
+    }
+
+    pub fn switches(&self) -> &[SwitchState] {
+        &self.switches
+    }
+
+    pub fn traps(&self) -> &Traps {
+        &self.traps
+    }
+
+    /// Opens and closes doors and runs the spike timer over `seconds`.
+    /// `players` are positions in world units.
+    pub fn update(&mut self, maze: &mut Maze, players: &[(f32, f32)], seconds: f32) {
+        self.traps.update(seconds);
+        for (index, door) in self.doors.iter_mut().enumerate() {
+            let centre = cell_centre(door.x, door.y);
+            let near = players.iter().any(|&(x, y)| (x - centre.0).hypot(y - centre.1) < AUTO_DOOR_RANGE);
+            if (door.automatic && near) || switched(&self.switches, Target::Door(index)) {
+                door.opening = true;
+                door.hold = DOOR_OPEN_SECONDS;
+            }
+            if door.opening {
+                door.open = (door.open + DOOR_SPEED * seconds).min(1.0);
+                if door.open >= 1.0 {
+                    door.hold -= seconds;
+                    // Closing on someone standing in the doorway would trap them
+                    if door.hold <= 0.0 && !occupied(door.x, door.y, players, self.player_radius) {
+                        door.opening = false;
+                    }
+                }
+            } else {
+                door.open = (door.open - DOOR_SPEED * seconds).max(0.0);
+            }
+            maze.set_wall(door.x, door.y, door.open < 1.0);
+        }
+    }
+
+    /// A player at `(x, y)` looking along `angle` uses what's in front of
+    /// them: opens a door or pushes a push wall, or failing that flips the
+    /// nearest switch in reach. Returns whether anything happened.
+    pub fn use_from(&mut self, maze: &mut Maze, players: &[(f32, f32)], x: f32, y: f32, angle: f32) -> bool {
+        let (sin, cos) = angle.sin_cos();
+        let mut distance = 0.0;
+        while distance <= USE_RANGE {
+            let (px, py) = (x + cos * distance, y + sin * distance);
+            if px < 0.0 || py < 0.0 {
+                break;
+            }
+            let cell = ((px / CELL_SIZE) as usize, (py / CELL_SIZE) as usize);
+            if let Some(door) = self.doors.iter_mut().find(|door| (door.x, door.y) == cell) {
+                if door.automatic {
+                    return false;
+                }
+                door.opening = true;
+                door.hold = DOOR_OPEN_SECONDS;
+                return true;
+            }
+            if let Some(index) = self.push_walls.iter().position(|&wall| wall == cell) {
+                if !switched(&self.switches, Target::PushWall(index)) {
+                    return self.push(maze, players, index, x, y);
+                }
+            }
+            if maze.is_wall(cell.0, cell.1) {
+                break;
+            }
+            distance += USE_STEP;
+        }
+
+        let nearest = (0..self.switches.len())
+            .map(|index| {
+                let switch = &self.switches[index];
+                (index, (switch.x * CELL_SIZE - x).hypot(switch.y * CELL_SIZE - y))
+            })
+            .filter(|&(_, distance)| distance <= USE_RANGE)
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        match nearest {
+            Some((index, _)) => self.flip(maze, players, index),
+            None => false,
+        }
+    }
+
+    /// Slides push wall `index` one cell away from a player at `(x, y)`, if
+    /// the cell behind it is empty floor.
+    fn push(&mut self, maze: &mut Maze, players: &[(f32, f32)], index: usize, x: f32, y: f32) -> bool {
+        let (wall_x, wall_y) = self.push_walls[index];
+        let centre = cell_centre(wall_x, wall_y);
+        let (dx, dy) = (centre.0 - x, centre.1 - y);
+        let step: (isize, isize) = if dx.abs() > dy.abs() { (dx.signum() as isize, 0) } else { (0, dy.signum() as isize) };
+        let Some(target) = wall_x.checked_add_signed(step.0).zip(wall_y.checked_add_signed(step.1)) else {
+            return false;
+        };
+        let free = !maze.is_wall(target.0, target.1)
+            && maze.one_way(target.0, target.1).is_none()
+            && target != maze.exit
+            && !occupied(target.0, target.1, players, self.player_radius)
+            && self.door_at(target.0, target.1).is_none();
+        if !free {
+            return false;
+        }
+        maze.set_wall(wall_x, wall_y, false);
+        maze.set_wall(target.0, target.1, true);
+        self.push_walls[index] = target;
+        true
+    }
+
+    /// Flips switch `index`, holding its doors open and taking its push
+    /// walls away, or letting them back. Refuses while a player stands where
+    /// a push wall would come back.
+    fn flip(&mut self, maze: &mut Maze, players: &[(f32, f32)], index: usize) -> bool {
+        let blocked = self.switches[index].targets.iter().any(|&target| match target {
+            Target::PushWall(wall) => {
+                let (x, y) = self.push_walls[wall];
+                !maze.is_wall(x, y) && occupied(x, y, players, self.player_radius)
+            }
+            Target::Door(_) => false,
+        });
+        if blocked {
+            return false;
+        }
+        self.switches[index].on = !self.switches[index].on;
+        self.place_push_walls(maze);
+        true
+    }
+
+    /// Puts every push wall into the maze, except those a switch has taken away.
+    fn place_push_walls(&self, maze: &mut Maze) {
+        for (index, &(x, y)) in self.push_walls.iter().enumerate() {
+            maze.set_wall(x, y, !switched(&self.switches, Target::PushWall(index)));
+        }
+    }
+
+    pub fn state(&self) -> MechanismState {
+        MechanismState {
+            doors: self.doors.iter().map(|door| (door.open * 100.0).round() as u8).collect(),
+            switches: self.switches.iter().map(|switch| switch.on).collect(),
+            push_walls: self.push_walls.clone(),
+            spikes_raised: self.traps.spikes_raised(),
+        }
+    }
+
+    /// Takes over a state from the server and updates the maze to match. A
+    /// state for a different level, with other counts, is ignored.
+    pub fn apply(&mut self, maze: &mut Maze, state: &MechanismState) {
+        let matches = state.doors.len() == self.doors.len()
+            && state.switches.len() == self.switches.len()
+            && state.push_walls.len() == self.push_walls.len();
+        if !matches {
+            return;
+        }
+        self.traps.set_spikes_raised(state.spikes_raised);
+        for (door, &open) in self.doors.iter_mut().zip(&state.doors) {
+            door.open = open.min(100) as f32 / 100.0;
+            maze.set_wall(door.x, door.y, door.open < 1.0);
+        }
+        for (switch, &on) in self.switches.iter_mut().zip(&state.switches) {
+            switch.on = on;
+        }
+        for (wall, &cell) in self.push_walls.iter_mut().zip(&state.push_walls) {
+            if *wall != cell {
+                maze.set_wall(wall.0, wall.1, false);
+                *wall = cell;
+            }
+        }
+        self.place_push_walls(maze);
+    }
+}
+
+/// Whether `target` is worked by a switch that's on. Two switches on the
+/// same target undo each other.
+fn switched(switches: &[SwitchState], target: Target) -> bool {
+    switches.iter().filter(|switch| switch.on && switch.targets.contains(&target)).count() % 2 == 1
+}
+
+fn cell_centre(x: usize, y: usize) -> (f32, f32) {
+    ((x as f32 + 0.5) * CELL_SIZE, (y as f32 + 0.5) * CELL_SIZE)
+}
+
+/// Whether any player's collision circle overlaps the cell.
+fn occupied(x: usize, y: usize, players: &[(f32, f32)], radius: f32) -> bool {
+    let (left, top) = (x as f32 * CELL_SIZE, y as f32 * CELL_SIZE);
+    players.iter().any(|&(px, py)| {
+        let closest_x = px.clamp(left, left + CELL_SIZE);
+        let closest_y = py.clamp(top, top + CELL_SIZE);
+        (px - closest_x).hypot(py - closest_y) < radius
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::Switch;
+
+    fn level(grid: &[&str]) -> Level {
+        let mut level = Level::from_maze("Test", 1, &Maze::new(grid[0].len(), grid.len()));
+        level.grid = grid.iter().map(|row| row.to_string()).collect();
+        level
+    }
+
+    fn at(x: f32, y: f32) -> (f32, f32) {
+        (x * CELL_SIZE, y * CELL_SIZE)
+    }
+
+    #[test]
+    fn used_doors_open_and_close_again() {
+        let level = level(&["#####", "#.D.#", "#####"]);
+        let mut maze = level.to_maze();
+        let mut mechanisms = Mechanisms::new(&level);
+        let player = at(1.5, 1.5);
+
+        // Facing away there's nothing to use
+        assert!(!mechanisms.use_from(&mut maze, &[player], player.0, player.1, std::f32::consts::PI));
+        assert!(mechanisms.use_from(&mut maze, &[player], player.0, player.1, 0.0));
+        mechanisms.update(&mut maze, &[player], 0.5);
+        assert!(maze.is_wall(2, 1));
+        mechanisms.update(&mut maze, &[player], 0.5);
+        assert!(!maze.is_wall(2, 1));
+
+        // It waits for the player to leave the doorway before closing
+        let doorway = at(2.5, 1.5);
+        mechanisms.update(&mut maze, &[doorway], DOOR_OPEN_SECONDS + 1.0);
+        mechanisms.update(&mut maze, &[doorway], 0.1);
+        assert!(!maze.is_wall(2, 1));
+        mechanisms.update(&mut maze, &[player], 0.1);
+        mechanisms.update(&mut maze, &[player], 0.1);
+        assert!(maze.is_wall(2, 1));
+        assert!(mechanisms.doors()[0].open < 1.0);
+    }
+
+    #[test]
+    fn push_walls_slide_away_from_the_player() {
+        let level = level(&["#######", "#.P...#", "#######"]);
+        let mut maze = level.to_maze();
+        let mut mechanisms = Mechanisms::new(&level);
+        let player = at(1.5, 1.5);
+
+        assert!(mechanisms.use_from(&mut maze, &[player], player.0, player.1, 0.0));
+        assert!(!maze.is_wall(2, 1) && maze.is_wall(3, 1));
+
+        // Out of reach now, step up to push it again until it would cover the exit
+        assert!(!mechanisms.use_from(&mut maze, &[player], player.0, player.1, 0.0));
+        let closer = at(2.5, 1.5);
+        assert!(mechanisms.use_from(&mut maze, &[closer], closer.0, closer.1, 0.0));
+        assert!(maze.is_wall(4, 1));
+        let closest = at(3.5, 1.5);
+        assert!(!mechanisms.use_from(&mut maze, &[closest], closest.0, closest.1, 0.0));
+    }
+
+    #[test]
+    fn switches_hold_doors_open_and_take_push_walls_away() {
+        let mut level = level(&["#####", "#...#", "#PD.#", "#####"]);
+        level.switches.push(Switch { x: 1.5, y: 1.5, targets: vec![(1, 2), (2, 2)] });
+        let mut maze = level.to_maze();
+        let mut mechanisms = Mechanisms::new(&level);
+        let player = at(3.5, 1.5);
+
+        assert!(mechanisms.use_from(&mut maze, &[player], at(1.5, 1.5).0, at(1.5, 1.5).1, std::f32::consts::PI));
+        assert!(!maze.is_wall(1, 2) && maze.is_wall(2, 2));
+        mechanisms.update(&mut maze, &[player], DOOR_OPEN_SECONDS + 1.0);
+        mechanisms.update(&mut maze, &[player], DOOR_OPEN_SECONDS + 1.0);
+        assert!(!maze.is_wall(2, 2));
+
+        let mut client_maze = level.to_maze();
+        let mut client = Mechanisms::new(&level);
+        client.apply(&mut client_maze, &mechanisms.state());
+        assert_eq!(client_maze, maze);
+
+        // Someone standing where the push wall was stops it coming back
+        let user = at(1.5, 1.5);
+        assert!(!mechanisms.use_from(&mut maze, &[user, at(1.5, 2.5)], user.0, user.1, std::f32::consts::PI));
+        assert!(mechanisms.use_from(&mut maze, &[user], user.0, user.1, std::f32::consts::PI));
+        assert!(maze.is_wall(1, 2));
+        mechanisms.update(&mut maze, &[user], DOOR_OPEN_SECONDS + 1.0);
+        mechanisms.update(&mut maze, &[user], 1.0);
+        assert!(maze.is_wall(2, 2));
+    }
+}
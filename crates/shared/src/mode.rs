@@ -0,0 +1,202 @@
+//! Game modes and what dying costs in each.
+//!
+//! A dead player waits [`RESPAWN_SECONDS`] and comes back at their spawn
+//! point. Depending on the mode the death takes a life or some score (see
+//! [`crate::score`]); with no lives left the game is over until the player
+//! restarts. Online the server decides all of this and tells the client.
+
+use serde::{Deserialize, Serialize};
+
+use crate::team::{CAPTURE_LIMIT, KILL_LIMIT};
+
+/// Seconds from dying to coming back.
+pub const RESPAWN_SECONDS: f32 = 3.0;
+
+/// Lives at the start of a survival game.
+pub const STARTING_LIVES: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum GameMode {
+    /// A few lives, then game over.
+    #[default]
+    Survival,
+    /// Endless respawns, each death costs score.
+    Deathmatch,
+    /// Deathmatch in two teams, the first to [`KILL_LIMIT`] kills wins the round.
+    TeamDeathmatch,
+    /// Two teams stealing each other's flag, the first to [`CAPTURE_LIMIT`]
+    /// captures wins the round. See [`crate::flag`].
+    CaptureTheFlag,
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 4] = [GameMode::Survival, GameMode::Deathmatch, GameMode::TeamDeathmatch, GameMode::CaptureTheFlag];
+
+    /// Name on the wire and on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            GameMode::Survival => "survival",
+            GameMode::Deathmatch => "deathmatch",
+            GameMode::TeamDeathmatch => "team-deathmatch",
+            GameMode::CaptureTheFlag => "ctf",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+
+    /// Lives a player starts with, `None` when they're unlimited.
+    pub fn starting_lives(self) -> Option<u32> {
+        match self {
+            GameMode::Survival => Some(STARTING_LIVES),
+            GameMode::Deathmatch | GameMode::TeamDeathmatch | GameMode::CaptureTheFlag => None,
+        }
+    }
+
+    /// Whether players are split into teams, which also gives them team chat.
+    pub fn has_teams(self) -> bool {
+        match self {
+            GameMode::Survival | GameMode::Deathmatch => false,
+            GameMode::TeamDeathmatch | GameMode::CaptureTheFlag => true,
+        }
+    }
+
+    /// Whether teams have flags to steal, see [`crate::flag`].
+    pub fn has_flags(self) -> bool {
+        self == GameMode::CaptureTheFlag
+    }
+
+    /// Whether players can shoot each other. Survival is everyone against
+    /// the enemies.
+    pub fn players_fight(self) -> bool {
+        self != GameMode::Survival
+    }
+
+    /// What a team needs to win the round, kills or captures. `None` in
+    /// modes without teams, where the round ends at the exit.
+    pub fn team_score_limit(self) -> Option<u32> {
+        match self {
+            GameMode::Survival | GameMode::Deathmatch => None,
+            GameMode::TeamDeathmatch => Some(KILL_LIMIT),
+            GameMode::CaptureTheFlag => Some(CAPTURE_LIMIT),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LifeState {
+    Alive,
+    /// Waiting to respawn, with seconds left.
+    Dead { killer: String, respawn: f32 },
+    /// Out of lives.
+    GameOver { killer: String },
+}
+
+/// Whether a player is alive and how many lives they have left.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Life {
+    mode: GameMode,
+    lives: Option<u32>,
+    state: LifeState,
+}
+
+impl Life {
+    pub fn new(mode: GameMode) -> Self {
+        Self { mode, lives: mode.starting_lives(), state: LifeState::Alive }
+    }
+
+    pub fn mode(&self) -> GameMode {
+        self.mode
+    }
+
+    /// Lives left, `None` when they're unlimited.
+    pub fn lives(&self) -> Option<u32> {
+        self.lives
+    }
+
+    pub fn state(&self) -> &LifeState {
+        &self.state
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.state == LifeState::Alive
+    }
+
+    /// Records a death, taking a life in modes that count them.
+    pub fn die(&mut self, killer: &str) {
+        let lives = self.lives.map(|lives| lives.saturating_sub(1));
+        self.died(killer, lives);
+    }
+
+    /// Records a death the server reported, leaving `lives`.
+    pub fn died(&mut self, killer: &str, lives: Option<u32>) {
+        let killer = killer.to_string();
+        self.lives = lives;
+        self.state = match lives {
+            Some(0) => LifeState::GameOver { killer },
+            _ => LifeState::Dead { killer, respawn: RESPAWN_SECONDS },
+        };
+    }
+
+    /// Runs the respawn countdown. Returns whether it has run out.
+    pub fn update(&mut self, seconds: f32) -> bool {
+        match &mut self.state {
+            LifeState::Dead { respawn, .. } => {
+                *respawn = (*respawn - seconds).max(0.0);
+                *respawn == 0.0
+            }
+            LifeState::Alive | LifeState::GameOver { .. } => false,
+        }
+    }
+
+    /// Back to life after the countdown. Does nothing once the game is over.
+    pub fn respawn(&mut self) {
+        if let LifeState::Dead { .. } = self.state {
+            self.state = LifeState::Alive;
+        }
+    }
+
+    /// Starts over with all lives.
+    pub fn restart(&mut self) {
+        *self = Self::new(self.mode);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn survival_ends_when_the_lives_run_out() {
+        let mut life = Life::new(GameMode::Survival);
+        for left in (1..STARTING_LIVES).rev() {
+            life.die("Grunt");
+            assert_eq!(life.lives(), Some(left));
+            assert!(!life.update(RESPAWN_SECONDS - 1.0));
+            assert!(life.update(1.0));
+            life.respawn();
+            assert!(life.is_alive());
+        }
+        life.die("Spikes");
+        assert_eq!(life.state(), &LifeState::GameOver { killer: "Spikes".to_string() });
+        assert!(!life.update(RESPAWN_SECONDS));
+        life.respawn();
+        assert!(!life.is_alive());
+        life.restart();
+        assert_eq!((life.is_alive(), life.lives()), (true, Some(STARTING_LIVES)));
+    }
+
+    #[test]
+    fn deathmatch_lives_never_run_out() {
+        let mut life = Life::new(GameMode::Deathmatch);
+        for _ in 0..10 {
+            life.die("Brute");
+        }
+        assert_eq!(life.lives(), None);
+        assert!(matches!(life.state(), LifeState::Dead { .. }));
+        assert_eq!(GameMode::parse("deathmatch"), Some(GameMode::Deathmatch));
+        assert_eq!(GameMode::parse("ctf"), Some(GameMode::CaptureTheFlag));
+        assert_eq!(GameMode::parse("tag"), None);
+    }
+}
@@ -0,0 +1,440 @@
+//! Player movement and collision against the maze grid.
+//!
+//! Players are circles moving through a grid of square cells. Moves are
+//! resolved one axis at a time so that pushing diagonally into a wall slides
+//! along it instead of stopping dead.
+//!
+//! Movement is simulated in fixed steps of [`STEP_SECONDS`] driven by
+//! [`PlayerInput`] commands. The client predicts with [`step_player`] and the
+//! server runs the exact same function on the same inputs, so both end up
+//! with bit-identical positions regardless of frame rate.
+
+use crate::CELL_SIZE;
+
+/// Radius of a player's collision circle in world units.
+pub const DEFAULT_PLAYER_RADIUS: f32 = 16.0;
+
+/// Simulation steps per second.
+pub const STEP_RATE: u32 = 60;
+
+/// Duration of one simulation step.
+pub const STEP_SECONDS: f32 = 1.0 / STEP_RATE as f32;
+
+/// Forward/backward speed in world units per second.
+pub const MOVE_SPEED: f32 = 200.0;
+
+/// Sideways speed in world units per second.
+pub const STRAFE_SPEED: f32 = 180.0;
+
+/// How close to a wall alongside the circle may be and still slide past
+/// it, so rounding doesn't snag a circle touching a wall on every cell.
+const CONTACT_TOLERANCE: f32 = 1e-3;
+
+/// Most simulation time a single frame may catch up on. Longer hitches are
+/// dropped instead of running hundreds of steps at once.
+const MAX_FRAME_SECONDS: f64 = 0.25;
+
+/// One step's worth of player intent.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PlayerInput {
+    /// -1.0 (backward) to 1.0 (forward).
+    pub forward: f32,
+    /// -1.0 (left) to 1.0 (right).
+    pub strafe: f32,
+    /// View yaw in radians at the time the input was sampled.
+    pub angle: f32,
+    /// View pitch in radians at the time the input was sampled.
+    pub pitch: f32,
+}
+
+impl PlayerInput {
+    /// Clamps the movement vector to unit length so diagonals are no faster
+    /// than moving straight. Analog input below full tilt is kept as is.
+    /// Non-finite values, which a client could send, are zeroed.
+    pub fn normalized(mut self) -> Self {
+        let finite = |value: f32| if value.is_finite() { value } else { 0.0 };
+        self.forward = finite(self.forward).clamp(-1.0, 1.0);
+        self.strafe = finite(self.strafe).clamp(-1.0, 1.0);
+        self.angle = finite(self.angle);
+        let length = self.forward.hypot(self.strafe);
+        if length > 1.0 {
+            self.forward /= length;
+            self.strafe /= length;
+        }
+        self.pitch = crate::clamp_pitch(self.pitch);
+        self
+    }
+}
+
+/// Simulated state of one player.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PlayerState {
+    pub x: f32,
+    pub y: f32,
+    pub angle: f32,
+    pub pitch: f32,
+}
+
+impl PlayerState {
+    pub fn new(x: f32, y: f32) -> Self {
+        Self { x, y, angle: 0.0, pitch: 0.0 }
+    }
+
+    /// Blends positions for rendering between two simulation steps.
+    pub fn lerp(&self, next: &PlayerState, alpha: f32) -> (f32, f32) {
+        (
+            self.x + (next.x - self.x) * alpha,
+            self.y + (next.y - self.y) * alpha,
+        )
+    }
+}
+
+/// Advances a player by one fixed step.
+pub fn step_player<G: Grid + ?Sized>(grid: &G, state: &PlayerState, input: &PlayerInput, radius: f32) -> (PlayerState, MoveResult) {
+    step_player_at(grid, state, input, radius, 1.0)
+}
+
+/// [`step_player`] with the speeds scaled by `speed`, e.g. for a speed boost.
+pub fn step_player_at<G: Grid + ?Sized>(grid: &G, state: &PlayerState, input: &PlayerInput, radius: f32, speed: f32) -> (PlayerState, MoveResult) {
+    let input = input.normalized();
+    let (sin, cos) = input.angle.sin_cos();
+    let forward = input.forward * MOVE_SPEED * speed * STEP_SECONDS;
+    let strafe = input.strafe * STRAFE_SPEED * speed * STEP_SECONDS;
+    // Strafing right is a quarter turn clockwise from facing: (-sin, cos)
+    let dx = cos * forward - sin * strafe;
+    let dy = sin * forward + cos * strafe;
+
+    let result = slide_move(grid, state.x, state.y, dx, dy, radius);
+    let next = PlayerState {
+        x: result.x,
+        y: result.y,
+        angle: input.angle,
+        pitch: input.pitch,
+    };
+    (next, result)
+}
+
+/// Turns variable frame times into a whole number of fixed steps.
+#[derive(Debug, Default)]
+pub struct FixedTimestep {
+    accumulator: f64,
+}
+
+impl FixedTimestep {
+    /// Adds a frame's duration and returns how many steps to simulate.
+    pub fn advance(&mut self, frame_seconds: f32) -> u32 {
+        self.accumulator += (frame_seconds as f64).clamp(0.0, MAX_FRAME_SECONDS);
+        let steps = (self.accumulator / STEP_SECONDS as f64).floor();
+        self.accumulator -= steps * STEP_SECONDS as f64;
+        steps as u32
+    }
+
+    /// Fraction of a step left over, used to interpolate rendering between
+    /// the previous and current step.
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / STEP_SECONDS as f64) as f32
+    }
+}
+
+/// A grid of cells that may block movement.
+pub trait Grid {
+    /// Whether the cell at the given column and row blocks movement. Cells
+    /// outside the grid are always solid.
+    fn is_solid(&self, cell_x: i32, cell_y: i32) -> bool;
+
+    /// Whether the cell stops a circle moving by `(dx, dy)` from overlapping
+    /// it. Grids with one-way cells override this; by default only solid
+    /// cells block.
+    fn blocks(&self, cell_x: i32, cell_y: i32, dx: f32, dy: f32) -> bool {
+        let _ = (dx, dy);
+        self.is_solid(cell_x, cell_y)
+    }
+}
+
+impl<const W: usize, const H: usize> Grid for [[bool; W]; H] {
+    fn is_solid(&self, cell_x: i32, cell_y: i32) -> bool {
+        if cell_x < 0 || cell_y < 0 || cell_x as usize >= W || cell_y as usize >= H {
+            return true;
+        }
+        self[cell_y as usize][cell_x as usize]
+    }
+}
+
+/// Outcome of [`slide_move`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MoveResult {
+    pub x: f32,
+    pub y: f32,
+    /// The horizontal part of the move was stopped by a wall.
+    pub blocked_x: bool,
+    /// The vertical part of the move was stopped by a wall.
+    pub blocked_y: bool,
+}
+
+/// Whether a circle at `(x, y)` overlaps any solid cell.
+pub fn circle_collides<G: Grid + ?Sized>(grid: &G, x: f32, y: f32, radius: f32) -> bool {
+    circle_overlaps(x, y, radius, |cell_x, cell_y| grid.is_solid(cell_x, cell_y))
+}
+
+/// Whether a circle at `(x, y)` overlaps any cell for which `blocking` is true.
+fn circle_overlaps(x: f32, y: f32, radius: f32, blocking: impl Fn(i32, i32) -> bool) -> bool {
+    let min_x = ((x - radius) / CELL_SIZE).floor() as i32;
+    let max_x = ((x + radius) / CELL_SIZE).floor() as i32;
+    let min_y = ((y - radius) / CELL_SIZE).floor() as i32;
+    let max_y = ((y + radius) / CELL_SIZE).floor() as i32;
+
+    for cell_y in min_y..=max_y {
+        for cell_x in min_x..=max_x {
+            if !blocking(cell_x, cell_y) {
+                continue;
+            }
+            // Closest point of the cell's square to the circle centre
+            let left = cell_x as f32 * CELL_SIZE;
+            let top = cell_y as f32 * CELL_SIZE;
+            let closest_x = x.clamp(left, left + CELL_SIZE);
+            let closest_y = y.clamp(top, top + CELL_SIZE);
+            let dx = x - closest_x;
+            let dy = y - closest_y;
+            if dx * dx + dy * dy < radius * radius {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Moves a circle by `(dx, dy)`, sliding along walls.
+///
+/// The x and y components are applied separately so a blocked axis doesn't
+/// cancel the other one. Long moves are split into steps no larger than half
+/// the radius so fast movement can't tunnel through a wall. A blocked step
+/// still moves up to the wall, so the circle ends up touching it.
+pub fn slide_move<G: Grid + ?Sized>(grid: &G, x: f32, y: f32, dx: f32, dy: f32, radius: f32) -> MoveResult {
+    let max_step = (radius / 2.0).max(1.0);
+    let steps = (dx.abs().max(dy.abs()) / max_step).ceil().max(1.0) as u32;
+    let step_x = dx / steps as f32;
+    let step_y = dy / steps as f32;
+
+    let mut result = MoveResult { x, y, blocked_x: false, blocked_y: false };
+    for _ in 0..steps {
+        if !result.blocked_x && step_x != 0.0 {
+            let travel = travel_along(result.x, result.y, step_x, radius, |cx, cy| grid.blocks(cx, cy, step_x, 0.0));
+            result.blocked_x = travel != step_x;
+            result.x += travel;
+        }
+        if !result.blocked_y && step_y != 0.0 {
+            // The same thing with the axes swapped
+            let travel = travel_along(result.y, result.x, step_y, radius, |cy, cx| grid.blocks(cx, cy, 0.0, step_y));
+            result.blocked_y = travel != step_y;
+            result.y += travel;
+        }
+    }
+    result
+}
+
+/// How far a circle at `(along, across)` can move by `delta` along the
+/// first axis before it touches a cell for which `blocking` is true, cells
+/// given as `(along, across)` too. `delta` itself when nothing is in the way.
+fn travel_along(along: f32, across: f32, delta: f32, radius: f32, blocking: impl Fn(i32, i32) -> bool) -> f32 {
+    let (from, to) = if delta > 0.0 { (along, along + delta) } else { (along + delta, along) };
+    let min_along = ((from - radius) / CELL_SIZE).floor() as i32;
+    let max_along = ((to + radius) / CELL_SIZE).floor() as i32;
+    let min_across = ((across - radius) / CELL_SIZE).floor() as i32;
+    let max_across = ((across + radius) / CELL_SIZE).floor() as i32;
+
+    let mut allowed = delta.abs();
+    for cell_across in min_across..=max_across {
+        // How far the circle reaches along the axis at the cell's nearest edge
+        let near = cell_across as f32 * CELL_SIZE;
+        let offset = across - across.clamp(near, near + CELL_SIZE);
+        // Touching a wall alongside isn't running into it
+        if offset.abs() >= radius - CONTACT_TOLERANCE {
+            continue;
+        }
+        let reach = (radius * radius - offset * offset).sqrt();
+        for cell_along in min_along..=max_along {
+            if !blocking(cell_along, cell_across) {
+                continue;
+            }
+            let face = if delta > 0.0 { cell_along as f32 * CELL_SIZE - along } else { along - (cell_along + 1) as f32 * CELL_SIZE };
+            // Cells behind the centre can't be run into
+            if face >= 0.0 {
+                allowed = allowed.min((face - reach).max(0.0));
+            }
+        }
+    }
+    if allowed >= delta.abs() {
+        delta
+    } else {
+        allowed.copysign(delta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const R: f32 = DEFAULT_PLAYER_RADIUS;
+
+    /// Builds a grid from rows of `#` (wall) and `.` (floor).
+    fn grid<const W: usize, const H: usize>(rows: [&str; H]) -> [[bool; W]; H] {
+        let mut cells = [[false; W]; H];
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                cells[y][x] = c == '#';
+            }
+        }
+        cells
+    }
+
+    fn centre(cell: f32) -> f32 {
+        (cell + 0.5) * CELL_SIZE
+    }
+
+    fn room() -> [[bool; 5]; 5] {
+        grid(["#####", "#...#", "#...#", "#...#", "#####"])
+    }
+
+    #[test]
+    fn open_floor_moves_freely() {
+        let result = slide_move(&room(), centre(2.0), centre(2.0), 10.0, -7.0, R);
+        assert_eq!((result.x, result.y), (centre(2.0) + 10.0, centre(2.0) - 7.0));
+        assert!(!result.blocked_x && !result.blocked_y);
+    }
+
+    #[test]
+    fn head_on_wall_stops_at_radius() {
+        let grid = room();
+        let result = slide_move(&grid, centre(3.0), centre(2.0), 100.0, 0.0, R);
+        assert!(result.blocked_x);
+        // Flush against the right wall, which starts at x = 4 * CELL_SIZE
+        assert!((result.x + R - 4.0 * CELL_SIZE).abs() < 1e-3);
+        assert!(!circle_collides(&grid, result.x, result.y, R));
+        assert_eq!(result.y, centre(2.0));
+    }
+
+    #[test]
+    fn diagonal_into_wall_slides_along_it() {
+        let grid = room();
+        let start_y = centre(2.0);
+        let result = slide_move(&grid, centre(3.0), start_y, 40.0, 20.0, R);
+        assert!(result.blocked_x);
+        assert!(!result.blocked_y);
+        assert_eq!(result.y, start_y + 20.0);
+    }
+
+    #[test]
+    fn slides_along_a_wall_it_touches() {
+        let grid: [[bool; 8]; 4] = grid(["########", "#......#", "#......#", "########"]);
+        // Pressed against the bottom wall, then along it past several cells
+        let down = slide_move(&grid, centre(1.0), centre(2.0), 0.0, CELL_SIZE, R);
+        assert!(down.blocked_y);
+        assert!((down.y + R - 3.0 * CELL_SIZE).abs() < 1e-3);
+        let along = slide_move(&grid, down.x, down.y, 3.0 * CELL_SIZE, 10.0, R);
+        assert!(!along.blocked_x && along.blocked_y);
+        assert_eq!(along.x, down.x + 3.0 * CELL_SIZE);
+    }
+
+    #[test]
+    fn concave_corner_blocks_both_axes() {
+        let grid = room();
+        let result = slide_move(&grid, centre(3.0), centre(3.0), 40.0, 40.0, R);
+        assert!(result.blocked_x && result.blocked_y);
+        assert!(!circle_collides(&grid, result.x, result.y, R));
+    }
+
+    #[test]
+    fn convex_corner_is_rounded() {
+        // A single pillar in the middle of the room
+        let grid: [[bool; 5]; 5] = grid(["#####", "#...#", "#.#.#", "#...#", "#####"]);
+        let pillar_corner = 2.0 * CELL_SIZE;
+        // Diagonally off the pillar's corner: inside its bounding box reach
+        // on both axes but further than the radius from the corner itself.
+        let offset = R * 0.8;
+        assert!(!circle_collides(&grid, pillar_corner - offset, pillar_corner - offset, R));
+        assert!(circle_collides(&grid, pillar_corner - offset / 2.0, pillar_corner - offset / 2.0, R));
+    }
+
+    #[test]
+    fn walks_down_single_cell_corridor() {
+        let grid: [[bool; 3]; 6] = grid(["###", "#.#", "#.#", "#.#", "#.#", "###"]);
+        let result = slide_move(&grid, centre(1.0), centre(1.0), 0.0, 3.0 * CELL_SIZE, R);
+        assert!(!result.blocked_x);
+        assert_eq!(result.y, centre(4.0));
+    }
+
+    #[test]
+    fn diagonal_in_corridor_slides_without_entering_walls() {
+        let grid: [[bool; 3]; 6] = grid(["###", "#.#", "#.#", "#.#", "#.#", "###"]);
+        let result = slide_move(&grid, centre(1.0), centre(1.0), 30.0, 100.0, R);
+        assert!(result.blocked_x);
+        assert!((result.y - (centre(1.0) + 100.0)).abs() < 0.01);
+        assert!(!circle_collides(&grid, result.x, result.y, R));
+    }
+
+    #[test]
+    fn too_wide_for_corridor() {
+        let grid: [[bool; 3]; 3] = grid(["###", "#.#", "###"]);
+        assert!(circle_collides(&grid, centre(1.0), centre(1.0), CELL_SIZE * 0.6));
+        assert!(!circle_collides(&grid, centre(1.0), centre(1.0), CELL_SIZE * 0.4));
+    }
+
+    #[test]
+    fn fast_move_does_not_tunnel() {
+        let grid: [[bool; 5]; 3] = grid(["#####", "#.#.#", "#####"]);
+        let result = slide_move(&grid, centre(1.0), centre(1.0), 2.0 * CELL_SIZE, 0.0, R);
+        assert!(result.blocked_x);
+        assert!(result.x < 2.0 * CELL_SIZE);
+    }
+
+    #[test]
+    fn diagonal_is_not_faster_than_straight() {
+        let grid = [[false; 8]; 8];
+        let start = PlayerState::new(centre(4.0), centre(4.0));
+        let straight = PlayerInput { forward: 1.0, ..Default::default() };
+        let diagonal = PlayerInput { forward: 1.0, strafe: 1.0, ..Default::default() };
+
+        let (a, _) = step_player(&grid, &start, &straight, R);
+        let (b, _) = step_player(&grid, &start, &diagonal, R);
+        let straight_distance = (a.x - start.x).hypot(a.y - start.y);
+        let diagonal_distance = (b.x - start.x).hypot(b.y - start.y);
+        assert!(diagonal_distance <= straight_distance + 1e-4);
+    }
+
+    #[test]
+    fn frame_rate_does_not_change_step_count() {
+        let mut slow = FixedTimestep::default();
+        let mut fast = FixedTimestep::default();
+        let slow_steps: u32 = (0..60).map(|_| slow.advance(1.0 / 30.0)).sum();
+        let fast_steps: u32 = (0..288).map(|_| fast.advance(1.0 / 144.0)).sum();
+        // Two seconds either way, give or take the step still accumulating
+        assert!(slow_steps.abs_diff(2 * STEP_RATE) <= 1);
+        assert!(fast_steps.abs_diff(2 * STEP_RATE) <= 1);
+        assert!((0.0..1.0).contains(&slow.alpha()));
+    }
+
+    #[test]
+    fn replaying_inputs_is_deterministic() {
+        let grid = room();
+        let inputs: Vec<PlayerInput> = (0..120)
+            .map(|i| PlayerInput { forward: 1.0, strafe: (i % 3) as f32 - 1.0, angle: i as f32 * 0.05, pitch: 0.0 })
+            .collect();
+        let run = || {
+            inputs.iter().fold(PlayerState::new(centre(2.0), centre(2.0)), |state, input| {
+                step_player(&grid, &state, input, R).0
+            })
+        };
+        let first = run();
+        let second = run();
+        assert_eq!(first.x.to_bits(), second.x.to_bits());
+        assert_eq!(first.y.to_bits(), second.y.to_bits());
+    }
+
+    #[test]
+    fn outside_grid_is_solid() {
+        let grid = room();
+        assert!(grid.is_solid(-1, 2));
+        assert!(grid.is_solid(2, 5));
+        assert!(!grid.is_solid(2, 2));
+    }
+}
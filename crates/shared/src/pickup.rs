@@ -0,0 +1,204 @@
+//! Items lying in the level: ammo packs, health kits and timed boosts.
+//!
+//! A collected item comes back after its kind's respawn time. The server
+//! decides who gets what and tells clients where the items are and which
+//! ones are there right now; offline the client runs this itself.
+
+use crate::level::{Level, PickupKind, FLOOR};
+use crate::maze::Maze;
+use crate::rng::Rng;
+use crate::CELL_SIZE;
+
+/// How close a player has to come to take an item, in world units.
+pub const PICKUP_RANGE: f32 = CELL_SIZE * 0.4;
+
+/// Bullets in one ammo pack.
+pub const AMMO_PACK: i32 = 15;
+
+/// Rockets in one ammo pack, on top of the bullets.
+pub const ROCKET_PACK: i32 = 2;
+
+/// Health in one health kit.
+pub const HEALTH_PACK: i32 = 25;
+
+/// How long a boost lasts.
+pub const BOOST_SECONDS: f32 = 10.0;
+
+/// Movement speed with a speed boost, as a multiple of the normal speed.
+pub const SPEED_BOOST: f32 = 1.5;
+
+/// Damage with a damage boost, as a multiple of the normal damage.
+pub const DAMAGE_BOOST: i32 = 2;
+
+/// Items placed when a level doesn't list any.
+const SCATTERED_PICKUPS: usize = 6;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PickupState {
+    pub kind: PickupKind,
+    /// Position in cells, like in the level file.
+    pub x: f32,
+    pub y: f32,
+    /// Seconds until a taken item is back, 0 while it's there.
+    pub respawn: f32,
+}
+
+impl PickupState {
+    pub fn available(&self) -> bool {
+        self.respawn <= 0.0
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Pickups {
+    items: Vec<PickupState>,
+}
+
+impl Pickups {
+    /// The level's items, or a few scattered over the floor from `seed` if
+    /// it has none, so generated levels and old level files get some too.
+    pub fn new(level: &Level, maze: &Maze, seed: u64) -> Self {
+        if level.pickups.is_empty() {
+            return Self::scattered(level, maze, seed);
+        }
+        let items = level
+            .pickups
+            .iter()
+            .map(|pickup| PickupState { kind: pickup.kind, x: pickup.x, y: pickup.y, respawn: 0.0 })
+            .collect();
+        Self { items }
+    }
+
+    fn scattered(level: &Level, maze: &Maze, seed: u64) -> Self {
+        let start = maze.start;
+        let mut cells: Vec<(usize, usize)> = maze
+            .distances_from(start)
+            .iter()
+            .enumerate()
+            .filter(|(_, distance)| distance.is_some_and(|distance| distance >= 3))
+            .map(|(index, _)| (index % maze.width(), index / maze.width()))
+            .filter(|&cell| cell != maze.exit && level.cell(cell.0, cell.1) == FLOOR)
+            .collect();
+        let mut rng = Rng::new(seed);
+        rng.shuffle(&mut cells);
+        let items = cells
+            .into_iter()
+            .take(SCATTERED_PICKUPS)
+            .zip(PickupKind::ALL.iter().cycle())
+            .map(|((x, y), &kind)| PickupState { kind, x: x as f32 + 0.5, y: y as f32 + 0.5, respawn: 0.0 })
+            .collect();
+        Self { items }
+    }
+
+    /// Items as they are on the server.
+    pub fn from_items(items: Vec<PickupState>) -> Self {
+        Self { items }
+    }
+
+    pub fn items(&self) -> &[PickupState] {
+        &self.items
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Counts down respawn timers. Returns whether an item came back.
+    pub fn update(&mut self, seconds: f32) -> bool {
+        let mut changed = false;
+        for item in self.items.iter_mut().filter(|item| !item.available()) {
+            item.respawn -= seconds;
+            changed |= item.available();
+        }
+        changed
+    }
+
+    /// Takes the first item within reach of `(x, y)`, in world units, that
+    /// the player `wants`, e.g. no health kits at full health.
+    pub fn collect(&mut self, x: f32, y: f32, wants: impl Fn(PickupKind) -> bool) -> Option<PickupKind> {
+        let item = self.items.iter_mut().find(|item| {
+            item.available()
+                && wants(item.kind)
+                && (item.x * CELL_SIZE - x).hypot(item.y * CELL_SIZE - y) < PICKUP_RANGE
+        })?;
+        item.respawn = item.kind.respawn_seconds();
+        Some(item.kind)
+    }
+}
+
+/// Boosts a player has running, as seconds left.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Boosts {
+    pub speed: f32,
+    pub damage: f32,
+}
+
+impl Boosts {
+    /// Starts or restarts the boost a pickup gives, if it is one.
+    pub fn grant(&mut self, kind: PickupKind) {
+        match kind {
+            PickupKind::Speed => self.speed = BOOST_SECONDS,
+            PickupKind::Damage => self.damage = BOOST_SECONDS,
+            PickupKind::Ammo | PickupKind::Health => {}
+        }
+    }
+
+    pub fn update(&mut self, seconds: f32) {
+        self.speed = (self.speed - seconds).max(0.0);
+        self.damage = (self.damage - seconds).max(0.0);
+    }
+
+    pub fn speed_factor(&self) -> f32 {
+        if self.speed > 0.0 { SPEED_BOOST } else { 1.0 }
+    }
+
+    pub fn damage_factor(&self) -> i32 {
+        if self.damage > 0.0 { DAMAGE_BOOST } else { 1 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::Pickup;
+
+    #[test]
+    fn collected_items_respawn() {
+        let mut level = Level::numbered(1, 0);
+        level.pickups = vec![Pickup { kind: PickupKind::Health, x: 2.5, y: 1.5 }];
+        let mut pickups = Pickups::new(&level, &level.to_maze(), 0);
+        let (x, y) = (2.5 * CELL_SIZE, 1.5 * CELL_SIZE);
+
+        assert_eq!(pickups.collect(x, y, |kind| kind != PickupKind::Health), None);
+        assert_eq!(pickups.collect(x + CELL_SIZE, y, |_| true), None);
+        assert_eq!(pickups.collect(x, y, |_| true), Some(PickupKind::Health));
+        assert_eq!(pickups.collect(x, y, |_| true), None);
+
+        assert!(!pickups.update(PickupKind::Health.respawn_seconds() - 1.0));
+        assert!(pickups.update(1.0));
+        assert!(pickups.items()[0].available());
+    }
+
+    #[test]
+    fn levels_without_items_get_some_on_open_floor() {
+        let level = Level::numbered(2, 0);
+        assert!(level.pickups.is_empty());
+        let maze = level.to_maze();
+        let pickups = Pickups::new(&level, &maze, 7);
+        assert_eq!(pickups.items().len(), SCATTERED_PICKUPS);
+        for item in pickups.items() {
+            assert!(!maze.is_wall(item.x as usize, item.y as usize));
+        }
+        assert_eq!(pickups.items(), Pickups::new(&level, &maze, 7).items());
+    }
+
+    #[test]
+    fn boosts_run_out() {
+        let mut boosts = Boosts::default();
+        boosts.grant(PickupKind::Speed);
+        boosts.grant(PickupKind::Ammo);
+        assert_eq!((boosts.speed_factor(), boosts.damage_factor()), (SPEED_BOOST, 1));
+        boosts.update(BOOST_SECONDS);
+        assert_eq!(boosts.speed_factor(), 1.0);
+    }
+}
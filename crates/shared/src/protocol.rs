@@ -0,0 +1,1070 @@
+//! Text based UDP protocol, see `docs/protocol.md` for the wire format.
+//!
+//! Every datagram is a single message of the form `KIND:<payload>`. Fields in a
+//! payload are separated by `,` and records (e.g. players in a snapshot) by `;`.
+
+use crate::flag::{FlagEvent, FlagState};
+use crate::level::{EnemyKind, LevelSource, PickupKind};
+use crate::mechanism::MechanismState;
+use crate::mode::GameMode;
+use crate::movement::PlayerInput;
+use crate::pickup::PickupState;
+use crate::team::Team;
+
+/// Largest datagram either side reads, the most UDP over IPv4 carries.
+/// Snapshots and scoreboards grow with the number of players.
+pub const MAX_DATAGRAM: usize = 65507;
+
+/// Most players a server can be set to take. A snapshot or scoreboard with
+/// all of them still fits in [`MAX_DATAGRAM`].
+pub const MAX_PLAYERS: usize = 64;
+
+/// Longest username the server will accept, in characters.
+pub const MAX_USERNAME_LEN: usize = 16;
+
+/// Longest chat message, in characters. Longer ones are cut short.
+pub const MAX_CHAT_LEN: usize = 120;
+
+/// Player id chat messages from the server itself carry. Players are
+/// numbered from 1.
+pub const SERVER_CHAT_ID: u32 = 0;
+
+/// Messages sent from a client to the server.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientMessage {
+    /// Initial connection request.
+    Connect { username: String },
+    /// Input for one simulation step, numbered so the server can
+    /// acknowledge which inputs it has applied.
+    Input { seq: u32, input: PlayerInput },
+    /// Use the door, push wall or switch in front of the player.
+    Use,
+    /// Vote for one of the intermission options, by index. Voting again
+    /// changes the vote.
+    Vote { option: usize },
+    /// Asks for one chunk of the level file with this hash, see
+    /// [`crate::transfer`].
+    Fetch { hash: u64, chunk: usize },
+    /// The player took damage from something only the client runs, i.e.
+    /// an enemy. The server owns health and applies it.
+    Hurt { damage: i32, by: EnemyKind },
+    /// Whether the player carries all the ammo they can, so the server
+    /// leaves ammo packs for others. The client counts ammo, so it says
+    /// when this changes, as `Reliable`.
+    AmmoFull(bool),
+    /// Start over after the game is over.
+    Restart,
+    /// Clean disconnect.
+    Disconnect,
+    /// Looks for servers on the local network, see [`crate::discovery`].
+    /// Needs no connection; servers answer with `Info` carrying the same
+    /// token.
+    Discover { token: u64 },
+    /// Asks what the server is doing, answered with `Status` carrying the
+    /// same token. Needs no connection either.
+    Query { token: u64 },
+    /// Answer to `Ping`, echoing its token.
+    Pong { token: u64 },
+    /// A chat message, to everyone or only the player's team. Comes as
+    /// `Reliable`.
+    Say { team: bool, text: String },
+    /// A message that has to arrive, sent until acknowledged. Numbered per
+    /// connection, see [`crate::reliable`].
+    Reliable { seq: u32, message: Box<ClientMessage> },
+    /// Acknowledges every server `Reliable` up to `seq`.
+    Ack { seq: u32 },
+    /// The player killed an enemy. Enemies run on the client, so it
+    /// reports the kill and the server scores it.
+    Kill { victim: EnemyKind },
+    /// The player shot another player for `damage`, boosts included. The
+    /// server decides whether it counts, see [`crate::team::FriendlyFire`].
+    Hit { player_id: u32, damage: i32 },
+}
+
+/// Messages sent from the server to clients.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerMessage {
+    /// Connection accepted, carries the id assigned to the player and the
+    /// mode the server runs.
+    Accept { player_id: u32, mode: GameMode },
+    /// Connection refused, e.g. because the server is full.
+    Reject { reason: String },
+    /// Message of the day, sent after `Accept` when the server has one.
+    Motd(String),
+    /// World state update with every connected player.
+    Snapshot { players: Vec<PlayerSnapshot> },
+    /// Doors, switches and push walls, sent with every snapshot on levels
+    /// that have any.
+    World(MechanismState),
+    /// The level everyone is playing. Sent on join and whenever it changes.
+    /// `hash` is [`Level::content_hash`](crate::level::Level::content_hash)
+    /// of the level the server loaded from `source`.
+    Level { source: LevelSource, hash: u64 },
+    /// The round is over and players vote for the next level. Sent when the
+    /// intermission starts, on every vote and as the countdown ticks.
+    Intermission {
+        seconds_left: u32,
+        /// Player who reached the exit.
+        finisher: String,
+        options: Vec<VoteOption>,
+    },
+    /// One piece of the level file with this hash, in reply to a fetch.
+    Chunk { hash: u64, index: usize, count: usize, data: Vec<u8> },
+    /// Every item in the level and how long until taken ones are back,
+    /// sent with every snapshot.
+    Pickups(Vec<PickupState>),
+    /// A player took an item. The player applies ammo and boosts from it,
+    /// health comes with the snapshots.
+    Collected { player_id: u32, kind: PickupKind },
+    /// A player died. `lives` is what they have left, `None` in modes
+    /// without lives; at 0 their game is over.
+    Died { player_id: u32, killer: String, lives: Option<u32> },
+    /// A dead player is back at their spawn point, or started over.
+    Respawn { player_id: u32 },
+    /// Answer to `Discover`, echoing its token.
+    Info { token: u64, info: ServerInfo },
+    /// Answer to `Query`, echoing its token.
+    Status { token: u64, status: ServerStatus },
+    /// Sent to every player now and then to measure their ping; they
+    /// answer `Pong` with the same token straight away.
+    Ping { token: u64 },
+    /// A chat message for this player. Comes as `Reliable`.
+    Chat(ChatMessage),
+    /// A message that has to arrive, such as chat or a player's death.
+    /// Sent until acknowledged and numbered per player, see
+    /// [`crate::reliable`].
+    Reliable { seq: u32, message: Box<ServerMessage> },
+    /// Acknowledges every client `Reliable` up to `seq`.
+    Ack { seq: u32 },
+    /// A player killed something, for the kill feed. Deaths come as `Died`.
+    Kill { player_id: u32, victim: String },
+    /// Every player's kills, deaths, score and ping, sent now and then and
+    /// when a round ends.
+    Scores(Vec<PlayerStatus>),
+    /// Kills or captures per team, by [`Team::index`]. Sent with the
+    /// scores in team modes.
+    TeamScores([u32; 2]),
+    /// Where the flags are, sent with every snapshot in capture the flag.
+    Flags(Vec<(Team, FlagState)>),
+    /// Something happened to `team`'s flag, for the kill feed. No player
+    /// when a dropped flag went home by itself.
+    Flag { player_id: Option<u32>, team: Team, event: FlagEvent },
+}
+
+/// A chat message as the server hands it out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatMessage {
+    /// Who sent it, [`SERVER_CHAT_ID`] for the server.
+    pub player_id: u32,
+    pub username: String,
+    /// Sent to the sender's team only.
+    pub team: bool,
+    pub text: String,
+}
+
+/// What a server tells anyone looking for a game.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerInfo {
+    pub name: String,
+    /// Name of the level being played.
+    pub level: String,
+    pub mode: GameMode,
+    pub players: usize,
+    pub max_players: usize,
+}
+
+/// Everything `Query` tells about a server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerStatus {
+    pub version: String,
+    /// Seconds since the server started.
+    pub uptime: u64,
+    pub info: ServerInfo,
+    pub players: Vec<PlayerStatus>,
+}
+
+/// One player in a [`ServerStatus`] or on the scoreboard.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerStatus {
+    pub id: u32,
+    pub username: String,
+    pub kills: u32,
+    pub deaths: u32,
+    pub score: i32,
+    /// Round trip in milliseconds, `None` until first measured.
+    pub ping: Option<u32>,
+}
+
+/// A level on the intermission ballot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoteOption {
+    pub name: String,
+    pub votes: u32,
+}
+
+/// State of one player as broadcast in a snapshot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerSnapshot {
+    pub id: u32,
+    pub username: String,
+    pub x: f32,
+    pub y: f32,
+    pub angle: f32,
+    pub pitch: f32,
+    /// Sequence number of the last input the server applied for this player.
+    pub last_input: u32,
+    pub health: i32,
+    /// `None` in modes without teams.
+    pub team: Option<Team>,
+}
+
+/// Strips protocol separators and control characters from a username and
+/// truncates it to [`MAX_USERNAME_LEN`].
+pub fn sanitize_username(name: &str) -> String {
+    name.chars()
+        .filter(|c| !c.is_control() && !matches!(c, ':' | ',' | ';'))
+        .take(MAX_USERNAME_LEN)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UsernameError {
+    Empty,
+    TooLong,
+    /// A protocol separator or control character.
+    InvalidCharacter(char),
+}
+
+impl std::fmt::Display for UsernameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            UsernameError::Empty => write!(f, "name can't be empty"),
+            UsernameError::TooLong => write!(f, "name can't be longer than {} characters", MAX_USERNAME_LEN),
+            UsernameError::InvalidCharacter(c) => write!(f, "name can't contain {:?}", c),
+        }
+    }
+}
+
+impl std::error::Error for UsernameError {}
+
+/// Checks a name a player typed, returning it without surrounding spaces.
+/// Unlike [`sanitize_username`] nothing is silently dropped.
+pub fn validate_username(name: &str) -> Result<&str, UsernameError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(UsernameError::Empty);
+    }
+    if let Some(c) = name.chars().find(|c| c.is_control() || matches!(c, ':' | ',' | ';')) {
+        return Err(UsernameError::InvalidCharacter(c));
+    }
+    if name.chars().count() > MAX_USERNAME_LEN {
+        return Err(UsernameError::TooLong);
+    }
+    Ok(name)
+}
+
+/// Cleans up a chat message: control characters removed, cut to
+/// [`MAX_CHAT_LEN`] and trimmed. Empty when nothing is left to send.
+pub fn sanitize_chat(text: &str) -> String {
+    text.chars().filter(|c| !c.is_control()).take(MAX_CHAT_LEN).collect::<String>().trim().to_string()
+}
+
+/// Removes control characters from text that fills a whole message, where
+/// separators are fine.
+fn strip_control(text: &str) -> String {
+    text.chars().filter(|c| !c.is_control()).collect()
+}
+
+/// Removes protocol separators from free text such as level names.
+fn strip_separators(text: &str) -> String {
+    text.chars().filter(|c| !c.is_control() && !matches!(c, ',' | ';')).collect()
+}
+
+impl ClientMessage {
+    pub fn encode(&self) -> String {
+        match self {
+            ClientMessage::Connect { username } => format!("CONNECT:{}", username),
+            ClientMessage::Input { seq, input } => format!(
+                "INPUT:{},{},{},{},{}",
+                seq, input.forward, input.strafe, input.angle, input.pitch
+            ),
+            ClientMessage::Use => "USE".to_string(),
+            ClientMessage::Vote { option } => format!("VOTE:{}", option),
+            ClientMessage::Fetch { hash, chunk } => format!("FETCH:{:016x},{}", hash, chunk),
+            ClientMessage::Hurt { damage, by } => format!("HURT:{},{}", damage, enemy_name(*by)),
+            ClientMessage::AmmoFull(full) => format!("AMMO:{}", if *full { "full" } else { "room" }),
+            ClientMessage::Restart => "RESTART".to_string(),
+            ClientMessage::Disconnect => "DISCONNECT".to_string(),
+            ClientMessage::Discover { token } => format!("DISCOVER:{}", token),
+            ClientMessage::Query { token } => format!("QUERY:{}", token),
+            ClientMessage::Pong { token } => format!("PONG:{}", token),
+            ClientMessage::Say { team, text } => format!("SAY:{},{}", audience(*team), strip_control(text)),
+            ClientMessage::Reliable { seq, message } => format!("RELIABLE:{},{}", seq, message.encode()),
+            ClientMessage::Ack { seq } => format!("ACK:{}", seq),
+            ClientMessage::Kill { victim } => format!("KILL:{}", enemy_name(*victim)),
+            ClientMessage::Hit { player_id, damage } => format!("HIT:{},{}", player_id, damage),
+        }
+    }
+
+    pub fn decode(msg: &str) -> Option<Self> {
+        let (kind, payload) = split_kind(msg);
+        match kind {
+            "CONNECT" => Some(ClientMessage::Connect {
+                username: payload.to_string(),
+            }),
+            "INPUT" => {
+                let (seq, rest) = payload.split_once(',')?;
+                let fields = parse_floats(rest)?;
+                match fields[..] {
+                    [forward, strafe, angle, pitch] => Some(ClientMessage::Input {
+                        seq: seq.parse().ok()?,
+                        input: PlayerInput { forward, strafe, angle, pitch },
+                    }),
+                    _ => None,
+                }
+            }
+            "USE" => Some(ClientMessage::Use),
+            "VOTE" => Some(ClientMessage::Vote {
+                option: payload.parse().ok()?,
+            }),
+            "FETCH" => {
+                let (hash, chunk) = payload.split_once(',')?;
+                Some(ClientMessage::Fetch {
+                    hash: u64::from_str_radix(hash, 16).ok()?,
+                    chunk: chunk.parse().ok()?,
+                })
+            }
+            "HURT" => {
+                let (damage, by) = payload.split_once(',')?;
+                Some(ClientMessage::Hurt { damage: damage.parse().ok()?, by: parse_enemy(by)? })
+            }
+            "AMMO" => match payload {
+                "full" => Some(ClientMessage::AmmoFull(true)),
+                "room" => Some(ClientMessage::AmmoFull(false)),
+                _ => None,
+            },
+            "RESTART" => Some(ClientMessage::Restart),
+            "DISCONNECT" => Some(ClientMessage::Disconnect),
+            "DISCOVER" => Some(ClientMessage::Discover {
+                token: payload.parse().ok()?,
+            }),
+            "QUERY" => Some(ClientMessage::Query {
+                token: payload.parse().ok()?,
+            }),
+            "PONG" => Some(ClientMessage::Pong {
+                token: payload.parse().ok()?,
+            }),
+            "SAY" => {
+                // The text comes last and may contain separators
+                let (team, text) = payload.split_once(',')?;
+                Some(ClientMessage::Say { team: parse_audience(team)?, text: text.to_string() })
+            }
+            "RELIABLE" => {
+                let (seq, message) = payload.split_once(',')?;
+                match ClientMessage::decode(message)? {
+                    ClientMessage::Reliable { .. } => None,
+                    message => Some(ClientMessage::Reliable { seq: seq.parse().ok()?, message: Box::new(message) }),
+                }
+            }
+            "ACK" => Some(ClientMessage::Ack {
+                seq: payload.parse().ok()?,
+            }),
+            "KILL" => Some(ClientMessage::Kill {
+                victim: parse_enemy(payload)?,
+            }),
+            "HIT" => {
+                let (player_id, damage) = payload.split_once(',')?;
+                Some(ClientMessage::Hit { player_id: player_id.parse().ok()?, damage: damage.parse().ok()? })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl ServerMessage {
+    pub fn encode(&self) -> String {
+        match self {
+            ServerMessage::Accept { player_id, mode } => format!("ACCEPT:{},{}", player_id, mode.name()),
+            ServerMessage::Reject { reason } => format!("REJECT:{}", strip_control(reason)),
+            ServerMessage::Motd(text) => format!("MOTD:{}", strip_control(text)),
+            ServerMessage::Snapshot { players } => {
+                let records: Vec<String> = players
+                    .iter()
+                    .map(|p| {
+                        format!(
+                            "{},{},{},{},{},{},{},{},{}",
+                            p.id,
+                            p.username,
+                            p.x,
+                            p.y,
+                            p.angle,
+                            p.pitch,
+                            p.last_input,
+                            p.health,
+                            p.team.map_or("-", Team::name)
+                        )
+                    })
+                    .collect();
+                format!("SNAPSHOT:{}", records.join(";"))
+            }
+            ServerMessage::World(state) => {
+                let doors: Vec<String> = state.doors.iter().map(u8::to_string).collect();
+                let switches: Vec<&str> = state.switches.iter().map(|&on| if on { "1" } else { "0" }).collect();
+                let walls: Vec<String> = state.push_walls.iter().map(|(x, y)| format!("{},{}", x, y)).collect();
+                let spikes = if state.spikes_raised { 1 } else { 0 };
+                format!("WORLD:{};{};{};{}", doors.join(","), switches.join(","), walls.join(","), spikes)
+            }
+            ServerMessage::Level { source, hash } => format!("LEVEL:{},{:016x}", source.encode(), hash),
+            ServerMessage::Intermission { seconds_left, finisher, options } => {
+                let mut records = vec![format!("{},{}", seconds_left, strip_separators(finisher))];
+                records.extend(options.iter().map(|o| format!("{},{}", strip_separators(&o.name), o.votes)));
+                format!("INTERMISSION:{}", records.join(";"))
+            }
+            ServerMessage::Chunk { hash, index, count, data } => {
+                format!("CHUNK:{:016x},{},{},{}", hash, index, count, to_hex(data))
+            }
+            ServerMessage::Pickups(items) => {
+                let records: Vec<String> = items
+                    .iter()
+                    .map(|item| {
+                        let respawn = item.respawn.max(0.0).ceil() as u32;
+                        format!("{},{},{},{}", pickup_name(item.kind), item.x, item.y, respawn)
+                    })
+                    .collect();
+                format!("PICKUPS:{}", records.join(";"))
+            }
+            ServerMessage::Collected { player_id, kind } => format!("COLLECTED:{},{}", player_id, pickup_name(*kind)),
+            ServerMessage::Died { player_id, killer, lives } => match lives {
+                Some(lives) => format!("DIED:{},{},{}", player_id, strip_separators(killer), lives),
+                None => format!("DIED:{},{}", player_id, strip_separators(killer)),
+            },
+            ServerMessage::Respawn { player_id } => format!("RESPAWN:{}", player_id),
+            ServerMessage::Info { token, info } => format!(
+                "INFO:{},{},{},{},{},{}",
+                token,
+                strip_separators(&info.name),
+                strip_separators(&info.level),
+                info.mode.name(),
+                info.players,
+                info.max_players
+            ),
+            ServerMessage::Status { token, status } => {
+                let info = &status.info;
+                let mut records = vec![format!(
+                    "{},{},{},{},{},{},{},{}",
+                    token,
+                    strip_separators(&status.version),
+                    status.uptime,
+                    strip_separators(&info.name),
+                    strip_separators(&info.level),
+                    info.mode.name(),
+                    info.players,
+                    info.max_players
+                )];
+                records.extend(status.players.iter().map(encode_player_status));
+                format!("STATUS:{}", records.join(";"))
+            }
+            ServerMessage::Ping { token } => format!("PING:{}", token),
+            ServerMessage::Chat(message) => format!(
+                "CHAT:{},{},{},{}",
+                message.player_id,
+                audience(message.team),
+                strip_separators(&message.username),
+                strip_control(&message.text)
+            ),
+            ServerMessage::Reliable { seq, message } => format!("RELIABLE:{},{}", seq, message.encode()),
+            ServerMessage::Ack { seq } => format!("ACK:{}", seq),
+            ServerMessage::Kill { player_id, victim } => format!("KILL:{},{}", player_id, strip_separators(victim)),
+            ServerMessage::Scores(players) => {
+                let records: Vec<String> = players.iter().map(encode_player_status).collect();
+                format!("SCORES:{}", records.join(";"))
+            }
+            ServerMessage::TeamScores([red, blue]) => format!("TEAMS:{},{}", red, blue),
+            ServerMessage::Flags(flags) => {
+                let records: Vec<String> = flags
+                    .iter()
+                    .map(|(team, state)| match state {
+                        FlagState::AtBase => format!("{},base", team.name()),
+                        FlagState::Carried { player_id } => format!("{},carried,{}", team.name(), player_id),
+                        FlagState::Dropped { x, y, return_in } => {
+                            format!("{},dropped,{},{},{}", team.name(), x, y, return_in.max(0.0).ceil() as u32)
+                        }
+                    })
+                    .collect();
+                format!("FLAGS:{}", records.join(";"))
+            }
+            ServerMessage::Flag { player_id, team, event } => {
+                let player = player_id.map_or("-".to_string(), |id| id.to_string());
+                format!("FLAG:{},{},{}", player, team.name(), event.name())
+            }
+        }
+    }
+
+    pub fn decode(msg: &str) -> Option<Self> {
+        let (kind, payload) = split_kind(msg);
+        match kind {
+            "ACCEPT" => {
+                let (player_id, mode) = payload.split_once(',')?;
+                Some(ServerMessage::Accept { player_id: player_id.parse().ok()?, mode: GameMode::parse(mode)? })
+            }
+            "REJECT" => Some(ServerMessage::Reject { reason: payload.to_string() }),
+            "MOTD" => Some(ServerMessage::Motd(payload.to_string())),
+            "SNAPSHOT" => {
+                let mut players = Vec::new();
+                for record in payload.split(';').filter(|r| !r.is_empty()) {
+                    let fields: Vec<&str> = record.split(',').collect();
+                    if fields.len() != 9 {
+                        return None;
+                    }
+                    players.push(PlayerSnapshot {
+                        id: fields[0].parse().ok()?,
+                        username: fields[1].to_string(),
+                        x: fields[2].parse().ok()?,
+                        y: fields[3].parse().ok()?,
+                        angle: fields[4].parse().ok()?,
+                        pitch: fields[5].parse().ok()?,
+                        last_input: fields[6].parse().ok()?,
+                        health: fields[7].parse().ok()?,
+                        team: parse_team(fields[8])?,
+                    });
+                }
+                Some(ServerMessage::Snapshot { players })
+            }
+            "WORLD" => {
+                let fields: Vec<&str> = payload.split(';').collect();
+                let [doors, switches, walls, spikes] = fields[..] else { return None };
+                let numbers = |field: &str| -> Option<Vec<usize>> {
+                    field.split(',').filter(|n| !n.is_empty()).map(|n| n.parse().ok()).collect()
+                };
+                let walls = numbers(walls)?;
+                if !walls.len().is_multiple_of(2) {
+                    return None;
+                }
+                Some(ServerMessage::World(MechanismState {
+                    doors: numbers(doors)?.into_iter().map(|open| open.min(100) as u8).collect(),
+                    switches: numbers(switches)?.into_iter().map(|on| on != 0).collect(),
+                    push_walls: walls.chunks(2).map(|cell| (cell[0], cell[1])).collect(),
+                    spikes_raised: spikes.parse::<u8>().ok()? != 0,
+                }))
+            }
+            "LEVEL" => {
+                let (source, hash) = payload.split_once(',')?;
+                Some(ServerMessage::Level {
+                    source: LevelSource::decode(source)?,
+                    hash: u64::from_str_radix(hash, 16).ok()?,
+                })
+            }
+            "INTERMISSION" => {
+                let mut records = payload.split(';');
+                let (seconds_left, finisher) = records.next()?.split_once(',')?;
+                let options = records
+                    .map(|record| {
+                        let (name, votes) = record.rsplit_once(',')?;
+                        Some(VoteOption { name: name.to_string(), votes: votes.parse().ok()? })
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                Some(ServerMessage::Intermission {
+                    seconds_left: seconds_left.parse().ok()?,
+                    finisher: finisher.to_string(),
+                    options,
+                })
+            }
+            "CHUNK" => {
+                let fields: Vec<&str> = payload.split(',').collect();
+                let [hash, index, count, data] = fields[..] else { return None };
+                Some(ServerMessage::Chunk {
+                    hash: u64::from_str_radix(hash, 16).ok()?,
+                    index: index.parse().ok()?,
+                    count: count.parse().ok()?,
+                    data: from_hex(data)?,
+                })
+            }
+            "PICKUPS" => {
+                let items = payload
+                    .split(';')
+                    .filter(|record| !record.is_empty())
+                    .map(|record| {
+                        let fields: Vec<&str> = record.split(',').collect();
+                        let [kind, x, y, respawn] = fields[..] else { return None };
+                        Some(PickupState {
+                            kind: parse_pickup(kind)?,
+                            x: x.parse().ok()?,
+                            y: y.parse().ok()?,
+                            respawn: respawn.parse::<u32>().ok()? as f32,
+                        })
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                Some(ServerMessage::Pickups(items))
+            }
+            "COLLECTED" => {
+                let (player_id, kind) = payload.split_once(',')?;
+                Some(ServerMessage::Collected { player_id: player_id.parse().ok()?, kind: parse_pickup(kind)? })
+            }
+            "DIED" => {
+                let fields: Vec<&str> = payload.split(',').collect();
+                let (player_id, killer, lives) = match fields[..] {
+                    [player_id, killer] => (player_id, killer, None),
+                    [player_id, killer, lives] => (player_id, killer, Some(lives.parse().ok()?)),
+                    _ => return None,
+                };
+                Some(ServerMessage::Died { player_id: player_id.parse().ok()?, killer: killer.to_string(), lives })
+            }
+            "RESPAWN" => Some(ServerMessage::Respawn {
+                player_id: payload.parse().ok()?,
+            }),
+            "INFO" => {
+                let fields: Vec<&str> = payload.split(',').collect();
+                let [token, name, level, mode, players, max_players] = fields[..] else { return None };
+                Some(ServerMessage::Info {
+                    token: token.parse().ok()?,
+                    info: ServerInfo {
+                        name: name.to_string(),
+                        level: level.to_string(),
+                        mode: GameMode::parse(mode)?,
+                        players: players.parse().ok()?,
+                        max_players: max_players.parse().ok()?,
+                    },
+                })
+            }
+            "STATUS" => {
+                let mut records = payload.split(';');
+                let fields: Vec<&str> = records.next()?.split(',').collect();
+                let [token, version, uptime, name, level, mode, players, max_players] = fields[..] else {
+                    return None;
+                };
+                let info = ServerInfo {
+                    name: name.to_string(),
+                    level: level.to_string(),
+                    mode: GameMode::parse(mode)?,
+                    players: players.parse().ok()?,
+                    max_players: max_players.parse().ok()?,
+                };
+                let players = records
+                    .filter(|record| !record.is_empty())
+                    .map(decode_player_status)
+                    .collect::<Option<Vec<_>>>()?;
+                Some(ServerMessage::Status {
+                    token: token.parse().ok()?,
+                    status: ServerStatus { version: version.to_string(), uptime: uptime.parse().ok()?, info, players },
+                })
+            }
+            "PING" => Some(ServerMessage::Ping {
+                token: payload.parse().ok()?,
+            }),
+            "CHAT" => {
+                let mut fields = payload.splitn(4, ',');
+                let (player_id, team) = (fields.next()?, fields.next()?);
+                let (username, text) = (fields.next()?, fields.next()?);
+                Some(ServerMessage::Chat(ChatMessage {
+                    player_id: player_id.parse().ok()?,
+                    username: username.to_string(),
+                    team: parse_audience(team)?,
+                    text: text.to_string(),
+                }))
+            }
+            "RELIABLE" => {
+                let (seq, message) = payload.split_once(',')?;
+                match ServerMessage::decode(message)? {
+                    ServerMessage::Reliable { .. } => None,
+                    message => Some(ServerMessage::Reliable { seq: seq.parse().ok()?, message: Box::new(message) }),
+                }
+            }
+            "ACK" => Some(ServerMessage::Ack {
+                seq: payload.parse().ok()?,
+            }),
+            "KILL" => {
+                let (player_id, victim) = payload.split_once(',')?;
+                Some(ServerMessage::Kill { player_id: player_id.parse().ok()?, victim: victim.to_string() })
+            }
+            "SCORES" => Some(ServerMessage::Scores(
+                payload
+                    .split(';')
+                    .filter(|record| !record.is_empty())
+                    .map(decode_player_status)
+                    .collect::<Option<Vec<_>>>()?,
+            )),
+            "TEAMS" => {
+                let (red, blue) = payload.split_once(',')?;
+                Some(ServerMessage::TeamScores([red.parse().ok()?, blue.parse().ok()?]))
+            }
+            "FLAGS" => {
+                let flags = payload
+                    .split(';')
+                    .filter(|record| !record.is_empty())
+                    .map(|record| {
+                        let fields: Vec<&str> = record.split(',').collect();
+                        let state = match fields[1..] {
+                            ["base"] => FlagState::AtBase,
+                            ["carried", player_id] => FlagState::Carried { player_id: player_id.parse().ok()? },
+                            ["dropped", x, y, return_in] => FlagState::Dropped {
+                                x: x.parse().ok()?,
+                                y: y.parse().ok()?,
+                                return_in: return_in.parse::<u32>().ok()? as f32,
+                            },
+                            _ => return None,
+                        };
+                        Some((Team::parse(fields[0])?, state))
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                Some(ServerMessage::Flags(flags))
+            }
+            "FLAG" => {
+                let fields: Vec<&str> = payload.split(',').collect();
+                let [player_id, team, event] = fields[..] else { return None };
+                Some(ServerMessage::Flag {
+                    player_id: match player_id {
+                        "-" => None,
+                        id => Some(id.parse().ok()?),
+                    },
+                    team: Team::parse(team)?,
+                    event: FlagEvent::parse(event)?,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// `ammo`, `health`, `speed` or `damage`.
+fn pickup_name(kind: PickupKind) -> String {
+    kind.label().to_lowercase()
+}
+
+fn parse_pickup(name: &str) -> Option<PickupKind> {
+    PickupKind::ALL.into_iter().find(|&kind| pickup_name(kind) == name)
+}
+
+/// `id,username,kills,deaths,score,ping`, ping `-` while unknown.
+fn encode_player_status(p: &PlayerStatus) -> String {
+    let ping = p.ping.map_or("-".to_string(), |ping| ping.to_string());
+    format!("{},{},{},{},{},{}", p.id, strip_separators(&p.username), p.kills, p.deaths, p.score, ping)
+}
+
+fn decode_player_status(record: &str) -> Option<PlayerStatus> {
+    let fields: Vec<&str> = record.split(',').collect();
+    let [id, username, kills, deaths, score, ping] = fields[..] else { return None };
+    Some(PlayerStatus {
+        id: id.parse().ok()?,
+        username: username.to_string(),
+        kills: kills.parse().ok()?,
+        deaths: deaths.parse().ok()?,
+        score: score.parse().ok()?,
+        ping: match ping {
+            "-" => None,
+            ping => Some(ping.parse().ok()?),
+        },
+    })
+}
+
+/// `grunt`, `scout` or `brute`.
+fn enemy_name(kind: EnemyKind) -> String {
+    kind.label().to_lowercase()
+}
+
+fn parse_enemy(name: &str) -> Option<EnemyKind> {
+    EnemyKind::ALL.into_iter().find(|&kind| enemy_name(kind) == name)
+}
+
+/// A player's team in a snapshot, `-` for none.
+fn parse_team(text: &str) -> Option<Option<Team>> {
+    match text {
+        "-" => Some(None),
+        name => Some(Some(Team::parse(name)?)),
+    }
+}
+
+/// `team` or `all`, who a chat message is for.
+fn audience(team: bool) -> &'static str {
+    if team { "team" } else { "all" }
+}
+
+fn parse_audience(text: &str) -> Option<bool> {
+    match text {
+        "team" => Some(true),
+        "all" => Some(false),
+        _ => None,
+    }
+}
+
+fn split_kind(msg: &str) -> (&str, &str) {
+    let msg = msg.trim();
+    msg.split_once(':').unwrap_or((msg, ""))
+}
+
+/// Binary data such as level file chunks travels as hex so it can't
+/// contain separators.
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_floats(payload: &str) -> Option<Vec<f32>> {
+    payload.split(',').map(|f| f.parse().ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_full_server_fits_in_a_datagram() {
+        // Longest names in four byte characters, and the widest numbers
+        let username = "\u{1F600}".repeat(MAX_USERNAME_LEN);
+        let snapshot = ServerMessage::Snapshot {
+            players: (0..MAX_PLAYERS)
+                .map(|_| PlayerSnapshot {
+                    id: u32::MAX,
+                    username: username.clone(),
+                    x: -1234.5677,
+                    y: -1234.5677,
+                    angle: -0.000_012_345_678,
+                    pitch: -0.399_999_98,
+                    last_input: u32::MAX,
+                    health: i32::MIN,
+                    team: Some(Team::Blue),
+                })
+                .collect(),
+        };
+        let status = PlayerStatus { id: u32::MAX, username, kills: u32::MAX, deaths: u32::MAX, score: i32::MIN, ping: Some(u32::MAX) };
+        let scores = ServerMessage::Scores(vec![status; MAX_PLAYERS]);
+        for message in [snapshot, scores] {
+            let reliable = ServerMessage::Reliable { seq: u32::MAX, message: Box::new(message) };
+            assert!(reliable.encode().len() <= MAX_DATAGRAM, "{} bytes", reliable.encode().len());
+        }
+    }
+
+    #[test]
+    fn level_messages_round_trip() {
+        let level = ServerMessage::Level {
+            source: LevelSource::Numbered { number: 5, seed: 99 },
+            hash: 0x00ab_cdef_0123_4567,
+        };
+        assert_eq!(level.encode(), "LEVEL:5@99,00abcdef01234567");
+        assert_eq!(ServerMessage::decode(&level.encode()), Some(level));
+    }
+
+    #[test]
+    fn world_state_round_trips() {
+        let world = ServerMessage::World(MechanismState {
+            doors: vec![0, 45, 100],
+            switches: vec![true, false],
+            push_walls: vec![(3, 4)],
+            spikes_raised: true,
+        });
+        assert_eq!(world.encode(), "WORLD:0,45,100;1,0;3,4;1");
+        assert_eq!(ServerMessage::decode(&world.encode()), Some(world));
+        let empty = ServerMessage::World(MechanismState::default());
+        assert_eq!(ServerMessage::decode(&empty.encode()), Some(empty));
+    }
+
+    #[test]
+    fn chunks_round_trip() {
+        let chunk = ServerMessage::Chunk { hash: 7, index: 1, count: 3, data: b"grid: [\"#.#\"],\n".to_vec() };
+        assert_eq!(ServerMessage::decode(&chunk.encode()), Some(chunk));
+        assert_eq!(ServerMessage::decode("CHUNK:7,0,1,abc"), None);
+        let fetch = ClientMessage::Fetch { hash: u64::MAX, chunk: 12 };
+        assert_eq!(ClientMessage::decode(&fetch.encode()), Some(fetch));
+    }
+
+    #[test]
+    fn pickups_round_trip_with_whole_seconds() {
+        let pickups = ServerMessage::Pickups(vec![
+            PickupState { kind: PickupKind::Speed, x: 2.5, y: 7.5, respawn: 0.0 },
+            PickupState { kind: PickupKind::Ammo, x: 1.5, y: 1.5, respawn: 11.2 },
+        ]);
+        assert_eq!(pickups.encode(), "PICKUPS:speed,2.5,7.5,0;ammo,1.5,1.5,12");
+        let Some(ServerMessage::Pickups(items)) = ServerMessage::decode(&pickups.encode()) else {
+            panic!("pickups didn't decode");
+        };
+        assert!(items[0].available() && !items[1].available());
+        let collected = ServerMessage::Collected { player_id: 3, kind: PickupKind::Damage };
+        assert_eq!(ServerMessage::decode(&collected.encode()), Some(collected));
+        let full = ClientMessage::AmmoFull(true);
+        assert_eq!(full.encode(), "AMMO:full");
+        assert_eq!(ClientMessage::decode(&full.encode()), Some(full));
+        assert_eq!(ServerMessage::decode("COLLECTED:3,armour"), None);
+    }
+
+    #[test]
+    fn deaths_round_trip_with_and_without_lives() {
+        let died = ServerMessage::Died { player_id: 2, killer: "Spikes".to_string(), lives: Some(0) };
+        assert_eq!(died.encode(), "DIED:2,Spikes,0");
+        assert_eq!(ServerMessage::decode(&died.encode()), Some(died));
+        let died = ServerMessage::Died { player_id: 2, killer: "Brute".to_string(), lives: None };
+        assert_eq!(ServerMessage::decode(&died.encode()), Some(died));
+        let accept = ServerMessage::Accept { player_id: 4, mode: GameMode::Deathmatch };
+        assert_eq!(accept.encode(), "ACCEPT:4,deathmatch");
+        assert_eq!(ServerMessage::decode(&accept.encode()), Some(accept));
+        let motd = ServerMessage::Motd("Welcome: no camping, please; have fun".to_string());
+        assert_eq!(ServerMessage::decode(&motd.encode()), Some(motd));
+        let hurt = ClientMessage::Hurt { damage: 20, by: EnemyKind::Brute };
+        assert_eq!(hurt.encode(), "HURT:20,brute");
+        assert_eq!(ClientMessage::decode(&hurt.encode()), Some(hurt));
+        assert_eq!(ClientMessage::decode("HURT:20,Brute, the big one"), None);
+    }
+
+    #[test]
+    fn intermission_strips_separators_from_names() {
+        let intermission = ServerMessage::Intermission {
+            seconds_left: 12,
+            finisher: "alice".to_string(),
+            options: vec![
+                VoteOption { name: "Arena; the sequel, again".to_string(), votes: 2 },
+                VoteOption { name: "Level 4 - Generated".to_string(), votes: 0 },
+            ],
+        };
+        let Some(ServerMessage::Intermission { seconds_left, finisher, options }) =
+            ServerMessage::decode(&intermission.encode())
+        else {
+            panic!("intermission didn't decode");
+        };
+        assert_eq!((seconds_left, finisher.as_str()), (12, "alice"));
+        assert_eq!(options[0], VoteOption { name: "Arena the sequel again".to_string(), votes: 2 });
+        assert_eq!(options[1].name, "Level 4 - Generated");
+    }
+
+    #[test]
+    fn discovery_round_trips() {
+        let discover = ClientMessage::Discover { token: 42 };
+        assert_eq!(ClientMessage::decode(&discover.encode()), Some(discover));
+        let info = ServerMessage::Info {
+            token: 42,
+            info: ServerInfo {
+                name: "Office, 2nd floor".to_string(),
+                level: "Arena".to_string(),
+                mode: GameMode::Deathmatch,
+                players: 3,
+                max_players: 8,
+            },
+        };
+        let Some(ServerMessage::Info { token, info }) = ServerMessage::decode(&info.encode()) else {
+            panic!("info didn't decode");
+        };
+        assert_eq!((token, info.name.as_str(), info.players, info.max_players), (42, "Office 2nd floor", 3, 8));
+    }
+
+    #[test]
+    fn status_round_trips_with_and_without_players() {
+        let mut status = ServerStatus {
+            version: "0.1.0".to_string(),
+            uptime: 3725,
+            info: ServerInfo {
+                name: "Office".to_string(),
+                level: "Level 2; the sequel".to_string(),
+                mode: GameMode::Survival,
+                players: 2,
+                max_players: 8,
+            },
+            players: vec![
+                PlayerStatus { id: 1, username: "alice".to_string(), kills: 3, deaths: 1, score: 150, ping: Some(12) },
+                PlayerStatus { id: 4, username: "bob".to_string(), kills: 0, deaths: 2, score: 0, ping: None },
+            ],
+        };
+        let message = ServerMessage::Status { token: 7, status: status.clone() };
+        let Some(ServerMessage::Status { token: 7, status: decoded }) = ServerMessage::decode(&message.encode()) else {
+            panic!("status didn't decode");
+        };
+        assert_eq!(decoded.info.level, "Level 2 the sequel");
+        status.info.level = decoded.info.level.clone();
+        assert_eq!(decoded, status);
+
+        let scores = ServerMessage::Scores(status.players.clone());
+        assert_eq!(scores.encode(), "SCORES:1,alice,3,1,150,12;4,bob,0,2,0,-");
+        assert_eq!(ServerMessage::decode(&scores.encode()), Some(scores));
+
+        status.players.clear();
+        let empty = ServerMessage::Status { token: 7, status };
+        assert_eq!(ServerMessage::decode(&empty.encode()), Some(empty));
+        assert_eq!(ClientMessage::decode("QUERY:7"), Some(ClientMessage::Query { token: 7 }));
+        assert_eq!(ClientMessage::decode("QUERY"), None);
+    }
+
+    #[test]
+    fn kills_round_trip() {
+        let kill = ClientMessage::Kill { victim: EnemyKind::Brute };
+        assert_eq!(kill.encode(), "KILL:brute");
+        assert_eq!(ClientMessage::decode(&kill.encode()), Some(kill));
+        assert_eq!(ClientMessage::decode("KILL:dragon"), None);
+        let feed = ServerMessage::Kill { player_id: 3, victim: "Brute".to_string() };
+        assert_eq!(ServerMessage::decode(&feed.encode()), Some(feed));
+    }
+
+    #[test]
+    fn teams_and_flags_round_trip() {
+        let player = |team| PlayerSnapshot {
+            id: 2,
+            username: "alice".to_string(),
+            x: 96.0,
+            y: 160.5,
+            angle: 1.5,
+            pitch: 0.0,
+            last_input: 40,
+            health: 75,
+            team,
+        };
+        let snapshot = ServerMessage::Snapshot { players: vec![player(Some(Team::Blue)), player(None)] };
+        assert_eq!(ServerMessage::decode(&snapshot.encode()), Some(snapshot));
+        assert_eq!(ServerMessage::decode("SNAPSHOT:2,alice,96,160,1,0,40,75,green"), None);
+
+        let flags = ServerMessage::Flags(vec![
+            (Team::Red, FlagState::Carried { player_id: 3 }),
+            (Team::Blue, FlagState::Dropped { x: 100.5, y: 64.0, return_in: 11.2 }),
+        ]);
+        assert_eq!(flags.encode(), "FLAGS:red,carried,3;blue,dropped,100.5,64,12");
+        let Some(ServerMessage::Flags(decoded)) = ServerMessage::decode(&flags.encode()) else {
+            panic!("flags didn't decode");
+        };
+        assert_eq!(decoded[1].1, FlagState::Dropped { x: 100.5, y: 64.0, return_in: 12.0 });
+        let returned = ServerMessage::Flag { player_id: None, team: Team::Red, event: FlagEvent::Returned };
+        assert_eq!(returned.encode(), "FLAG:-,red,returned");
+        assert_eq!(ServerMessage::decode(&returned.encode()), Some(returned));
+        let teams = ServerMessage::TeamScores([2, 1]);
+        assert_eq!(ServerMessage::decode(&teams.encode()), Some(teams));
+        let hit = ClientMessage::Hit { player_id: 3, damage: 24 };
+        assert_eq!(ClientMessage::decode(&hit.encode()), Some(hit));
+    }
+
+    #[test]
+    fn chat_round_trips_with_separators_in_the_text() {
+        let say = ClientMessage::Reliable {
+            seq: 3,
+            message: Box::new(ClientMessage::Say { team: true, text: "go left, then: up; fast".to_string() }),
+        };
+        assert_eq!(say.encode(), "RELIABLE:3,SAY:team,go left, then: up; fast");
+        assert_eq!(ClientMessage::decode(&say.encode()), Some(say));
+        let chat = ServerMessage::Reliable {
+            seq: 12,
+            message: Box::new(ServerMessage::Chat(ChatMessage {
+                player_id: 2,
+                username: "alice".to_string(),
+                team: false,
+                text: "gg, well played".to_string(),
+            })),
+        };
+        assert_eq!(ServerMessage::decode(&chat.encode()), Some(chat));
+        assert_eq!(ServerMessage::decode("ACK:12"), Some(ServerMessage::Ack { seq: 12 }));
+        assert_eq!(ClientMessage::decode("SAY:everyone,hi"), None);
+        // Nothing is wrapped twice
+        assert_eq!(ClientMessage::decode("RELIABLE:1,RELIABLE:2,SAY:all,hi"), None);
+        assert_eq!(sanitize_chat(&format!("  hi\n{}", "x".repeat(MAX_CHAT_LEN))).chars().count(), MAX_CHAT_LEN - 2);
+    }
+
+    #[test]
+    fn usernames_are_validated_not_mangled() {
+        assert_eq!(validate_username("  alice "), Ok("alice"));
+        assert_eq!(validate_username(" "), Err(UsernameError::Empty));
+        assert_eq!(validate_username(&"x".repeat(MAX_USERNAME_LEN + 1)), Err(UsernameError::TooLong));
+        assert_eq!(validate_username("a,b"), Err(UsernameError::InvalidCharacter(',')));
+    }
+}
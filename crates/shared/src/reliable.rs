@@ -0,0 +1,167 @@
+//! Messages that have to arrive, such as chat, over datagrams that may be
+//! lost, duplicated or reordered.
+//!
+//! The sender numbers each message from 1 and keeps sending everything not
+//! yet acknowledged every [`RESEND`]. The receiver only takes the message
+//! after the last one it took and acknowledges the highest number taken so
+//! far, which covers every message before it too. Later messages that
+//! arrive early are dropped and come again with the next resend, so they
+//! are taken once each and in order.
+//!
+//! A receiver that stops acknowledging would leave the sender queueing
+//! forever, so [`Outbox::stalled`] tells when to give up on it.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How long to wait for an acknowledgement before sending again.
+pub const RESEND: Duration = Duration::from_millis(250);
+
+/// Most messages waiting for an acknowledgement before the other side
+/// counts as stalled.
+pub const MAX_PENDING: usize = 256;
+
+/// Messages on their way, until the other side acknowledges them.
+#[derive(Debug)]
+pub struct Outbox<T> {
+    next_seq: u32,
+    pending: VecDeque<(u32, T)>,
+    last_sent: Option<Instant>,
+    /// Since when messages have waited without any being acknowledged.
+    waiting_since: Option<Instant>,
+}
+
+impl<T> Default for Outbox<T> {
+    fn default() -> Self {
+        Self { next_seq: 1, pending: VecDeque::new(), last_sent: None, waiting_since: None }
+    }
+}
+
+impl<T: Clone> Outbox<T> {
+    /// Queues a message to go out with the next [`due`](Self::due) and
+    /// returns its sequence number.
+    pub fn push(&mut self, message: T) -> u32 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.pending.push_back((seq, message));
+        self.last_sent = None;
+        self.waiting_since.get_or_insert_with(Instant::now);
+        seq
+    }
+
+    /// Forgets every message up to and including `seq`.
+    pub fn ack(&mut self, seq: u32) {
+        let waiting = self.pending.len();
+        self.pending.retain(|(pending, _)| *pending > seq);
+        if self.pending.is_empty() {
+            self.waiting_since = None;
+        } else if self.pending.len() < waiting {
+            self.waiting_since = Some(Instant::now());
+        }
+    }
+
+    /// Whether the other side looks gone: nothing acknowledged for
+    /// `patience`, or more than [`MAX_PENDING`] messages waiting.
+    pub fn stalled(&self, now: Instant, patience: Duration) -> bool {
+        self.pending.len() > MAX_PENDING
+            || self.waiting_since.is_some_and(|since| now.saturating_duration_since(since) >= patience)
+    }
+
+    /// Everything still unacknowledged when it's time to send it (again),
+    /// oldest first.
+    pub fn due(&mut self, now: Instant) -> Vec<(u32, T)> {
+        if self.pending.is_empty() || self.last_sent.is_some_and(|sent| now.duration_since(sent) < RESEND) {
+            return Vec::new();
+        }
+        self.last_sent = Some(now);
+        self.pending.iter().cloned().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+/// Which messages have been taken, on the receiving side.
+#[derive(Debug, Default)]
+pub struct Inbox {
+    received: u32,
+}
+
+impl Inbox {
+    /// Whether message `seq` is the next one and should be taken. Answer
+    /// every message with [`received`](Self::received) either way, the
+    /// acknowledgement may have been lost.
+    pub fn accept(&mut self, seq: u32) -> bool {
+        let next = seq == self.received + 1;
+        if next {
+            self.received = seq;
+        }
+        next
+    }
+
+    /// Highest sequence number taken, 0 before the first.
+    pub fn received(&self) -> u32 {
+        self.received
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lost_messages_are_sent_again_until_acknowledged() {
+        let start = Instant::now();
+        let mut outbox = Outbox::default();
+        assert_eq!((outbox.push("hello"), outbox.push("again")), (1, 2));
+        assert_eq!(outbox.due(start), vec![(1, "hello"), (2, "again")]);
+        assert!(outbox.due(start + RESEND / 2).is_empty());
+
+        // Both lost, then the first one arrives
+        let mut inbox = Inbox::default();
+        assert_eq!(outbox.due(start + RESEND).len(), 2);
+        assert!(inbox.accept(1));
+        outbox.ack(inbox.received());
+        assert_eq!(outbox.due(start + RESEND * 2), vec![(2, "again")]);
+        assert!(inbox.accept(2));
+        outbox.ack(inbox.received());
+        assert!(outbox.is_empty() && outbox.due(start + RESEND * 3).is_empty());
+    }
+
+    #[test]
+    fn a_receiver_that_stops_acknowledging_stalls() {
+        let patience = RESEND * 4;
+        let mut outbox = Outbox::default();
+        assert!(!outbox.stalled(Instant::now() + patience * 2, patience));
+        outbox.push(1);
+        outbox.push(2);
+        let start = Instant::now();
+        assert!(!outbox.stalled(start, patience));
+        assert!(outbox.stalled(start + patience, patience));
+
+        // Progress starts the wait over
+        outbox.ack(1);
+        let acked = Instant::now();
+        assert!(!outbox.stalled(acked + patience / 2, patience));
+        assert!(outbox.stalled(acked + patience, patience));
+        outbox.ack(2);
+        assert!(!outbox.stalled(acked + patience * 2, patience));
+
+        for i in 0..=MAX_PENDING {
+            outbox.push(i);
+        }
+        assert!(outbox.stalled(Instant::now(), Duration::MAX));
+    }
+
+    #[test]
+    fn messages_are_taken_once_and_in_order() {
+        let mut inbox = Inbox::default();
+        // The second overtook the first, so it comes again later
+        assert!(!inbox.accept(2));
+        assert!(inbox.accept(1));
+        assert!(!inbox.accept(1));
+        assert!(inbox.accept(2));
+        assert_eq!(inbox.received(), 2);
+    }
+}
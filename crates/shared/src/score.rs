@@ -0,0 +1,96 @@
+//! What players score for, per mode.
+//!
+//! Online the server keeps every player's [`Tally`] and sends it around,
+//! offline the client keeps its own with the same rules.
+
+use crate::mode::GameMode;
+
+/// Points for killing an enemy or a player on the other team.
+pub const KILL_POINTS: i32 = 50;
+
+/// Points for everyone when a round ends, i.e. someone reached the exit.
+pub const ROUND_POINTS: i32 = 100;
+
+/// Score lost per death in the modes without lives.
+pub const DEATH_PENALTY: i32 = 50;
+
+/// Points for bringing the other team's flag home.
+pub const CAPTURE_POINTS: i32 = 200;
+
+/// Points for sending the own team's dropped flag home.
+pub const RETURN_POINTS: i32 = 25;
+
+/// Something a player scores for, or loses score for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreEvent {
+    Kill,
+    Death,
+    /// The round ended.
+    Round,
+    /// Captured the other team's flag.
+    Capture,
+    /// Returned the own team's flag.
+    Return,
+}
+
+impl ScoreEvent {
+    /// How many points this is worth in `mode`, negative for a loss.
+    pub fn points(self, mode: GameMode) -> i32 {
+        match (self, mode) {
+            (ScoreEvent::Kill, _) => KILL_POINTS,
+            (ScoreEvent::Round, _) => ROUND_POINTS,
+            (ScoreEvent::Capture, _) => CAPTURE_POINTS,
+            (ScoreEvent::Return, _) => RETURN_POINTS,
+            // Survival takes a life instead
+            (ScoreEvent::Death, GameMode::Survival) => 0,
+            (ScoreEvent::Death, GameMode::Deathmatch | GameMode::TeamDeathmatch | GameMode::CaptureTheFlag) => -DEATH_PENALTY,
+        }
+    }
+}
+
+/// One player's kills, deaths and score.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub kills: u32,
+    pub deaths: u32,
+    pub score: i32,
+}
+
+impl Tally {
+    /// Counts `event` and its points. The score never goes below 0.
+    pub fn record(&mut self, mode: GameMode, event: ScoreEvent) {
+        match event {
+            ScoreEvent::Kill => self.kills += 1,
+            ScoreEvent::Death => self.deaths += 1,
+            ScoreEvent::Round | ScoreEvent::Capture | ScoreEvent::Return => {}
+        }
+        self.score = (self.score + event.points(mode)).max(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deaths_cost_score_only_without_lives() {
+        let mut survival = Tally::default();
+        survival.record(GameMode::Survival, ScoreEvent::Kill);
+        survival.record(GameMode::Survival, ScoreEvent::Death);
+        assert_eq!(survival, Tally { kills: 1, deaths: 1, score: KILL_POINTS });
+
+        let mut deathmatch = Tally::default();
+        deathmatch.record(GameMode::Deathmatch, ScoreEvent::Round);
+        deathmatch.record(GameMode::Deathmatch, ScoreEvent::Death);
+        assert_eq!(deathmatch.score, ROUND_POINTS - DEATH_PENALTY);
+        for _ in 0..5 {
+            deathmatch.record(GameMode::Deathmatch, ScoreEvent::Death);
+        }
+        assert_eq!(deathmatch, Tally { kills: 0, deaths: 6, score: 0 });
+
+        let mut ctf = Tally::default();
+        ctf.record(GameMode::CaptureTheFlag, ScoreEvent::Capture);
+        ctf.record(GameMode::CaptureTheFlag, ScoreEvent::Death);
+        assert_eq!(ctf.score, CAPTURE_POINTS - DEATH_PENALTY);
+    }
+}
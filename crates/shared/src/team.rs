@@ -0,0 +1,135 @@
+//! Teams in the team modes, keeping them even and what shooting a teammate
+//! does.
+//!
+//! The server puts every player who joins on the smaller team. When players
+//! leave and one team ends up two or more players bigger, someone from it
+//! is moved over.
+
+use serde::{Deserialize, Serialize};
+
+/// Kills a team needs to win a round of team deathmatch.
+pub const KILL_LIMIT: u32 = 20;
+
+/// Captures a team needs to win a round of capture the flag.
+pub const CAPTURE_LIMIT: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Team {
+    Red,
+    Blue,
+}
+
+impl Team {
+    pub const ALL: [Team; 2] = [Team::Red, Team::Blue];
+
+    /// Name on the wire.
+    pub fn name(self) -> &'static str {
+        match self {
+            Team::Red => "red",
+            Team::Blue => "blue",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|team| team.name() == name)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Team::Red => "Red",
+            Team::Blue => "Blue",
+        }
+    }
+
+    pub fn other(self) -> Self {
+        match self {
+            Team::Red => Team::Blue,
+            Team::Blue => Team::Red,
+        }
+    }
+
+    /// Position in [`Team::ALL`], for per-team arrays.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// The team a new player joins: the smaller one, red when they're even.
+pub fn smaller_team(red: usize, blue: usize) -> Team {
+    if blue < red { Team::Blue } else { Team::Red }
+}
+
+/// The team someone should move out of to even things up, if the teams
+/// are two or more players apart.
+pub fn overfull_team(red: usize, blue: usize) -> Option<Team> {
+    if red >= blue + 2 {
+        Some(Team::Red)
+    } else if blue >= red + 2 {
+        Some(Team::Blue)
+    } else {
+        None
+    }
+}
+
+/// What happens when a player shoots a teammate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FriendlyFire {
+    /// Teammates can't hurt each other.
+    #[default]
+    Off,
+    /// Teammates take damage like anyone else.
+    On,
+    /// The shooter takes the damage instead.
+    Reflect,
+}
+
+impl FriendlyFire {
+    pub const ALL: [FriendlyFire; 3] = [FriendlyFire::Off, FriendlyFire::On, FriendlyFire::Reflect];
+
+    /// Name on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            FriendlyFire::Off => "off",
+            FriendlyFire::On => "on",
+            FriendlyFire::Reflect => "reflect",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|setting| setting.name() == name)
+    }
+
+    /// Damage a shot at a teammate does to the teammate and to the shooter.
+    pub fn split(self, damage: i32) -> (i32, i32) {
+        match self {
+            FriendlyFire::Off => (0, 0),
+            FriendlyFire::On => (damage, 0),
+            FriendlyFire::Reflect => (0, damage),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn players_join_the_smaller_team_and_big_gaps_are_evened_out() {
+        assert_eq!(smaller_team(0, 0), Team::Red);
+        assert_eq!(smaller_team(1, 0), Team::Blue);
+        assert_eq!(smaller_team(2, 3), Team::Red);
+        assert_eq!(overfull_team(2, 1), None);
+        assert_eq!(overfull_team(3, 1), Some(Team::Red));
+        assert_eq!(overfull_team(0, 2), Some(Team::Blue));
+        assert_eq!(Team::parse("blue").map(Team::other), Some(Team::Red));
+    }
+
+    #[test]
+    fn friendly_fire_settings() {
+        assert_eq!(FriendlyFire::default().split(25), (0, 0));
+        assert_eq!(FriendlyFire::On.split(25), (25, 0));
+        assert_eq!(FriendlyFire::Reflect.split(25), (0, 25));
+        assert_eq!(FriendlyFire::parse("reflect"), Some(FriendlyFire::Reflect));
+        assert_eq!(FriendlyFire::parse("half"), None);
+    }
+}
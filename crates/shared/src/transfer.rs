@@ -0,0 +1,150 @@
+//! Sending a level file from the server to a client in datagram sized
+//! chunks, and putting it back together on the other end.
+//!
+//! The file is the level's RON form, so the hash of what arrives can be
+//! checked against [`Level::content_hash`].
+
+use std::fmt;
+
+use crate::level::{Level, LoadError};
+
+/// Bytes of level file per chunk. Hex encoded on the wire, this keeps a
+/// chunk message under 1024 bytes, small enough to cross any network in
+/// one piece.
+pub const CHUNK_SIZE: usize = 256;
+
+/// Number of chunks a file of `len` bytes is sent in. Even an empty file
+/// takes one, so the receiver learns the count.
+pub fn chunk_count(len: usize) -> usize {
+    len.div_ceil(CHUNK_SIZE).max(1)
+}
+
+/// Chunk `index` of `text`, `None` past the end.
+pub fn chunk(text: &str, index: usize) -> Option<&[u8]> {
+    let bytes = text.as_bytes();
+    if index >= chunk_count(bytes.len()) {
+        return None;
+    }
+    let start = index * CHUNK_SIZE;
+    Some(&bytes[start..(start + CHUNK_SIZE).min(bytes.len())])
+}
+
+#[derive(Debug)]
+pub enum TransferError {
+    /// The chunks don't form UTF-8 text.
+    NotText,
+    Parse(LoadError),
+    /// The level arrived but isn't the one that was announced.
+    HashMismatch { expected: u64, actual: u64 },
+}
+
+impl fmt::Display for TransferError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransferError::NotText => write!(f, "level data is not text"),
+            TransferError::Parse(e) => write!(f, "level data doesn't parse: {}", e),
+            TransferError::HashMismatch { expected, actual } => {
+                write!(f, "level hash is {:016x}, expected {:016x}", actual, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TransferError {}
+
+/// Collects the chunks of the level with a given hash, in any order.
+pub struct Assembler {
+    hash: u64,
+    // Empty until the first chunk says how many there are
+    chunks: Vec<Option<Vec<u8>>>,
+}
+
+impl Assembler {
+    pub fn new(hash: u64) -> Self {
+        Self { hash, chunks: Vec::new() }
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// Stores a chunk. Chunks for another level, or that disagree with the
+    /// count seen so far, are ignored.
+    pub fn insert(&mut self, hash: u64, index: usize, count: usize, data: Vec<u8>) {
+        if hash != self.hash || index >= count || data.len() > CHUNK_SIZE {
+            return;
+        }
+        if self.chunks.is_empty() {
+            self.chunks = vec![None; count];
+        }
+        if self.chunks.len() == count {
+            self.chunks[index] = Some(data);
+        }
+    }
+
+    /// Chunks still to ask for. Before the count is known that's the first one.
+    pub fn missing(&self) -> Vec<usize> {
+        if self.chunks.is_empty() {
+            return vec![0];
+        }
+        (0..self.chunks.len()).filter(|&index| self.chunks[index].is_none()).collect()
+    }
+
+    /// (received, total) for progress displays, total 0 while unknown.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.chunks.iter().filter(|chunk| chunk.is_some()).count(), self.chunks.len())
+    }
+
+    /// The level once every chunk is in, checked against the hash.
+    pub fn finish(&self) -> Option<Result<Level, TransferError>> {
+        if !self.missing().is_empty() {
+            return None;
+        }
+        let bytes: Vec<u8> = self.chunks.iter().flatten().flatten().copied().collect();
+        Some(self.verify(bytes))
+    }
+
+    fn verify(&self, bytes: Vec<u8>) -> Result<Level, TransferError> {
+        let text = String::from_utf8(bytes).map_err(|_| TransferError::NotText)?;
+        let level = Level::parse(&text).map_err(TransferError::Parse)?;
+        let actual = level.content_hash();
+        if actual != self.hash {
+            return Err(TransferError::HashMismatch { expected: self.hash, actual });
+        }
+        Ok(level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send(text: &str, hash: u64, order: impl Iterator<Item = usize>) -> Option<Result<Level, TransferError>> {
+        let count = chunk_count(text.len());
+        let mut assembler = Assembler::new(hash);
+        for index in order {
+            assembler.insert(hash, index, count, chunk(text, index).unwrap().to_vec());
+        }
+        assembler.finish()
+    }
+
+    #[test]
+    fn levels_arrive_in_any_order() {
+        let level = Level::numbered(6, 3);
+        let text = level.to_ron().unwrap();
+        let count = chunk_count(text.len());
+        assert!(count > 1);
+
+        let received = send(&text, level.content_hash(), (0..count).rev());
+        assert_eq!(received.unwrap().unwrap(), level);
+        assert!(send(&text, level.content_hash(), 1..count).is_none());
+    }
+
+    #[test]
+    fn rejects_a_level_with_the_wrong_hash() {
+        let level = Level::numbered(1, 0);
+        let text = level.to_ron().unwrap();
+        let result = send(&text, 42, 0..chunk_count(text.len()));
+        assert!(matches!(result, Some(Err(TransferError::HashMismatch { expected: 42, .. }))));
+    }
+}
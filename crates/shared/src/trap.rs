@@ -0,0 +1,231 @@
+//! Traps: hazard floors that hurt, spikes that come up on a timer and
+//! teleporters between two cells.
+//!
+//! They never block movement, they only do something to whoever stands on
+//! them. The server applies them to every player; offline the client does.
+//! Only the spike timer changes, and it's synced along with the other
+//! [mechanisms](crate::mechanism).
+
+use std::collections::BTreeMap;
+
+use crate::level::{Level, HAZARD_FLOOR, SPIKES};
+use crate::CELL_SIZE;
+
+/// Damage each time a hazard floor hurts.
+pub const HAZARD_DAMAGE: i32 = 10;
+
+/// Damage each time raised spikes hurt.
+pub const SPIKE_DAMAGE: i32 = 25;
+
+/// Seconds between two hits for someone standing on a trap, so damage
+/// doesn't depend on the tick rate.
+pub const HURT_INTERVAL: f32 = 0.5;
+
+/// Seconds from one raising of the spikes to the next.
+const SPIKE_CYCLE: f32 = 3.0;
+
+/// Seconds of each cycle the spikes are up.
+const SPIKES_UP: f32 = 1.2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrapKind {
+    /// Hurts all the time.
+    Hazard,
+    /// Hurts while raised.
+    Spikes,
+    /// Moves whoever steps on it to its destination.
+    Teleporter,
+}
+
+impl TrapKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            TrapKind::Hazard => "Hazard floor",
+            TrapKind::Spikes => "Spikes",
+            TrapKind::Teleporter => "Teleporter",
+        }
+    }
+}
+
+/// What standing on a trap did to a player.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrapEffect {
+    /// Damage done by a trap of this kind.
+    Damage(TrapKind, i32),
+    /// Move to this position, in world units.
+    Teleport { x: f32, y: f32 },
+}
+
+/// The traps of one level.
+#[derive(Debug, Clone, Default)]
+pub struct Traps {
+    cells: BTreeMap<(usize, usize), TrapKind>,
+    teleports: BTreeMap<(usize, usize), (usize, usize)>,
+    clock: f32,
+    spikes_raised: bool,
+}
+
+impl Traps {
+    pub fn new(level: &Level) -> Self {
+        let mut traps = Self::default();
+        for (y, row) in level.grid.iter().enumerate() {
+            for (x, symbol) in row.chars().enumerate() {
+                match symbol {
+                    HAZARD_FLOOR => traps.cells.insert((x, y), TrapKind::Hazard),
+                    SPIKES => traps.cells.insert((x, y), TrapKind::Spikes),
+                    _ => None,
+                };
+            }
+        }
+        for teleporter in &level.teleporters {
+            traps.cells.insert(teleporter.from, TrapKind::Teleporter);
+            traps.teleports.insert(teleporter.from, teleporter.to);
+        }
+        traps
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn has_spikes(&self) -> bool {
+        self.cells.values().any(|&kind| kind == TrapKind::Spikes)
+    }
+
+    pub fn at(&self, x: usize, y: usize) -> Option<TrapKind> {
+        self.cells.get(&(x, y)).copied()
+    }
+
+    /// Every trap cell.
+    pub fn cells(&self) -> impl Iterator<Item = ((usize, usize), TrapKind)> + '_ {
+        self.cells.iter().map(|(&cell, &kind)| (cell, kind))
+    }
+
+    /// Cell the teleporter at `(x, y)` leads to.
+    pub fn destination(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        self.teleports.get(&(x, y)).copied()
+    }
+
+    pub fn spikes_raised(&self) -> bool {
+        self.spikes_raised
+    }
+
+    /// Runs the spike timer.
+    pub fn update(&mut self, seconds: f32) {
+        self.clock = (self.clock + seconds) % SPIKE_CYCLE;
+        self.spikes_raised = self.clock < SPIKES_UP;
+    }
+
+    /// Takes over the spike state from the server.
+    pub fn set_spikes_raised(&mut self, raised: bool) {
+        self.spikes_raised = raised;
+    }
+}
+
+/// How one player is doing with the traps: where they stood last and how
+/// long until a trap can hurt them again.
+#[derive(Debug, Clone, Default)]
+pub struct Exposure {
+    cell: Option<(usize, usize)>,
+    cooldown: f32,
+}
+
+impl Exposure {
+    /// Applies the trap under a player at `(x, y)` for `seconds`. Teleporters
+    /// only fire when stepped onto, so arriving on one doesn't bounce the
+    /// player straight back.
+    pub fn step(&mut self, traps: &Traps, x: f32, y: f32, seconds: f32) -> Option<TrapEffect> {
+        self.cooldown = (self.cooldown - seconds).max(0.0);
+        let cell = ((x / CELL_SIZE).max(0.0) as usize, (y / CELL_SIZE).max(0.0) as usize);
+        let entered = self.cell != Some(cell);
+        self.cell = Some(cell);
+
+        let kind = traps.at(cell.0, cell.1)?;
+        let damage = match kind {
+            TrapKind::Teleporter => {
+                let to = traps.destination(cell.0, cell.1).filter(|_| entered)?;
+                self.cell = Some(to);
+                return Some(TrapEffect::Teleport {
+                    x: (to.0 as f32 + 0.5) * CELL_SIZE,
+                    y: (to.1 as f32 + 0.5) * CELL_SIZE,
+                });
+            }
+            TrapKind::Hazard => HAZARD_DAMAGE,
+            TrapKind::Spikes if traps.spikes_raised() => SPIKE_DAMAGE,
+            TrapKind::Spikes => return None,
+        };
+        if self.cooldown > 0.0 {
+            return None;
+        }
+        self.cooldown = HURT_INTERVAL;
+        Some(TrapEffect::Damage(kind, damage))
+    }
+
+    /// Forgets the last cell, e.g. after a respawn.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::Teleporter;
+    use crate::maze::Maze;
+
+    fn traps(grid: &[&str], teleporters: Vec<Teleporter>) -> Traps {
+        let mut level = Level::from_maze("Test", 1, &Maze::new(grid[0].len(), grid.len()));
+        level.grid = grid.iter().map(|row| row.to_string()).collect();
+        level.teleporters = teleporters;
+        Traps::new(&level)
+    }
+
+    fn centre(x: usize, y: usize) -> (f32, f32) {
+        ((x as f32 + 0.5) * CELL_SIZE, (y as f32 + 0.5) * CELL_SIZE)
+    }
+
+    #[test]
+    fn hazards_hurt_at_a_fixed_rate() {
+        let traps = traps(&["#####", "#.~.#", "#####"], Vec::new());
+        let mut exposure = Exposure::default();
+        let (x, y) = centre(2, 1);
+        let mut total = 0;
+        // One second at 60 ticks a second
+        for _ in 0..60 {
+            if let Some(TrapEffect::Damage(_, damage)) = exposure.step(&traps, x, y, 1.0 / 60.0) {
+                total += damage;
+            }
+        }
+        assert_eq!(total, 2 * HAZARD_DAMAGE);
+        let (x, y) = centre(1, 1);
+        assert_eq!(exposure.step(&traps, x, y, 1.0), None);
+    }
+
+    #[test]
+    fn spikes_only_hurt_while_raised() {
+        let mut traps = traps(&["#####", "#.!.#", "#####"], Vec::new());
+        let (x, y) = centre(2, 1);
+        let mut exposure = Exposure::default();
+        traps.update(SPIKES_UP + 0.1);
+        assert!(!traps.spikes_raised());
+        assert_eq!(exposure.step(&traps, x, y, 0.1), None);
+        traps.update(SPIKE_CYCLE - SPIKES_UP);
+        assert!(traps.spikes_raised());
+        assert_eq!(exposure.step(&traps, x, y, 0.1), Some(TrapEffect::Damage(TrapKind::Spikes, SPIKE_DAMAGE)));
+    }
+
+    #[test]
+    fn teleporters_fire_once_per_step_onto_them() {
+        let teleporters = vec![Teleporter { from: (1, 1), to: (3, 1) }, Teleporter { from: (3, 1), to: (1, 1) }];
+        let traps = traps(&["#####", "#...#", "#####"], teleporters);
+        let mut exposure = Exposure::default();
+        let (x, y) = centre(1, 1);
+        let (to_x, to_y) = centre(3, 1);
+        assert_eq!(exposure.step(&traps, x, y, 0.1), Some(TrapEffect::Teleport { x: to_x, y: to_y }));
+        // Arriving on the way back doesn't send the player back again
+        assert_eq!(exposure.step(&traps, to_x, to_y, 0.1), None);
+        let (x, y) = centre(2, 1);
+        assert_eq!(exposure.step(&traps, x, y, 0.1), None);
+        assert!(matches!(exposure.step(&traps, to_x, to_y, 0.1), Some(TrapEffect::Teleport { .. })));
+    }
+}
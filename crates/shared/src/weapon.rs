@@ -0,0 +1,337 @@
+//! Weapons: what each one does, the ammo a player carries and the rockets
+//! in flight.
+//!
+//! Hitscan weapons hit the first thing along the aim line within their
+//! range. The launcher fires a [`Projectile`] that flies until it meets a
+//! wall or a target and hurts everything around the impact.
+
+use crate::maze::Maze;
+use crate::CELL_SIZE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AmmoKind {
+    Bullets,
+    Rockets,
+}
+
+impl AmmoKind {
+    /// Most a player can carry on top of what's loaded.
+    pub fn max(self) -> i32 {
+        match self {
+            AmmoKind::Bullets => 120,
+            AmmoKind::Rockets => 10,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WeaponKind {
+    Pistol,
+    /// Fires as long as the trigger is held.
+    Rifle,
+    Launcher,
+}
+
+/// How a weapon behaves.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WeaponDef {
+    pub name: &'static str,
+    /// Seconds between shots.
+    pub fire_interval: f32,
+    pub damage: i32,
+    /// World units. For the launcher, how far a rocket flies.
+    pub range: f32,
+    /// Largest angle a shot strays from the aim, radians either way.
+    pub spread: f32,
+    pub ammo: AmmoKind,
+    /// Shots per magazine.
+    pub magazine: i32,
+    pub reload_seconds: f32,
+    /// Keeps firing while the trigger is held rather than once per press.
+    pub automatic: bool,
+    /// World units per second, 0 for hitscan.
+    pub projectile_speed: f32,
+    /// Anything this close to the impact is hurt, rockets only.
+    pub splash_radius: f32,
+}
+
+impl WeaponKind {
+    pub const ALL: [WeaponKind; 3] = [WeaponKind::Pistol, WeaponKind::Rifle, WeaponKind::Launcher];
+
+    pub fn def(self) -> WeaponDef {
+        match self {
+            WeaponKind::Pistol => WeaponDef {
+                name: "Pistol",
+                fire_interval: 0.35,
+                damage: 25,
+                range: 300.0,
+                spread: 0.02,
+                ammo: AmmoKind::Bullets,
+                magazine: 12,
+                reload_seconds: 1.2,
+                automatic: false,
+                projectile_speed: 0.0,
+                splash_radius: 0.0,
+            },
+            WeaponKind::Rifle => WeaponDef {
+                name: "Rifle",
+                fire_interval: 0.1,
+                damage: 12,
+                range: 400.0,
+                spread: 0.06,
+                ammo: AmmoKind::Bullets,
+                magazine: 30,
+                reload_seconds: 2.0,
+                automatic: true,
+                projectile_speed: 0.0,
+                splash_radius: 0.0,
+            },
+            WeaponKind::Launcher => WeaponDef {
+                name: "Launcher",
+                fire_interval: 0.9,
+                damage: 60,
+                range: CELL_SIZE * 12.0,
+                spread: 0.0,
+                ammo: AmmoKind::Rockets,
+                magazine: 3,
+                reload_seconds: 2.5,
+                automatic: false,
+                projectile_speed: 300.0,
+                splash_radius: CELL_SIZE * 0.75,
+            },
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// The weapons a player carries: one of each, with what's loaded in each
+/// and spare ammo shared by weapons that take the same kind.
+#[derive(Debug, Clone)]
+pub struct Arsenal {
+    current: WeaponKind,
+    loaded: [i32; 3],
+    reserve: [i32; 2],
+    // Seconds until the current weapon can fire again
+    cooldown: f32,
+    // Seconds left of a reload in progress
+    reloading: Option<f32>,
+}
+
+impl Default for Arsenal {
+    /// Full magazines and a little spare ammo, holding the pistol.
+    fn default() -> Self {
+        Self {
+            current: WeaponKind::Pistol,
+            loaded: WeaponKind::ALL.map(|kind| kind.def().magazine),
+            reserve: [30, 3],
+            cooldown: 0.0,
+            reloading: None,
+        }
+    }
+}
+
+impl Arsenal {
+    pub fn current(&self) -> WeaponKind {
+        self.current
+    }
+
+    pub fn loaded(&self, weapon: WeaponKind) -> i32 {
+        self.loaded[weapon.index()]
+    }
+
+    pub fn reserve(&self, ammo: AmmoKind) -> i32 {
+        self.reserve[ammo.index()]
+    }
+
+    /// Fraction of the reload done, `None` when not reloading.
+    pub fn reload_progress(&self) -> Option<f32> {
+        let total = self.current.def().reload_seconds;
+        self.reloading.map(|left| 1.0 - left / total)
+    }
+
+    /// Switches weapons, abandoning a reload.
+    pub fn select(&mut self, weapon: WeaponKind) {
+        if weapon != self.current {
+            self.current = weapon;
+            self.reloading = None;
+            self.cooldown = self.cooldown.max(0.2);
+        }
+    }
+
+    /// Switches to the next (`step` 1) or previous (-1) weapon.
+    pub fn cycle(&mut self, step: i32) {
+        let count = WeaponKind::ALL.len() as i32;
+        let next = (self.current.index() as i32 + step).rem_euclid(count) as usize;
+        self.select(WeaponKind::ALL[next]);
+    }
+
+    /// Starts reloading the current weapon if there's room in the magazine
+    /// and spare ammo for it. Returns whether it started.
+    pub fn reload(&mut self) -> bool {
+        let def = self.current.def();
+        if self.reloading.is_some() || self.loaded(self.current) >= def.magazine || self.reserve(def.ammo) == 0 {
+            return false;
+        }
+        self.reloading = Some(def.reload_seconds);
+        true
+    }
+
+    /// Runs the fire and reload timers.
+    pub fn update(&mut self, seconds: f32) {
+        self.cooldown = (self.cooldown - seconds).max(0.0);
+        let Some(left) = self.reloading else { return };
+        if left > seconds {
+            self.reloading = Some(left - seconds);
+            return;
+        }
+        self.reloading = None;
+        let def = self.current.def();
+        let moved = (def.magazine - self.loaded(self.current)).min(self.reserve(def.ammo));
+        self.loaded[self.current.index()] += moved;
+        self.reserve[def.ammo.index()] -= moved;
+    }
+
+    /// Pulls the trigger: `pressed` this frame, or `held` down. Returns the
+    /// weapon that fired, if one did. An empty magazine starts a reload.
+    pub fn fire(&mut self, pressed: bool, held: bool) -> Option<WeaponKind> {
+        let def = self.current.def();
+        let trigger = if def.automatic { held } else { pressed };
+        if !trigger || self.cooldown > 0.0 || self.reloading.is_some() {
+            return None;
+        }
+        if self.loaded(self.current) == 0 {
+            self.reload();
+            return None;
+        }
+        self.loaded[self.current.index()] -= 1;
+        self.cooldown = def.fire_interval;
+        Some(self.current)
+    }
+
+    /// Whether there's room for more of `ammo`.
+    pub fn wants(&self, ammo: AmmoKind) -> bool {
+        self.reserve(ammo) < ammo.max()
+    }
+
+    /// Whether an ammo pack would add anything.
+    pub fn wants_ammo(&self) -> bool {
+        self.wants(AmmoKind::Bullets) || self.wants(AmmoKind::Rockets)
+    }
+
+    /// Adds spare ammo, up to the most a player can carry.
+    pub fn add_ammo(&mut self, ammo: AmmoKind, amount: i32) {
+        let reserve = &mut self.reserve[ammo.index()];
+        *reserve = (*reserve + amount).min(ammo.max());
+    }
+}
+
+/// A rocket in flight.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Projectile {
+    pub x: f32,
+    pub y: f32,
+    pub angle: f32,
+    pub weapon: WeaponKind,
+    traveled: f32,
+}
+
+impl Projectile {
+    pub fn new(weapon: WeaponKind, x: f32, y: f32, angle: f32) -> Self {
+        Self { x, y, angle, weapon, traveled: 0.0 }
+    }
+
+    /// Moves the rocket on by `seconds`. Returns `false` once it hits a wall
+    /// or has flown its range, leaving it at the point of impact.
+    pub fn advance(&mut self, maze: &Maze, seconds: f32) -> bool {
+        let def = self.weapon.def();
+        let (sin, cos) = self.angle.sin_cos();
+        let distance = def.projectile_speed * seconds;
+        // Small steps so a fast rocket can't skip through a wall corner
+        let steps = (distance / 4.0).ceil().max(1.0) as usize;
+        for _ in 0..steps {
+            let (next_x, next_y) = (self.x + cos * distance / steps as f32, self.y + sin * distance / steps as f32);
+            if next_x < 0.0 || next_y < 0.0 || maze.is_wall((next_x / CELL_SIZE) as usize, (next_y / CELL_SIZE) as usize) {
+                return false;
+            }
+            self.x = next_x;
+            self.y = next_y;
+        }
+        self.traveled += distance;
+        self.traveled < def.range
+    }
+
+    /// Damage to something at `distance` from the impact, less towards the
+    /// edge of the blast.
+    pub fn splash_damage(&self, distance: f32) -> i32 {
+        let def = self.weapon.def();
+        if distance >= def.splash_radius {
+            return 0;
+        }
+        (def.damage as f32 * (1.0 - 0.5 * distance / def.splash_radius)).round() as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn magazines_empty_and_reload_from_the_reserve() {
+        let mut arsenal = Arsenal::default();
+        let pistol = WeaponKind::Pistol.def();
+        for _ in 0..pistol.magazine {
+            assert_eq!(arsenal.fire(true, true), Some(WeaponKind::Pistol));
+            // Semi-automatic: holding the trigger doesn't fire again
+            assert_eq!(arsenal.fire(false, true), None);
+            arsenal.update(pistol.fire_interval);
+        }
+        // The empty click starts a reload
+        assert_eq!(arsenal.fire(true, true), None);
+        assert!(arsenal.reload_progress().is_some());
+        arsenal.update(pistol.reload_seconds);
+        assert_eq!(arsenal.loaded(WeaponKind::Pistol), pistol.magazine);
+        assert_eq!(arsenal.reserve(AmmoKind::Bullets), 30 - pistol.magazine);
+    }
+
+    #[test]
+    fn switching_weapons_cancels_a_reload() {
+        let mut arsenal = Arsenal::default();
+        arsenal.select(WeaponKind::Rifle);
+        arsenal.update(1.0);
+        assert_eq!(arsenal.fire(false, true), Some(WeaponKind::Rifle));
+        assert!(arsenal.reload());
+        arsenal.cycle(1);
+        assert_eq!(arsenal.current(), WeaponKind::Launcher);
+        assert_eq!(arsenal.reload_progress(), None);
+        arsenal.cycle(-2);
+        assert_eq!(arsenal.current(), WeaponKind::Pistol);
+
+        arsenal.add_ammo(AmmoKind::Rockets, 100);
+        assert_eq!(arsenal.reserve(AmmoKind::Rockets), AmmoKind::Rockets.max());
+        assert!(!arsenal.wants(AmmoKind::Rockets));
+    }
+
+    #[test]
+    fn rockets_stop_at_walls() {
+        let maze = Maze::new(7, 3);
+        let mut rocket = Projectile::new(WeaponKind::Launcher, CELL_SIZE * 1.5, CELL_SIZE * 1.5, 0.0);
+        let mut flying = true;
+        for _ in 0..600 {
+            flying = rocket.advance(&maze, 1.0 / 60.0);
+            if !flying {
+                break;
+            }
+        }
+        assert!(!flying);
+        assert!(rocket.x < CELL_SIZE * 6.0 && rocket.x > CELL_SIZE * 5.0);
+        assert_eq!(rocket.splash_damage(0.0), WeaponKind::Launcher.def().damage);
+        assert_eq!(rocket.splash_damage(CELL_SIZE), 0);
+    }
+}